@@ -39,7 +39,7 @@ macro_rules! timeit {
 fn main() {
     let file_path = test_ieee39::IEEE_39;
     let net: Network = serde_json::from_str(file_path).unwrap();
-    let pf = PFNetwork::from(net);
+    let pf = PFNetwork::try_from(net).unwrap();
     let v_init = pf.create_v_init();
     let tol = Some(1e-8);
     let max_it = Some(10);
@@ -0,0 +1,54 @@
+use std::env;
+
+use rustpower::{io::pandapower::*, prelude::*};
+
+#[macro_export]
+macro_rules! timeit {
+    ($name:ident, $times:expr, $block:expr) => {{
+        use std::time::{Duration, Instant};
+        let mut total_duration = Duration::new(0, 0);
+        let mut max_duration = Duration::new(0, 0);
+        let mut min_duration = Duration::new(u64::MAX, 999_999_999);
+
+        for _ in 0..$times {
+            let start_time = Instant::now();
+            let _result = $block();
+            let end_time = Instant::now();
+            let duration = end_time - start_time;
+
+            total_duration += duration;
+            if duration > max_duration {
+                max_duration = duration;
+            }
+            if duration < min_duration {
+                min_duration = duration;
+            }
+        }
+
+        let avg_duration = total_duration / $times;
+        println!(
+            " {} loops, {} - Average: {:?}, Max: {:?}, Min: {:?}",
+            $times,
+            stringify!($name),
+            avg_duration,
+            max_duration,
+            min_duration
+        );
+    }};
+}
+
+fn main() {
+    let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let zipfile = format!("{}/cases/pegase9241/data.zip", dir);
+    let net = load_csv_zip(zipfile).unwrap();
+    let pf = PFNetwork::try_from(net).unwrap();
+    let nodes = pf.buses.len();
+
+    // full assembly: sparsity pattern and values
+    timeit!(ybus_assemble, 20, || pf.create_y_bus());
+
+    // re-solve: only the values are updated in the cached pattern
+    let pattern = YBusPattern::new(nodes, &pf.y_br);
+    let mut ybus = pattern.assemble(&pf.y_br, pf.s_base);
+    timeit!(ybus_update, 20, || pattern.update(&mut ybus, &pf.y_br, pf.s_base));
+}
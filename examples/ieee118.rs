@@ -42,7 +42,7 @@ fn main() {
     let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
     let zipfile = format!("{}/cases/IEEE118/data.zip", dir);
     let net = load_csv_zip(zipfile).unwrap();
-    let pf = PFNetwork::from(net);
+    let pf = PFNetwork::try_from(net).unwrap();
     let v_init = pf.create_v_init();
     let tol = Some(1e-6);
     let max_it = Some(10);
@@ -0,0 +1,130 @@
+use std::fmt;
+
+use nalgebra::DVector;
+use num_complex::Complex64;
+
+/// Errors that can occur while solving a power flow.
+#[derive(Debug, Clone)]
+pub enum PowerFlowError {
+    /// The Newton-Raphson iteration did not reach the tolerance.
+    ///
+    /// Carries the number of iterations performed and the last voltage estimate.
+    NotConverged {
+        iterations: usize,
+        v: DVector<Complex64>,
+    },
+    /// The measurements don't determine the state, the gain matrix of the state estimation
+    /// is singular.
+    Unobservable,
+    /// A mismatch or voltage became non-finite (NaN or infinite), or the linear solver failed.
+    ///
+    /// Carries the iteration at which the breakdown was detected.
+    NumericalBreakdown { iteration: usize },
+    /// The mismatch of the Newton-Raphson iteration stopped decreasing, e.g. because the
+    /// iterates oscillate.
+    ///
+    /// Carries the iteration at which the solve was aborted.
+    Stalled { iteration: usize },
+    /// The results were requested before the first solve or after a modification of the
+    /// network that wasn't solved yet.
+    StaleResults,
+    /// The network has neither an external grid nor a slack generator in service at a bus in
+    /// service, so the voltage angle reference and the power balance are undetermined.
+    NoSlackBus,
+    /// The slack bus configured for an island doesn't exist or lies in another island than
+    /// the bus identifying the island.
+    ///
+    /// Carries the identifier of the configured slack bus.
+    InvalidIslandSlack { bus: i64 },
+    /// Two entries of `PowerFlowConfig::island_slacks` identify the same island.
+    ///
+    /// Carries the member bus of the second entry, in the order of the member buses.
+    DuplicateIslandSlack { bus: i64 },
+    /// An option isn't supported together with `PowerFlowConfig::island_slacks`.
+    ///
+    /// Carries the name of the option.
+    UnsupportedWithIslandSlacks { option: &'static str },
+    /// The external grid injects a constant current, see `ExtGridMode::Current`, but has no
+    /// short-circuit power to derive its source impedance from.
+    MissingSourceImpedance,
+    /// The controllers, e.g. transformer tap controllers, still changed their settings after
+    /// the maximum number of control rounds, typically because a voltage band is narrower than
+    /// one step.
+    ///
+    /// Carries the number of control rounds performed.
+    ControlNotConverged { rounds: usize },
+    /// A custom admittance element connects to a node outside the network.
+    ///
+    /// Carries the offending node index.
+    InvalidCustomNode { node: usize },
+    /// The selected linear solver isn't compiled in, e.g. `SolverBackend::Faer` without the
+    /// `faer` feature.
+    SolverUnavailable,
+    /// An input vector doesn't have one entry per node.
+    ///
+    /// Carries the expected and the actual length.
+    DimensionMismatch { expected: usize, actual: usize },
+    /// A line in service has no length, e.g. a pandapower file without the `length_km`
+    /// column whose lengths weren't inferred, see `Network::infer_line_lengths`.
+    ///
+    /// Carries the index of the line.
+    MissingLineLength { line: i64 },
+}
+
+impl fmt::Display for PowerFlowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PowerFlowError::NotConverged { iterations, .. } => {
+                write!(
+                    f,
+                    "power flow did not converge within {} iterations",
+                    iterations
+                )
+            }
+            PowerFlowError::Unobservable => {
+                write!(f, "the network is not observable from the measurements")
+            }
+            PowerFlowError::NumericalBreakdown { iteration } => {
+                write!(f, "numerical breakdown in power flow iteration {}", iteration)
+            }
+            PowerFlowError::Stalled { iteration } => {
+                write!(f, "power flow stalled at iteration {}", iteration)
+            }
+            PowerFlowError::StaleResults => {
+                write!(f, "the network was modified since the last solve")
+            }
+            PowerFlowError::NoSlackBus => {
+                write!(f, "the network has no external grid or slack generator")
+            }
+            PowerFlowError::InvalidIslandSlack { bus } => {
+                write!(f, "the island slack bus {} is not in its island", bus)
+            }
+            PowerFlowError::DuplicateIslandSlack { bus } => {
+                write!(f, "the island of bus {} already has a slack", bus)
+            }
+            PowerFlowError::UnsupportedWithIslandSlacks { option } => {
+                write!(f, "{} is not supported together with island slacks", option)
+            }
+            PowerFlowError::MissingSourceImpedance => {
+                write!(f, "the constant-current external grid has no source impedance")
+            }
+            PowerFlowError::ControlNotConverged { rounds } => {
+                write!(f, "the controllers did not settle within {} rounds", rounds)
+            }
+            PowerFlowError::InvalidCustomNode { node } => {
+                write!(f, "a custom element connects to the unknown node {}", node)
+            }
+            PowerFlowError::SolverUnavailable => {
+                write!(f, "the selected linear solver is not enabled in this build")
+            }
+            PowerFlowError::DimensionMismatch { expected, actual } => {
+                write!(f, "expected {} entries, one per node, but got {}", expected, actual)
+            }
+            PowerFlowError::MissingLineLength { line } => {
+                write!(f, "the line {} has no length", line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PowerFlowError {}
@@ -1,7 +1,9 @@
 pub(crate) mod dsbus_dv;
+pub(crate) mod error;
 pub(crate) mod newtonpf;
 
 pub mod solver;
 pub(crate) mod sparse;
 pub mod system;
-pub use newtonpf::newton_pf;
+pub use error::PowerFlowError;
+pub use newtonpf::{levenberg_marquardt_pf, newton_pf, newton_pf_traced};
@@ -1,6 +1,6 @@
 use std::f64::consts::PI;
 
-use super::{dsbus_dv::dSbus_dV, solver::Solve, sparse::slice::*};
+use super::{dsbus_dv::dSbus_dV, solver::Solve, sparse::slice::*, PowerFlowError};
 use crate::basic::sparse::{
     conj::RealImage,
     stack::{csc_hstack, csc_vstack},
@@ -21,12 +21,16 @@ use num_complex::Complex64;
 /// * `npq` - The number of PQ buses.
 /// * `tolerance` - The tolerance for convergence (optional).
 /// * `max_iter` - The maximum number of iterations (optional).
+/// * `stall_patience` - The number of consecutive iterations without a new lowest mismatch
+///   after which the iteration is aborted (optional, never aborts if not given).
 /// * `solver` - The solver for the linear system.
 ///
 /// # Returns
 ///
 /// A result containing the converged voltage vector and the number of iterations.
-/// Returns an error if the algorithm did not converge.
+/// Returns an error if the algorithm did not converge, or aborts with
+/// [`PowerFlowError::NumericalBreakdown`] as soon as a mismatch or voltage is non-finite, or
+/// with [`PowerFlowError::Stalled`] once the mismatch stops decreasing.
 #[allow(non_snake_case)]
 pub fn newton_pf<Solver: Solve>(
     Ybus: &CscMatrix<Complex64>,
@@ -36,14 +40,131 @@ pub fn newton_pf<Solver: Solve>(
     npq: usize,
     tolerance: Option<f64>,
     max_iter: Option<usize>,
+    stall_patience: Option<usize>,
     solver: &mut Solver,
-) -> Result<(DVector<Complex64>, usize), (String, DVector<Complex64>)> {
+) -> Result<(DVector<Complex64>, usize), PowerFlowError> {
+    let mut history = Vec::new();
+    newton_pf_traced(
+        Ybus,
+        Sbus,
+        v_init,
+        npv,
+        npq,
+        tolerance,
+        max_iter,
+        stall_patience,
+        solver,
+        &mut history,
+    )
+}
+
+/// Performs a Newton-Raphson power flow calculation, recording the mismatch of every iterate.
+///
+/// Takes the same parameters as [`newton_pf`], and additionally:
+///
+/// * `history` - Cleared and filled with the 2-norm of the mismatch vector in per unit, of the
+///   initial voltages and after every iteration.
+#[allow(non_snake_case, clippy::too_many_arguments)]
+pub fn newton_pf_traced<Solver: Solve>(
+    Ybus: &CscMatrix<Complex64>,
+    Sbus: &DVector<Complex64>,
+    v_init: &DVector<Complex64>,
+    npv: usize,
+    npq: usize,
+    tolerance: Option<f64>,
+    max_iter: Option<usize>,
+    stall_patience: Option<usize>,
+    solver: &mut Solver,
+    history: &mut Vec<f64>,
+) -> Result<(DVector<Complex64>, usize), PowerFlowError> {
+    let (tol, stall) = (tolerance, stall_patience);
+    let loads = &[];
+    newton_pf_with_loads(Ybus, Sbus, loads, v_init, npv, npq, tol, max_iter, stall, solver, history)
+}
+
+/// A load whose power depends on the voltage magnitude at its bus, see
+/// [`newton_pf_with_loads`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct VoltageDependentLoad {
+    /// The position of the bus in the voltage vector.
+    pub bus: usize,
+    /// The power consumed at 1 pu, in per unit.
+    pub s: Complex64,
+    /// The exponents of the active and the reactive power, `P = s.re * vm^np`.
+    pub exponents: (f64, f64),
+}
+
+impl VoltageDependentLoad {
+    /// Returns the power consumed at a voltage magnitude and its derivative by the magnitude.
+    fn power(&self, vm: f64) -> (Complex64, Complex64) {
+        let (np, nq) = self.exponents;
+        let s = Complex64::new(self.s.re * vm.powf(np), self.s.im * vm.powf(nq));
+        let ds_dvm = Complex64::new(
+            self.s.re * np * vm.powf(np - 1.0),
+            self.s.im * nq * vm.powf(nq - 1.0),
+        );
+        (s, ds_dvm)
+    }
+}
+
+/// Computes the complex power mismatch `V conj(Ybus V) - Sbus` including the voltage-dependent
+/// loads, which are consumed on top of `Sbus`.
+#[allow(non_snake_case)]
+fn power_mismatch(
+    Ybus: &CscMatrix<Complex64>,
+    Sbus: &DVector<Complex64>,
+    loads: &[VoltageDependentLoad],
+    v: &DVector<Complex64>,
+) -> DVector<Complex64> {
+    let mut mis = v.component_mul(&(Ybus * v).conjugate()) - Sbus;
+    for x in loads {
+        mis[x.bus] += x.power(v[x.bus].norm()).0;
+    }
+    mis
+}
+
+/// Adds the derivatives of the voltage-dependent loads to the diagonal of `dS_dVm`.
+#[allow(non_snake_case)]
+fn add_load_derivatives(
+    dS_dVm: &mut CscMatrix<Complex64>,
+    loads: &[VoltageDependentLoad],
+    v: &DVector<Complex64>,
+) {
+    for x in loads {
+        if let SparseEntryMut::NonZero(entry) = dS_dVm.index_entry_mut(x.bus, x.bus) {
+            *entry += x.power(v[x.bus].norm()).1;
+        }
+    }
+}
+
+/// Performs a Newton-Raphson power flow calculation with voltage-dependent loads, recording
+/// the mismatch of every iterate.
+///
+/// Takes the same parameters as [`newton_pf_traced`], and additionally:
+///
+/// * `loads` - The loads whose power is evaluated at the voltages of every iterate, in the
+///   mismatch and in the Jacobian; their power must not be part of `Sbus`.
+#[allow(non_snake_case, clippy::too_many_arguments)]
+pub(crate) fn newton_pf_with_loads<Solver: Solve>(
+    Ybus: &CscMatrix<Complex64>,
+    Sbus: &DVector<Complex64>,
+    loads: &[VoltageDependentLoad],
+    v_init: &DVector<Complex64>,
+    npv: usize,
+    npq: usize,
+    tolerance: Option<f64>,
+    max_iter: Option<usize>,
+    stall_patience: Option<usize>,
+    solver: &mut Solver,
+    history: &mut Vec<f64>,
+) -> Result<(DVector<Complex64>, usize), PowerFlowError> {
+    history.clear();
     let mut v = v_init.clone();
     let mut v_norm = v.map(|e| e.simd_signum());
     let max_iter = max_iter.unwrap_or(100);
     let tol = tolerance.unwrap_or(1e-6);
 
-    let mut mis = &v.component_mul(&(Ybus * &v).conjugate()) - Sbus;
+    let mut mis = power_mismatch(Ybus, Sbus, loads, &v);
 
     let n_ext = v.len() - npv - npq;
     let n_bus = npq + npv;
@@ -51,44 +172,227 @@ pub fn newton_pf<Solver: Solve>(
 
     let mut F = DVector::zeros(num_state);
     assemble_f(&mut F, n_bus, &mis, num_state, npv);
+    if !is_finite(&F, &v) {
+        return Err(PowerFlowError::NumericalBreakdown { iteration: 0 });
+    }
 
     let mut v_m = v.map(|e| e.simd_modulus());
     let mut v_a = v.map(|e| e.simd_argument());
     let mut cache: Option<JacobianCache> = None;
+    let mut best = F.norm();
+    let mut stalled = 0;
+    history.push(best);
+    // with every node held by a slack there is nothing to solve
+    if num_state == 0 {
+        return Ok((v, 0));
+    }
 
     for iterations in 0..max_iter {
-        let (dS_dVm, dS_dVa) = dSbus_dV(Ybus, &v, &v_norm);
+        let (mut dS_dVm, dS_dVa) = dSbus_dV(Ybus, &v, &v_norm);
+        add_load_derivatives(&mut dS_dVm, loads, &v);
         let jacobian = build_jacobian_cached(&dS_dVm, &dS_dVa, &mut cache, npv, n_ext);
 
         let n = jacobian.nrows();
         let (mut Ap, mut Ai, mut Ax) = jacobian.disassemble();
 
-        let _err = unsafe {
-            solver
-                .solve(
-                    Ap.as_mut_slice(),
-                    Ai.as_mut_slice(),
-                    Ax.as_mut_slice(),
-                    F.data.as_mut_slice_unchecked(),
-                    n,
-                )
-                .unwrap()
+        let solved = unsafe {
+            solver.solve(
+                Ap.as_mut_slice(),
+                Ai.as_mut_slice(),
+                Ax.as_mut_slice(),
+                F.data.as_mut_slice_unchecked(),
+                n,
+            )
         };
+        if solved.is_err() {
+            return Err(PowerFlowError::NumericalBreakdown { iteration: iterations });
+        }
 
         let dx = &F;
         update_v(&mut v_a, dx, n_bus, &mut v_m, npv, num_state, &mut v_norm, &mut v);
 
-        v.component_mul(&(Ybus * &v).conjugate())
-            .sub_to(Sbus, &mut mis);
+        if loads.is_empty() {
+            v.component_mul(&(Ybus * &v).conjugate())
+                .sub_to(Sbus, &mut mis);
+        } else {
+            mis = power_mismatch(Ybus, Sbus, loads, &v);
+        }
 
         assemble_f(&mut F, n_bus, &mis, num_state, npv);
-  
-        if F.norm() < tol {
+        if !is_finite(&F, &v) {
+            return Err(PowerFlowError::NumericalBreakdown { iteration: iterations });
+        }
+
+        let norm = F.norm();
+        history.push(norm);
+        if norm < tol {
             return Ok((v, iterations));
         }
+        if norm < best {
+            best = norm;
+            stalled = 0;
+        } else {
+            stalled += 1;
+            if stall_patience.is_some_and(|x| stalled >= x) {
+                return Err(PowerFlowError::Stalled { iteration: iterations });
+            }
+        }
     }
 
-    Err((String::from("Did not converge!"), v))
+    Err(PowerFlowError::NotConverged {
+        iterations: max_iter,
+        v,
+    })
+}
+
+/// Performs a power flow calculation with Levenberg-Marquardt regularized Newton steps.
+///
+/// Each step solves the regularized normal equations `(J^T J + mu I) dx = J^T F`, which stay
+/// solvable where the Jacobian is (nearly) singular. A step is accepted and `mu` decreased if it
+/// reduces the mismatch, otherwise it is rejected and `mu` increased, so that the iteration
+/// moves between Newton steps and short gradient steps. This converges in more cases than
+/// [`newton_pf`], e.g. close to the loadability limit, but takes more iterations.
+///
+/// # Parameters
+///
+/// * `Ybus` - The bus admittance matrix.
+/// * `Sbus` - The bus power injections.
+/// * `v_init` - The initial voltage vector.
+/// * `npv` - The number of PV buses.
+/// * `npq` - The number of PQ buses.
+/// * `tolerance` - The tolerance for convergence (optional).
+/// * `max_iter` - The maximum number of iterations, including rejected steps (optional).
+/// * `solver` - The solver for the linear system.
+///
+/// # Returns
+///
+/// A result containing the converged voltage vector and the number of iterations.
+/// Returns an error if the algorithm did not converge, or aborts with
+/// [`PowerFlowError::NumericalBreakdown`] as soon as a mismatch or voltage is non-finite.
+#[allow(non_snake_case)]
+#[allow(clippy::too_many_arguments)]
+pub fn levenberg_marquardt_pf<Solver: Solve>(
+    Ybus: &CscMatrix<Complex64>,
+    Sbus: &DVector<Complex64>,
+    v_init: &DVector<Complex64>,
+    npv: usize,
+    npq: usize,
+    tolerance: Option<f64>,
+    max_iter: Option<usize>,
+    solver: &mut Solver,
+) -> Result<(DVector<Complex64>, usize), PowerFlowError> {
+    let (loads, tol) = (&[], tolerance);
+    levenberg_marquardt_pf_with_loads(Ybus, Sbus, loads, v_init, npv, npq, tol, max_iter, solver)
+}
+
+/// Performs a power flow calculation with Levenberg-Marquardt regularized Newton steps and
+/// voltage-dependent loads, see [`levenberg_marquardt_pf`] and [`newton_pf_with_loads`].
+#[allow(non_snake_case, clippy::too_many_arguments)]
+pub(crate) fn levenberg_marquardt_pf_with_loads<Solver: Solve>(
+    Ybus: &CscMatrix<Complex64>,
+    Sbus: &DVector<Complex64>,
+    loads: &[VoltageDependentLoad],
+    v_init: &DVector<Complex64>,
+    npv: usize,
+    npq: usize,
+    tolerance: Option<f64>,
+    max_iter: Option<usize>,
+    solver: &mut Solver,
+) -> Result<(DVector<Complex64>, usize), PowerFlowError> {
+    let mut v = v_init.clone();
+    let mut v_norm = v.map(|e| e.simd_signum());
+    let max_iter = max_iter.unwrap_or(100);
+    let tol = tolerance.unwrap_or(1e-6);
+
+    let n_ext = v.len() - npv - npq;
+    let n_bus = npq + npv;
+    let num_state = npv + 2 * npq;
+
+    let mismatch = |v: &DVector<Complex64>| {
+        let mis = power_mismatch(Ybus, Sbus, loads, v);
+        let mut F = DVector::zeros(num_state);
+        assemble_f(&mut F, n_bus, &mis, num_state, npv);
+        F
+    };
+    let mut F = mismatch(&v);
+    if !is_finite(&F, &v) {
+        return Err(PowerFlowError::NumericalBreakdown { iteration: 0 });
+    }
+    if F.norm() < tol {
+        return Ok((v, 0));
+    }
+
+    let mut v_m = v.map(|e| e.simd_modulus());
+    let mut v_a = v.map(|e| e.simd_argument());
+    let mut mu = None;
+
+    for iterations in 0..max_iter {
+        let (mut dS_dVm, dS_dVa) = dSbus_dV(Ybus, &v, &v_norm);
+        add_load_derivatives(&mut dS_dVm, loads, &v);
+        let jacobian = build_jacobian(&dS_dVm, &dS_dVa, npv, n_ext);
+        let jacobian_t = jacobian.transpose();
+        let normal = &jacobian_t * &jacobian;
+        // start with a damping relative to the scale of the normal matrix
+        let mu_k = *mu.get_or_insert_with(|| {
+            let diag = (0..num_state).filter_map(|i| normal.get_entry(i, i));
+            1e-3 * diag.map(|x| x.into_value()).fold(0.0, f64::max)
+        });
+        let regularized = &normal + &(CscMatrix::identity(num_state) * mu_k);
+
+        let (mut Ap, mut Ai, mut Ax) = regularized.disassemble();
+        let mut dx = &jacobian_t * &F;
+        let solved = unsafe {
+            solver.solve(
+                Ap.as_mut_slice(),
+                Ai.as_mut_slice(),
+                Ax.as_mut_slice(),
+                dx.data.as_mut_slice_unchecked(),
+                num_state,
+            )
+        };
+        if solved.is_err() {
+            return Err(PowerFlowError::NumericalBreakdown { iteration: iterations });
+        }
+
+        let (mut trial_a, mut trial_m) = (v_a.clone(), v_m.clone());
+        let (mut trial_norm, mut trial) = (v_norm.clone(), v.clone());
+        let dx = DVector::from_column_slice(dx.as_slice());
+        update_v(
+            &mut trial_a,
+            &dx,
+            n_bus,
+            &mut trial_m,
+            npv,
+            num_state,
+            &mut trial_norm,
+            &mut trial,
+        );
+        let trial_f = mismatch(&trial);
+        if !is_finite(&trial_f, &trial) {
+            return Err(PowerFlowError::NumericalBreakdown { iteration: iterations });
+        }
+
+        if trial_f.norm() < F.norm() {
+            (v_a, v_m, v_norm, v, F) = (trial_a, trial_m, trial_norm, trial, trial_f);
+            mu = Some(mu_k / 10.0);
+            if F.norm() < tol {
+                return Ok((v, iterations));
+            }
+        } else {
+            mu = Some(mu_k * 10.0);
+        }
+    }
+
+    Err(PowerFlowError::NotConverged {
+        iterations: max_iter,
+        v,
+    })
+}
+
+/// Checks that the mismatch vector and the voltages are free of NaN and infinite values.
+#[inline(always)]
+fn is_finite(f: &DVector<f64>, v: &DVector<Complex64>) -> bool {
+    f.iter().all(|x| x.is_finite()) && v.iter().all(|x| x.is_finite())
 }
 
 /// Assembles the mismatch vector.
@@ -214,7 +518,6 @@ impl<T: Copy + Clone + Zero + Scalar + ClosedAddAssign> SliceTo for CscMatrix<T>
 ///
 /// The Jacobian matrix.
 #[allow(non_snake_case)]
-#[allow(dead_code)]
 #[inline(always)]
 fn build_jacobian(
     ds_dvm: &CscMatrix<Complex64>,
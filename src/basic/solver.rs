@@ -1,4 +1,5 @@
-use rsparse::{self, data, lusol};
+use nalgebra::{DMatrix, DVectorViewMut};
+use rsparse::{self, data, lsolve, lu, lusol, sqr, usolve};
 
 #[cfg(feature = "klu")]
 #[derive(Default)]
@@ -7,6 +8,80 @@ pub struct KLUSolver(pub klu_rs::KLUSolver);
 #[derive(Default)]
 pub struct RSparseSolver;
 
+/// An RSparse solver that keeps the symbolic analysis, the fill-reducing ordering, of the last
+/// matrix and reuses it as long as the sparsity pattern stays the same.
+///
+/// The numeric factorization still runs for every system, as the values change.
+#[derive(Debug, Clone, Default)]
+pub struct CachedRSparseSolver {
+    /// The column pointers and row indices of the analyzed matrix and its analysis.
+    symbolic: Option<(Vec<usize>, Vec<usize>, data::Symb)>,
+}
+
+/// A sparse LU solver based on `faer`, which fails unless the `faer` feature is enabled.
+#[derive(Default)]
+pub struct FaerSolver;
+
+/// A dense LU solver, which outperforms sparse factorization on small systems.
+#[derive(Default)]
+pub struct DenseSolver;
+
+/// Solves the linear systems with another solver after permuting their rows and columns
+/// symmetrically, e.g. into an order that factorizes with little fill-in.
+///
+/// This pays off for solvers that factorize in the given order; it doesn't change the
+/// solution.
+pub struct PermutedSolver<'a, S> {
+    /// The solver of the permuted systems.
+    pub solver: &'a mut S,
+    /// The row and column of the original matrix at every position of the permuted one.
+    pub order: &'a [usize],
+}
+
+/// Selects the linear solver used in the Newton-Raphson iterations.
+///
+/// The `faer` backend is a pure-Rust supernodal LU that needs no SuiteSparse installation,
+/// but it is only available with the `faer` feature, which adds a large dependency and
+/// compile time. Which backend is fastest depends on the network, so measure before switching.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SolverBackend {
+    /// Uses the dense solver for networks of fewer than [`DENSE_NODE_LIMIT`] nodes, where it
+    /// beats the sparse one, and the sparse solver otherwise.
+    #[default]
+    Auto,
+    /// Always uses the sparse solver (KLU if enabled, RSparse otherwise).
+    Sparse,
+    /// Always uses the dense solver.
+    Dense,
+    /// Always uses the sparse LU of `faer`; the solve fails with
+    /// [`PowerFlowError::SolverUnavailable`] unless the `faer` feature is enabled.
+    ///
+    /// [`PowerFlowError::SolverUnavailable`]: crate::basic::PowerFlowError::SolverUnavailable
+    Faer,
+}
+
+/// The number of nodes from which [`SolverBackend::Auto`] uses the sparse solver.
+pub const DENSE_NODE_LIMIT: usize = 50;
+
+impl SolverBackend {
+    /// Resolves [`SolverBackend::Auto`] into the backend used for a network.
+    ///
+    /// # Arguments
+    ///
+    /// * `n_nodes` - The number of nodes of the network.
+    ///
+    /// # Returns
+    ///
+    /// The dense or sparse backend for `Auto`, the backend itself otherwise.
+    pub fn resolve(self, n_nodes: usize) -> SolverBackend {
+        match self {
+            SolverBackend::Auto if n_nodes < DENSE_NODE_LIMIT => SolverBackend::Dense,
+            SolverBackend::Auto => SolverBackend::Sparse,
+            backend => backend,
+        }
+    }
+}
+
 #[allow(non_snake_case)]
 /// A trait for solving sparse linear systems.
 pub trait Solve {
@@ -130,4 +205,228 @@ impl Solve for RSparseSolver {
         lusol(&mat, b, 1, 1e-6);
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[allow(non_snake_case)]
+impl Solve for CachedRSparseSolver {
+    /// Solves the sparse linear system using the RSparse solver, reusing the symbolic analysis
+    /// if the matrix has the sparsity pattern of the previous one.
+    ///
+    /// # Parameters
+    ///
+    /// * `Ap` - Column pointers of the matrix.
+    /// * `Ai` - Row indices of the matrix.
+    /// * `Ax` - Non-zero values of the matrix.
+    /// * `b` - Right-hand side vector.
+    /// * `n` - Dimension of the system.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating success or failure.
+    fn solve(
+        &mut self,
+        Ap: &mut [usize],
+        Ai: &mut [usize],
+        Ax: &mut [f64],
+        b: &mut [f64],
+        n: usize,
+    ) -> Result<(), &'static str> {
+        let mut mat = data::Sprs::zeros(n, n, Ai.len());
+        mat.i.clone_from_slice(Ai);
+        for (x, &p) in mat.p.iter_mut().zip(Ap.iter()) {
+            *x = p as isize;
+        }
+        mat.x.clone_from_slice(Ax);
+
+        let symbolic = match self.symbolic.take() {
+            Some((p, i, s)) if p == Ap && i == Ai => (p, i, s),
+            _ => (Ap.to_vec(), Ai.to_vec(), sqr(&mat, 1, false)),
+        };
+        let mut s = symbolic.2.clone();
+        self.symbolic = Some(symbolic);
+        let num = lu(&mat, &mut s, 1e-6);
+
+        // x = P b, x = L \ x, x = U \ x, b = Q x
+        let mut x = vec![0.0; n];
+        permute(&num.pinv, b, &mut x);
+        lsolve(&num.l, &mut x);
+        usolve(&num.u, &mut x);
+        permute(&s.q, &x, b);
+        Ok(())
+    }
+}
+
+/// Scatters `b` into `x` by the permutation `p`, the identity if not given.
+fn permute(p: &Option<Vec<isize>>, b: &[f64], x: &mut [f64]) {
+    match p {
+        Some(p) => p.iter().zip(b).for_each(|(&k, &v)| x[k as usize] = v),
+        None => x.copy_from_slice(b),
+    }
+}
+
+#[cfg(feature = "faer")]
+#[allow(non_snake_case)]
+impl Solve for FaerSolver {
+    /// Solves the sparse linear system by the sparse LU decomposition of `faer`.
+    ///
+    /// # Parameters
+    ///
+    /// * `Ap` - Column pointers of the matrix.
+    /// * `Ai` - Row indices of the matrix.
+    /// * `Ax` - Non-zero values of the matrix.
+    /// * `b` - Right-hand side vector.
+    /// * `n` - Dimension of the system.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating success or failure.
+    fn solve(
+        &mut self,
+        Ap: &mut [usize],
+        Ai: &mut [usize],
+        Ax: &mut [f64],
+        b: &mut [f64],
+        n: usize,
+    ) -> Result<(), &'static str> {
+        use faer::prelude::Solve as _;
+        use faer::sparse::{SparseColMatRef, SymbolicSparseColMatRef};
+
+        let symbolic = SymbolicSparseColMatRef::new_unsorted_checked(n, n, Ap, None, Ai);
+        let lu = SparseColMatRef::new(symbolic, Ax)
+            .sp_lu()
+            .map_err(|_| "the matrix is singular!")?;
+        lu.solve_in_place(faer::MatMut::from_column_major_slice_mut(b, n, 1));
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "faer"))]
+#[allow(non_snake_case)]
+impl Solve for FaerSolver {
+    /// Fails, as the `faer` feature isn't enabled.
+    fn solve(
+        &mut self,
+        _Ap: &mut [usize],
+        _Ai: &mut [usize],
+        _Ax: &mut [f64],
+        _b: &mut [f64],
+        _n: usize,
+    ) -> Result<(), &'static str> {
+        Err("the faer feature is not enabled!")
+    }
+}
+
+#[allow(non_snake_case)]
+impl Solve for DenseSolver {
+    /// Solves the linear system by a dense LU decomposition.
+    ///
+    /// # Parameters
+    ///
+    /// * `Ap` - Column pointers of the matrix.
+    /// * `Ai` - Row indices of the matrix.
+    /// * `Ax` - Non-zero values of the matrix.
+    /// * `b` - Right-hand side vector.
+    /// * `n` - Dimension of the system.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating success or failure.
+    fn solve(
+        &mut self,
+        Ap: &mut [usize],
+        Ai: &mut [usize],
+        Ax: &mut [f64],
+        b: &mut [f64],
+        n: usize,
+    ) -> Result<(), &'static str> {
+        let mut mat = DMatrix::zeros(n, n);
+        for col in 0..n {
+            for idx in Ap[col]..Ap[col + 1] {
+                mat[(Ai[idx], col)] += Ax[idx];
+            }
+        }
+        let mut rhs = DVectorViewMut::from_slice(b, n);
+        if !mat.lu().solve_mut(&mut rhs) {
+            return Err("the matrix is singular!");
+        }
+        Ok(())
+    }
+}
+
+#[allow(non_snake_case)]
+impl<S: Solve> Solve for PermutedSolver<'_, S> {
+    /// Solves the permuted linear system and scatters the solution back.
+    ///
+    /// # Parameters
+    ///
+    /// * `Ap` - Column pointers of the matrix.
+    /// * `Ai` - Row indices of the matrix.
+    /// * `Ax` - Non-zero values of the matrix.
+    /// * `b` - Right-hand side vector.
+    /// * `n` - Dimension of the system.
+    ///
+    /// # Returns
+    ///
+    /// A result indicating success or failure.
+    fn solve(
+        &mut self,
+        Ap: &mut [usize],
+        Ai: &mut [usize],
+        Ax: &mut [f64],
+        b: &mut [f64],
+        n: usize,
+    ) -> Result<(), &'static str> {
+        if self.order.len() != n {
+            return Err("the permutation doesn't match the dimension!");
+        }
+        let mut position = vec![0; n];
+        self.order.iter().enumerate().for_each(|(pos, &x)| position[x] = pos);
+
+        let mut p = Vec::with_capacity(n + 1);
+        let (mut i, mut x) = (Vec::with_capacity(Ai.len()), Vec::with_capacity(Ax.len()));
+        p.push(0);
+        for &col in self.order {
+            let mut entries: Vec<(usize, f64)> =
+                (Ap[col]..Ap[col + 1]).map(|k| (position[Ai[k]], Ax[k])).collect();
+            entries.sort_unstable_by_key(|e| e.0);
+            entries.into_iter().for_each(|(row, value)| {
+                i.push(row);
+                x.push(value);
+            });
+            p.push(i.len());
+        }
+        let mut rhs: Vec<f64> = self.order.iter().map(|&k| b[k]).collect();
+        self.solver.solve(&mut p, &mut i, &mut x, &mut rhs, n)?;
+        self.order.iter().zip(rhs).for_each(|(&k, value)| b[k] = value);
+        Ok(())
+    }
+}
+
+#[test]
+/// Tests that the permuted solver returns the solution of the original system.
+fn permuted_test() {
+    let (mut ap, mut ai) = (vec![0, 2, 4, 6], vec![0, 1, 0, 1, 1, 2]);
+    let values = [4.0, 1.0, 2.0, 5.0, 1.0, 3.0];
+    let (mut permuted, mut dense) = (vec![1.0, 2.0, 3.0], vec![1.0, 2.0, 3.0]);
+    let mut solver = PermutedSolver {
+        solver: &mut RSparseSolver,
+        order: &[2, 0, 1],
+    };
+    solver.solve(&mut ap, &mut ai, &mut values.clone(), &mut permuted, 3).unwrap();
+    DenseSolver.solve(&mut ap, &mut ai, &mut values.clone(), &mut dense, 3).unwrap();
+    assert!(permuted.iter().zip(&dense).all(|(a, b)| (a - b).abs() < 1e-12));
+}
+
+#[test]
+/// Tests that the cached RSparse solver matches the dense one when reusing its analysis.
+fn cached_rsparse_test() {
+    let (mut ap, mut ai) = (vec![0, 2, 4, 6], vec![0, 1, 0, 1, 1, 2]);
+    let mut solver = CachedRSparseSolver::default();
+    for values in [[4.0, 1.0, 2.0, 5.0, 1.0, 3.0], [2.0, -1.0, 1.0, 6.0, 2.0, 7.0]] {
+        let (mut cached, mut dense) = (vec![1.0, 2.0, 3.0], vec![1.0, 2.0, 3.0]);
+        solver.solve(&mut ap, &mut ai, &mut values.clone(), &mut cached, 3).unwrap();
+        DenseSolver.solve(&mut ap, &mut ai, &mut values.clone(), &mut dense, 3).unwrap();
+        assert!(cached.iter().zip(&dense).all(|(a, b)| (a - b).abs() < 1e-12));
+        assert!(solver.symbolic.is_some());
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+use super::TransformerLoadingMode;
+use crate::basic::solver::SolverBackend;
+use crate::io::pandapower::SWITCH_BIG_ADMITTANCE;
+
+/// The unit of the convergence tolerance of the power mismatch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ToleranceUnit {
+    /// Per unit on the base power of the network.
+    #[default]
+    PerUnit,
+    /// MVA, converted to per unit with the base power of the network.
+    Mva,
+}
+
+/// The initial voltages of the Newton-Raphson iteration.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InitMode {
+    /// The given initial voltages, e.g. a flat start or the previous solution.
+    #[default]
+    Flat,
+    /// The magnitudes of the given initial voltages with the angles of the DC power flow, see
+    /// [`PFNetwork::dc_warm_start`].
+    ///
+    /// [`PFNetwork::dc_warm_start`]: super::PFNetwork::dc_warm_start
+    DcWarmStart,
+}
+
+/// The voltage reference of an island, see [`PowerFlowConfig::island_slacks`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IslandSlack {
+    /// The identifier of the bus holding the voltage, which must lie in the island.
+    pub bus: i64,
+    /// The voltage magnitude in per unit.
+    pub vm_pu: f64,
+    /// The voltage angle in degrees.
+    pub va_degree: f64,
+}
+
+/// Options controlling the power flow solve.
+#[derive(Debug, Clone)]
+pub struct PowerFlowConfig {
+    /// The maximum number of Newton-Raphson iterations.
+    pub max_iterations: usize,
+    /// The convergence tolerance of the power mismatch, in the unit given by `tolerance_unit`.
+    pub tolerance: f64,
+    /// The unit of `tolerance`.
+    pub tolerance_unit: ToleranceUnit,
+    /// The linear solver used in the iterations.
+    pub linear_solver: SolverBackend,
+    /// Whether the external grid is modeled as an ideal source behind its short-circuit
+    /// impedance instead of an infinite bus. Only applies if `s_sc_max_mva` is given.
+    pub ext_grid_impedance: bool,
+    /// Whether the reactive power limits of the external grid are enforced.
+    pub enforce_q_limits: bool,
+    /// Whether the reactive power limits of the voltage-controlled generators are enforced by
+    /// switching violating generators to a fixed reactive power at the limit.
+    pub enforce_gen_q_limits: bool,
+    /// The number of consecutive iterations without a new lowest mismatch after which the
+    /// solve is aborted as stalled, `None` (the default) to always run up to `max_iterations`.
+    pub stall_patience: Option<usize>,
+    /// The reference of the transformer loading reported by [`PowerGrid::trafo_loading`].
+    ///
+    /// [`PowerGrid::trafo_loading`]: super::PowerGrid::trafo_loading
+    pub trafo_loading: TransformerLoadingMode,
+    /// Whether the Newton steps are regularized with Levenberg-Marquardt damping, which is
+    /// slower but pushes through regions where the Jacobian is nearly singular.
+    pub regularize: bool,
+    /// Whether the magnetizing branches of the transformers, given by their no-load losses
+    /// and currents, are modeled. Disabling it reproduces tools that omit them.
+    pub include_trafo_magnetizing: bool,
+    /// The per-unit admittance modeling ideal closed bus-bus switches. Lower values improve the
+    /// conditioning of the Ybus, higher ones reduce the voltage drop across the switches.
+    pub switch_big_admittance: f64,
+    /// Whether buses joined by closed ideal bus-bus switches or by lines without impedance are
+    /// merged into one node, the default. Merging improves the conditioning of the Ybus, but
+    /// the merged buses then only appear in the results through their first bus; otherwise
+    /// such branches are modeled with `switch_big_admittance`, which keeps every bus as a
+    /// node, e.g. for placing measurements.
+    pub merge_zero_impedance: bool,
+    /// The initial voltages of the iteration.
+    pub init: InitMode,
+    /// The voltage references of islands, keyed by the identifier of any bus of the island.
+    /// Each island without the external grid needs an entry to be solvable; an entry for the
+    /// island of the external grid replaces its bus and voltage, and two entries for one
+    /// island are an error. The islands are then solved together with ideal slacks; the
+    /// source impedance of the external grid (`ext_grid_impedance`) and its reactive power
+    /// limits (`enforce_q_limits`) are not supported and fail the solve. The power of each
+    /// slack is reported in `SolvedNetwork::res_island_slacks`.
+    pub island_slacks: HashMap<i64, IslandSlack>,
+}
+
+impl Default for PowerFlowConfig {
+    fn default() -> Self {
+        Self {
+            max_iterations: 100,
+            tolerance: 1e-6,
+            tolerance_unit: ToleranceUnit::PerUnit,
+            linear_solver: SolverBackend::default(),
+            ext_grid_impedance: false,
+            enforce_q_limits: false,
+            enforce_gen_q_limits: false,
+            stall_patience: None,
+            trafo_loading: TransformerLoadingMode::Current,
+            regularize: false,
+            include_trafo_magnetizing: true,
+            switch_big_admittance: SWITCH_BIG_ADMITTANCE,
+            merge_zero_impedance: true,
+            init: InitMode::Flat,
+            island_slacks: HashMap::new(),
+        }
+    }
+}
+
+/// The options of a [`PowerFlowConfig`] that change the assembled model, see
+/// [`PFNetwork::from_network`].
+///
+/// [`PFNetwork::from_network`]: super::PFNetwork::from_network
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct AssemblyOptions {
+    include_trafo_magnetizing: bool,
+    switch_big_admittance: f64,
+    merge_zero_impedance: bool,
+}
+
+impl PowerFlowConfig {
+    /// Returns the options that change the assembled model.
+    pub(crate) fn assembly_options(&self) -> AssemblyOptions {
+        AssemblyOptions {
+            include_trafo_magnetizing: self.include_trafo_magnetizing,
+            switch_big_admittance: self.switch_big_admittance,
+            merge_zero_impedance: self.merge_zero_impedance,
+        }
+    }
+
+    /// Returns the convergence tolerance in per unit.
+    ///
+    /// # Arguments
+    ///
+    /// * `s_base` - The base power of the network in MVA.
+    pub fn tolerance_pu(&self, s_base: f64) -> f64 {
+        match self.tolerance_unit {
+            ToleranceUnit::PerUnit => self.tolerance,
+            ToleranceUnit::Mva => self.tolerance / s_base,
+        }
+    }
+}
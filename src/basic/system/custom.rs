@@ -0,0 +1,144 @@
+use nalgebra::DMatrix;
+use nalgebra_sparse::{CooMatrix, CsrMatrix};
+use num_complex::Complex64;
+
+use crate::basic::error::PowerFlowError;
+
+/// A user-defined element given by its nodal admittance block.
+///
+/// The block is added to the Ybus as is, which allows modeling devices without a built-in
+/// equivalent, including non-reciprocal ones such as phase shifters.
+#[derive(Debug, Clone)]
+pub struct CustomAdmittance {
+    /// The node indices the ports connect to, in the order of the rows of `y`.
+    pub nodes: Vec<usize>,
+    /// The admittance block in per unit on the system base.
+    pub y: DMatrix<Complex64>,
+}
+
+impl CustomAdmittance {
+    /// Creates a custom element from its admittance block.
+    ///
+    /// # Arguments
+    ///
+    /// * `nodes` - The node indices the ports connect to.
+    /// * `y` - The admittance block in per unit, with one row and column per node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the block isn't square with one row per node.
+    pub fn new(nodes: Vec<usize>, y: DMatrix<Complex64>) -> Self {
+        assert!(
+            y.nrows() == nodes.len() && y.ncols() == nodes.len(),
+            "the admittance block must have one row and column per node"
+        );
+        Self { nodes, y }
+    }
+
+    /// Creates a series admittance between two nodes.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The node index of the first port.
+    /// * `to` - The node index of the second port.
+    /// * `y` - The series admittance in per unit.
+    pub fn series(from: usize, to: usize, y: Complex64) -> Self {
+        Self::new(
+            vec![from, to],
+            DMatrix::from_row_slice(2, 2, &[y, -y, -y, y]),
+        )
+    }
+}
+
+/// Checks that the custom elements only connect to nodes of the network.
+///
+/// # Arguments
+///
+/// * `custom` - The custom elements.
+/// * `nodes` - The number of nodes of the network.
+///
+/// # Returns
+///
+/// [`PowerFlowError::InvalidCustomNode`] for the first node index out of range.
+pub(crate) fn check_custom_nodes(
+    custom: &[CustomAdmittance],
+    nodes: usize,
+) -> Result<(), PowerFlowError> {
+    match custom.iter().flat_map(|x| &x.nodes).find(|&&node| node >= nodes) {
+        Some(&node) => Err(PowerFlowError::InvalidCustomNode { node }),
+        None => Ok(()),
+    }
+}
+
+/// Adds the admittance blocks of custom elements to a Ybus.
+///
+/// The node indices must have been checked with [`check_custom_nodes`].
+///
+/// # Arguments
+///
+/// * `ybus` - The Ybus of the built-in elements.
+/// * `custom` - The custom elements.
+///
+/// # Returns
+///
+/// The Ybus including the custom elements.
+pub(crate) fn add_custom_admittances(
+    ybus: CsrMatrix<Complex64>,
+    custom: &[CustomAdmittance],
+) -> CsrMatrix<Complex64> {
+    if custom.is_empty() {
+        return ybus;
+    }
+    let mut coo = CooMatrix::from(&ybus);
+    for x in custom {
+        for (i, &row) in x.nodes.iter().enumerate() {
+            for (j, &col) in x.nodes.iter().enumerate() {
+                coo.push(row, col, x.y[(i, j)]);
+            }
+        }
+    }
+    CsrMatrix::from(&coo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::system::{test_system, PowerFlowConfig, RunPF};
+
+    #[test]
+    fn test_custom_admittance_in_ybus() {
+        let (mut pf, _, _, _) = test_system();
+        let base = pf.create_y_bus();
+        let y = Complex64::new(2.0, -20.0);
+        let shunt = Complex64::new(0.0, 0.5);
+        pf.custom.push(CustomAdmittance::series(3, 17, y));
+        pf.custom.push(CustomAdmittance::new(
+            vec![5],
+            DMatrix::from_element(1, 1, shunt),
+        ));
+
+        let ybus = pf.create_y_bus();
+        let entry = |y: &CsrMatrix<Complex64>, i: usize, j: usize| {
+            y.get_entry(i, j)
+                .map_or(Complex64::new(0.0, 0.0), |x| x.into_value())
+        };
+        assert!((entry(&ybus, 3, 17) - entry(&base, 3, 17) + y).norm() < 1e-12);
+        assert!((entry(&ybus, 17, 3) - entry(&base, 17, 3) + y).norm() < 1e-12);
+        assert!((entry(&ybus, 3, 3) - entry(&base, 3, 3) - y).norm() < 1e-12);
+        assert!((entry(&ybus, 5, 5) - entry(&base, 5, 5) - shunt).norm() < 1e-12);
+        assert!((entry(&ybus, 0, 0) - entry(&base, 0, 0)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_custom_admittance_unknown_node() {
+        let (mut pf, _, _, _) = test_system();
+        let nodes = pf.buses.len();
+        pf.custom.push(CustomAdmittance::series(3, nodes, Complex64::new(2.0, -20.0)));
+        let v_init = pf.create_v_init();
+        let result = pf.run_pf_with_config(v_init, &PowerFlowConfig::default());
+        assert!(matches!(
+            result,
+            Err(PowerFlowError::InvalidCustomNode { node }) if node == nodes
+        ));
+    }
+}
@@ -0,0 +1,147 @@
+use nalgebra::DVector;
+use nalgebra_sparse::{CooMatrix, CscMatrix};
+use num_complex::Complex64;
+
+use super::{PFNetwork, PowerFlowConfig, RunPF, GND};
+use crate::basic::solver::FaerSolver;
+#[cfg(feature = "klu")]
+use crate::basic::solver::KLUSolver;
+#[cfg(not(feature = "klu"))]
+use crate::basic::solver::RSparseSolver;
+use crate::basic::solver::{DenseSolver, Solve, SolverBackend};
+
+impl PFNetwork {
+    /// Solves the DC power flow for the voltage angles.
+    ///
+    /// The DC power flow assumes flat voltage magnitudes and lossless series branches, with the
+    /// magnitude `1/|z|` of the per-unit admittance as susceptance; shunt branches are left
+    /// out. The active injections of the loads, generators and PV nodes are balanced by the
+    /// slack.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The power flow options, which select the linear solver.
+    ///
+    /// # Returns
+    ///
+    /// The voltage angle of every node in radians relative to the external grid, or `None` if
+    /// the system is singular, e.g. because a part of the network has no path to the slack.
+    pub fn dc_angles(&self, config: &PowerFlowConfig) -> Option<DVector<f64>> {
+        let n = self.buses.len();
+        let slack = self.ext.bus as usize;
+        // the position of a node in the system without the slack
+        let reduced = |i: usize| (i != slack).then(|| if i < slack { i } else { i - 1 });
+
+        let mut coo = CooMatrix::new(n - 1, n - 1);
+        let mut p = self.create_s_bus().map(|x| x.re);
+        for br in &self.y_br {
+            let (f, t) = (br.port.0[0], br.port.0[1]);
+            if f == GND || t == GND {
+                continue;
+            }
+            let b = br.y.0.norm() * br.v_base * br.v_base / self.s_base;
+            // the flow b (theta_f - theta_t - shift) moves the shift to the injections
+            p[f as usize] += b * br.shift;
+            p[t as usize] -= b * br.shift;
+            let (f, t) = (reduced(f as usize), reduced(t as usize));
+            for x in [f, t].into_iter().flatten() {
+                coo.push(x, x, b);
+            }
+            if let (Some(f), Some(t)) = (f, t) {
+                coo.push(f, t, -b);
+                coo.push(t, f, -b);
+            }
+        }
+        let mut rhs: Vec<f64> = (0..n).filter(|i| *i != slack).map(|i| p[i]).collect();
+        if n > 1 {
+            solve_real(config.linear_solver, &CscMatrix::from(&coo), &mut rhs).ok()?;
+        }
+
+        let theta = DVector::from_fn(n, |i, _| reduced(i).map_or(0.0, |x| rhs[x]));
+        theta.iter().all(|x| x.is_finite()).then_some(theta)
+    }
+
+    /// Replaces the angles of initial voltages by the angles of the DC power flow.
+    ///
+    /// The DC angles are usually much closer to the solution than a flat start, which saves
+    /// Newton-Raphson iterations on transmission networks.
+    ///
+    /// # Arguments
+    ///
+    /// * `v_init` - The initial voltages, whose magnitudes are kept.
+    /// * `config` - The power flow options, which select the linear solver.
+    ///
+    /// # Returns
+    ///
+    /// The initial voltages with the DC angles shifted by the angle of the external grid, or
+    /// `v_init` unchanged if the DC power flow is singular.
+    pub fn dc_warm_start(
+        &self,
+        v_init: DVector<Complex64>,
+        config: &PowerFlowConfig,
+    ) -> DVector<Complex64> {
+        match self.dc_angles(config) {
+            Some(theta) => v_init.zip_map(&theta, |v, theta| {
+                Complex64::from_polar(v.norm(), self.ext.phase + theta)
+            }),
+            None => v_init,
+        }
+    }
+}
+
+/// Solves a real linear system with the solver of a backend.
+///
+/// # Arguments
+///
+/// * `backend` - The solver backend.
+/// * `a` - The system matrix.
+/// * `b` - The right-hand side, overwritten with the solution.
+pub(crate) fn solve_real(
+    backend: SolverBackend,
+    a: &CscMatrix<f64>,
+    b: &mut [f64],
+) -> Result<(), &'static str> {
+    let n = a.nrows();
+    let mut ap = a.col_offsets().to_vec();
+    let mut ai = a.row_indices().to_vec();
+    let mut ax = a.values().to_vec();
+    match backend.resolve(n) {
+        SolverBackend::Dense => DenseSolver.solve(&mut ap, &mut ai, &mut ax, b, n),
+        SolverBackend::Faer => FaerSolver.solve(&mut ap, &mut ai, &mut ax, b, n),
+        #[cfg(feature = "klu")]
+        _ => KLUSolver::default().solve(&mut ap, &mut ai, &mut ax, b, n),
+        #[cfg(not(feature = "klu"))]
+        _ => RSparseSolver.solve(&mut ap, &mut ai, &mut ax, b, n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::system::InitMode;
+    use crate::io::pandapower::load_csv_zip;
+    use std::env;
+
+    #[test]
+    fn test_dc_warm_start() {
+        let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let net = load_csv_zip(format!("{}/cases/IEEE118/data.zip", dir)).unwrap();
+        let pf = PFNetwork::try_from(&net).unwrap();
+        let solve = |init| {
+            let config = PowerFlowConfig {
+                init,
+                ..Default::default()
+            };
+            pf.run_pf_with_config(pf.create_v_init(), &config).unwrap()
+        };
+        let (v_flat, it_flat) = solve(InitMode::Flat);
+        let (v_dc, it_dc) = solve(InitMode::DcWarmStart);
+        assert!(it_dc < it_flat, "{} {}", it_dc, it_flat);
+        assert!((&v_flat - v_dc).camax() < 1e-6);
+
+        // the DC angles are a fair estimate of the AC ones
+        let theta = pf.dc_angles(&PowerFlowConfig::default()).unwrap();
+        let error = v_flat.zip_map(&theta, |v, theta| (v.arg() - pf.ext.phase - theta).abs());
+        assert!(error.max() < 0.1, "{}", error.max());
+    }
+}
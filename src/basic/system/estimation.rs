@@ -0,0 +1,446 @@
+use nalgebra::{DMatrix, DVector};
+use num_complex::Complex64;
+
+use super::{ElementIndex, ElementRef, NodeLookup, PFNetwork, RunPF, GND};
+use crate::basic::PowerFlowError;
+use crate::io::pandapower::{Measurement, Network};
+
+/// A measured quantity in terms of the power flow model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MeasuredQuantity {
+    /// The voltage magnitude of a node.
+    Vm { node: usize },
+    /// The voltage angle of a node.
+    Va { node: usize },
+    /// The active power injection of a node.
+    P { node: usize },
+    /// The reactive power injection of a node.
+    Q { node: usize },
+    /// The active power flowing into an element at the end connected to `node`.
+    Pf { element: ElementRef, node: usize },
+    /// The reactive power flowing into an element at the end connected to `node`.
+    Qf { element: ElementRef, node: usize },
+}
+
+/// A measurement in per unit, as used by the state estimation.
+///
+/// Injections use the generator convention; flows are positive into the element.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelMeasurement {
+    pub quantity: MeasuredQuantity,
+    /// The measured value in per unit (angles in radians).
+    pub value: f64,
+    /// The standard deviation in per unit (angles in radians).
+    pub std_dev: f64,
+}
+
+/// The results of a weighted-least-squares state estimation.
+#[derive(Debug, Clone)]
+pub struct StateEstimate {
+    /// The estimated complex bus voltages in per unit, indexed by node.
+    pub v: DVector<Complex64>,
+    /// The number of Gauss-Newton iterations needed to converge.
+    pub iterations: usize,
+    /// The weighted sum of squared residuals at the estimate.
+    pub objective: f64,
+    /// The number of measurements minus the number of state variables.
+    pub degrees_of_freedom: usize,
+    /// Whether the chi-square test at 99 % confidence suspects bad data.
+    pub bad_data_suspected: bool,
+}
+
+/// Converts pandapower measurements to the power flow model.
+///
+/// Bus powers are measured in the load convention as in pandapower, branch powers into the
+/// branch at the given side (`"from"`/`"hv"`, `"to"`/`"lv"` or a bus id). Current measurements
+/// and measurements of unknown elements are skipped.
+///
+/// # Arguments
+///
+/// * `net` - The network the measurements belong to.
+/// * `pf` - The power flow network assembled from `net`.
+pub fn model_measurements(net: &Network, pf: &PFNetwork) -> Vec<ModelMeasurement> {
+    let lookup = NodeLookup::new(&pf.buses);
+    let lines = ElementIndex::new(net.line.iter().flatten().map(|x| x.index));
+    let trafos = ElementIndex::new(net.trafo.iter().flatten().map(|x| x.index));
+    let branch = |m: &Measurement| -> Option<(ElementRef, usize)> {
+        let (element, from, to) = match m.element_type.as_str() {
+            "line" => {
+                let pos = lines.get(m.element)?;
+                let line = &net.line.as_ref()?[pos];
+                (ElementRef::Line(pos), line.from_bus, line.to_bus)
+            }
+            "trafo" => {
+                let pos = trafos.get(m.element)?;
+                let trafo = &net.trafo.as_ref()?[pos];
+                (
+                    ElementRef::Trafo(pos),
+                    trafo.hv_bus as i64,
+                    trafo.lv_bus as i64,
+                )
+            }
+            _ => return None,
+        };
+        let bus = match m.side.as_deref() {
+            Some("from") | Some("hv") | None => from,
+            Some("to") | Some("lv") => to,
+            Some(side) => side.parse::<f64>().ok()? as i64,
+        };
+        Some((element, lookup.get(bus)?))
+    };
+
+    let s_base = pf.s_base;
+    net.measurement
+        .iter()
+        .flatten()
+        .filter_map(|m| {
+            let bus = || lookup.get(m.element).filter(|_| m.element_type == "bus");
+            let (quantity, scale) = match m.measurement_type.as_str() {
+                "v" => (MeasuredQuantity::Vm { node: bus()? }, 1.0),
+                "va" => (MeasuredQuantity::Va { node: bus()? }, 1f64.to_radians()),
+                "p" if m.element_type == "bus" => {
+                    (MeasuredQuantity::P { node: bus()? }, -1.0 / s_base)
+                }
+                "q" if m.element_type == "bus" => {
+                    (MeasuredQuantity::Q { node: bus()? }, -1.0 / s_base)
+                }
+                "p" => {
+                    let (element, node) = branch(m)?;
+                    (MeasuredQuantity::Pf { element, node }, 1.0 / s_base)
+                }
+                "q" => {
+                    let (element, node) = branch(m)?;
+                    (MeasuredQuantity::Qf { element, node }, 1.0 / s_base)
+                }
+                _ => return None,
+            };
+            Some(ModelMeasurement {
+                quantity,
+                value: m.value * scale,
+                std_dev: m.std_dev * scale.abs(),
+            })
+        })
+        .collect()
+}
+
+/// The coefficients of a complex power `S = V_m conj(sum(c_k V_k))`.
+type PowerCoefficients = Vec<(usize, Complex64)>;
+
+impl PFNetwork {
+    /// Estimates the bus voltages from measurements by weighted least squares.
+    ///
+    /// The state consists of all voltage magnitudes and all angles except the slack angle,
+    /// which is held at its setpoint. The normal equations are solved by Gauss-Newton
+    /// iterations with a dense gain matrix.
+    ///
+    /// # Arguments
+    ///
+    /// * `measurements` - The measurements in per unit.
+    /// * `max_it` - The maximum number of iterations.
+    /// * `tol` - The convergence tolerance of the state update.
+    ///
+    /// # Returns
+    ///
+    /// The state estimate, or an error if the network is unobservable or the iteration
+    /// didn't converge.
+    pub fn estimate_state(
+        &self,
+        measurements: &[ModelMeasurement],
+        max_it: usize,
+        tol: f64,
+    ) -> Result<StateEstimate, PowerFlowError> {
+        let n = self.buses.len();
+        let slack = self.ext.bus as usize;
+        // column of the angle of every node, the magnitudes follow the angles
+        let mut va_col = vec![None; n];
+        for (col, node) in (0..n).filter(|x| *x != slack).enumerate() {
+            va_col[node] = Some(col);
+        }
+        let n_state = 2 * n - 1;
+        if measurements.len() < n_state {
+            return Err(PowerFlowError::Unobservable);
+        }
+
+        let ybus = self.create_y_bus();
+        let powers: Vec<Option<(usize, PowerCoefficients)>> = measurements
+            .iter()
+            .map(|m| match m.quantity {
+                MeasuredQuantity::P { node } | MeasuredQuantity::Q { node } => {
+                    let row = ybus.row(node);
+                    let c = row.col_indices().iter().copied();
+                    Some((node, c.zip(row.values().iter().copied()).collect()))
+                }
+                MeasuredQuantity::Pf { element, node } | MeasuredQuantity::Qf { element, node } => {
+                    Some((node, self.flow_coefficients(element, node)))
+                }
+                _ => None,
+            })
+            .collect();
+        let weights = DVector::from_iterator(
+            measurements.len(),
+            measurements.iter().map(|m| 1.0 / (m.std_dev * m.std_dev)),
+        );
+
+        let mut vm = DVector::from_element(n, 1.0);
+        let mut va = DVector::from_element(n, self.ext.phase);
+        vm[slack] = self.ext.v;
+        for iterations in 1..=max_it {
+            let v = DVector::from_fn(n, |i, _| Complex64::from_polar(vm[i], va[i]));
+            let mut h = DMatrix::zeros(measurements.len(), n_state);
+            let mut r = DVector::zeros(measurements.len());
+            for (row, (m, power)) in measurements.iter().zip(powers.iter()).enumerate() {
+                let estimate = match (m.quantity, power) {
+                    (MeasuredQuantity::Vm { node }, _) => {
+                        h[(row, n - 1 + node)] = 1.0;
+                        vm[node]
+                    }
+                    (MeasuredQuantity::Va { node }, _) => {
+                        if let Some(col) = va_col[node] {
+                            h[(row, col)] = 1.0;
+                        }
+                        va[node]
+                    }
+                    (quantity, Some((node, c))) => {
+                        let reactive = matches!(
+                            quantity,
+                            MeasuredQuantity::Q { .. } | MeasuredQuantity::Qf { .. }
+                        );
+                        let part = |x: Complex64| if reactive { x.im } else { x.re };
+                        let (s, derivatives) = power_derivatives(&v, *node, c);
+                        for (k, ds_dva, ds_dvm) in derivatives {
+                            if let Some(col) = va_col[k] {
+                                h[(row, col)] += part(ds_dva);
+                            }
+                            h[(row, n - 1 + k)] += part(ds_dvm);
+                        }
+                        part(s)
+                    }
+                    (_, None) => unreachable!("power measurements have coefficients"),
+                };
+                r[row] = m.value - estimate;
+            }
+
+            let ht_w = h.transpose() * DMatrix::from_diagonal(&weights);
+            let gain = &ht_w * &h;
+            let dx = gain
+                .lu()
+                .solve(&(&ht_w * &r))
+                .ok_or(PowerFlowError::Unobservable)?;
+            for node in 0..n {
+                if let Some(col) = va_col[node] {
+                    va[node] += dx[col];
+                }
+                vm[node] += dx[n - 1 + node];
+            }
+
+            if dx.amax() < tol {
+                let v = DVector::from_fn(n, |i, _| Complex64::from_polar(vm[i], va[i]));
+                let objective = r.component_mul(&r).dot(&weights);
+                let degrees_of_freedom = measurements.len() - n_state;
+                return Ok(StateEstimate {
+                    v,
+                    iterations,
+                    objective,
+                    degrees_of_freedom,
+                    bad_data_suspected: objective > chi_square_99(degrees_of_freedom),
+                });
+            }
+        }
+        Err(PowerFlowError::NotConverged {
+            iterations: max_it,
+            v: DVector::from_fn(n, |i, _| Complex64::from_polar(vm[i], va[i])),
+        })
+    }
+
+    /// Builds the coefficients of the current flowing into an element at the end at `node`.
+    fn flow_coefficients(&self, element: ElementRef, node: usize) -> PowerCoefficients {
+        let mut c: PowerCoefficients = Vec::new();
+        for br in self.y_br.iter().filter(|x| x.element == Some(element)) {
+            let y = br.y.0 * br.v_base * br.v_base / self.s_base;
+            let (f, t) = (br.port.0[0], br.port.0[1]);
+            let rotation = Complex64::from_polar(1.0, br.shift);
+            let (other, rotation) = match (
+                f as usize == node && f != GND,
+                t as usize == node && t != GND,
+            ) {
+                (true, _) => (t, rotation),
+                (_, true) => (f, rotation.conj()),
+                _ => continue,
+            };
+            c.push((node, y));
+            if other != GND {
+                c.push((other as usize, -y * rotation));
+            }
+        }
+        c
+    }
+}
+
+/// Computes `S = V_m conj(sum(c_k V_k))` and its derivatives with respect to the voltage
+/// angles and magnitudes of the nodes involved.
+///
+/// # Returns
+///
+/// The power and, for every node involved, the derivatives `(k, dS/dva_k, dS/dvm_k)`, which
+/// are to be summed per node.
+fn power_derivatives(
+    v: &DVector<Complex64>,
+    node: usize,
+    c: &PowerCoefficients,
+) -> (Complex64, Vec<(usize, Complex64, Complex64)>) {
+    let j = Complex64::new(0.0, 1.0);
+    let i: Complex64 = c.iter().map(|(k, y)| y * v[*k]).sum();
+    let s = v[node] * i.conj();
+    let mut derivatives = vec![(node, j * s, s / v[node].norm())];
+    for (k, y) in c {
+        let term = (y * v[*k]).conj();
+        derivatives.push((*k, -j * v[node] * term, v[node] * term / v[*k].norm()));
+    }
+    (s, derivatives)
+}
+
+/// Approximates the 99 % quantile of the chi-square distribution (Wilson-Hilferty).
+fn chi_square_99(degrees_of_freedom: usize) -> f64 {
+    if degrees_of_freedom == 0 {
+        return f64::INFINITY;
+    }
+    let k = degrees_of_freedom as f64;
+    let z = 2.326_347_874;
+    k * (1.0 - 2.0 / (9.0 * k) + z * (2.0 / (9.0 * k)).sqrt()).powi(3)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::basic::system::PowerGrid;
+    use crate::io::pandapower::*;
+
+    /// Builds a 20 kV three-bus feeder with lines 10 (0-1) and 11 (1-2).
+    fn feeder() -> Network {
+        Network {
+            bus: (0..3)
+                .map(|index| Bus {
+                    index,
+                    in_service: true,
+                    vn_kv: 20.0,
+                    ..Default::default()
+                })
+                .collect(),
+            ext_grid: Some(vec![ExtGrid {
+                bus: 0,
+                in_service: true,
+                vm_pu: 1.0,
+                ..Default::default()
+            }]),
+            line: Some(
+                [(10, 0, 1), (11, 1, 2)]
+                    .into_iter()
+                    .map(|(index, from_bus, to_bus)| Line {
+                        index,
+                        from_bus,
+                        to_bus,
+                        in_service: true,
+                        length_km: 3.0,
+                        parallel: 1,
+                        r_ohm_per_km: 0.2,
+                        x_ohm_per_km: 0.4,
+                        c_nf_per_km: 10.0,
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            load: Some(
+                [(1, 3.0, 1.0), (2, 4.0, 1.5)]
+                    .into_iter()
+                    .map(|(bus, p_mw, q_mvar)| Load {
+                        bus,
+                        in_service: true,
+                        p_mw,
+                        q_mvar,
+                        scaling: 1.0,
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    fn measurement(
+        measurement_type: &str,
+        element_type: &str,
+        element: i64,
+        value: f64,
+        std_dev: f64,
+    ) -> Measurement {
+        Measurement {
+            measurement_type: measurement_type.to_string(),
+            element_type: element_type.to_string(),
+            element,
+            value,
+            std_dev,
+            side: Some("from".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_state_estimation() {
+        let mut net = feeder();
+        let truth = PowerGrid::new(feeder()).solve().unwrap();
+
+        // deterministic noise of at most one standard deviation
+        let noise = [0.3, -0.8, 0.5, -0.2, 1.0, -0.6, 0.1, 0.7, -0.4, -1.0, 0.4];
+        let mut noise = noise.iter();
+        let mut measured = |value: f64, std_dev: f64| value + std_dev * noise.next().unwrap();
+        let mut measurements = Vec::new();
+        for b in truth.res_bus.iter() {
+            measurements.push(measurement(
+                "v",
+                "bus",
+                b.bus,
+                measured(b.vm_pu, 0.002),
+                0.002,
+            ));
+        }
+        for b in truth.res_bus.iter().skip(1) {
+            measurements.push(measurement("p", "bus", b.bus, measured(b.p_mw, 0.05), 0.05));
+            measurements.push(measurement(
+                "q",
+                "bus",
+                b.bus,
+                measured(b.q_mvar, 0.05),
+                0.05,
+            ));
+        }
+        for (id, l) in [10, 11].into_iter().zip(truth.res_line.iter()) {
+            measurements.push(measurement(
+                "p",
+                "line",
+                id,
+                measured(l.p_from_mw, 0.05),
+                0.05,
+            ));
+            measurements.push(measurement(
+                "q",
+                "line",
+                id,
+                measured(l.q_from_mvar, 0.05),
+                0.05,
+            ));
+        }
+        net.measurement = Some(measurements);
+
+        let estimate = PowerGrid::new(net).run_state_estimation().unwrap();
+        assert_eq!(estimate.degrees_of_freedom, 11 - 5);
+        assert!(!estimate.bad_data_suspected, "{}", estimate.objective);
+        for (v, b) in estimate.v.iter().zip(truth.res_bus.iter()) {
+            assert!(
+                (v.norm() - b.vm_pu).abs() < 2e-3,
+                "{} {}",
+                v.norm(),
+                b.vm_pu
+            );
+            assert!((v.arg().to_degrees() - b.va_degree).abs() < 0.05);
+        }
+    }
+}
@@ -0,0 +1,741 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt::Write;
+
+use nalgebra_sparse::{CooMatrix, CscMatrix, CsrMatrix};
+use num_complex::Complex64;
+
+use super::{ElementRef, NodeLookup, PFNetwork, PowerFlowConfig, GND};
+use crate::basic::PowerFlowError;
+use crate::io::pandapower::{Bus, Network};
+
+/// The branch-node incidence matrix of the series admittance branches.
+#[derive(Debug, Clone)]
+pub struct BranchIncidence {
+    /// The incidence matrix with one row per branch and one column per node, holding +1 at the
+    /// from node and -1 at the to node of the branch.
+    pub matrix: CsrMatrix<f64>,
+    /// The position in `y_br` of the branch of each row.
+    pub branches: Vec<usize>,
+}
+
+/// The electrical nodes formed by the buses connected through closed bus-bus switches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeMapping {
+    /// The bus identifiers in table order.
+    buses: Vec<i64>,
+    /// The node of each bus in table order, numbered by the first bus of each node.
+    nodes: Vec<usize>,
+    /// The number of nodes.
+    n_nodes: usize,
+}
+
+impl NodeMapping {
+    /// Merges the buses of a network connected by closed bus-bus switches.
+    ///
+    /// Only the bus and switch tables are read, so the mapping is cheap to build. Switches
+    /// with an impedance are merged like ideal ones; switches to unknown buses are skipped.
+    pub fn new(net: &Network) -> Self {
+        let switches = net.switch.iter().flatten();
+        let switches = switches.filter(|x| x.closed && x.et.as_deref() == Some("b"));
+        Self::from_pairs(&net.bus, switches.map(|x| (x.bus, x.element)))
+    }
+
+    /// Merges the given pairs of buses.
+    ///
+    /// # Arguments
+    ///
+    /// * `buses` - The buses of the network.
+    /// * `pairs` - The identifiers of the buses to merge; pairs with unknown buses are skipped.
+    pub(crate) fn from_pairs(buses: &[Bus], pairs: impl IntoIterator<Item = (i64, i64)>) -> Self {
+        let lookup = NodeLookup::new(buses);
+        let mut parent: Vec<usize> = (0..buses.len()).collect();
+        for (a, b) in pairs {
+            if let (Some(a), Some(b)) = (lookup.get(a), lookup.get(b)) {
+                let (a, b) = (find_root(&mut parent, a), find_root(&mut parent, b));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+
+        let mut numbers = BTreeMap::new();
+        let nodes = (0..parent.len())
+            .map(|x| {
+                let next = numbers.len();
+                *numbers.entry(find_root(&mut parent, x)).or_insert(next)
+            })
+            .collect();
+        Self {
+            buses: buses.iter().map(|x| x.index).collect(),
+            nodes,
+            n_nodes: numbers.len(),
+        }
+    }
+
+    /// Returns the bus identifiers in table order with the node of each.
+    pub(crate) fn bus_nodes(&self) -> impl Iterator<Item = (i64, usize)> + '_ {
+        self.buses.iter().copied().zip(self.nodes.iter().copied())
+    }
+
+    /// Returns the node of a bus, or `None` if the bus doesn't exist.
+    pub fn node(&self, bus: i64) -> Option<usize> {
+        let pos = self.buses.iter().position(|x| *x == bus)?;
+        Some(self.nodes[pos])
+    }
+
+    /// Returns the identifiers of the buses merged into a node.
+    pub fn buses(&self, node: usize) -> impl Iterator<Item = i64> + '_ {
+        self.buses
+            .iter()
+            .zip(&self.nodes)
+            .filter(move |(_, x)| **x == node)
+            .map(|(bus, _)| *bus)
+    }
+
+    /// Returns the partition of the buses into nodes, for inspecting the merges.
+    ///
+    /// Each set holds the bus identifiers of one node in table order; the sets are ordered by
+    /// node.
+    pub fn sets(&self) -> Vec<Vec<i64>> {
+        let mut sets = vec![Vec::new(); self.n_nodes];
+        for (bus, node) in self.buses.iter().zip(&self.nodes) {
+            sets[*node].push(*bus);
+        }
+        sets
+    }
+
+    /// Returns the number of nodes.
+    pub fn len(&self) -> usize {
+        self.n_nodes
+    }
+
+    /// Returns whether there are no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.n_nodes == 0
+    }
+}
+
+/// Finds the representative of a node in a union-find forest, halving the path on the way.
+pub(crate) fn find_root(parent: &mut [usize], mut x: usize) -> usize {
+    while parent[x] != x {
+        parent[x] = parent[parent[x]];
+        x = parent[x];
+    }
+    x
+}
+
+/// Returns the element type shown as edge label.
+fn element_type(element: Option<ElementRef>) -> &'static str {
+    match element {
+        Some(ElementRef::Line(_)) => "line",
+        Some(ElementRef::Trafo(_)) => "trafo",
+        Some(ElementRef::Switch(_)) => "switch",
+        Some(ElementRef::Load(_)) => "load",
+        Some(ElementRef::Shunt(_)) => "shunt",
+        Some(ElementRef::Gen(_)) => "gen",
+        Some(ElementRef::SGen(_)) => "sgen",
+        None => "branch",
+    }
+}
+
+impl PFNetwork {
+    /// Returns the map from bus identifiers to the node indices, which number the columns of
+    /// the matrices of the network.
+    pub fn node_lookup(&self) -> NodeLookup {
+        NodeLookup::new(&self.buses)
+    }
+
+    /// Builds the branch-node incidence matrix.
+    ///
+    /// Every admittance branch between two nodes is a row; shunt branches to ground are left
+    /// out. Unlike the topology exports, elements modeled by several branches contribute one
+    /// row per branch.
+    pub fn branch_incidence(&self) -> BranchIncidence {
+        let series = self.y_br.iter().enumerate().filter(|(_, br)| {
+            let (f, t) = (br.port.0[0], br.port.0[1]);
+            f != GND && t != GND
+        });
+        let branches: Vec<usize> = series.map(|(pos, _)| pos).collect();
+        let mut coo = CooMatrix::new(branches.len(), self.buses.len());
+        for (row, &pos) in branches.iter().enumerate() {
+            let port = &self.y_br[pos].port.0;
+            coo.push(row, port[0] as usize, 1.0);
+            coo.push(row, port[1] as usize, -1.0);
+        }
+        BranchIncidence {
+            matrix: CsrMatrix::from(&coo),
+            branches,
+        }
+    }
+
+    /// Builds the weighted Laplacian of the network graph.
+    ///
+    /// The Laplacian is `A^T W A` with the incidence matrix `A` of [`PFNetwork::branch_incidence`]
+    /// and the magnitudes of the per-unit branch susceptances as weights `W`, so that branches
+    /// of different voltage levels compare as in the Ybus. It is symmetric, its rows sum to
+    /// zero, and it has one zero eigenvalue per connected component.
+    pub fn laplacian(&self) -> CsrMatrix<f64> {
+        let incidence = self.branch_incidence();
+        let mut weights = CooMatrix::new(incidence.branches.len(), incidence.branches.len());
+        for (row, &pos) in incidence.branches.iter().enumerate() {
+            let br = &self.y_br[pos];
+            let susceptance = br.y.0.im.abs() * br.v_base * br.v_base / self.s_base;
+            weights.push(row, row, susceptance);
+        }
+        let a = &incidence.matrix;
+        &a.transpose() * &(&CsrMatrix::from(&weights) * a)
+    }
+
+    /// Finds the islands, the sets of buses connected through series admittance branches.
+    ///
+    /// The output is sorted so that it doesn't depend on the order of the bus and branch
+    /// tables: the bus identifiers of each island ascend, and the islands are ordered by their
+    /// smallest bus identifier.
+    ///
+    /// # Returns
+    ///
+    /// The bus identifiers of each island.
+    pub fn islands(&self) -> Vec<Vec<i64>> {
+        let mut parent: Vec<usize> = (0..self.buses.len()).collect();
+        for br in &self.y_br {
+            let (f, t) = (br.port.0[0], br.port.0[1]);
+            if f != GND && t != GND {
+                let (a, b) = (
+                    find_root(&mut parent, f as usize),
+                    find_root(&mut parent, t as usize),
+                );
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+
+        let mut islands: BTreeMap<usize, Vec<i64>> = BTreeMap::new();
+        for (node, bus) in self.buses.iter().enumerate() {
+            let root = find_root(&mut parent, node);
+            islands.entry(root).or_default().push(bus.index);
+        }
+        let mut islands: Vec<Vec<i64>> = islands.into_values().collect();
+        islands.iter_mut().for_each(|x| x.sort_unstable());
+        islands.sort_unstable_by_key(|x| x[0]);
+        islands
+    }
+
+    /// Computes an elimination order of the nodes of a radial network.
+    ///
+    /// In a tree every node eliminated after all nodes below it only has its parent left as
+    /// neighbour, so factorizing the Ybus in this order creates no fill-in. Parallel branches
+    /// between the same nodes count as one connection. The power flow orders its Jacobian
+    /// the same way, see [`radial_jacobian_ordering`].
+    ///
+    /// # Returns
+    ///
+    /// The nodes ordered from the leaves towards the slack, which comes last, or `None` if the
+    /// network is meshed or not connected.
+    pub fn radial_ordering(&self) -> Option<Vec<usize>> {
+        let n = self.buses.len();
+        let mut neighbours = vec![BTreeSet::new(); n];
+        for br in &self.y_br {
+            let (f, t) = (br.port.0[0], br.port.0[1]);
+            if f != GND && t != GND && f != t {
+                neighbours[f as usize].insert(t as usize);
+                neighbours[t as usize].insert(f as usize);
+            }
+        }
+        let n_edges: usize = neighbours.iter().map(BTreeSet::len).sum::<usize>() / 2;
+        if n_edges + 1 != n {
+            return None;
+        }
+        let (order, _) = leaves_first(&neighbours, [self.ext.bus as usize]);
+        (order.len() == n).then_some(order)
+    }
+
+    /// Counts the branches between every bus and its slack.
+    ///
+    /// A breadth-first search from the slacks of the power flow, the external grid and the
+    /// references of [`PowerFlowConfig::island_slacks`], runs over the series admittance
+    /// branches, so every closed switch and parallel branch counts as one hop. This is a
+    /// diagnostic for reference issues: buses that can't reach a slack float freely.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The power flow options, of which `island_slacks` adds slacks.
+    ///
+    /// # Returns
+    ///
+    /// The smallest number of branches between each bus and a slack bus, keyed by bus
+    /// identifier, or `None` for buses in islands without a slack; an error if the island
+    /// slacks are invalid, see [`PFNetwork::run_pf_with_config`].
+    pub fn distance_to_slack(
+        &self,
+        config: &PowerFlowConfig,
+    ) -> Result<HashMap<u64, Option<usize>>, PowerFlowError> {
+        let mut neighbours = vec![Vec::new(); self.buses.len()];
+        for br in &self.y_br {
+            let (f, t) = (br.port.0[0], br.port.0[1]);
+            if f != GND && t != GND {
+                neighbours[f as usize].push(t as usize);
+                neighbours[t as usize].push(f as usize);
+            }
+        }
+
+        let mut distances = vec![None; self.buses.len()];
+        let mut queue = VecDeque::new();
+        for (slack, _) in self.slack_nodes(&config.island_slacks)? {
+            distances[slack as usize] = Some(0);
+            queue.push_back(slack as usize);
+        }
+        while let Some(node) = queue.pop_front() {
+            let next = distances[node].map(|x| x + 1);
+            for &x in &neighbours[node] {
+                if distances[x].is_none() {
+                    distances[x] = next;
+                    queue.push_back(x);
+                }
+            }
+        }
+        Ok(self
+            .buses
+            .iter()
+            .zip(distances)
+            .map(|(bus, distance)| (bus.index as u64, distance))
+            .collect())
+    }
+
+    /// Accumulates the phase shifts of the branches along the paths from the slack.
+    ///
+    /// A breadth-first search from the external grid runs over the series admittance
+    /// branches; the voltage behind a phase-shifting branch lags by its shift at no load, which
+    /// gives the flat start of networks with e.g. `Dyn5` transformers.
+    ///
+    /// # Returns
+    ///
+    /// The angle in radians by which each node lags the slack at no load, in node order, zero
+    /// for nodes without a path to the slack.
+    pub fn phase_shift_angles(&self) -> Vec<f64> {
+        let mut neighbours = vec![Vec::new(); self.buses.len()];
+        for br in &self.y_br {
+            let (f, t) = (br.port.0[0], br.port.0[1]);
+            if f != GND && t != GND {
+                neighbours[f as usize].push((t as usize, br.shift));
+                neighbours[t as usize].push((f as usize, -br.shift));
+            }
+        }
+
+        let mut angles = vec![None; self.buses.len()];
+        let slack = self.ext.bus as usize;
+        angles[slack] = Some(0.0);
+        let mut queue = VecDeque::from([slack]);
+        while let Some(node) = queue.pop_front() {
+            let angle = angles[node].unwrap_or_default();
+            for &(x, shift) in &neighbours[node] {
+                if angles[x].is_none() {
+                    angles[x] = Some(angle + shift);
+                    queue.push_back(x);
+                }
+            }
+        }
+        angles.into_iter().map(Option::unwrap_or_default).collect()
+    }
+
+    /// Collects the edges of the bus-branch topology.
+    ///
+    /// Every element with a series admittance becomes one edge, regardless of how many
+    /// admittance branches model it; shunt admittances to ground are left out.
+    ///
+    /// # Returns
+    ///
+    /// The bus identifiers of the two ends and the element type of each edge.
+    fn topology_edges(&self) -> Vec<(i64, i64, &'static str)> {
+        let mut elements = BTreeMap::new();
+        let mut edges = Vec::new();
+        for br in &self.y_br {
+            let (f, t) = (br.port.0[0], br.port.0[1]);
+            if f == GND || t == GND {
+                continue;
+            }
+            let edge = (
+                self.buses[f as usize].index,
+                self.buses[t as usize].index,
+                element_type(br.element),
+            );
+            match br.element {
+                Some(element) => {
+                    elements.entry(element).or_insert(edge);
+                }
+                None => edges.push(edge),
+            }
+        }
+        elements.into_values().chain(edges).collect()
+    }
+
+    /// Exports the bus-branch topology in the DOT language of Graphviz.
+    ///
+    /// Nodes are labeled with the bus identifiers, edges with the element type. Closed switches
+    /// are edges, open ones are left out.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("graph network {\n");
+        for bus in &self.buses {
+            writeln!(out, "  \"{0}\" [label=\"{0}\"];", bus.index).unwrap();
+        }
+        for (f, t, kind) in self.topology_edges() {
+            writeln!(out, "  \"{}\" -- \"{}\" [label=\"{}\"];", f, t, kind).unwrap();
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Exports the bus-branch topology as GraphML.
+    ///
+    /// Nodes carry the bus identifier as `label`, edges the element type as `type`.
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+            "  <key id=\"type\" for=\"edge\" attr.name=\"type\" attr.type=\"string\"/>\n",
+            "  <graph id=\"network\" edgedefault=\"undirected\">\n",
+        ));
+        for bus in &self.buses {
+            writeln!(
+                out,
+                "    <node id=\"{0}\"><data key=\"label\">{0}</data></node>",
+                bus.index
+            )
+            .unwrap();
+        }
+        for (f, t, kind) in self.topology_edges() {
+            writeln!(
+                out,
+                "    <edge source=\"{}\" target=\"{}\"><data key=\"type\">{}</data></edge>",
+                f, t, kind
+            )
+            .unwrap();
+        }
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+}
+
+/// Orders the nodes reachable from the roots so that every node comes before the node it was
+/// reached from.
+///
+/// # Arguments
+///
+/// * `neighbours` - The adjacent nodes of every node.
+/// * `roots` - The nodes to start a breadth-first search from, unless already reached.
+///
+/// # Returns
+///
+/// The reached nodes from the leaves towards the roots, and the number of searches started.
+fn leaves_first(
+    neighbours: &[BTreeSet<usize>],
+    roots: impl IntoIterator<Item = usize>,
+) -> (Vec<usize>, usize) {
+    let mut visited = vec![false; neighbours.len()];
+    let mut order = Vec::with_capacity(neighbours.len());
+    let mut trees = 0;
+    for root in roots {
+        if visited[root] {
+            continue;
+        }
+        trees += 1;
+        visited[root] = true;
+        let mut next = order.len();
+        order.push(root);
+        // a breadth-first search visits every node after its parent
+        while let Some(&node) = order.get(next) {
+            next += 1;
+            for &x in &neighbours[node] {
+                if !visited[x] {
+                    visited[x] = true;
+                    order.push(x);
+                }
+            }
+        }
+    }
+    order.reverse();
+    (order, trees)
+}
+
+/// Computes a symmetric permutation of the power flow Jacobian that factorizes without
+/// fill-in if the nodes other than the slacks form a forest, as in a radial network.
+///
+/// The Jacobian couples the angle and, at PQ nodes, the magnitude of adjacent nodes, so both
+/// unknowns of a node are eliminated together, from the leaves towards the slacks.
+///
+/// # Arguments
+///
+/// * `ybus` - The admittance matrix permuted into PV, PQ and slack order.
+/// * `npv` - The number of PV nodes.
+/// * `npq` - The number of PQ nodes.
+///
+/// # Returns
+///
+/// The Jacobian row and column of every position in the new order, or `None` if the nodes
+/// are meshed.
+pub(crate) fn radial_jacobian_ordering(
+    ybus: &CscMatrix<Complex64>,
+    npv: usize,
+    npq: usize,
+) -> Option<Vec<usize>> {
+    let n = npv + npq;
+    let mut neighbours = vec![BTreeSet::new(); n];
+    for (i, j, _) in ybus.triplet_iter().filter(|(i, j, _)| i != j && *i < n && *j < n) {
+        neighbours[i].insert(j);
+        neighbours[j].insert(i);
+    }
+    let n_edges: usize = neighbours.iter().map(BTreeSet::len).sum::<usize>() / 2;
+    let (order, trees) = leaves_first(&neighbours, 0..n);
+    if n_edges + trees != n {
+        return None;
+    }
+    let mut columns = Vec::with_capacity(n + npq);
+    for node in order {
+        columns.push(node);
+        if node >= npv {
+            columns.push(n + node - npv);
+        }
+    }
+    Some(columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::system::test_ieee39;
+    use nalgebra::DMatrix;
+    use crate::basic::system::{IslandSlack, RunPF};
+    use crate::io::pandapower::{Network, Switch};
+    use crate::io::test_networks::{buses, line, load, radial_network};
+
+    #[test]
+    fn test_branch_incidence() {
+        let net: Network = serde_json::from_str(test_ieee39::IEEE_39).unwrap();
+        let pf = PFNetwork::try_from(&net).unwrap();
+        let lookup = pf.node_lookup();
+        let incidence = pf.branch_incidence();
+        let n_branches = net.line.as_ref().unwrap().len() + net.trafo.as_ref().unwrap().len();
+        assert_eq!(incidence.matrix.nrows(), n_branches);
+        assert_eq!(incidence.matrix.ncols(), lookup.len());
+
+        for (row, &pos) in incidence.matrix.row_iter().zip(&incidence.branches) {
+            let values = row.values();
+            assert_eq!(values.len(), 2);
+            assert_eq!(values.iter().filter(|x| **x == 1.0).count(), 1);
+            assert_eq!(values.iter().filter(|x| **x == -1.0).count(), 1);
+
+            let port = &pf.y_br[pos].port.0;
+            for (node, sign) in [(port[0], 1.0), (port[1], -1.0)] {
+                let bus = pf.buses[node as usize].index;
+                let col = row.col_indices().iter().position(|x| *x == lookup[bus]);
+                assert_eq!(values[col.unwrap()], sign);
+            }
+        }
+    }
+
+    #[test]
+    fn test_laplacian() {
+        let net: Network = serde_json::from_str(test_ieee39::IEEE_39).unwrap();
+        let mut pf = PFNetwork::try_from(&net).unwrap();
+        let n_components = |pf: &PFNetwork| {
+            let mut parent: Vec<usize> = (0..pf.buses.len()).collect();
+            for br in pf.y_br.iter().filter(|br| !br.port.0.iter().any(|x| *x == GND)) {
+                let (f, t) = (br.port.0[0] as usize, br.port.0[1] as usize);
+                let (a, b) = (find_root(&mut parent, f), find_root(&mut parent, t));
+                parent[a] = b;
+            }
+            (0..parent.len()).filter(|&x| find_root(&mut parent, x) == x).count()
+        };
+        let n_zero = |pf: &PFNetwork| {
+            let laplacian = DMatrix::from(&pf.laplacian());
+            assert_eq!(laplacian.nrows(), pf.buses.len());
+            for row in laplacian.row_iter() {
+                assert!(row.sum().abs() < 1e-9);
+            }
+            let eigenvalues = laplacian.symmetric_eigenvalues();
+            let tol = 1e-9 * eigenvalues.amax();
+            eigenvalues.iter().filter(|x| x.abs() < tol).count()
+        };
+        assert_eq!(n_zero(&pf), 1);
+
+        // the weights are the per-unit susceptances of the Ybus
+        let feeder = PFNetwork::try_from(&radial_network(3, &[(0, 1), (1, 2)])).unwrap();
+        let laplacian = DMatrix::from(&feeder.laplacian());
+        let ybus = DMatrix::from(&feeder.create_y_bus());
+        for (f, t) in [(0, 1), (1, 2)] {
+            assert!((laplacian[(f, t)] + ybus[(f, t)].im.abs()).abs() < 1e-9);
+        }
+
+        // cutting all branches of a node isolates it and the generator bus behind it
+        pf.y_br.retain(|br| br.port.0[0] != 1 && br.port.0[1] != 1);
+        assert_eq!(n_components(&pf), 3);
+        assert_eq!(n_zero(&pf), 3);
+    }
+
+    #[test]
+    fn test_islands_order() {
+        let net: Network = serde_json::from_str(test_ieee39::IEEE_39).unwrap();
+        let mut pf = PFNetwork::try_from(&net).unwrap();
+        // cutting all branches of a node isolates it and the generator bus behind it
+        pf.y_br.retain(|br| br.port.0[0] != 1 && br.port.0[1] != 1);
+        let islands = pf.islands();
+        assert_eq!(format!("{:?}", islands), format!("{:?}", pf.islands()));
+        assert_eq!(islands.len(), 3);
+        assert_eq!(islands.iter().map(Vec::len).sum::<usize>(), pf.buses.len());
+        for island in &islands {
+            assert!(island.windows(2).all(|x| x[0] < x[1]));
+        }
+        assert!(islands.windows(2).all(|x| x[0][0] < x[1][0]));
+
+        // the order doesn't depend on the order of the branches
+        pf.y_br.reverse();
+        assert_eq!(pf.islands(), islands);
+    }
+
+    /// Counts the entries created by eliminating the nodes of a symmetric pattern in order.
+    fn fill_in(n: usize, edges: &[(usize, usize)], order: &[usize]) -> usize {
+        let mut adjacent = vec![BTreeSet::new(); n];
+        for &(f, t) in edges {
+            adjacent[f].insert(t);
+            adjacent[t].insert(f);
+        }
+        let mut eliminated = vec![false; n];
+        let mut fill = 0;
+        for &k in order {
+            eliminated[k] = true;
+            let remaining: Vec<usize> =
+                adjacent[k].iter().copied().filter(|x| !eliminated[*x]).collect();
+            for (i, &a) in remaining.iter().enumerate() {
+                for &b in &remaining[i + 1..] {
+                    if adjacent[a].insert(b) {
+                        adjacent[b].insert(a);
+                        fill += 1;
+                    }
+                }
+            }
+        }
+        fill
+    }
+
+    #[test]
+    fn test_radial_ordering() {
+        // the hubs 1 and 3 come before their branches in the bus table
+        let branches = [(0, 1), (1, 2), (1, 3), (3, 4), (3, 5)];
+        let net = Network {
+            load: Some([2, 4, 5].map(|bus| load(bus, 1.0, 0.3)).into()),
+            ..radial_network(6, &branches)
+        };
+        let pf = PFNetwork::try_from(&net).unwrap();
+        let order = pf.radial_ordering().unwrap();
+        assert_eq!(order.last(), Some(&0));
+
+        // the Jacobian factorizes without fill-in in the radial order, unlike in node order
+        let (_, ybus, _, _, npv, npq) = pf.prepare_matrices(pf.create_v_init());
+        let (n, m) = (npv + npq, npv + 2 * npq);
+        let columns = radial_jacobian_ordering(&ybus, npv, npq).unwrap();
+        assert_eq!(columns.len(), m);
+        // the angles of all nodes come first, then the magnitudes of the PQ nodes
+        let node = |k: usize| if k < n { k } else { k - n + npv };
+        let pattern: BTreeSet<(usize, usize)> =
+            ybus.triplet_iter().map(|(i, j, _)| (i, j)).collect();
+        let edges: Vec<(usize, usize)> = (0..m)
+            .flat_map(|a| (a + 1..m).map(move |b| (a, b)))
+            .filter(|&(a, b)| pattern.contains(&(node(a), node(b))))
+            .collect();
+        assert_eq!(fill_in(m, &edges, &columns), 0);
+        assert!(fill_in(m, &edges, &(0..m).collect::<Vec<_>>()) > 0);
+
+        // the ordering leaves the solution unchanged
+        let (v, _) = pf.run_pf(pf.create_v_init(), Some(10), Some(1e-10));
+        let mismatch = pf.mismatch_fn()(&pf.state_layout().pack(&v));
+        assert!(mismatch.amax() < 1e-9);
+        assert!(v[5].norm() < v[3].norm() && v[3].norm() < v[1].norm());
+
+        // a mesh has no radial ordering
+        let mut meshed = net;
+        meshed.line.as_mut().unwrap().push(line(2, 4));
+        let meshed = PFNetwork::try_from(&meshed).unwrap();
+        assert_eq!(meshed.radial_ordering(), None);
+        let (_, ybus, _, _, npv, npq) = meshed.prepare_matrices(meshed.create_v_init());
+        assert_eq!(radial_jacobian_ordering(&ybus, npv, npq), None);
+    }
+
+    #[test]
+    fn test_distance_to_slack() {
+        // a feeder 0-1-2-3 with a lateral 1-4; bus 5 is not connected
+        let net = Network {
+            load: None,
+            ..radial_network(6, &[(0, 1), (1, 2), (2, 3), (1, 4)])
+        };
+        let pf = PFNetwork::try_from(&net).unwrap();
+        let mut config = PowerFlowConfig::default();
+        let distances = pf.distance_to_slack(&config).unwrap();
+        assert_eq!(distances.len(), 6);
+        for (bus, depth) in [(0, 0), (1, 1), (2, 2), (3, 3), (4, 2)] {
+            assert_eq!(distances[&bus], Some(depth));
+        }
+        assert_eq!(distances[&5], None);
+
+        // the island of bus 5 is reached from its own slack, and a reference in the island of
+        // the external grid takes its place
+        let slack = |bus| IslandSlack {
+            bus,
+            vm_pu: 1.0,
+            va_degree: 0.0,
+        };
+        config.island_slacks.insert(5, slack(5));
+        config.island_slacks.insert(0, slack(3));
+        let distances = pf.distance_to_slack(&config).unwrap();
+        for (bus, depth) in [(0, 3), (1, 2), (2, 1), (3, 0), (4, 3), (5, 0)] {
+            assert_eq!(distances[&bus], Some(depth));
+        }
+        config.island_slacks.insert(4, slack(2));
+        let error = pf.distance_to_slack(&config).unwrap_err();
+        assert!(matches!(error, PowerFlowError::DuplicateIslandSlack { bus: 4 }));
+    }
+
+    #[test]
+    fn test_topology_export() {
+        let net: Network = serde_json::from_str(test_ieee39::IEEE_39).unwrap();
+        let n_bus = net.bus.len();
+        let n_line = net.line.as_ref().unwrap().len();
+        let n_trafo = net.trafo.as_ref().unwrap().len();
+        let pf = PFNetwork::try_from(&net).unwrap();
+
+        let dot = pf.to_dot();
+        let nodes = dot
+            .lines()
+            .filter(|x| x.contains("[label=") && !x.contains("--"));
+        assert_eq!(nodes.count(), n_bus);
+        assert_eq!(dot.matches("[label=\"line\"]").count(), n_line);
+        assert_eq!(dot.matches("[label=\"trafo\"]").count(), n_trafo);
+        assert_eq!(dot.matches(" -- ").count(), n_line + n_trafo);
+
+        let graphml = pf.to_graphml();
+        assert_eq!(graphml.matches("<node ").count(), n_bus);
+        assert_eq!(graphml.matches("<edge ").count(), n_line + n_trafo);
+    }
+
+    #[test]
+    fn test_node_mapping_sets() {
+        let switch = |bus, element, closed| Switch {
+            bus,
+            element,
+            et: Some("b".to_string()),
+            closed,
+            ..Default::default()
+        };
+        let net = Network {
+            bus: buses(7, 20.0),
+            switch: Some(vec![
+                switch(0, 1, true),
+                switch(2, 3, true),
+                switch(3, 1, true),
+                switch(4, 5, true),
+                switch(5, 6, false),
+            ]),
+            ..Default::default()
+        };
+
+        let mapping = NodeMapping::new(&net);
+        assert_eq!(mapping.sets(), vec![vec![0, 1, 2, 3], vec![4, 5], vec![6]]);
+        assert_eq!(mapping.sets().len(), mapping.len());
+    }
+}
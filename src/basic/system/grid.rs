@@ -0,0 +1,1669 @@
+use std::collections::{HashMap, HashSet};
+
+use nalgebra::DVector;
+use num_complex::Complex64;
+
+use super::{
+    model_measurements, ApparentPowerLoading, AssemblyOptions, NodeMapping, PFNetwork,
+    PowerFlowConfig, ResultsDto, RunPF, SolvedNetwork, StateEstimate,
+};
+use crate::basic::PowerFlowError;
+use crate::io::pandapower::{ControlMode, Network, Shunt, Switch};
+
+/// The maximum number of control rounds of a solve.
+const MAX_CONTROL_ROUNDS: usize = 50;
+/// The maximum number of solves confirming a reactive compensation.
+const MAX_COMPENSATION_ROUNDS: usize = 10;
+/// The voltage deviation in per unit up to which a compensation is confirmed.
+const COMPENSATION_TOLERANCE_PU: f64 = 1e-6;
+
+/// Maps the pandapower ids of elements to their position in the element table.
+#[derive(Debug, Clone, Default)]
+pub struct ElementIndex(HashMap<i64, usize>);
+
+impl ElementIndex {
+    /// Creates the index from the element ids in table order.
+    pub fn new(ids: impl IntoIterator<Item = i64>) -> Self {
+        Self(ids.into_iter().enumerate().map(|(i, id)| (id, i)).collect())
+    }
+
+    /// Returns the position of an element, or `None` if the id doesn't exist.
+    pub fn get(&self, id: i64) -> Option<usize> {
+        self.0.get(&id).copied()
+    }
+}
+
+/// The element tables whose elements can be taken in or out of service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementTable {
+    Line,
+    Trafo,
+    Load,
+    Gen,
+    SGen,
+    Shunt,
+}
+
+/// A single modification of the network data, see [`GridPatch`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum GridChange {
+    /// Sets the power demand of a load.
+    Load { id: i64, p_mw: f64, q_mvar: f64 },
+    /// Opens or closes a switch.
+    Switch { id: i64, closed: bool },
+    /// Takes an element in or out of service.
+    InService {
+        table: ElementTable,
+        id: i64,
+        in_service: bool,
+    },
+}
+
+/// A set of modifications applied at once by [`PowerGrid::apply_patch`].
+///
+/// Elements are identified by their pandapower ids; later changes override earlier ones.
+#[derive(Debug, Clone, Default)]
+pub struct GridPatch {
+    /// The changes in the order they are applied.
+    pub changes: Vec<GridChange>,
+}
+
+impl GridPatch {
+    /// Creates an empty patch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a change of the power demand of a load.
+    pub fn set_load(mut self, id: i64, p_mw: f64, q_mvar: f64) -> Self {
+        self.changes.push(GridChange::Load { id, p_mw, q_mvar });
+        self
+    }
+
+    /// Adds a change of the state of a switch.
+    pub fn set_switch(mut self, id: i64, closed: bool) -> Self {
+        self.changes.push(GridChange::Switch { id, closed });
+        self
+    }
+
+    /// Adds a change of the service state of an element.
+    pub fn set_in_service(mut self, table: ElementTable, id: i64, in_service: bool) -> Self {
+        self.changes.push(GridChange::InService {
+            table,
+            id,
+            in_service,
+        });
+        self
+    }
+}
+
+/// A power grid built from imported network data.
+///
+/// `PowerGrid` bundles the assembly, the power flow solve and the post-processing behind a
+/// single call, for users who don't need control over the individual steps.
+///
+/// Elements can be modified between solves; each solve is warm-started from the previous
+/// solution. The assembled model is cached and tagged with a generation counter, which every
+/// modification increments, so a stale model is never solved.
+///
+/// A grid owns all of its state and the solvers are created per solve, without any global
+/// or thread-local scratch space. `PowerGrid` is therefore `Send` and `Sync`, and distinct
+/// grids can be solved concurrently from different threads.
+#[derive(Debug)]
+pub struct PowerGrid {
+    net: Network,
+    config: PowerFlowConfig,
+    loads: ElementIndex,
+    switches: ElementIndex,
+    /// Whether the element indices need to be rebuilt after a modification of the network.
+    stale_indices: bool,
+    generation: u64,
+    model: Option<(ModelTag, PFNetwork)>,
+    warm_start: Option<DVector<Complex64>>,
+    /// The results of the last converged solve, with the tag of the model they were solved for.
+    solution: Option<(ModelTag, SolvedNetwork)>,
+}
+
+/// The generation of the network and the assembly options a model was assembled for.
+type ModelTag = (u64, AssemblyOptions);
+
+impl From<Network> for PowerGrid {
+    fn from(net: Network) -> Self {
+        Self::new(net)
+    }
+}
+
+impl PowerGrid {
+    /// Creates a power grid from imported network data.
+    ///
+    /// The unit transformers of the generators are expanded into explicit elements, see
+    /// [`Network::expand_unit_transformers`].
+    pub fn new(mut net: Network) -> Self {
+        net.expand_unit_transformers();
+        let mut grid = Self {
+            net,
+            config: PowerFlowConfig::default(),
+            loads: ElementIndex::default(),
+            switches: ElementIndex::default(),
+            stale_indices: true,
+            generation: 0,
+            model: None,
+            warm_start: None,
+            solution: None,
+        };
+        grid.refresh_indices();
+        grid
+    }
+
+    /// Rebuilds the element indices if the network was modified directly.
+    fn refresh_indices(&mut self) {
+        if self.stale_indices {
+            self.loads = ElementIndex::new(self.net.load.iter().flatten().map(|x| x.index));
+            self.switches = ElementIndex::new(self.net.switch.iter().flatten().map(|x| x.index));
+            self.stale_indices = false;
+        }
+    }
+
+    /// Sets the power flow options used by [`PowerGrid::solve`].
+    ///
+    /// The cached model and results are invalidated only if options that change the assembly
+    /// differ, see [`PFNetwork::from_network`].
+    pub fn with_config(mut self, config: PowerFlowConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Returns the power flow options.
+    pub fn config(&self) -> &PowerFlowConfig {
+        &self.config
+    }
+
+    /// Returns the power flow options for modification.
+    ///
+    /// The cached model and results are invalidated only if options that change the assembly
+    /// are modified, see [`PFNetwork::from_network`].
+    pub fn config_mut(&mut self) -> &mut PowerFlowConfig {
+        &mut self.config
+    }
+
+    /// Returns the tag the cached model and results must carry to be current.
+    fn tag(&self) -> ModelTag {
+        (self.generation, self.config.assembly_options())
+    }
+
+    /// Returns the underlying network data.
+    pub fn network(&self) -> &Network {
+        &self.net
+    }
+
+    /// Returns the underlying network data for modification.
+    ///
+    /// The cached model is invalidated, so the next solve reassembles the network.
+    pub fn network_mut(&mut self) -> &mut Network {
+        self.generation += 1;
+        self.stale_indices = true;
+        &mut self.net
+    }
+
+    /// Returns the generation of the network data, which increases with every modification.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Sets the power demand of a load.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The pandapower id of the load.
+    /// * `p_mw` - The active power demand in MW.
+    /// * `q_mvar` - The reactive power demand in MVar.
+    ///
+    /// # Returns
+    ///
+    /// `false` if no load has the given id.
+    pub fn set_load(&mut self, id: i64, p_mw: f64, q_mvar: f64) -> bool {
+        self.refresh_indices();
+        let Some(pos) = self.loads.get(id) else {
+            return false;
+        };
+        let load = &mut self.net.load.as_mut().unwrap()[pos];
+        load.p_mw = p_mw;
+        load.q_mvar = q_mvar;
+        load.cos_phi = None;
+        self.generation += 1;
+        true
+    }
+
+    /// Opens or closes a switch.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The pandapower id of the switch.
+    /// * `closed` - The new switch state.
+    ///
+    /// # Returns
+    ///
+    /// `false` if no switch has the given id.
+    pub fn set_switch(&mut self, id: i64, closed: bool) -> bool {
+        self.refresh_indices();
+        let Some(pos) = self.switches.get(id) else {
+            return false;
+        };
+        self.net.switch.as_mut().unwrap()[pos].closed = closed;
+        self.generation += 1;
+        true
+    }
+
+    /// Returns the pandapower ids of all switches in table order.
+    pub fn switch_ids(&self) -> Vec<i64> {
+        self.net.switch.iter().flatten().map(|x| x.index).collect()
+    }
+
+    /// Returns whether a switch is closed, or `None` if no switch has the given id.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The pandapower id of the switch.
+    pub fn switch_closed(&self, id: i64) -> Option<bool> {
+        let mut switches = self.net.switch.iter().flatten();
+        switches.find(|x| x.index == id).map(|x| x.closed)
+    }
+
+    /// Adds a switch to the network.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - The pandapower id of the bus the switch is connected to.
+    /// * `element` - The pandapower id of the switched element.
+    /// * `et` - The type of the switched element: `"b"` (bus), `"l"` (line) or `"t"` (trafo).
+    /// * `z_ohm` - The impedance of the closed switch in ohm; 0 for an ideal switch.
+    /// * `closed` - The switch state.
+    ///
+    /// # Returns
+    ///
+    /// The pandapower id of the new switch, one above the largest existing id.
+    pub fn add_switch(
+        &mut self,
+        bus: i64,
+        element: i64,
+        et: &str,
+        z_ohm: f64,
+        closed: bool,
+    ) -> i64 {
+        let switches = self.net.switch.get_or_insert_with(Vec::new);
+        let index = switches.iter().map(|x| x.index + 1).max().unwrap_or(0);
+        switches.push(Switch {
+            index,
+            bus,
+            element,
+            et: Some(et.to_string()),
+            closed,
+            z_ohm,
+            ..Default::default()
+        });
+        self.stale_indices = true;
+        self.generation += 1;
+        index
+    }
+
+    /// Applies a set of modifications with a single invalidation of the cached model.
+    ///
+    /// The patch is applied atomically: if any element doesn't exist, the network is left
+    /// unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `patch` - The modifications to apply.
+    ///
+    /// # Returns
+    ///
+    /// `false` if an element of the patch doesn't exist.
+    pub fn apply_patch(&mut self, patch: &GridPatch) -> bool {
+        self.refresh_indices();
+        let mut positions = Vec::with_capacity(patch.changes.len());
+        for change in &patch.changes {
+            let pos = match change {
+                GridChange::Load { id, .. } => self.loads.get(*id),
+                GridChange::Switch { id, .. } => self.switches.get(*id),
+                GridChange::InService { table, id, .. } => self.position(*table, *id),
+            };
+            let Some(pos) = pos else {
+                return false;
+            };
+            positions.push(pos);
+        }
+
+        for (change, pos) in patch.changes.iter().zip(positions) {
+            match *change {
+                GridChange::Load { p_mw, q_mvar, .. } => {
+                    let load = &mut self.net.load.as_mut().unwrap()[pos];
+                    load.p_mw = p_mw;
+                    load.q_mvar = q_mvar;
+                    load.cos_phi = None;
+                }
+                GridChange::Switch { closed, .. } => {
+                    self.net.switch.as_mut().unwrap()[pos].closed = closed;
+                }
+                GridChange::InService {
+                    table, in_service, ..
+                } => {
+                    let net = &mut self.net;
+                    let flag = match table {
+                        ElementTable::Line => &mut net.line.as_mut().unwrap()[pos].in_service,
+                        ElementTable::Trafo => &mut net.trafo.as_mut().unwrap()[pos].in_service,
+                        ElementTable::Load => &mut net.load.as_mut().unwrap()[pos].in_service,
+                        ElementTable::Gen => &mut net.gen.as_mut().unwrap()[pos].in_service,
+                        ElementTable::SGen => &mut net.sgen.as_mut().unwrap()[pos].in_service,
+                        ElementTable::Shunt => &mut net.shunt.as_mut().unwrap()[pos].in_service,
+                    };
+                    *flag = in_service;
+                }
+            }
+        }
+        self.generation += 1;
+        true
+    }
+
+    /// Returns the position of an element in its table, or `None` if the id doesn't exist.
+    fn position(&self, table: ElementTable, id: i64) -> Option<usize> {
+        let net = &self.net;
+        let ids: Vec<i64> = match table {
+            ElementTable::Line => net.line.iter().flatten().map(|x| x.index).collect(),
+            ElementTable::Trafo => net.trafo.iter().flatten().map(|x| x.index).collect(),
+            ElementTable::Load => net.load.iter().flatten().map(|x| x.index).collect(),
+            ElementTable::Gen => net.gen.iter().flatten().map(|x| x.index).collect(),
+            ElementTable::SGen => net.sgen.iter().flatten().map(|x| x.index).collect(),
+            ElementTable::Shunt => net.shunt.iter().flatten().map(|x| x.index).collect(),
+        };
+        ids.iter().position(|x| *x == id)
+    }
+
+    /// Returns the power mismatch function of the assembled network.
+    ///
+    /// The function maps a packed voltage state (see [`PowerGrid::pack_state`]) to the power
+    /// mismatch in per unit, so that custom solvers can drive the same model.
+    ///
+    /// # Returns
+    ///
+    /// The mismatch function, or [`PowerFlowError::NoSlackBus`] if the network has no slack.
+    pub fn mismatch_fn(
+        &self,
+    ) -> Result<impl Fn(&DVector<f64>) -> DVector<f64>, PowerFlowError> {
+        Ok(self.assemble()?.mismatch_fn())
+    }
+
+    /// Packs complex bus voltages into the state vector of the mismatch function.
+    ///
+    /// The state is `[va(pv), va(pq), vm(pq)]` in radians and per unit.
+    ///
+    /// # Returns
+    ///
+    /// The state, or [`PowerFlowError::NoSlackBus`] if the network has no slack.
+    pub fn pack_state(&self, v: &DVector<Complex64>) -> Result<DVector<f64>, PowerFlowError> {
+        Ok(self.assemble()?.state_layout().pack(v))
+    }
+
+    /// Unpacks a state vector of the mismatch function into complex bus voltages.
+    ///
+    /// # Returns
+    ///
+    /// The voltages, or [`PowerFlowError::NoSlackBus`] if the network has no slack.
+    pub fn unpack_state(&self, x: &DVector<f64>) -> Result<DVector<Complex64>, PowerFlowError> {
+        Ok(self.assemble()?.state_layout().unpack(x))
+    }
+
+    /// Estimates the bus voltages from the measurements of the network by weighted least squares.
+    ///
+    /// Uses the iteration limit and tolerance of the power flow options.
+    ///
+    /// # Returns
+    ///
+    /// The state estimate, or an error if the network has no slack, is unobservable or the
+    /// estimation didn't converge.
+    pub fn run_state_estimation(&self) -> Result<StateEstimate, PowerFlowError> {
+        let pf = self.assemble()?;
+        let measurements = model_measurements(&self.net, &pf);
+        pf.estimate_state(
+            &measurements,
+            self.config.max_iterations,
+            self.config.tolerance,
+        )
+    }
+
+    /// Assembles the network, runs the power flow and derives the results.
+    ///
+    /// The first solve starts from a flat start, later ones from the previous solution. Storing
+    /// that solution for the next solve is why the grid is borrowed mutably.
+    ///
+    /// Controlled elements are adjusted in an outer loop, which solves the power flow again
+    /// until no control changes:
+    ///
+    /// * Transformers with [`ControlMode::TapControl`] whose bus voltage lies outside the band
+    ///   are stepped by one tap within their tap range.
+    /// * Shunts with [`ControlMode::SwitchedShunt`] are stepped by one step towards the voltage
+    ///   target as long as the voltage change of their previous step predicts an improvement.
+    ///
+    /// # Returns
+    ///
+    /// The solved network, or an error if the power flow failed or the controls didn't settle
+    /// within `MAX_CONTROL_ROUNDS` rounds.
+    pub fn solve(&mut self) -> Result<SolvedNetwork, PowerFlowError> {
+        let mut solved = self.solve_once()?;
+        let mut last_shunt_steps = HashMap::new();
+        for _ in 0..MAX_CONTROL_ROUNDS {
+            let taps = self.step_taps(&solved);
+            let shunts = self.step_shunts(&solved, &mut last_shunt_steps);
+            if !taps && !shunts {
+                return Ok(solved);
+            }
+            solved = self.solve_once()?;
+        }
+        Err(PowerFlowError::ControlNotConverged {
+            rounds: MAX_CONTROL_ROUNDS,
+        })
+    }
+
+    /// Merges the buses connected by closed bus-bus switches into nodes, without assembling or
+    /// solving the network.
+    ///
+    /// The power flow keeps the buses apart and models the switches as low impedances; the
+    /// mapping shows which buses form a single electrical node.
+    pub fn build_topology(&self) -> NodeMapping {
+        NodeMapping::new(&self.net)
+    }
+
+    /// Estimates the reactive compensation needed to bring the voltage of a bus to a target.
+    ///
+    /// The voltage deviation of the solved network is divided by the sensitivity of the bus
+    /// voltage to reactive injection, see [`PFNetwork::vq_sensitivity`]. With `confirm`, the
+    /// compensation is added as a shunt for trial solves, and the estimate is corrected with the
+    /// sensitivity at each trial solution until the target is met; the shunt is removed
+    /// afterwards, also if a solve fails. The tap positions and shunt steps moved by the
+    /// controllers in the solves are restored as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - The identifier of the bus.
+    /// * `target_vm_pu` - The voltage magnitude to reach in per unit.
+    /// * `confirm` - Whether to refine the estimate by trial solves.
+    ///
+    /// # Returns
+    ///
+    /// The reactive power to inject in MVar, positive for capacitive compensation; `None` if
+    /// the bus doesn't exist or holds its voltage (slack or PV bus), or an error if a solve
+    /// failed.
+    pub fn required_compensation(
+        &mut self,
+        bus: i64,
+        target_vm_pu: f64,
+        confirm: bool,
+    ) -> Result<Option<f64>, PowerFlowError> {
+        let controls = self.control_state();
+        let result = self.compensation_rounds(bus, target_vm_pu, confirm);
+        self.restore_control_state(controls);
+        self.generation += 1;
+        result
+    }
+
+    /// Runs the solves of [`PowerGrid::required_compensation`], leaving the controlled
+    /// settings as the last solve left them.
+    fn compensation_rounds(
+        &mut self,
+        bus: i64,
+        target_vm_pu: f64,
+        confirm: bool,
+    ) -> Result<Option<f64>, PowerFlowError> {
+        let mut q_mvar = 0.0;
+        let mut solved = self.solve()?;
+        for _ in 0..MAX_COMPENSATION_ROUNDS {
+            let pf = self.assemble()?;
+            let Some(node) = pf.node_lookup().get(bus) else {
+                return Ok(None);
+            };
+            let Some(sensitivity) = pf.vq_sensitivity(&solved.v, node, &self.config) else {
+                return Ok(None);
+            };
+            let deviation = target_vm_pu - solved.v[node].norm();
+            if deviation.abs() < COMPENSATION_TOLERANCE_PU {
+                break;
+            }
+            q_mvar += deviation / sensitivity * pf.s_base;
+            if !confirm {
+                break;
+            }
+
+            // shunts absorb positive reactive power
+            self.net.shunt.get_or_insert_with(Vec::new).push(Shunt {
+                bus,
+                q_mvar: -q_mvar,
+                step: 1,
+                max_step: 1,
+                in_service: true,
+                ..Default::default()
+            });
+            self.generation += 1;
+            let trial = self.solve();
+            self.net.shunt.as_mut().unwrap().pop();
+            self.generation += 1;
+            solved = trial?;
+        }
+        Ok(Some(q_mvar))
+    }
+
+    /// Solves the power flow with a set of lines opened for this solve only.
+    ///
+    /// All lines are opened at once, so that simultaneous outages (N-k) can be studied. The
+    /// service states of the lines and the tap positions and shunt steps moved by the
+    /// controllers are restored afterwards, also if the solve fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `lines` - The pandapower ids of the lines to open.
+    ///
+    /// # Returns
+    ///
+    /// The result of the solve, or `None` without solving if no line has one of the ids.
+    pub fn run_pf_with_outages(
+        &mut self,
+        lines: &[i64],
+    ) -> Option<Result<SolvedNetwork, PowerFlowError>> {
+        let positions = lines
+            .iter()
+            .map(|id| self.position(ElementTable::Line, *id))
+            .collect::<Option<Vec<_>>>()?;
+        let table = self.net.line.as_mut().unwrap();
+        let base: Vec<bool> = positions.iter().map(|pos| table[*pos].in_service).collect();
+        for pos in &positions {
+            table[*pos].in_service = false;
+        }
+        self.generation += 1;
+
+        let controls = self.control_state();
+        let solved = self.solve();
+        self.restore_control_state(controls);
+
+        let table = self.net.line.as_mut().unwrap();
+        for (pos, in_service) in positions.into_iter().zip(base) {
+            table[pos].in_service = in_service;
+        }
+        self.generation += 1;
+        Some(solved)
+    }
+
+    /// Returns the settings the controllers adjust, the tap positions of the transformers and
+    /// the steps of the shunts.
+    fn control_state(&self) -> (Vec<Option<f64>>, Vec<i32>) {
+        let taps = self.net.trafo.iter().flatten().map(|x| x.tap_pos).collect();
+        let steps = self.net.shunt.iter().flatten().map(|x| x.step).collect();
+        (taps, steps)
+    }
+
+    /// Restores the settings saved by [`PowerGrid::control_state`].
+    fn restore_control_state(&mut self, (taps, steps): (Vec<Option<f64>>, Vec<i32>)) {
+        for (trafo, tap_pos) in self.net.trafo.iter_mut().flatten().zip(taps) {
+            trafo.tap_pos = tap_pos;
+        }
+        for (shunt, step) in self.net.shunt.iter_mut().flatten().zip(steps) {
+            shunt.step = step;
+        }
+    }
+
+    /// Steps the taps of the controlled transformers towards their voltage bands.
+    ///
+    /// # Returns
+    ///
+    /// `true` if any tap position changed.
+    fn step_taps(&mut self, solved: &SolvedNetwork) -> bool {
+        let mut changed = false;
+        for trafo in self.net.trafo.iter_mut().flatten().filter(|x| x.in_service) {
+            let Some(ControlMode::TapControl {
+                bus,
+                vm_lower_pu,
+                vm_upper_pu,
+            }) = trafo.control
+            else {
+                continue;
+            };
+            let Some(vm_pu) = solved.bus_result(bus).map(|x| x.vm_pu) else {
+                continue;
+            };
+            let step = if vm_pu < vm_lower_pu {
+                trafo.raising_tap_step()
+            } else if vm_pu > vm_upper_pu {
+                -trafo.raising_tap_step()
+            } else {
+                continue;
+            };
+            let neutral = trafo.tap_neutral.unwrap_or(0.0);
+            let tap_pos = trafo.tap_pos.unwrap_or(neutral);
+            let new_pos = (tap_pos + step)
+                .min(trafo.tap_max.unwrap_or(f64::INFINITY))
+                .max(trafo.tap_min.unwrap_or(f64::NEG_INFINITY));
+            if new_pos != tap_pos {
+                trafo.tap_pos = Some(new_pos);
+                changed = true;
+            }
+        }
+        if changed {
+            self.generation += 1;
+        }
+        changed
+    }
+
+    /// Steps the switched shunts towards their voltage targets.
+    ///
+    /// # Arguments
+    ///
+    /// * `solved` - The results of the last solve.
+    /// * `last_steps` - The step and bus voltage of each shunt before its last move, from which
+    ///   the voltage change per step is estimated.
+    ///
+    /// # Returns
+    ///
+    /// `true` if any step changed.
+    fn step_shunts(
+        &mut self,
+        solved: &SolvedNetwork,
+        last_steps: &mut HashMap<usize, (i32, f64)>,
+    ) -> bool {
+        let mut changed = false;
+        let shunts = self.net.shunt.iter_mut().flatten().enumerate();
+        for (pos, shunt) in shunts.filter(|(_, x)| x.in_service) {
+            let Some(ControlMode::SwitchedShunt { bus, vm_pu: target }) = shunt.control else {
+                continue;
+            };
+            let Some(vm_pu) = solved.bus_result(bus).map(|x| x.vm_pu) else {
+                continue;
+            };
+            let deviation = vm_pu - target;
+            let step = if deviation < 0.0 {
+                shunt.raising_step()
+            } else {
+                -shunt.raising_step()
+            };
+            let new_step = shunt.step + step;
+            if new_step < 0 || new_step > shunt.max_step {
+                continue;
+            }
+            // settle once another step is predicted to move the voltage further off target
+            if let Some(&(last_step, last_vm_pu)) = last_steps.get(&pos) {
+                if last_step != shunt.step {
+                    let per_step = (vm_pu - last_vm_pu) / (shunt.step - last_step) as f64;
+                    if (deviation + per_step * step as f64).abs() >= deviation.abs() {
+                        continue;
+                    }
+                }
+            }
+            last_steps.insert(pos, (shunt.step, vm_pu));
+            shunt.step = new_step;
+            changed = true;
+        }
+        if changed {
+            self.generation += 1;
+        }
+        changed
+    }
+
+    /// Assembles the power flow model of the network according to the options.
+    fn assemble(&self) -> Result<PFNetwork, PowerFlowError> {
+        PFNetwork::from_network(&self.net, &self.config)
+    }
+
+    /// Runs a single power flow, reusing the cached model if the network is unchanged.
+    fn solve_once(&mut self) -> Result<SolvedNetwork, PowerFlowError> {
+        let pf = match self.model.take() {
+            Some((tag, pf)) if tag == self.tag() => pf,
+            _ => self.assemble()?,
+        };
+        let v_init = self.initial_voltage(&pf);
+        let solved = if self.config.enforce_gen_q_limits {
+            pf.solve_gen_q_limited(v_init, &self.config)
+        } else {
+            let result = pf.run_pf_traced(v_init, &self.config);
+            result.map(|(v, iterations, history)| {
+                SolvedNetwork::new(&pf, v, iterations).with_mismatch_history(history)
+            })
+        };
+        let solved = solved.map(|x| x.with_island_slacks(&pf, &self.config.island_slacks));
+        if let Ok(solved) = &solved {
+            self.warm_start = Some(solved.v.clone());
+            self.solution = Some((self.tag(), solved.clone()));
+        }
+        self.model = Some((self.tag(), pf));
+        solved
+    }
+
+    /// Returns whether the results of the last solve match the current network.
+    ///
+    /// The results become stale with every modification of the network after the solve.
+    pub fn results_valid(&self) -> bool {
+        matches!(self.solution, Some((tag, _)) if tag == self.tag())
+    }
+
+    /// Returns the results of the last converged solve, even if they are stale.
+    pub fn results(&self) -> Option<&SolvedNetwork> {
+        self.solution.as_ref().map(|(_, x)| x)
+    }
+
+    /// Returns the results of the last converged solve, if they match the current network.
+    ///
+    /// # Returns
+    ///
+    /// The results, or [`PowerFlowError::StaleResults`] if the grid wasn't solved since its
+    /// last modification.
+    pub fn valid_results(&self) -> Result<&SolvedNetwork, PowerFlowError> {
+        match &self.solution {
+            Some((tag, x)) if *tag == self.tag() => Ok(x),
+            _ => Err(PowerFlowError::StaleResults),
+        }
+    }
+
+    /// Computes the loading of the transformers from the results of the last solve.
+    ///
+    /// The reference of the loading is given by the `trafo_loading` option.
+    ///
+    /// # Returns
+    ///
+    /// The loading in percent, in the order of `res_trafo`, or
+    /// [`PowerFlowError::StaleResults`] if the grid wasn't solved since its last modification.
+    pub fn trafo_loading(&self) -> Result<Vec<f64>, PowerFlowError> {
+        let solved = self.valid_results()?;
+        Ok(solved.trafo_loading(&self.net, self.config.trafo_loading))
+    }
+
+    /// Computes the loading of the lines against their apparent power limit from the results
+    /// of the last solve, see [`SolvedNetwork::line_apparent_loading`].
+    ///
+    /// # Returns
+    ///
+    /// The loading in the order of `res_line`, or [`PowerFlowError::StaleResults`] if the grid
+    /// wasn't solved since its last modification.
+    pub fn line_apparent_loading(
+        &self,
+    ) -> Result<Vec<Option<ApparentPowerLoading>>, PowerFlowError> {
+        let solved = self.valid_results()?;
+        Ok(solved.line_apparent_loading(&self.net))
+    }
+
+    /// Iterates over the solved state of all in-service buses.
+    ///
+    /// The results are those of the last converged solve; the iterator is empty if the grid
+    /// hasn't been solved yet.
+    ///
+    /// # Returns
+    ///
+    /// An iterator of `(bus_id, vm_pu, va_degree, p_mw, q_mvar)` in bus order, keyed by the
+    /// pandapower id of the bus.
+    pub fn bus_results(&self) -> impl Iterator<Item = (i64, f64, f64, f64, f64)> + '_ {
+        let in_service: HashSet<i64> = self
+            .net
+            .bus
+            .iter()
+            .filter(|x| x.in_service)
+            .map(|x| x.index)
+            .collect();
+        self.solution
+            .iter()
+            .flat_map(|(_, x)| x.res_bus.iter())
+            .filter(move |x| in_service.contains(&x.bus))
+            .map(|x| (x.bus, x.vm_pu, x.va_degree, x.p_mw, x.q_mvar))
+    }
+
+    /// Solves the power flow and flattens the results into a serializable summary.
+    ///
+    /// If the power flow doesn't converge, the summary holds the results of the last iterate
+    /// and is marked as not converged.
+    ///
+    /// # Returns
+    ///
+    /// The summary, or the error if the power flow failed without an iterate to report.
+    pub fn to_results_dto(&mut self) -> Result<ResultsDto, PowerFlowError> {
+        match self.solve() {
+            Ok(solved) => Ok(ResultsDto::new(&self.net, &solved, true)),
+            Err(PowerFlowError::NotConverged { iterations, v }) => {
+                let pf = &self.model.as_ref().unwrap().1;
+                let v = v.rows(0, pf.buses.len()).into_owned();
+                let solved = SolvedNetwork::new(pf, v, iterations);
+                Ok(ResultsDto::new(&self.net, &solved, false))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the previous solution with the voltage setpoints applied, or a flat start.
+    fn initial_voltage(&self, pf: &PFNetwork) -> DVector<Complex64> {
+        let Some(mut v) = self.warm_start.clone().filter(|v| v.len() == pf.buses.len()) else {
+            return pf.create_v_init();
+        };
+        for x in &pf.pv_nodes {
+            let node = x.bus as usize;
+            v[node] = Complex64::from_polar(x.v, v[node].arg());
+        }
+        v[pf.ext.bus as usize] = Complex64::from_polar(pf.ext.v, pf.ext.phase);
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::system::{test_ieee39, ElementRef, ExtGridMode, ToleranceUnit};
+    use crate::io::pandapower::*;
+    use crate::io::test_networks::{line, load, substation, trafo};
+    use std::env;
+
+    #[test]
+    fn test_solve_ieee118() {
+        let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let net = load_csv_zip(format!("{}/cases/IEEE118/data.zip", dir)).unwrap();
+        let n_bus = net.bus.len();
+        let n_line = net.line.as_ref().unwrap().len();
+        let n_trafo = net.trafo.as_ref().unwrap().len();
+
+        let solved = PowerGrid::new(net).solve().unwrap();
+        assert!((1..10).contains(&solved.iterations), "{}", solved.iterations);
+        assert_eq!(solved.res_bus.len(), n_bus);
+        assert_eq!(solved.res_line.len(), n_line);
+        assert_eq!(solved.res_trafo.len(), n_trafo);
+        assert!(solved
+            .res_bus
+            .iter()
+            .all(|b| b.vm_pu > 0.9 && b.vm_pu < 1.1));
+
+        // the net injection of all buses is consumed by the branch losses
+        let injected: f64 = solved.res_bus.iter().map(|b| -b.p_mw).sum();
+        let losses: f64 = solved
+            .res_line
+            .iter()
+            .chain(solved.res_trafo.iter())
+            .map(|x| x.p_from_mw + x.p_to_mw)
+            .sum();
+        assert!((injected - losses).abs() < 1e-3, "{} {}", injected, losses);
+    }
+
+    #[test]
+    fn test_run_pf_with_outages() {
+        let mut net: Network = serde_json::from_str(test_ieee39::IEEE_39).unwrap();
+        for (i, line) in net.line.iter_mut().flatten().enumerate() {
+            line.index = i as i64;
+        }
+        let mut grid = PowerGrid::new(net);
+        let flow = |solved: &SolvedNetwork, line: usize| {
+            let line = solved.res_line.iter().find(|x| x.element == ElementRef::Line(line));
+            line.map(|x| x.p_from_mw)
+        };
+        let base = grid.solve().unwrap();
+        assert!(grid.run_pf_with_outages(&[2, 99]).is_none());
+
+        // opening lines 2-3 and 3-18 leaves line 3-4 to carry the whole load of bus 3
+        let outage = grid.run_pf_with_outages(&[2, 5]).unwrap().unwrap();
+        assert_eq!(outage.res_line.len(), base.res_line.len() - 2);
+        assert!(flow(&outage, 2).is_none() && flow(&outage, 5).is_none());
+        assert!((flow(&outage, 4).unwrap() + 322.0).abs() < 1e-3);
+        assert!((flow(&outage, 4).unwrap() - flow(&base, 4).unwrap()).abs() > 100.0);
+
+        assert!(grid.network().line.iter().flatten().all(|x| x.in_service));
+        assert!(!grid.results_valid());
+        let restored = grid.solve().unwrap();
+        for (a, b) in restored.res_line.iter().zip(&base.res_line) {
+            assert!((a.p_from_mw - b.p_from_mw).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_outages_keep_controls() {
+        // bus 2 is fed through two parallel lines and the tap controller holds its voltage
+        let mut net = substation();
+        net.bus.push(Bus {
+            index: 2,
+            ..net.bus[1].clone()
+        });
+        net.line = Some(vec![line(1, 2), Line { index: 1, ..line(1, 2) }]);
+        net.load = Some(vec![load(2, 10.0, 5.0)]);
+        net.trafo.as_mut().unwrap()[0].control = Some(ControlMode::TapControl {
+            bus: 2,
+            vm_lower_pu: 0.99,
+            vm_upper_pu: 1.01,
+        });
+        let mut grid = PowerGrid::new(net);
+        grid.solve().unwrap();
+        let tap_pos = grid.network().trafo.as_ref().unwrap()[0].tap_pos;
+
+        // the controller compensates the higher voltage drop of the outage for this solve only
+        let outage = grid.run_pf_with_outages(&[1]).unwrap().unwrap();
+        assert!((0.99..=1.01).contains(&outage.res_bus[2].vm_pu));
+        assert_eq!(grid.network().trafo.as_ref().unwrap()[0].tap_pos, tap_pos);
+    }
+
+    /// Builds a 20 kV line feeding two loads, with ids 3 and 7, from an external grid.
+    fn feeder(s_sc_max_mva: Option<f64>) -> Network {
+        Network {
+            bus: (0..2)
+                .map(|index| Bus {
+                    index,
+                    in_service: true,
+                    vn_kv: 20.0,
+                    ..Default::default()
+                })
+                .collect(),
+            ext_grid: Some(vec![ExtGrid {
+                bus: 0,
+                in_service: true,
+                vm_pu: 1.0,
+                s_sc_max_mva,
+                rx_max: Some(0.1),
+                ..Default::default()
+            }]),
+            line: Some(vec![Line {
+                from_bus: 0,
+                to_bus: 1,
+                in_service: true,
+                length_km: 2.0,
+                parallel: 1,
+                r_ohm_per_km: 0.25,
+                x_ohm_per_km: 0.5,
+                ..Default::default()
+            }]),
+            load: Some(
+                [3, 7]
+                    .into_iter()
+                    .map(|index| Load {
+                        index,
+                        bus: 1,
+                        in_service: true,
+                        p_mw: 2.5,
+                        q_mvar: 1.0,
+                        scaling: 1.0,
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_required_compensation() {
+        let mut grid = PowerGrid::new(feeder(None));
+        let vm = grid.solve().unwrap().bus_result(1).unwrap().vm_pu;
+        let target = vm + 0.01;
+        let estimate = grid.required_compensation(1, target, false).unwrap().unwrap();
+        let confirmed = grid.required_compensation(1, target, true).unwrap().unwrap();
+        assert!(estimate > 0.0);
+        assert!((confirmed - estimate).abs() < 0.05 * estimate, "{} {}", confirmed, estimate);
+        // the trial shunts are removed again
+        assert!(grid.network().shunt.iter().flatten().next().is_none());
+
+        let mut net = feeder(None);
+        net.shunt = Some(vec![Shunt {
+            bus: 1,
+            q_mvar: -confirmed,
+            step: 1,
+            in_service: true,
+            ..Default::default()
+        }]);
+        let compensated = PowerGrid::new(net).solve().unwrap();
+        let vm = compensated.bus_result(1).unwrap().vm_pu;
+        assert!((vm - target).abs() < 1e-5, "{} {}", vm, target);
+
+        assert_eq!(grid.required_compensation(0, 1.0, false).unwrap(), None);
+        assert_eq!(grid.required_compensation(9, 1.0, false).unwrap(), None);
+    }
+
+    #[test]
+    fn test_compensation_keeps_controls() {
+        let mut net = substation();
+        net.bus.push(Bus {
+            index: 2,
+            ..net.bus[1].clone()
+        });
+        net.line = Some(vec![line(1, 2)]);
+        net.load = Some(vec![load(2, 10.0, 5.0)]);
+        net.trafo.as_mut().unwrap()[0].control = Some(ControlMode::TapControl {
+            bus: 2,
+            vm_lower_pu: 0.99,
+            vm_upper_pu: 1.01,
+        });
+        net.shunt = Some(vec![Shunt {
+            bus: 2,
+            q_mvar: -1.0,
+            vn_kv: 20.0,
+            max_step: 4,
+            in_service: true,
+            control: Some(ControlMode::SwitchedShunt { bus: 2, vm_pu: 1.05 }),
+            ..Default::default()
+        }]);
+        let mut grid = PowerGrid::new(net);
+        let controls = grid.control_state();
+
+        // the trial solves move the tap and the shunt step for this estimate only
+        assert!(grid.required_compensation(2, 1.03, true).unwrap().is_some());
+        assert_eq!(grid.control_state(), controls);
+        let solved = grid.solve().unwrap();
+        assert_ne!(grid.control_state(), controls);
+        assert!((0.99..=1.01).contains(&solved.res_bus[2].vm_pu));
+    }
+
+    #[test]
+    fn test_concurrent_solves() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<PowerGrid>();
+
+        let results: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (1..=8)
+                .map(|k| {
+                    scope.spawn(move || {
+                        let mut grid = PowerGrid::new(feeder(None));
+                        grid.set_load(3, 0.5 * k as f64, 0.0);
+                        grid.solve().unwrap().res_bus[1].vm_pu
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|x| x.join().unwrap()).collect()
+        });
+        for (k, vm_pu) in results.iter().enumerate() {
+            let mut grid = PowerGrid::new(feeder(None));
+            grid.set_load(3, 0.5 * (k + 1) as f64, 0.0);
+            assert_eq!(*vm_pu, grid.solve().unwrap().res_bus[1].vm_pu);
+        }
+        // a heavier load gives a lower voltage
+        assert!(results.windows(2).all(|x| x[1] < x[0]));
+    }
+
+    #[test]
+    fn test_ext_grid_impedance() {
+        let feeder = |s_sc_max_mva| {
+            let net = Network {
+                bus: (0..2)
+                    .map(|index| Bus {
+                        index,
+                        in_service: true,
+                        vn_kv: 20.0,
+                        ..Default::default()
+                    })
+                    .collect(),
+                ext_grid: Some(vec![ExtGrid {
+                    bus: 0,
+                    in_service: true,
+                    vm_pu: 1.0,
+                    s_sc_max_mva: Some(s_sc_max_mva),
+                    rx_max: Some(0.1),
+                    ..Default::default()
+                }]),
+                line: Some(vec![Line {
+                    from_bus: 0,
+                    to_bus: 1,
+                    in_service: true,
+                    length_km: 2.0,
+                    parallel: 1,
+                    r_ohm_per_km: 0.25,
+                    x_ohm_per_km: 0.5,
+                    ..Default::default()
+                }]),
+                load: Some(vec![Load {
+                    bus: 1,
+                    in_service: true,
+                    p_mw: 5.0,
+                    q_mvar: 2.0,
+                    scaling: 1.0,
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            };
+            let config = PowerFlowConfig {
+                ext_grid_impedance: true,
+                ..Default::default()
+            };
+            PowerGrid::new(net).with_config(config).solve().unwrap()
+        };
+        let weak = feeder(50.0);
+        let stiff = feeder(5000.0);
+        assert_eq!(weak.res_bus.len(), 2);
+        assert!(weak.res_bus[0].vm_pu < stiff.res_bus[0].vm_pu);
+        assert!(weak.res_bus[1].vm_pu < stiff.res_bus[1].vm_pu);
+        assert!(stiff.res_bus[0].vm_pu < 1.0);
+        // the ext grid bus supplies the load and the line losses
+        assert!(-weak.res_bus[0].p_mw > 5.0);
+    }
+
+    #[test]
+    fn test_ext_grid_current_mode() {
+        let solve = |mode| {
+            let mut net = feeder(Some(50.0));
+            net.ext_grid.as_mut().unwrap()[0].mode = mode;
+            PowerGrid::new(net).solve().unwrap()
+        };
+        let voltage = solve(ExtGridMode::Voltage);
+        let current = solve(ExtGridMode::Current);
+        assert!((voltage.res_bus[0].vm_pu - 1.0).abs() < 1e-12);
+        // the loading pulls down the voltage at the ext grid bus
+        assert!(current.res_bus[0].vm_pu < 0.999);
+        assert!(current.res_bus[1].vm_pu < voltage.res_bus[1].vm_pu);
+
+        // an ideal external grid has no source impedance to inject its current through
+        let mut net = feeder(None);
+        net.ext_grid.as_mut().unwrap()[0].mode = ExtGridMode::Current;
+        assert!(matches!(
+            PowerGrid::new(net).solve(),
+            Err(PowerFlowError::MissingSourceImpedance)
+        ));
+    }
+
+    #[test]
+    fn test_tolerance_in_mva() {
+        let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let net = load_csv_zip(format!("{}/cases/IEEE118/data.zip", dir)).unwrap();
+        let s_base = net.sn_mva;
+        let mut grid = PowerGrid::new(net).with_config(PowerFlowConfig {
+            tolerance: 0.1,
+            tolerance_unit: ToleranceUnit::Mva,
+            ..Default::default()
+        });
+        let coarse = grid.solve().unwrap();
+        let mismatch = grid.mismatch_fn().unwrap()(&grid.pack_state(&coarse.v).unwrap());
+        assert!(mismatch.amax() * s_base < 0.1);
+
+        let mut grid = PowerGrid::new(grid.net).with_config(PowerFlowConfig {
+            tolerance: 1e-10,
+            ..Default::default()
+        });
+        assert!(grid.solve().unwrap().iterations > coarse.iterations);
+    }
+
+    #[test]
+    fn test_mismatch_fn() {
+        let mut grid = PowerGrid::new(feeder(None));
+        let solved = grid.solve().unwrap();
+        let mismatch = grid.mismatch_fn().unwrap();
+
+        let x = grid.pack_state(&solved.v).unwrap();
+        assert!(mismatch(&x).amax() < 1e-6);
+        assert!((grid.unpack_state(&x).unwrap() - &solved.v).norm() < 1e-12);
+
+        let flat = DVector::from_element(2, Complex64::new(1.0, 0.0));
+        assert!(mismatch(&grid.pack_state(&flat).unwrap()).amax() > 1e-2);
+
+        grid.network_mut().ext_grid = None;
+        assert!(matches!(grid.mismatch_fn(), Err(PowerFlowError::NoSlackBus)));
+        assert!(matches!(grid.pack_state(&flat), Err(PowerFlowError::NoSlackBus)));
+    }
+
+    #[test]
+    fn test_ext_grid_q_limit() {
+        let solve = |max_q_mvar: Option<f64>| {
+            let mut net = feeder(None);
+            net.bus.push(Bus {
+                index: 2,
+                in_service: true,
+                vn_kv: 20.0,
+                ..Default::default()
+            });
+            net.line.as_mut().unwrap().push(Line {
+                from_bus: 1,
+                to_bus: 2,
+                in_service: true,
+                length_km: 2.0,
+                parallel: 1,
+                r_ohm_per_km: 0.25,
+                x_ohm_per_km: 0.5,
+                ..Default::default()
+            });
+            net.gen = Some(vec![Gen {
+                bus: 2,
+                in_service: true,
+                p_mw: 1.0,
+                vm_pu: 0.98,
+                scaling: 1.0,
+                ..Default::default()
+            }]);
+            net.ext_grid.as_mut().unwrap()[0].max_q_mvar = max_q_mvar;
+            let config = PowerFlowConfig {
+                enforce_q_limits: true,
+                ..Default::default()
+            };
+            PowerGrid::new(net).with_config(config).solve().unwrap()
+        };
+        let free = solve(None);
+        let limited = solve(Some(0.5));
+        // the ext grid supplies more than its limit when unconstrained
+        assert!(-free.res_bus[0].q_mvar > 0.5);
+        assert!((-limited.res_bus[0].q_mvar - 0.5).abs() < 1e-4);
+        // it can't hold its voltage, and the load bus sags
+        assert!(limited.res_bus[0].vm_pu < 1.0 - 1e-3);
+        assert!(limited.res_bus[1].vm_pu < free.res_bus[1].vm_pu);
+        assert!(limited.res_bus[0].va_degree.abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_apply_patch() {
+        let build = |closed, loads: [(f64, f64); 3], load_7_in_service| {
+            let mut net = feeder(None);
+            net.load.as_mut().unwrap().push(Load {
+                index: 9,
+                bus: 1,
+                in_service: true,
+                scaling: 1.0,
+                ..Default::default()
+            });
+            for (load, (p_mw, q_mvar)) in net.load.as_mut().unwrap().iter_mut().zip(loads) {
+                load.p_mw = p_mw;
+                load.q_mvar = q_mvar;
+            }
+            net.load.as_mut().unwrap()[1].in_service = load_7_in_service;
+            net.switch = Some(vec![Switch {
+                index: 4,
+                bus: 0,
+                element: 1,
+                et: Some("b".to_string()),
+                closed,
+                ..Default::default()
+            }]);
+            net
+        };
+        let initial = [(2.5, 1.0); 3];
+        let target = [(1.0, 0.2), (4.0, 2.0), (3.0, 0.5)];
+
+        let mut grid = PowerGrid::new(build(true, initial, true));
+        grid.solve().unwrap();
+        let patch = GridPatch::new()
+            .set_load(3, 1.0, 0.2)
+            .set_load(7, 4.0, 2.0)
+            .set_load(9, 3.0, 0.5)
+            .set_switch(4, false)
+            .set_in_service(ElementTable::Load, 7, false);
+        let generation = grid.generation();
+        assert!(grid.apply_patch(&patch));
+        assert_eq!(grid.generation(), generation + 1);
+        let patched = grid.solve().unwrap();
+
+        let rebuilt = PowerGrid::new(build(false, target, false)).solve().unwrap();
+        for (a, b) in patched.res_bus.iter().zip(rebuilt.res_bus.iter()) {
+            assert!((a.vm_pu - b.vm_pu).abs() < 1e-6);
+            assert!((a.p_mw - b.p_mw).abs() < 1e-6);
+        }
+        // the out-of-service load doesn't contribute
+        assert!((patched.res_bus[1].p_mw - 4.0).abs() < 1e-3);
+
+        // a patch with an unknown element is rejected as a whole
+        let invalid = GridPatch::new().set_load(3, 9.0, 0.0).set_switch(5, true);
+        assert!(!grid.apply_patch(&invalid));
+        assert_eq!(grid.network().load.as_ref().unwrap()[0].p_mw, 1.0);
+    }
+
+    #[test]
+    fn test_switch_change_invalidates_model() {
+        let mut net = feeder(None);
+        net.switch = Some(vec![Switch {
+            index: 4,
+            bus: 0,
+            element: 1,
+            et: Some("b".to_string()),
+            closed: false,
+            ..Default::default()
+        }]);
+        let mut grid = PowerGrid::new(net);
+        let open = grid.solve().unwrap();
+        assert!(open.res_bus[1].vm_pu < 0.99);
+
+        // closing the switch bypasses the line and merges both buses into the slack node
+        let generation = grid.generation();
+        assert!(grid.set_switch(4, true));
+        assert!(grid.generation() > generation);
+        let closed = grid.solve().unwrap();
+        assert_eq!(closed.res_bus.len(), 1);
+        assert!((closed.res_bus[0].vm_pu - 1.0).abs() < 1e-9);
+
+        // direct modifications invalidate the model as well
+        grid.network_mut().switch.as_mut().unwrap()[0].closed = false;
+        let reopened = grid.solve().unwrap();
+        assert!((reopened.res_bus[1].vm_pu - open.res_bus[1].vm_pu).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_build_topology() {
+        let mut net = feeder(None);
+        net.bus.extend([10, 11].map(|index| Bus {
+            index,
+            in_service: true,
+            vn_kv: 20.0,
+            ..Default::default()
+        }));
+        let switch = |bus, element, et: &str, closed| Switch {
+            bus,
+            element,
+            et: Some(et.to_string()),
+            closed,
+            ..Default::default()
+        };
+        net.switch = Some(vec![
+            switch(10, 1, "b", true),
+            switch(10, 11, "b", false),
+            switch(11, 0, "l", true),
+        ]);
+        let grid = PowerGrid::new(net);
+
+        let mapping = grid.build_topology();
+        assert_eq!(mapping.len(), 3);
+        assert_eq!(mapping.node(0), Some(0));
+        assert_eq!(mapping.node(1), mapping.node(10));
+        assert_eq!(mapping.buses(1).collect::<Vec<_>>(), [1, 10]);
+        assert_eq!(mapping.node(11), Some(2));
+        assert_eq!(mapping.node(7), None);
+        assert!(grid.results().is_none());
+    }
+
+    #[test]
+    fn test_add_switch() {
+        let mut grid = PowerGrid::new(feeder(None));
+        grid.network_mut().bus.push(Bus {
+            index: 10,
+            in_service: true,
+            vn_kv: 20.0,
+            ..Default::default()
+        });
+        assert_eq!(grid.build_topology().len(), 3);
+
+        // a bus coupler merges the buses while it is closed
+        let generation = grid.generation();
+        let id = grid.add_switch(1, 10, "b", 0.0, true);
+        assert_eq!(id, 0);
+        assert!(grid.generation() > generation);
+        let mapping = grid.build_topology();
+        assert_eq!(mapping.len(), 2);
+        assert_eq!(mapping.node(1), mapping.node(10));
+
+        assert!(grid.set_switch(id, false));
+        let mapping = grid.build_topology();
+        assert_eq!(mapping.len(), 3);
+        assert_ne!(mapping.node(1), mapping.node(10));
+        assert_eq!(grid.add_switch(0, 1, "b", 0.1, false), 1);
+    }
+
+    #[test]
+    fn test_no_slack_bus() {
+        let mut net = feeder(None);
+        net.ext_grid = None;
+        assert!(matches!(PFNetwork::try_from(&net), Err(PowerFlowError::NoSlackBus)));
+        let mut grid = PowerGrid::new(net);
+        assert!(matches!(grid.solve(), Err(PowerFlowError::NoSlackBus)));
+
+        // an out-of-service external grid is no slack either
+        grid.network_mut().ext_grid = feeder(None).ext_grid;
+        grid.network_mut().ext_grid.as_mut().unwrap()[0].in_service = false;
+        assert!(matches!(grid.solve(), Err(PowerFlowError::NoSlackBus)));
+
+        // a slack generator takes over the external grid
+        grid.network_mut().ext_grid = None;
+        grid.network_mut().gen = Some(vec![Gen {
+            bus: 0,
+            in_service: true,
+            slack: true,
+            vm_pu: 1.02,
+            scaling: 1.0,
+            ..Default::default()
+        }]);
+        let solved = grid.solve().unwrap();
+        assert!((solved.res_bus[0].vm_pu - 1.02).abs() < 1e-9);
+        assert!((solved.res_slack.re - 5.0).abs() < 0.1, "{}", solved.res_slack);
+    }
+
+    #[test]
+    fn test_slack_bus_out_of_service() {
+        let mut net = feeder(None);
+        net.bus[0].in_service = false;
+        assert!(!net.has_slack());
+        let mut grid = PowerGrid::new(net);
+        assert!(matches!(grid.solve(), Err(PowerFlowError::NoSlackBus)));
+
+        // a second external grid at a bus in service takes over
+        let ext_grid = ExtGrid {
+            bus: 1,
+            in_service: true,
+            vm_pu: 1.01,
+            ..Default::default()
+        };
+        grid.network_mut().ext_grid.as_mut().unwrap().push(ext_grid);
+        assert_eq!(grid.network().slack_ext_grid().unwrap().bus, 1);
+        let solved = grid.solve().unwrap();
+        assert!((solved.bus_result(1).unwrap().vm_pu - 1.01).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_switch_ids() {
+        let mut grid = PowerGrid::new(feeder(None));
+        grid.network_mut().bus.extend([10, 11].map(|index| Bus {
+            index,
+            in_service: true,
+            vn_kv: 20.0,
+            ..Default::default()
+        }));
+        let coupler = grid.add_switch(1, 10, "b", 0.0, true);
+        let tie = grid.add_switch(10, 11, "b", 0.0, false);
+        grid.network_mut().switch.as_mut().unwrap()[1].name = Some("tie".to_string());
+        assert_eq!(grid.switch_ids(), [coupler, tie]);
+        assert_eq!(grid.switch_closed(tie), Some(false));
+        assert_eq!(grid.switch_closed(99), None);
+        assert_eq!(grid.build_topology().len(), 3);
+
+        // close the switch found by its name
+        let mut switches = grid.network().switch.iter().flatten();
+        let id = switches.find(|x| x.name.as_deref() == Some("tie")).unwrap().index;
+        let closed = grid.switch_closed(id).unwrap();
+        assert!(grid.set_switch(id, !closed));
+        assert_eq!(grid.switch_closed(id), Some(true));
+        let mapping = grid.build_topology();
+        assert_eq!(mapping.len(), 2);
+        assert_eq!(mapping.node(11), mapping.node(1));
+    }
+
+    #[test]
+    fn test_results_dto() {
+        let mut net = feeder(None);
+        net.line.as_mut().unwrap()[0].index = 5;
+        let n_bus = net.bus.len();
+        let mut grid = PowerGrid::new(net);
+        let dto = grid.to_results_dto().unwrap();
+        assert!(dto.converged);
+
+        let json = serde_json::to_value(&dto).unwrap();
+        assert_eq!(json["buses"].as_array().unwrap().len(), n_bus);
+        assert_eq!(json["lines"][0]["line"], 5);
+        assert_eq!(json["iterations"], dto.iterations);
+
+        // the last iterate is reported if the iteration limit is hit, other failures are errors
+        grid.config_mut().max_iterations = 1;
+        grid.set_load(3, 10.0, 4.0);
+        let dto = grid.to_results_dto().unwrap();
+        assert!(!dto.converged);
+        let config = PowerFlowConfig {
+            stall_patience: Some(3),
+            ..Default::default()
+        };
+        let mut grid = PowerGrid::new(feeder(None)).with_config(config);
+        grid.set_load(3, 5000.0, 2000.0);
+        assert!(matches!(grid.to_results_dto(), Err(PowerFlowError::Stalled { .. })));
+    }
+
+    #[test]
+    fn test_set_load() {
+        let mut grid = PowerGrid::new(feeder(None));
+        let before = grid.solve().unwrap();
+        assert!(grid.set_load(7, 5.0, 2.0));
+        assert!(!grid.set_load(1, 5.0, 2.0));
+        let after = grid.solve().unwrap();
+
+        // the warm start is already close to the new operating point
+        assert!(after.iterations <= before.iterations);
+        let p_before = before.res_line[0].p_from_mw;
+        let p_after = after.res_line[0].p_from_mw;
+        assert!(p_after - p_before > 2.5, "{} {}", p_before, p_after);
+        assert!(after.res_bus[1].vm_pu < before.res_bus[1].vm_pu);
+    }
+
+    #[test]
+    fn test_bus_results() {
+        let mut net = feeder(None);
+        net.bus.push(Bus {
+            index: 2,
+            in_service: false,
+            vn_kv: 20.0,
+            ..Default::default()
+        });
+        net.line.as_mut().unwrap().push(Line {
+            from_bus: 1,
+            to_bus: 2,
+            in_service: true,
+            length_km: 1.0,
+            parallel: 1,
+            r_ohm_per_km: 0.25,
+            x_ohm_per_km: 0.5,
+            ..Default::default()
+        });
+
+        let mut grid = PowerGrid::new(net);
+        assert_eq!(grid.bus_results().count(), 0);
+        let solved = grid.solve().unwrap();
+        let results: Vec<_> = grid.bus_results().collect();
+        assert_eq!(results.len(), 2);
+        for (bus, vm_pu, va_degree, p_mw, q_mvar) in results {
+            let x = solved.bus_result(bus).unwrap();
+            assert_eq!((vm_pu, va_degree), (x.vm_pu, x.va_degree));
+            assert_eq!((p_mw, q_mvar), (x.p_mw, x.q_mvar));
+        }
+    }
+
+    #[test]
+    fn test_tap_control() {
+        let net = Network {
+            load: Some(vec![load(1, 30.0, 15.0)]),
+            ..substation()
+        };
+
+        let mut grid = PowerGrid::new(net);
+        let sagging = grid.solve().unwrap().res_bus[1].vm_pu;
+        assert!(sagging < 0.97, "{}", sagging);
+
+        grid.network_mut().trafo.as_mut().unwrap()[0].control = Some(ControlMode::TapControl {
+            bus: 1,
+            vm_lower_pu: 0.99,
+            vm_upper_pu: 1.01,
+        });
+        let vm_pu = grid.solve().unwrap().res_bus[1].vm_pu;
+        assert!((0.99..=1.01).contains(&vm_pu), "{}", vm_pu);
+        let tap_pos = grid.network().trafo.as_ref().unwrap()[0].tap_pos.unwrap();
+        assert!(tap_pos < 0.0);
+
+        // a band narrower than one tap step makes the tap hunt
+        let control = |vm_lower_pu, vm_upper_pu| {
+            Some(ControlMode::TapControl {
+                bus: 1,
+                vm_lower_pu,
+                vm_upper_pu,
+            })
+        };
+        grid.network_mut().trafo.as_mut().unwrap()[0].control = control(0.9995, 1.0005);
+        assert!(matches!(
+            grid.solve(),
+            Err(PowerFlowError::ControlNotConverged { .. })
+        ));
+
+        // without a tap step there is nothing to control
+        let trafo = &mut grid.network_mut().trafo.as_mut().unwrap()[0];
+        trafo.control = control(0.99, 1.01);
+        trafo.tap_step_percent = None;
+        trafo.tap_pos = Some(0.0);
+        assert_eq!(trafo.raising_tap_step(), 0.0);
+        assert!(grid.solve().unwrap().res_bus[1].vm_pu < 0.97);
+        assert_eq!(grid.network().trafo.as_ref().unwrap()[0].tap_pos, Some(0.0));
+    }
+
+    #[test]
+    fn test_trafo_magnetizing_toggle() {
+        let net = Network {
+            load: Some(vec![load(1, 20.0, 5.0)]),
+            trafo: Some(vec![Transformer {
+                pfe_kw: 30.0,
+                i0_percent: 2.0,
+                ..trafo(0, 1)
+            }]),
+            ..substation()
+        };
+        let consumption = |x: &SolvedNetwork| {
+            let trafo = &x.res_trafo[0];
+            (trafo.p_from_mw + trafo.p_to_mw, trafo.q_from_mvar + trafo.q_to_mvar)
+        };
+
+        let mut grid = PowerGrid::new(net);
+        let (p_with, q_with) = consumption(&grid.solve().unwrap());
+        // only options changing the assembly invalidate the results
+        let generation = grid.generation();
+        grid.config_mut().max_iterations = 20;
+        assert!(grid.results_valid());
+        grid.config_mut().include_trafo_magnetizing = false;
+        assert!(!grid.results_valid());
+        assert_eq!(grid.generation(), generation);
+        let without = grid.solve().unwrap();
+        let (p_without, q_without) = consumption(&without);
+        assert!(q_with - q_without > 0.5, "{} {}", q_with, q_without);
+        assert!(p_with > p_without, "{} {}", p_with, p_without);
+
+        // the same transformer without no-load losses and currents is the plain pi-model
+        grid.config_mut().include_trafo_magnetizing = true;
+        let trafo = &mut grid.network_mut().trafo.as_mut().unwrap()[0];
+        (trafo.pfe_kw, trafo.i0_percent) = (0.0, 0.0);
+        let pi_model = grid.solve().unwrap();
+        assert!((&pi_model.v - &without.v).camax() < 1e-9);
+        let (p, q) = consumption(&pi_model);
+        assert!((p - p_without).abs() < 1e-6 && (q - q_without).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_switched_shunt_control() {
+        let mut net = feeder(None);
+        for load in net.load.iter_mut().flatten() {
+            (load.p_mw, load.q_mvar) = (5.0, 4.0);
+        }
+        net.shunt = Some(vec![Shunt {
+            bus: 1,
+            q_mvar: -2.0,
+            vn_kv: 20.0,
+            step: 0,
+            max_step: 8,
+            in_service: true,
+            ..Default::default()
+        }]);
+
+        let mut grid = PowerGrid::new(net);
+        let sagging = grid.solve().unwrap().res_bus[1].vm_pu;
+        assert!(sagging < 0.97, "{}", sagging);
+
+        grid.network_mut().shunt.as_mut().unwrap()[0].control = Some(ControlMode::SwitchedShunt {
+            bus: 1,
+            vm_pu: 1.0,
+        });
+        let vm_pu = grid.solve().unwrap().res_bus[1].vm_pu;
+        let step = grid.network().shunt.as_ref().unwrap()[0].step;
+        assert!((vm_pu - 1.0).abs() < 0.003, "{} {}", vm_pu, step);
+        assert!(step > 0 && step < 8);
+    }
+
+    #[test]
+    fn test_stale_results() {
+        let mut grid = PowerGrid::new(feeder(None));
+        assert!(!grid.results_valid());
+        assert!(matches!(grid.valid_results(), Err(PowerFlowError::StaleResults)));
+
+        grid.solve().unwrap();
+        assert!(grid.results_valid());
+        assert!(grid.valid_results().is_ok());
+
+        assert!(grid.set_load(3, 4.0, 1.5));
+        assert!(!grid.results_valid());
+        assert!(matches!(grid.valid_results(), Err(PowerFlowError::StaleResults)));
+        // the stale results stay readable on request
+        assert!(grid.results().is_some());
+
+        grid.solve().unwrap();
+        assert!(grid.results_valid());
+    }
+}
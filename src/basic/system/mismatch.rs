@@ -0,0 +1,258 @@
+use nalgebra::DVector;
+use nalgebra_sparse::{CooMatrix, CscMatrix};
+use num_complex::Complex64;
+
+use super::dcpf::solve_real;
+use super::{PFNetwork, PowerFlowConfig, RunPF};
+use crate::basic::dsbus_dv::dSbus_dV;
+
+/// Describes how the bus voltages are packed into the state vector of the Newton-Raphson method.
+///
+/// The state is `[va(pv), va(pq), vm(pq)]`, with angles in radians and magnitudes in per unit,
+/// as in MATPOWER. The slack voltage and the PV magnitudes are fixed and taken from the
+/// reference voltage.
+#[derive(Debug, Clone)]
+pub struct StateLayout {
+    pv: Vec<usize>,
+    pq: Vec<usize>,
+    v_ref: DVector<Complex64>,
+}
+
+impl StateLayout {
+    /// Returns the length of the state vector.
+    pub fn len(&self) -> usize {
+        self.pv.len() + 2 * self.pq.len()
+    }
+
+    /// Returns `true` if the state vector is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Packs the complex bus voltages into a state vector.
+    pub fn pack(&self, v: &DVector<Complex64>) -> DVector<f64> {
+        let va = self.pv.iter().chain(self.pq.iter()).map(|&i| v[i].arg());
+        let vm = self.pq.iter().map(|&i| v[i].norm());
+        DVector::from_iterator(self.len(), va.chain(vm))
+    }
+
+    /// Unpacks a state vector into the complex bus voltages.
+    pub fn unpack(&self, x: &DVector<f64>) -> DVector<Complex64> {
+        let (npv, npq) = (self.pv.len(), self.pq.len());
+        let mut v = self.v_ref.clone();
+        for (k, &i) in self.pv.iter().enumerate() {
+            v[i] = Complex64::from_polar(v[i].norm(), x[k]);
+        }
+        for (k, &i) in self.pq.iter().enumerate() {
+            v[i] = Complex64::from_polar(x[npv + npq + k], x[npv + k]);
+        }
+        v
+    }
+}
+
+/// The blocks of the power flow Jacobian.
+///
+/// The rows are the mismatches `[p(pv), p(pq)]` and `q(pq)`, the columns the states
+/// `[va(pv), va(pq)]` and `vm(pq)`, in the order of the [`StateLayout`], so that the full
+/// Jacobian is `[h n; m l]`.
+#[derive(Debug, Clone)]
+pub struct JacobianBlocks {
+    /// The derivatives of the active power with respect to the voltage angles.
+    pub h: CscMatrix<f64>,
+    /// The derivatives of the active power with respect to the voltage magnitudes.
+    pub n: CscMatrix<f64>,
+    /// The derivatives of the reactive power with respect to the voltage angles.
+    pub m: CscMatrix<f64>,
+    /// The derivatives of the reactive power with respect to the voltage magnitudes.
+    pub l: CscMatrix<f64>,
+}
+
+/// Extracts the real or imaginary part of the given rows and columns of a complex matrix.
+fn block(
+    mat: &CscMatrix<Complex64>,
+    rows: &[usize],
+    cols: &[usize],
+    part: fn(&Complex64) -> f64,
+) -> CscMatrix<f64> {
+    let position = |nodes: &[usize]| {
+        let mut pos = vec![None; mat.nrows()];
+        nodes.iter().enumerate().for_each(|(k, &i)| pos[i] = Some(k));
+        pos
+    };
+    let (row_pos, col_pos) = (position(rows), position(cols));
+    let mut coo = CooMatrix::new(rows.len(), cols.len());
+    for (i, j, x) in mat.triplet_iter() {
+        if let (Some(r), Some(c)) = (row_pos[i], col_pos[j]) {
+            coo.push(r, c, part(x));
+        }
+    }
+    CscMatrix::from(&coo)
+}
+
+impl PFNetwork {
+    /// Computes the derivatives of the bus power injections with respect to the voltage
+    /// magnitudes and angles, in this order.
+    fn injection_derivatives(
+        &self,
+        v: &DVector<Complex64>,
+    ) -> (CscMatrix<Complex64>, CscMatrix<Complex64>) {
+        let ybus = CscMatrix::from(&self.create_y_bus());
+        let v_norm = v.map(|x| x / x.norm());
+        dSbus_dV(&ybus, v, &v_norm)
+    }
+
+    /// Computes the power flow Jacobian at the given voltages.
+    ///
+    /// The Jacobian is the derivative of [`PFNetwork::mismatch_fn`] with respect to the state
+    /// vector, see [`StateLayout`].
+    ///
+    /// # Arguments
+    ///
+    /// * `v` - The complex bus voltages.
+    pub fn jacobian(&self, v: &DVector<Complex64>) -> CscMatrix<f64> {
+        let layout = self.state_layout();
+        let (npv, npq) = (layout.pv.len(), layout.pq.len());
+        let mut p_pos = vec![None; v.len()];
+        let mut q_pos = vec![None; v.len()];
+        for (k, &i) in layout.pv.iter().chain(layout.pq.iter()).enumerate() {
+            p_pos[i] = Some(k);
+        }
+        for (k, &i) in layout.pq.iter().enumerate() {
+            q_pos[i] = Some(npv + npq + k);
+        }
+
+        let (ds_dvm, ds_dva) = self.injection_derivatives(v);
+        let mut coo = CooMatrix::new(layout.len(), layout.len());
+        for (ds, cols) in [(&ds_dva, &p_pos), (&ds_dvm, &q_pos)] {
+            for (i, j, x) in ds.triplet_iter() {
+                let Some(c) = cols[j] else {
+                    continue;
+                };
+                if let Some(r) = p_pos[i] {
+                    coo.push(r, c, x.re);
+                }
+                if let Some(r) = q_pos[i] {
+                    coo.push(r, c, x.im);
+                }
+            }
+        }
+        CscMatrix::from(&coo)
+    }
+
+    /// Computes the sensitivity of the voltage magnitude of a PQ node to its reactive injection.
+    ///
+    /// The sensitivity follows from the Jacobian at the given voltages, with the voltages of
+    /// the slack and the PV nodes held.
+    ///
+    /// # Arguments
+    ///
+    /// * `v` - The complex bus voltages, usually a power flow solution.
+    /// * `node` - The node index.
+    /// * `config` - The power flow options, which select the linear solver.
+    ///
+    /// # Returns
+    ///
+    /// The change of the voltage magnitude per injected reactive power, both in per unit, or
+    /// `None` if the node isn't a PQ node or the Jacobian is singular.
+    pub fn vq_sensitivity(
+        &self,
+        v: &DVector<Complex64>,
+        node: usize,
+        config: &PowerFlowConfig,
+    ) -> Option<f64> {
+        let layout = self.state_layout();
+        let k = layout.pq.iter().position(|x| *x == node)?;
+        let row = layout.pv.len() + layout.pq.len() + k;
+        let mut b = vec![0.0; layout.len()];
+        b[row] = 1.0;
+        solve_real(config.linear_solver, &self.jacobian(v), &mut b).ok()?;
+        b[row].is_finite().then_some(b[row])
+    }
+
+    /// Computes the blocks of the power flow Jacobian at the given voltages.
+    ///
+    /// # Arguments
+    ///
+    /// * `v` - The complex bus voltages.
+    pub fn jacobian_blocks(&self, v: &DVector<Complex64>) -> JacobianBlocks {
+        let layout = self.state_layout();
+        let pvpq: Vec<usize> = layout.pv.iter().chain(layout.pq.iter()).copied().collect();
+        let (ds_dvm, ds_dva) = self.injection_derivatives(v);
+        JacobianBlocks {
+            h: block(&ds_dva, &pvpq, &pvpq, |x| x.re),
+            n: block(&ds_dvm, &pvpq, &layout.pq, |x| x.re),
+            m: block(&ds_dva, &layout.pq, &pvpq, |x| x.im),
+            l: block(&ds_dvm, &layout.pq, &layout.pq, |x| x.im),
+        }
+    }
+
+    /// Returns the layout of the state vector, with the flat start as reference voltage.
+    pub fn state_layout(&self) -> StateLayout {
+        let (pv, pq, _) = self.bus_types();
+        StateLayout {
+            pv: pv.into_iter().map(|x| x as usize).collect(),
+            pq: pq.into_iter().map(|x| x as usize).collect(),
+            v_ref: self.create_v_init(),
+        }
+    }
+
+    /// Returns the power mismatch function of the network.
+    ///
+    /// The function maps a state vector (see [`StateLayout`]) to the mismatch
+    /// `[p(pv), p(pq), q(pq)]` in per unit, which is zero at the power flow solution.
+    pub fn mismatch_fn(&self) -> impl Fn(&DVector<f64>) -> DVector<f64> {
+        let layout = self.state_layout();
+        let ybus = self.create_y_bus();
+        let sbus = self.create_s_bus();
+        move |x| {
+            let v = layout.unpack(x);
+            let mis = v.component_mul(&(&ybus * &v).conjugate()) - &sbus;
+            let p = layout.pv.iter().chain(layout.pq.iter()).map(|&i| mis[i].re);
+            let q = layout.pq.iter().map(|&i| mis[i].im);
+            DVector::from_iterator(layout.len(), p.chain(q))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::DMatrix;
+    use crate::basic::system::test_system;
+
+    #[test]
+    fn test_pack_unpack() {
+        let (pf, _, _, _) = test_system();
+        let (v, _) = pf.run_pf(pf.create_v_init(), None, None);
+        let layout = pf.state_layout();
+        let x = layout.pack(&v);
+        assert_eq!(x.len(), layout.len());
+        assert!((layout.unpack(&x) - &v).norm() < 1e-9);
+    }
+
+    #[test]
+    fn test_jacobian_blocks() {
+        let (pf, _, _, _) = test_system();
+        let (v, _) = pf.run_pf(pf.create_v_init(), None, None);
+        let jacobian = DMatrix::from(&pf.jacobian(&v));
+        let blocks = pf.jacobian_blocks(&v);
+        let (h, n) = (DMatrix::from(&blocks.h), DMatrix::from(&blocks.n));
+        let (m, l) = (DMatrix::from(&blocks.m), DMatrix::from(&blocks.l));
+        let n_p = h.nrows();
+        assert_eq!(jacobian.view((0, 0), h.shape()), h);
+        assert_eq!(jacobian.view((0, n_p), n.shape()), n);
+        assert_eq!(jacobian.view((n_p, 0), m.shape()), m);
+        assert_eq!(jacobian.view((n_p, n_p), l.shape()), l);
+
+        // the Jacobian is the derivative of the mismatch function
+        let mismatch = pf.mismatch_fn();
+        let x = pf.state_layout().pack(&v);
+        let eps = 1e-7;
+        for j in 0..x.len() {
+            let mut x_eps = x.clone();
+            x_eps[j] += eps;
+            let column = (mismatch(&x_eps) - mismatch(&x)) / eps;
+            assert!((column - jacobian.column(j)).amax() < 1e-4);
+        }
+    }
+}
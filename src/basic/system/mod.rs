@@ -1,4 +1,29 @@
 pub(crate) mod admittance;
+pub(crate) mod config;
+pub(crate) mod custom;
+pub(crate) mod dcpf;
+pub(crate) mod estimation;
+pub(crate) mod graph;
+pub(crate) mod grid;
+pub(crate) mod mismatch;
+pub(crate) mod results;
+pub(crate) mod scenario;
+pub(crate) mod sequence;
+pub(crate) mod short_circuit;
 pub(crate) mod system;
+pub(crate) mod ybus;
+pub(crate) mod zones;
+pub use config::*;
+pub use custom::*;
+pub use estimation::*;
+pub use graph::*;
+pub use grid::*;
+pub use mismatch::*;
+pub use results::*;
+pub use scenario::*;
+pub use sequence::*;
+pub use short_circuit::*;
 pub use system::*;
+pub use ybus::*;
+pub use zones::*;
 pub mod test_ieee39;
@@ -0,0 +1,1629 @@
+use std::collections::{BTreeMap, HashMap};
+
+use nalgebra::{ComplexField, DVector};
+use num_complex::Complex64;
+use serde::{Deserialize, Serialize};
+
+use super::{AdmittanceBranch, ElementRef, NodeLookup, PFNetwork, PVNode, PowerFlowConfig};
+use super::{IslandSlack, RunPF, GND};
+use crate::io::pandapower::{zero_impedance_pairs, Network};
+
+/// The numbering base used to display bus identifiers.
+///
+/// Results are always keyed by the original pandapower bus identifiers, which are 0-based;
+/// the base only shifts them for display, e.g. to match MATPOWER bus numbers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResultIndexBase {
+    /// The pandapower identifiers as they are.
+    #[default]
+    Zero,
+    /// The identifiers shifted by one.
+    One,
+}
+
+impl ResultIndexBase {
+    /// Shifts a bus identifier to this numbering base.
+    pub fn display(self, bus: i64) -> i64 {
+        match self {
+            ResultIndexBase::Zero => bus,
+            ResultIndexBase::One => bus + 1,
+        }
+    }
+}
+
+/// The reference of the transformer loading, as pandapower's `trafo_loading` option.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransformerLoadingMode {
+    /// The terminal currents relative to the rated currents of the windings.
+    #[default]
+    Current,
+    /// The terminal apparent powers relative to the rated power.
+    Power,
+}
+
+/// Represents the power flow results of a bus.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BusResult {
+    /// The original pandapower identifier of the bus, not the internal node index.
+    #[serde(alias = "index")]
+    pub bus: i64,
+    /// The voltage magnitude in per unit.
+    pub vm_pu: f64,
+    /// The voltage angle in degrees.
+    pub va_degree: f64,
+    /// The net active power demand in MW (load convention, as in pandapower).
+    pub p_mw: f64,
+    /// The net reactive power demand in MVar (load convention, as in pandapower).
+    pub q_mvar: f64,
+}
+
+/// The differences of the bus voltages to a reference solution, see
+/// [`SolvedNetwork::compare_results`].
+#[derive(Debug, Clone, Default)]
+pub struct ResultComparison {
+    /// The largest absolute difference of the voltage magnitudes in per unit.
+    pub max_vm_diff_pu: f64,
+    /// The largest difference of the voltage magnitudes relative to the reference.
+    pub max_vm_rel_diff: f64,
+    /// The largest absolute difference of the voltage angles in degrees.
+    pub max_va_diff_degree: f64,
+    /// The largest difference of the voltage angles relative to the reference; buses with a
+    /// zero reference angle are left out.
+    pub max_va_rel_diff: f64,
+    /// The buses whose voltage magnitude or angle differ by more than the tolerance.
+    pub exceeding: Vec<i64>,
+    /// The buses of the reference without a result.
+    pub missing: Vec<i64>,
+}
+
+impl ResultComparison {
+    /// Returns whether all buses of the reference agree within the tolerance.
+    pub fn agrees(&self) -> bool {
+        self.exceeding.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// Represents the power flow results of a branch element such as a line or transformer.
+///
+/// Powers are flowing into the element at the respective end.
+#[derive(Debug, Clone)]
+pub struct BranchResult {
+    /// The element the results belong to.
+    pub element: ElementRef,
+    /// The bus at the from (HV) end.
+    pub from_bus: i64,
+    /// The bus at the to (LV) end.
+    pub to_bus: i64,
+    /// The active power at the from end in MW.
+    pub p_from_mw: f64,
+    /// The reactive power at the from end in MVar.
+    pub q_from_mvar: f64,
+    /// The active power at the to end in MW.
+    pub p_to_mw: f64,
+    /// The reactive power at the to end in MVar.
+    pub q_to_mvar: f64,
+    /// The power factor at the from end, negative if leading.
+    pub pf_from: f64,
+    /// The power factor at the to end, negative if leading.
+    pub pf_to: f64,
+    /// The active power losses in MW, `p_from_mw + p_to_mw`.
+    pub pl_mw: f64,
+    /// The reactive power consumed in MVar, `q_from_mvar + q_to_mvar`, including the charging
+    /// of the shunt admittances.
+    pub ql_mvar: f64,
+}
+
+/// Computes the signed power factor of a power flow.
+///
+/// The power factor is lagging (positive) if the active and reactive power flow in the same
+/// direction, as into an inductive load, and leading (negative) otherwise. Without any flow
+/// it is 1.
+///
+/// # Arguments
+///
+/// * `p` - The active power.
+/// * `q` - The reactive power.
+pub fn power_factor(p: f64, q: f64) -> f64 {
+    let s = p.hypot(q);
+    if s == 0.0 {
+        return 1.0;
+    }
+    let cos_phi = p.abs() / s;
+    if p * q < 0.0 {
+        -cos_phi
+    } else {
+        cos_phi
+    }
+}
+
+/// Represents the power flow results of a generator or static generator.
+///
+/// Powers use the generator convention (injection positive, as in pandapower).
+#[derive(Debug, Clone)]
+pub struct GenResult {
+    /// The element the results belong to.
+    pub element: ElementRef,
+    /// The bus the element is connected to.
+    pub bus: i64,
+    /// The active power output in MW.
+    pub p_mw: f64,
+    /// The reactive power output in MVar.
+    pub q_mvar: f64,
+}
+
+/// How a voltage-controlled generator ended up in the power flow.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VoltageControl {
+    /// The generator holds its voltage setpoint within its reactive power limits.
+    Controlled,
+    /// The generator was switched to a fixed reactive power at its lower limit in MVar.
+    AtMinQ(f64),
+    /// The generator was switched to a fixed reactive power at its upper limit in MVar.
+    AtMaxQ(f64),
+}
+
+impl VoltageControl {
+    /// Returns the reactive power limit the generator is fixed at in MVar, or `None` if it
+    /// controls its voltage.
+    pub fn q_limit(&self) -> Option<f64> {
+        match self {
+            VoltageControl::Controlled => None,
+            VoltageControl::AtMinQ(q) | VoltageControl::AtMaxQ(q) => Some(*q),
+        }
+    }
+}
+
+/// The voltage control state of a voltage-controlled generator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoltageControlStatus {
+    /// The generator.
+    pub element: ElementRef,
+    /// The bus the generator is connected to.
+    pub bus: i64,
+    /// Whether the generator controls its voltage or sits at a reactive power limit.
+    pub control: VoltageControl,
+}
+
+/// The owned results of a converged power flow.
+#[derive(Debug, Clone)]
+pub struct SolvedNetwork {
+    /// The complex bus voltages in per unit, indexed by node.
+    pub v: DVector<Complex64>,
+    /// The number of Newton-Raphson iterations needed to converge.
+    pub iterations: usize,
+    /// The bus results.
+    pub res_bus: Vec<BusResult>,
+    /// The line results.
+    pub res_line: Vec<BranchResult>,
+    /// The transformer results.
+    pub res_trafo: Vec<BranchResult>,
+    /// The results of the closed bus-bus switches modeled as admittance branches; switches
+    /// merged with others or with their buses have none, see [`SolvedNetwork::switch_result`].
+    pub res_switch: Vec<BranchResult>,
+    /// The generator results.
+    pub res_gen: Vec<GenResult>,
+    /// The static generator results.
+    pub res_sgen: Vec<GenResult>,
+    /// The complex power supplied by the external grid, the slack, in MW and MVar. With
+    /// island slacks, see [`SolvedNetwork::with_island_slacks`], it is the power of the slack
+    /// of the island of the external grid.
+    pub res_slack: Complex64,
+    /// The complex power supplied by the slack of every island of
+    /// [`PowerFlowConfig::island_slacks`] in MW and MVar, keyed by the slack bus; empty
+    /// without island slacks.
+    pub res_island_slacks: BTreeMap<i64, Complex64>,
+    /// The mismatch of the global complex power balance in MVA, see
+    /// [`SolvedNetwork::is_power_balanced`].
+    pub balance_residual: f64,
+    /// The 2-norm of the mismatch in per unit of every Newton-Raphson iterate, empty if not
+    /// recorded.
+    pub mismatch_history: Vec<f64>,
+    /// The voltage control state of the voltage-controlled generators, see
+    /// [`SolvedNetwork::voltage_control_status`].
+    pub voltage_control: Vec<VoltageControlStatus>,
+}
+
+impl SolvedNetwork {
+    /// Derives the bus and branch results from the converged voltages of a network.
+    ///
+    /// # Arguments
+    ///
+    /// * `pf` - The power flow network the voltages were solved for.
+    /// * `v` - The converged voltage vector.
+    /// * `iterations` - The number of iterations needed to converge.
+    pub fn new(pf: &PFNetwork, v: DVector<Complex64>, iterations: usize) -> Self {
+        // voltage-dependent loads are accounted for at their power at the solved voltages
+        let evaluated;
+        let pf = if pf.pq_loads.iter().any(|x| x.exponents.is_some()) {
+            evaluated = pf.loads_at(&v);
+            &evaluated
+        } else {
+            pf
+        };
+        let s_bus =
+            v.component_mul(&(pf.create_y_bus() * &v).conjugate()) * Complex64::from(pf.s_base);
+        let res_bus = pf
+            .buses
+            .iter()
+            .enumerate()
+            .map(|(node, bus)| BusResult {
+                bus: bus.index,
+                vm_pu: v[node].modulus(),
+                va_degree: v[node].argument().to_degrees(),
+                p_mw: -s_bus[node].re,
+                q_mvar: -s_bus[node].im,
+            })
+            .collect();
+
+        let branches = branch_results(pf, &v);
+        let res_line = branches
+            .iter()
+            .filter(|x| matches!(x.element, ElementRef::Line(_)))
+            .cloned()
+            .collect();
+        let res_trafo = branches
+            .iter()
+            .filter(|x| matches!(x.element, ElementRef::Trafo(_)))
+            .cloned()
+            .collect();
+        let res_switch = branches
+            .iter()
+            .filter(|x| matches!(x.element, ElementRef::Switch(_)))
+            .cloned()
+            .collect();
+
+        let fixed_gens = pf.pq_loads.iter().filter_map(|x| match x.element {
+            Some(element @ (ElementRef::SGen(_) | ElementRef::Gen(_))) => Some(GenResult {
+                element,
+                bus: pf.buses[x.bus as usize].index,
+                p_mw: -x.s.re,
+                q_mvar: -x.s.im,
+            }),
+            _ => None,
+        });
+        let (mut res_gen, res_sgen): (Vec<_>, Vec<_>) =
+            fixed_gens.partition(|x| matches!(x.element, ElementRef::Gen(_)));
+        res_gen.extend(gen_results(pf, &v, &s_bus));
+        res_gen.sort_by_key(|x| x.element);
+
+        let res_slack = slack_power(pf, &s_bus, pf.ext.bus as usize);
+        let balance_residual = balance_residual(pf, &s_bus, &v);
+        let voltage_control = pf
+            .pv_nodes
+            .iter()
+            .filter_map(|x| {
+                Some(VoltageControlStatus {
+                    element: x.element?,
+                    bus: pf.buses[x.bus as usize].index,
+                    control: VoltageControl::Controlled,
+                })
+            })
+            .collect();
+
+        Self {
+            v,
+            iterations,
+            res_bus,
+            res_line,
+            res_trafo,
+            res_switch,
+            res_gen,
+            res_sgen,
+            res_slack,
+            res_island_slacks: BTreeMap::new(),
+            balance_residual,
+            mismatch_history: Vec::new(),
+            voltage_control,
+        }
+    }
+
+    /// Returns which generators kept their voltage setpoint and which were switched to a fixed
+    /// reactive power at a limit.
+    ///
+    /// Generators are only switched if [`PowerFlowConfig::enforce_gen_q_limits`] is set;
+    /// otherwise all voltage-controlled generators are reported as controlled.
+    ///
+    /// [`PowerFlowConfig::enforce_gen_q_limits`]: super::PowerFlowConfig::enforce_gen_q_limits
+    pub fn voltage_control_status(&self) -> &[VoltageControlStatus] {
+        &self.voltage_control
+    }
+
+    /// Attaches the power supplied by the slack of every island, for results solved with
+    /// [`PowerFlowConfig::island_slacks`].
+    ///
+    /// # Arguments
+    ///
+    /// * `pf` - The power flow network the voltages were solved for.
+    /// * `island_slacks` - The island slacks the voltages were solved with.
+    pub fn with_island_slacks(
+        mut self,
+        pf: &PFNetwork,
+        island_slacks: &HashMap<i64, IslandSlack>,
+    ) -> Self {
+        if island_slacks.is_empty() {
+            return self;
+        }
+        let pf = pf.loads_at(&self.v);
+        let s_bus = self.v.component_mul(&(pf.create_y_bus() * &self.v).conjugate())
+            * Complex64::from(pf.s_base);
+        let lookup = pf.node_lookup();
+        let ext_bus = pf.buses[pf.ext.bus as usize].index;
+        let ext_island = pf.islands().into_iter().find(|x| x.contains(&ext_bus));
+        for slack in island_slacks.values() {
+            let Some(node) = lookup.get(slack.bus) else {
+                continue;
+            };
+            let s = slack_power(&pf, &s_bus, node);
+            let bus = pf.buses[node].index;
+            self.res_island_slacks.insert(bus, s);
+            if ext_island.as_ref().is_some_and(|x| x.contains(&bus)) {
+                self.res_slack = s;
+            }
+        }
+        self
+    }
+
+    /// Attaches the mismatch history of the solve, see [`PFNetwork::run_pf_traced`].
+    pub fn with_mismatch_history(mut self, history: Vec<f64>) -> Self {
+        self.mismatch_history = history;
+        self
+    }
+
+    /// Estimates the observed order of convergence from the last three mismatches.
+    ///
+    /// The order is `ln(e[k+1] / e[k]) / ln(e[k] / e[k-1])`, which approaches 2 for the
+    /// Newton-Raphson iteration near the solution. An order close to 1 means the convergence
+    /// degraded to linear, which points to an inexact Jacobian or bad conditioning.
+    ///
+    /// # Returns
+    ///
+    /// The estimated order, or `None` if fewer than three mismatches were recorded or the last
+    /// ones don't decrease.
+    pub fn convergence_order_estimate(&self) -> Option<f64> {
+        convergence_order(&self.mismatch_history)
+    }
+
+    /// Returns whether the convergence degraded to linear, i.e. the estimated order is below
+    /// 1.5. Returns `false` if the order can't be estimated.
+    pub fn has_linear_convergence(&self) -> bool {
+        self.convergence_order_estimate().is_some_and(|x| x < 1.5)
+    }
+
+    /// Returns whether the global complex power balance closes within a tolerance.
+    ///
+    /// The sum of the nodal injections must equal the power consumed by the admittance
+    /// branches and custom elements. Both sides are computed from the same voltages, so the
+    /// residual stays at round-off level regardless of the convergence tolerance; a larger
+    /// one indicates that the Ybus doesn't match the branches it was assembled from.
+    ///
+    /// # Arguments
+    ///
+    /// * `tolerance_mva` - The largest accepted residual in MVA.
+    pub fn is_power_balanced(&self, tolerance_mva: f64) -> bool {
+        self.balance_residual <= tolerance_mva
+    }
+
+    /// Returns the total losses of the network in MW and MVar.
+    ///
+    /// The losses are the net injection of all buses: the series and shunt losses of the
+    /// branches and the consumption of the bus shunts.
+    pub fn total_losses(&self) -> (f64, f64) {
+        let p = self.res_bus.iter().map(|x| -x.p_mw).sum();
+        let q = self.res_bus.iter().map(|x| -x.q_mvar).sum();
+        (p, q)
+    }
+
+    /// Returns the power supplied by the slack in MW and MVar.
+    ///
+    /// The slack is the external grid, which balances the load, the losses and the scheduled
+    /// generation.
+    pub fn slack_power(&self) -> (f64, f64) {
+        (self.res_slack.re, self.res_slack.im)
+    }
+
+    /// Computes the reactive power reserve of the voltage-controlled generators.
+    ///
+    /// The reserve is the headroom `max_q_mvar - q_mvar` up to the upper reactive power limit;
+    /// a negative reserve means the limit is violated, and it is infinite without a limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `net` - The network data the results were solved for.
+    ///
+    /// # Returns
+    ///
+    /// The generators with their reserve in MVar, in the order of `res_gen`.
+    pub fn q_reserve(&self, net: &Network) -> Vec<(ElementRef, f64)> {
+        let gens = net.gen.as_deref().unwrap_or_default();
+        self.res_gen
+            .iter()
+            .filter_map(|x| {
+                let ElementRef::Gen(pos) = x.element else {
+                    return None;
+                };
+                let gen = &gens[pos];
+                let reserve = gen.max_q_mvar.unwrap_or(f64::INFINITY) - x.q_mvar;
+                gen.is_voltage_controlled().then_some((x.element, reserve))
+            })
+            .collect()
+    }
+
+    /// Estimates the dispatch if the active power of the slack were picked up by the generators.
+    ///
+    /// The slack power is shared in proportion to the participation factors. This is a
+    /// post-processing estimate: the flows are not re-solved and the reactive powers are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `participation_factors` - The participation factors of the generators and static
+    ///   generators; elements without a factor keep their dispatch.
+    ///
+    /// # Returns
+    ///
+    /// The generator and static generator results with the adjusted active power, or `None` if
+    /// the factors of the existing generators don't sum to a positive value.
+    pub fn distribute_slack(
+        &self,
+        participation_factors: &BTreeMap<ElementRef, f64>,
+    ) -> Option<Vec<GenResult>> {
+        let gens = self.res_gen.iter().chain(&self.res_sgen);
+        let factor = |x: &GenResult| participation_factors.get(&x.element).copied();
+        let total: f64 = gens.clone().filter_map(factor).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let pickup = self.res_slack.re / total;
+        let dispatch = gens.map(|x| GenResult {
+            p_mw: x.p_mw + factor(x).unwrap_or(0.0) * pickup,
+            ..x.clone()
+        });
+        Some(dispatch.collect())
+    }
+
+    /// Returns the results of a bus by its pandapower identifier.
+    pub fn bus_result(&self, bus: i64) -> Option<&BusResult> {
+        self.res_bus.iter().find(|x| x.bus == bus)
+    }
+
+    /// Returns the power flow through a switch by its pandapower identifier.
+    ///
+    /// The flow through a switch modeled as an admittance branch follows from the voltages
+    /// across it. An ideal switch merged into a node with its buses, see
+    /// [`PowerFlowConfig::merge_zero_impedance`], carries the power that the buses on one side
+    /// of it exchange with the rest of the network, the cut-set flow. The elements at each
+    /// bus are evaluated at the voltage of the node; the reactive power of generators is
+    /// attributed as in `res_gen`.
+    ///
+    /// # Arguments
+    ///
+    /// * `net` - The network data the results were solved for.
+    /// * `config` - The power flow options the results were solved with.
+    /// * `switch` - The identifier of the switch.
+    ///
+    /// # Returns
+    ///
+    /// The results of the switch, or `None` if it doesn't exist, is open, or closes a loop of
+    /// ideal switches, e.g. parallel to another one, so that its flow is undetermined.
+    pub fn switch_result(
+        &self,
+        net: &Network,
+        config: &PowerFlowConfig,
+        switch: i64,
+    ) -> Option<BranchResult> {
+        let switches = net.switch.as_deref().unwrap_or_default();
+        let pos = switches.iter().position(|x| x.index == switch)?;
+        let element = ElementRef::Switch(pos);
+        if let Some(res) = self.res_switch.iter().find(|x| x.element == element) {
+            return Some(res.clone());
+        }
+        let sw = &switches[pos];
+        let ideal = sw.closed && sw.et.as_deref() == Some("b") && sw.z_ohm <= 0.0;
+        if !ideal || !config.merge_zero_impedance {
+            return None;
+        }
+        let s = self.cut_set_flow(net, config, (sw.bus, sw.element))?;
+        Some(BranchResult {
+            element,
+            from_bus: sw.bus,
+            to_bus: sw.element,
+            p_from_mw: s.re,
+            q_from_mvar: s.im,
+            p_to_mw: -s.re,
+            q_to_mvar: -s.im,
+            pf_from: power_factor(s.re, s.im),
+            pf_to: power_factor(-s.re, -s.im),
+            pl_mw: 0.0,
+            ql_mvar: 0.0,
+        })
+    }
+
+    /// Computes the power flowing through an ideal connection merged into a node.
+    ///
+    /// The buses of the node are joined by the ideal switches and lines. Without the given
+    /// connection they fall into the side of its first bus and the side of its second bus;
+    /// the power into the connection is what the elements of the first side inject, or what
+    /// the elements of the second side consume if the first side holds the slack, whose
+    /// injection isn't attributed to its bus.
+    ///
+    /// # Returns
+    ///
+    /// The power in MVA flowing from the first bus into the connection, or `None` if a bus
+    /// doesn't exist or the buses stay connected without it.
+    fn cut_set_flow(
+        &self,
+        net: &Network,
+        config: &PowerFlowConfig,
+        (from, to): (i64, i64),
+    ) -> Option<Complex64> {
+        let unmerged = PFNetwork::from_network(
+            net,
+            &PowerFlowConfig {
+                merge_zero_impedance: false,
+                ..config.clone()
+            },
+        )
+        .ok()?;
+        let lookup = NodeLookup::new(&unmerged.buses);
+        let mut pairs = zero_impedance_pairs(net);
+        let removed = pairs.iter().position(|x| *x == (from, to))?;
+        pairs.swap_remove(removed);
+        let (merged, nodes) = NodeLookup::merged(&unmerged.buses, &zero_impedance_pairs(net));
+        if nodes.len() != self.v.len() {
+            return None;
+        }
+
+        // the buses reached from the first bus without the connection
+        let (from, to) = (lookup.get(from)?, lookup.get(to)?);
+        let mut neighbours = vec![Vec::new(); unmerged.buses.len()];
+        for (a, b) in pairs {
+            if let (Some(a), Some(b)) = (lookup.get(a), lookup.get(b)) {
+                neighbours[a].push(b);
+                neighbours[b].push(a);
+            }
+        }
+        let mut side = vec![false; unmerged.buses.len()];
+        side[from] = true;
+        let mut queue = vec![from];
+        while let Some(node) = queue.pop() {
+            for &x in &neighbours[node] {
+                if !side[x] {
+                    side[x] = true;
+                    queue.push(x);
+                }
+            }
+        }
+        if side[to] {
+            return None;
+        }
+
+        // the power every bus of the node injects, from the voltage of the node
+        let v = DVector::from_iterator(
+            unmerged.buses.len(),
+            unmerged.buses.iter().map(|x| self.v[merged[x.index]]),
+        );
+        let pf = unmerged.loads_at(&v);
+        let mut injected = vec![Complex64::new(0.0, 0.0); pf.buses.len()];
+        for br in &pf.y_br {
+            for (node, s) in terminal_powers(&pf, br, &v) {
+                if node != GND {
+                    injected[node as usize] -= s;
+                }
+            }
+        }
+        for x in &pf.pq_loads {
+            injected[x.bus as usize] -= x.s;
+        }
+        for x in &pf.pv_nodes {
+            let gen = self.res_gen.iter().find(|g| Some(g.element) == x.element);
+            if let Some(gen) = gen {
+                injected[x.bus as usize] += Complex64::new(gen.p_mw, gen.q_mvar);
+            }
+        }
+
+        let node = merged[pf.buses[from].index];
+        let in_node = |x: &usize| merged[pf.buses[*x].index] == node;
+        let slack = pf.ext.bus as usize;
+        let flow = if side[slack] {
+            let other = (0..pf.buses.len()).filter(|x| in_node(x) && !side[*x]);
+            -other.map(|x| injected[x]).sum::<Complex64>()
+        } else {
+            (0..pf.buses.len()).filter(|x| side[*x]).map(|x| injected[x]).sum()
+        };
+        Some(flow)
+    }
+
+    /// Compares the bus voltages to a reference solution, e.g. the `res_bus` table of a
+    /// network solved by pandapower, see [`Network::res_bus`].
+    ///
+    /// # Arguments
+    ///
+    /// * `reference` - The reference results, keyed by the pandapower bus identifiers.
+    /// * `tolerance` - The largest allowed difference in per unit; the angles are compared in
+    ///   radians.
+    pub fn compare_results(&self, reference: &[BusResult], tolerance: f64) -> ResultComparison {
+        let mut out = ResultComparison::default();
+        for expected in reference {
+            let Some(actual) = self.bus_result(expected.bus) else {
+                out.missing.push(expected.bus);
+                continue;
+            };
+            let dvm = (actual.vm_pu - expected.vm_pu).abs();
+            let dva = (actual.va_degree - expected.va_degree).abs();
+            out.max_vm_diff_pu = out.max_vm_diff_pu.max(dvm);
+            out.max_vm_rel_diff = out.max_vm_rel_diff.max(dvm / expected.vm_pu.abs());
+            out.max_va_diff_degree = out.max_va_diff_degree.max(dva);
+            if expected.va_degree != 0.0 {
+                out.max_va_rel_diff = out.max_va_rel_diff.max(dva / expected.va_degree.abs());
+            }
+            if dvm > tolerance || dva.to_radians() > tolerance {
+                out.exceeding.push(expected.bus);
+            }
+        }
+        out
+    }
+
+    /// Rotates all voltage angles so that the given bus becomes the angle reference.
+    ///
+    /// This is pure post-processing: magnitudes, angle differences and flows are unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - The identifier of the new reference bus.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the bus doesn't exist.
+    pub fn rotate_reference(&mut self, bus: i64) -> bool {
+        let Some(node) = self.res_bus.iter().position(|x| x.bus == bus) else {
+            return false;
+        };
+        let va_ref = self.res_bus[node].va_degree;
+        let rotation = Complex64::from_polar(1.0, -va_ref.to_radians());
+        self.v.apply(|x| *x *= rotation);
+        self.res_bus.iter_mut().for_each(|x| x.va_degree -= va_ref);
+        true
+    }
+
+    /// Computes the loading of the lines against their apparent power limit `max_s_mva`.
+    ///
+    /// The apparent power of each end is `sqrt(p^2 + q^2)`; the larger of the two is related
+    /// to the limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `net` - The network data the results were solved for.
+    ///
+    /// # Returns
+    ///
+    /// The loading in the order of `res_line`, `None` for lines without `max_s_mva`.
+    pub fn line_apparent_loading(&self, net: &Network) -> Vec<Option<ApparentPowerLoading>> {
+        let lines = net.line.as_deref().unwrap_or_default();
+        self.res_line
+            .iter()
+            .map(|x| {
+                let ElementRef::Line(pos) = x.element else {
+                    return None;
+                };
+                let max_s_mva = lines[pos].max_s_mva.filter(|x| *x > 0.0)?;
+                let s_mva = x.p_from_mw.hypot(x.q_from_mvar).max(x.p_to_mw.hypot(x.q_to_mvar));
+                Some(ApparentPowerLoading {
+                    loading_percent: 100.0 * s_mva / max_s_mva,
+                    margin_mva: max_s_mva - s_mva,
+                })
+            })
+            .collect()
+    }
+
+    /// Computes the loading of the transformers.
+    ///
+    /// The loading is the larger of the two terminal loadings. In current mode the current of
+    /// a terminal follows from its apparent power and the voltage magnitude of its bus, and is
+    /// related to the rated current of the winding; it therefore exceeds the power loading if
+    /// the voltage is below the rated voltage of the winding.
+    ///
+    /// # Arguments
+    ///
+    /// * `net` - The network data the results were solved for.
+    /// * `mode` - The reference of the loading.
+    ///
+    /// # Returns
+    ///
+    /// The loading in percent, in the order of `res_trafo`.
+    pub fn trafo_loading(&self, net: &Network, mode: TransformerLoadingMode) -> Vec<f64> {
+        let trafos = net.trafo.as_deref().unwrap_or_default();
+        let vn_kv = |bus: i64| net.bus.iter().find(|x| x.index == bus).map_or(1.0, |x| x.vn_kv);
+        let vm_pu = |bus: i64| self.bus_result(bus).map_or(1.0, |x| x.vm_pu);
+        self.res_trafo
+            .iter()
+            .map(|x| {
+                let ElementRef::Trafo(pos) = x.element else {
+                    return 0.0;
+                };
+                let trafo = &trafos[pos];
+                let sn_mva = trafo.rated_mva();
+                let terminals = [
+                    (x.from_bus, x.p_from_mw.hypot(x.q_from_mvar), trafo.vn_hv_kv),
+                    (x.to_bus, x.p_to_mw.hypot(x.q_to_mvar), trafo.vn_lv_kv),
+                ];
+                let loading = terminals.map(|(bus, s_mva, vn_winding_kv)| match mode {
+                    TransformerLoadingMode::Power => s_mva / sn_mva,
+                    TransformerLoadingMode::Current => {
+                        let v_kv = vm_pu(bus) * vn_kv(bus);
+                        s_mva / v_kv * vn_winding_kv / sn_mva
+                    }
+                });
+                100.0 * loading[0].max(loading[1])
+            })
+            .collect()
+    }
+}
+
+/// The loading of a line against its apparent power limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ApparentPowerLoading {
+    /// The larger apparent power of both ends in percent of `max_s_mva`.
+    pub loading_percent: f64,
+    /// The apparent power still available in MVA, negative if the limit is violated.
+    pub margin_mva: f64,
+}
+
+/// Represents the power flow results of a line, identified by its pandapower id.
+#[derive(Debug, Clone, Serialize)]
+pub struct LineResult {
+    /// The line identifier.
+    pub line: i64,
+    /// The bus at the from end.
+    pub from_bus: i64,
+    /// The bus at the to end.
+    pub to_bus: i64,
+    /// The active power at the from end in MW.
+    pub p_from_mw: f64,
+    /// The reactive power at the from end in MVar.
+    pub q_from_mvar: f64,
+    /// The active power at the to end in MW.
+    pub p_to_mw: f64,
+    /// The reactive power at the to end in MVar.
+    pub q_to_mvar: f64,
+}
+
+/// A flat, serializable summary of the power flow results for exchange with other services.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResultsDto {
+    /// The bus results.
+    pub buses: Vec<BusResult>,
+    /// The line results.
+    pub lines: Vec<LineResult>,
+    /// Whether the power flow converged. If not, the results belong to the last iterate.
+    pub converged: bool,
+    /// The number of Newton-Raphson iterations performed.
+    pub iterations: usize,
+}
+
+impl ResultsDto {
+    /// Flattens the results of a power flow.
+    ///
+    /// # Arguments
+    ///
+    /// * `net` - The network data, used to map the results to the element ids.
+    /// * `solved` - The results of the power flow.
+    /// * `converged` - Whether the power flow converged.
+    pub fn new(net: &Network, solved: &SolvedNetwork, converged: bool) -> Self {
+        let lines = net.line.as_deref().unwrap_or_default();
+        let lines = solved
+            .res_line
+            .iter()
+            .filter_map(|x| match x.element {
+                ElementRef::Line(pos) => Some(LineResult {
+                    line: lines[pos].index,
+                    from_bus: x.from_bus,
+                    to_bus: x.to_bus,
+                    p_from_mw: x.p_from_mw,
+                    q_from_mvar: x.q_from_mvar,
+                    p_to_mw: x.p_to_mw,
+                    q_to_mvar: x.q_to_mvar,
+                }),
+                _ => None,
+            })
+            .collect();
+        Self {
+            buses: solved.res_bus.clone(),
+            lines,
+            converged,
+            iterations: solved.iterations,
+        }
+    }
+}
+
+/// Computes the power supplied by a slack: the injection of its node less the fixed injections
+/// of the other elements there, in MVA.
+fn slack_power(pf: &PFNetwork, s_bus: &DVector<Complex64>, node: usize) -> Complex64 {
+    let fixed: Complex64 = pf
+        .pq_loads
+        .iter()
+        .filter(|x| x.bus as usize == node)
+        .map(|x| x.s)
+        .sum();
+    let pv: f64 = pf.pv_nodes.iter().filter(|x| x.bus as usize == node).map(|x| x.p).sum();
+    s_bus[node] + fixed - pv
+}
+
+/// Attributes the solved reactive power of the PV buses to their generators.
+///
+/// The reactive power supplied by the generators of a bus is the solved injection plus the
+/// fixed demand of the bus (loads, shunts and static generators). A generator in voltage droop
+/// control supplies `(v - vm) / droop` at the solved voltage; the rest is shared between the
+/// other generators in proportion to their rated power `sn_mva`, or equally if any of them has
+/// no rating. The active power is the setpoint of each generator.
+fn gen_results(
+    pf: &PFNetwork,
+    v: &DVector<Complex64>,
+    s_bus: &DVector<Complex64>,
+) -> Vec<GenResult> {
+    let droop_q = |x: &PVNode| {
+        let k = x.droop.filter(|k| *k > 0.0 && x.element.is_some())?;
+        Some((x.v - v[x.bus as usize].norm()) / k)
+    };
+    let mut q_fixed = vec![0.0; pf.buses.len()];
+    for x in &pf.pq_loads {
+        q_fixed[x.bus as usize] += x.s.im;
+    }
+    let mut weights: BTreeMap<i64, Vec<Option<f64>>> = BTreeMap::new();
+    for x in &pf.pv_nodes {
+        match droop_q(x) {
+            Some(q) => q_fixed[x.bus as usize] -= q,
+            None => weights
+                .entry(x.bus)
+                .or_default()
+                .push(x.sn_mva.filter(|s| *s > 0.0)),
+        }
+    }
+    let share = |x: &PVNode| {
+        let rated = &weights[&x.bus];
+        match x.sn_mva {
+            Some(sn_mva) if rated.iter().all(Option::is_some) => {
+                sn_mva / rated.iter().flatten().sum::<f64>()
+            }
+            _ => 1.0 / rated.len() as f64,
+        }
+    };
+
+    pf.pv_nodes
+        .iter()
+        .filter_map(|x| {
+            let node = x.bus as usize;
+            let q_bus = s_bus[node].im + q_fixed[node];
+            Some(GenResult {
+                element: x.element?,
+                bus: pf.buses[node].index,
+                p_mw: x.p,
+                q_mvar: droop_q(x).unwrap_or_else(|| q_bus * share(x)),
+            })
+        })
+        .collect()
+}
+
+/// Computes the powers flowing into an admittance branch at its two ports.
+///
+/// # Returns
+///
+/// The node and power in MVA of each port; the power at a ground port is zero.
+pub(crate) fn terminal_powers(
+    pf: &PFNetwork,
+    br: &AdmittanceBranch,
+    v: &DVector<Complex64>,
+) -> [(i32, Complex64); 2] {
+    let node_voltage = |node: i32| {
+        if node == GND {
+            Complex64::new(0.0, 0.0)
+        } else {
+            v[node as usize]
+        }
+    };
+    let y = br.y.0 * br.v_base * br.v_base / pf.s_base;
+    let (f, t) = (br.port.0[0], br.port.0[1]);
+    let (vf, vt) = (node_voltage(f), node_voltage(t));
+    let rotation = Complex64::from_polar(1.0, br.shift);
+    let i_f = y * (vf - rotation * vt);
+    let i_t = y * (vt - rotation.conj() * vf);
+    [
+        (f, vf * i_f.conj() * pf.s_base),
+        (t, vt * i_t.conj() * pf.s_base),
+    ]
+}
+
+/// Estimates the order of convergence from the last three entries of a mismatch history.
+fn convergence_order(history: &[f64]) -> Option<f64> {
+    let [a, b, c] = history.get(history.len().checked_sub(3)?..)? else {
+        return None;
+    };
+    if !(*c < *b && *b < *a && *c > 0.0) {
+        return None;
+    }
+    Some((c / b).ln() / (b / a).ln())
+}
+
+/// Computes the mismatch between the sum of the nodal injections and the power consumed by
+/// the admittance branches and custom elements, in MVA.
+fn balance_residual(pf: &PFNetwork, s_bus: &DVector<Complex64>, v: &DVector<Complex64>) -> f64 {
+    let branches: Complex64 = pf
+        .y_br
+        .iter()
+        .flat_map(|br| terminal_powers(pf, br, v))
+        .filter(|(node, _)| *node != GND)
+        .map(|(_, s)| s)
+        .sum();
+    let custom: Complex64 = pf
+        .custom
+        .iter()
+        .map(|x| {
+            let v = DVector::from_iterator(x.nodes.len(), x.nodes.iter().map(|&n| v[n]));
+            v.dot(&(&x.y * &v).conjugate()) * pf.s_base
+        })
+        .sum();
+    (s_bus.sum() - branches - custom).norm()
+}
+
+/// Computes the terminal powers of every element by summing the flows of its admittance branches.
+///
+/// The terminal buses are tracked by node index and reported by bus identifier.
+fn branch_results(pf: &PFNetwork, v: &DVector<Complex64>) -> Vec<BranchResult> {
+    let mut results: BTreeMap<ElementRef, BranchResult> = BTreeMap::new();
+    for br in pf.y_br.iter() {
+        let (f, t) = (br.port.0[0], br.port.0[1]);
+        if let (Some(element), true) = (br.element, f != GND && t != GND) {
+            results.entry(element).or_insert(BranchResult {
+                element,
+                from_bus: f as i64,
+                to_bus: t as i64,
+                p_from_mw: 0.0,
+                q_from_mvar: 0.0,
+                p_to_mw: 0.0,
+                q_to_mvar: 0.0,
+                pf_from: 1.0,
+                pf_to: 1.0,
+                pl_mw: 0.0,
+                ql_mvar: 0.0,
+            });
+        }
+    }
+
+    for br in pf.y_br.iter() {
+        let Some(res) = br.element.and_then(|e| results.get_mut(&e)) else {
+            continue;
+        };
+        for (node, s) in terminal_powers(pf, br, v) {
+            if node == GND {
+                continue;
+            } else if node as i64 == res.from_bus {
+                res.p_from_mw += s.re;
+                res.q_from_mvar += s.im;
+            } else if node as i64 == res.to_bus {
+                res.p_to_mw += s.re;
+                res.q_to_mvar += s.im;
+            }
+        }
+    }
+    results
+        .into_values()
+        .map(|x| BranchResult {
+            from_bus: pf.buses[x.from_bus as usize].index,
+            to_bus: pf.buses[x.to_bus as usize].index,
+            pf_from: power_factor(x.p_from_mw, x.q_from_mvar),
+            pf_to: power_factor(x.p_to_mw, x.q_to_mvar),
+            pl_mw: x.p_from_mw + x.p_to_mw,
+            ql_mvar: x.q_from_mvar + x.q_to_mvar,
+            ..x
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::system::{test_system, PowerFlowConfig, PowerGrid};
+    use crate::io::pandapower::*;
+    use crate::io::test_networks::*;
+
+    #[test]
+    fn test_gen_reactive_power_sharing() {
+        let net = Network {
+            load: Some(vec![load(1, 4.0, 3.0)]),
+            sgen: Some(vec![SGen {
+                bus: 1,
+                in_service: true,
+                p_mw: 0.5,
+                q_mvar: 0.2,
+                scaling: 1.0,
+                ..Default::default()
+            }]),
+            gen: Some(
+                [(1.0, 10.0), (2.0, 30.0)]
+                    .into_iter()
+                    .map(|(p_mw, sn_mva)| Gen {
+                        bus: 1,
+                        in_service: true,
+                        p_mw,
+                        vm_pu: 1.02,
+                        sn_mva: Some(sn_mva),
+                        scaling: 1.0,
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..radial_network(3, &[(0, 1), (1, 2)])
+        };
+        let pf = PFNetwork::try_from(&net).unwrap();
+        let (v, iterations) = pf.run_pf(pf.create_v_init(), None, None);
+        let solved = SolvedNetwork::new(&pf, v, iterations);
+        assert!((solved.res_bus[1].vm_pu - 1.02).abs() < 1e-9);
+
+        let gens = &solved.res_gen;
+        assert_eq!(gens.len(), 2);
+        assert_eq!(gens[1].element, ElementRef::Gen(1));
+        assert_eq!((gens[0].p_mw, gens[1].p_mw), (1.0, 2.0));
+        // the generators supply the bus injection plus the load, minus the sgen output
+        let q_gen = -solved.res_bus[1].q_mvar + 3.0 - 0.2;
+        assert!((gens[0].q_mvar + gens[1].q_mvar - q_gen).abs() < 1e-9);
+        assert!((gens[1].q_mvar - 3.0 * gens[0].q_mvar).abs() < 1e-9);
+
+        assert_eq!(solved.res_sgen.len(), 1);
+        assert_eq!((solved.res_sgen[0].p_mw, solved.res_sgen[0].q_mvar), (0.5, 0.2));
+    }
+
+    #[test]
+    fn test_reactive_power_controlled_gen() {
+        let net = Network {
+            gen: Some(vec![Gen {
+                bus: 1,
+                in_service: true,
+                p_mw: 1.0,
+                q_mvar: 1.5,
+                vm_pu: 1.05,
+                scaling: 1.0,
+                control_mode: GenControlMode::ReactivePowerControl,
+                ..Default::default()
+            }]),
+            load: None,
+            ..radial_network(2, &[(0, 1)])
+        };
+        let pf = PFNetwork::try_from(&net).unwrap();
+        assert!(pf.pv_nodes.is_empty());
+        let (v, iterations) = pf.run_pf(pf.create_v_init(), None, None);
+        let solved = SolvedNetwork::new(&pf, v, iterations);
+
+        // the bus floats instead of being held at the voltage setpoint
+        assert!((solved.res_bus[1].vm_pu - 1.05).abs() > 1e-3);
+        assert!((solved.res_bus[1].q_mvar + 1.5).abs() < 1e-3);
+        assert_eq!(solved.res_gen.len(), 1);
+        assert_eq!(solved.res_gen[0].element, ElementRef::Gen(0));
+        assert_eq!((solved.res_gen[0].p_mw, solved.res_gen[0].q_mvar), (1.0, 1.5));
+    }
+
+    #[test]
+    fn test_trafo_loading_mode() {
+        let mut net = Network {
+            ext_grid: Some(vec![ExtGrid {
+                vm_pu: 0.95,
+                ..ext_grid(0)
+            }]),
+            ..substation()
+        };
+        let pf = PFNetwork::try_from(&net).unwrap();
+        let (v, iterations) = pf.run_pf(pf.create_v_init(), None, None);
+        let solved = SolvedNetwork::new(&pf, v, iterations);
+
+        let x = &solved.res_trafo[0];
+        let s_hv = x.p_from_mw.hypot(x.q_from_mvar);
+        let power = solved.trafo_loading(&net, TransformerLoadingMode::Power)[0];
+        assert!((power - 100.0 * s_hv / 40.0).abs() < 1e-9);
+
+        // below rated voltage the same power takes a higher current
+        let current = solved.trafo_loading(&net, TransformerLoadingMode::Current)[0];
+        let vm_lv = solved.res_bus[1].vm_pu;
+        let s_lv = x.p_to_mw.hypot(x.q_to_mvar);
+        assert!((current - 100.0 * s_lv / vm_lv / 40.0).abs() < 1e-9);
+        assert!(current > power + 1.0, "{} {}", current, power);
+
+        // derating and parallel units scale the rated power, not the flow
+        net.trafo.as_mut().unwrap()[0].df = 0.8;
+        let derated = solved.trafo_loading(&net, TransformerLoadingMode::Power)[0];
+        assert!((derated - power / 0.8).abs() < 1e-9);
+        let derated = solved.trafo_loading(&net, TransformerLoadingMode::Current)[0];
+        assert!((derated - current / 0.8).abs() < 1e-9);
+        net.trafo.as_mut().unwrap()[0].parallel = 2;
+        let doubled = solved.trafo_loading(&net, TransformerLoadingMode::Power)[0];
+        assert!((doubled - power / 1.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_slack_power() {
+        let (pf, _, _, _) = test_system();
+        let (v, iterations) = pf.run_pf(pf.create_v_init(), None, None);
+        let solved = SolvedNetwork::new(&pf, v, iterations);
+
+        let load: f64 = pf
+            .pq_loads
+            .iter()
+            .filter(|x| !matches!(x.element, Some(ElementRef::Gen(_) | ElementRef::SGen(_))))
+            .map(|x| x.s.re)
+            .sum();
+        let losses: f64 = solved
+            .res_line
+            .iter()
+            .chain(&solved.res_trafo)
+            .map(|x| x.p_from_mw + x.p_to_mw)
+            .sum();
+        let generation: f64 = solved.res_gen.iter().chain(&solved.res_sgen).map(|x| x.p_mw).sum();
+
+        let (p, q) = solved.slack_power();
+        assert!((p - (load + losses - generation)).abs() < 1e-3, "{}", p);
+        assert!(p > 0.0 && q.is_finite());
+        assert_eq!((p, q), (solved.res_slack.re, solved.res_slack.im));
+    }
+
+    #[test]
+    fn test_convergence_order_estimate() {
+        let (pf, _, _, _) = test_system();
+        let config = PowerFlowConfig {
+            tolerance: 1e-8,
+            ..Default::default()
+        };
+        let (v, iterations, history) = pf.run_pf_traced(pf.create_v_init(), &config).unwrap();
+        assert_eq!(history.len(), iterations + 2);
+        let solved = SolvedNetwork::new(&pf, v, iterations).with_mismatch_history(history);
+        let order = solved.convergence_order_estimate().unwrap();
+        assert!((1.7..2.5).contains(&order), "{:?}", solved.mismatch_history);
+        assert!(!solved.has_linear_convergence());
+
+        assert!((convergence_order(&[1.0, 0.5, 0.25, 0.125]).unwrap() - 1.0).abs() < 1e-12);
+        assert_eq!(convergence_order(&[1.0, 0.1]), None);
+        assert_eq!(convergence_order(&[1.0, 0.1, 0.2]), None);
+    }
+
+    #[test]
+    fn test_power_balance_residual() {
+        let (pf, _, _, _) = test_system();
+        let (v, iterations) = pf.run_pf(pf.create_v_init(), None, None);
+        let solved = SolvedNetwork::new(&pf, v.clone(), iterations);
+        assert!(solved.balance_residual < 1e-6, "{}", solved.balance_residual);
+        assert!(solved.is_power_balanced(1e-6));
+
+        // a Ybus assembled from a modified branch no longer matches the branch flows
+        let mut broken = pf.clone();
+        broken.y_br[0].y.0 *= 2.0;
+        let s_bus = v.component_mul(&(broken.create_y_bus() * &v).conjugate()).scale(pf.s_base);
+        assert!(balance_residual(&pf, &s_bus, &v) > 1.0);
+    }
+
+    /// Builds a chain of four 20 kV buses with voltage-controlled generators at the middle
+    /// buses, given their upper reactive power limits, and a load at the end.
+    fn generator_chain(max_q_mvar: [f64; 2]) -> Network {
+        Network {
+            gen: Some(
+                [(1, 1.0, max_q_mvar[0]), (2, 1.01, max_q_mvar[1])]
+                    .into_iter()
+                    .map(|(bus, vm_pu, max_q_mvar)| Gen {
+                        bus,
+                        in_service: true,
+                        p_mw: 1.0,
+                        vm_pu,
+                        max_q_mvar: Some(max_q_mvar),
+                        min_q_mvar: Some(-100.0),
+                        scaling: 1.0,
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            ..radial_network(4, &[(0, 1), (1, 2), (2, 3)])
+        }
+    }
+
+    #[test]
+    fn test_voltage_droop() {
+        // two droop generators at buses 1 and 2, joined by a line, each hold the voltage of
+        // their bus on their droop line
+        let mut net = generator_chain([100.0, 100.0]);
+        for (gen, droop) in net.gen.as_mut().unwrap().iter_mut().zip([0.002, 0.004]) {
+            gen.vm_pu = 1.03;
+            gen.control_mode = GenControlMode::VoltageDroop;
+            gen.droop_pu_per_mvar = droop;
+        }
+        let solved = PowerGrid::new(net).solve().unwrap();
+        let [a, b] = [0, 1].map(|pos| {
+            let gen = solved.res_gen.iter().find(|x| x.element == ElementRef::Gen(pos));
+            let vm_pu = solved.bus_result(pos as i64 + 1).unwrap().vm_pu;
+            (gen.unwrap().q_mvar, vm_pu)
+        });
+        assert!(a.0 > 1.0, "{}", a.0);
+        assert!((a.1 - (1.03 - 0.002 * a.0)).abs() < 1e-6);
+        assert!((b.1 - (1.03 - 0.004 * b.0)).abs() < 1e-6);
+        // the reactive powers are inversely proportional to the slopes, weighted by the voltage
+        // drops below the setpoint; bus 2 is nearer to the load, so its generator supplies more
+        // than half of the reactive power of the other
+        let ratio = a.0 / b.0;
+        assert!((ratio - 2.0 * (1.03 - a.1) / (1.03 - b.1)).abs() < 1e-3, "{}", ratio);
+        assert!((1.5..2.0).contains(&ratio), "{}", ratio);
+        // the droop is part of a single Newton-Raphson solve
+        assert!(solved.iterations < 10, "{}", solved.iterations);
+    }
+
+    #[test]
+    fn test_island_slack_power() {
+        // the islands 0-1 and 2-3, with a droop generator at bus 1 and the load at bus 3
+        let mut net = radial_network(4, &[(0, 1), (2, 3)]);
+        net.gen = Some(vec![Gen {
+            bus: 1,
+            in_service: true,
+            p_mw: 1.0,
+            vm_pu: 1.02,
+            scaling: 1.0,
+            control_mode: GenControlMode::VoltageDroop,
+            droop_pu_per_mvar: 0.01,
+            ..Default::default()
+        }]);
+        let mut config = PowerFlowConfig::default();
+        let slack = |bus, vm_pu| IslandSlack {
+            bus,
+            vm_pu,
+            va_degree: 0.0,
+        };
+        config.island_slacks.insert(1, slack(0, 1.0));
+        config.island_slacks.insert(3, slack(2, 1.01));
+        let solved = PowerGrid::new(net).with_config(config).solve().unwrap();
+        assert_eq!(solved.res_island_slacks.len(), 2);
+        assert_eq!(solved.res_slack, solved.res_island_slacks[&0]);
+
+        // each slack supplies the load and losses of its own island, up to the tolerance of the
+        // solve in MVA
+        let losses = |pos: usize| solved.res_line[pos].pl_mw;
+        let island_2 = solved.res_island_slacks[&2];
+        assert!((island_2.re - (4.0 + losses(1))).abs() < 1e-4, "{}", island_2);
+        let island_0 = solved.res_island_slacks[&0];
+        assert!((island_0.re - (losses(0) - 1.0)).abs() < 1e-4, "{}", island_0);
+
+        // the droop holds within the islands
+        let gen = &solved.res_gen[0];
+        let vm_pu = solved.bus_result(1).unwrap().vm_pu;
+        assert!(gen.q_mvar.abs() > 0.1, "{}", gen.q_mvar);
+        assert!((vm_pu - (1.02 - 0.01 * gen.q_mvar)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_line_apparent_loading() {
+        let mut net = generator_chain([100.0, 100.0]);
+        let lines = net.line.as_mut().unwrap();
+        lines[0].max_s_mva = Some(2.0);
+        lines[2].max_s_mva = Some(10.0);
+        let mut grid = PowerGrid::new(net);
+        let solved = grid.solve().unwrap();
+        let loading = solved.line_apparent_loading(grid.network());
+        assert_eq!(loading.len(), 3);
+        assert_eq!(loading[1], None);
+        assert_eq!(grid.line_apparent_loading().unwrap(), loading);
+
+        for (pos, max_s_mva) in [(0, 2.0), (2, 10.0)] {
+            let x = &solved.res_line[pos];
+            let s_from = x.p_from_mw.hypot(x.q_from_mvar);
+            let s_to = x.p_to_mw.hypot(x.q_to_mvar);
+            let loading = loading[pos].unwrap();
+            let expected = 100.0 * s_from.max(s_to) / max_s_mva;
+            assert!((loading.loading_percent - expected).abs() < 1e-9);
+            assert!((loading.margin_mva - (max_s_mva - s_from.max(s_to))).abs() < 1e-9);
+        }
+        // the last line carries the load of 5 MVA and more
+        assert!(loading[2].unwrap().loading_percent > 50.0);
+        assert!(loading[2].unwrap().margin_mva > 0.0);
+    }
+
+    #[test]
+    fn test_switch_result() {
+        let mut net = generator_chain([100.0, 100.0]);
+        net.switch = Some(
+            [(2, 3, true), (1, 2, false)]
+                .into_iter()
+                .enumerate()
+                .map(|(index, (bus, element, closed))| Switch {
+                    index: 10 + index as i64,
+                    bus,
+                    element,
+                    et: Some("b".to_string()),
+                    closed,
+                    z_ohm: 0.1,
+                    ..Default::default()
+                })
+                .collect(),
+        );
+        let mut grid = PowerGrid::new(net);
+        let solved = grid.solve().unwrap();
+        assert_eq!(solved.res_switch.len(), 1);
+        let (net, config) = (grid.network(), grid.config());
+        assert!(solved.switch_result(net, config, 11).is_none());
+        assert!(solved.switch_result(net, config, 12).is_none());
+
+        // the flow into the switch is V2 conj(y (V2 - V3)), with y = 1 / 0.1 ohm in per unit
+        let res = solved.switch_result(net, config, 10).unwrap();
+        assert_eq!((res.from_bus, res.to_bus), (2, 3));
+        let (v2, v3) = (solved.v[2], solved.v[3]);
+        let y = Complex64::new(10.0 * 20.0 * 20.0 / 100.0, 0.0);
+        let s_from = v2 * (y * (v2 - v3)).conj() * 100.0;
+        assert!(s_from.norm() > 0.1);
+        assert!((Complex64::new(res.p_from_mw, res.q_from_mvar) - s_from).norm() < 1e-9);
+        assert!((res.p_from_mw + res.p_to_mw - res.pl_mw).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_merged_switch_result() {
+        // the feeder 0-1 couples via the ideal switches 1-2 and 2-3 to the line 3-4; the
+        // switch 0-5 connects a load to the slack
+        let mut net = radial_network(6, &[(0, 1), (3, 4)]);
+        net.load.as_mut().unwrap().extend([load(2, 1.0, 0.5), load(4, 2.0, 1.0)]);
+        let switch = |index, bus, element| Switch {
+            index,
+            bus,
+            element,
+            et: Some("b".to_string()),
+            closed: true,
+            ..Default::default()
+        };
+        net.switch = Some(vec![switch(20, 1, 2), switch(21, 2, 3), switch(22, 0, 5)]);
+        let mut grid = PowerGrid::new(net);
+        let solved = grid.solve().unwrap();
+        assert!(solved.res_switch.is_empty());
+        let (net, config) = (grid.network(), grid.config());
+        let flow = |id| {
+            let res = solved.switch_result(net, config, id).unwrap();
+            assert_eq!(res.element, ElementRef::Switch(id as usize - 20));
+            assert_eq!((res.p_to_mw, res.q_to_mvar), (-res.p_from_mw, -res.q_from_mvar));
+            Complex64::new(res.p_from_mw, res.q_from_mvar)
+        };
+
+        // the flow through the switches is what the buses behind them draw, up to the mismatch
+        // of the solve on the side of the switches facing the feeder
+        let line = solved.res_line.iter().find(|x| x.element == ElementRef::Line(1)).unwrap();
+        let into_line = Complex64::new(line.p_from_mw, line.q_from_mvar);
+        assert!(into_line.re > 2.0);
+        assert!((flow(21) - into_line).norm() < 1e-4, "{} {}", flow(21), into_line);
+        let into_bus2 = into_line + Complex64::new(1.0, 0.5);
+        assert!((flow(20) - into_bus2).norm() < 1e-4, "{} {}", flow(20), into_bus2);
+        assert!((flow(22) - Complex64::new(4.0, 3.0)).norm() < 1e-9, "{}", flow(22));
+
+        // a parallel switch leaves the flow undetermined, but not that of the switch before
+        let switches = grid.network_mut().switch.as_mut().unwrap();
+        switches.push(switch(23, 3, 2));
+        let solved = grid.solve().unwrap();
+        let (net, config) = (grid.network(), grid.config());
+        assert!(solved.switch_result(net, config, 21).is_none());
+        assert!(solved.switch_result(net, config, 23).is_none());
+        let res = solved.switch_result(net, config, 20).unwrap();
+        assert!((Complex64::new(res.p_from_mw, res.q_from_mvar) - into_bus2).norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_line_losses() {
+        let net = generator_chain([100.0, 100.0]);
+        let solved = PowerGrid::new(net).solve().unwrap();
+        let (p_loss, q_loss) = solved.total_losses();
+        let p_lines: f64 = solved.res_line.iter().map(|x| x.pl_mw).sum();
+        let q_lines: f64 = solved.res_line.iter().map(|x| x.ql_mvar).sum();
+        assert!(p_loss > 1e-3);
+        assert!((p_lines - p_loss).abs() < 1e-6, "{} {}", p_lines, p_loss);
+        assert!((q_lines - q_loss).abs() < 1e-6, "{} {}", q_lines, q_loss);
+        for x in &solved.res_line {
+            assert!(x.pl_mw > 0.0);
+            assert!((x.pl_mw - (x.p_from_mw + x.p_to_mw)).abs() < 1e-12);
+        }
+
+        // with transformers, the losses of all branches add up
+        let (pf, _, _, _) = test_system();
+        let (v, iterations) = pf.run_pf(pf.create_v_init(), None, None);
+        let solved = SolvedNetwork::new(&pf, v, iterations);
+        let branches = solved.res_line.iter().chain(&solved.res_trafo);
+        let p_branches: f64 = branches.map(|x| x.pl_mw).sum();
+        assert!((p_branches - solved.total_losses().0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_q_reserve() {
+        // the generator next to the load supplies its reactive power close to its limit, the other
+        // one runs almost idle
+        let mut net = generator_chain([100.0, 9.0]);
+        let pf = PFNetwork::try_from(&net).unwrap();
+        let (v, iterations) = pf.run_pf(pf.create_v_init(), None, None);
+        let solved = SolvedNetwork::new(&pf, v, iterations);
+
+        let reserve = solved.q_reserve(&net);
+        assert_eq!(reserve.len(), 2);
+        for ((element, reserve), gen) in reserve.iter().zip(&solved.res_gen) {
+            assert_eq!(*element, gen.element);
+            let max_q_mvar = net.gen.as_ref().unwrap()[gen.bus as usize - 1].max_q_mvar;
+            assert!((reserve - (max_q_mvar.unwrap() - gen.q_mvar)).abs() < 1e-9);
+        }
+        assert!(reserve[0].1 > 90.0);
+        assert!(reserve[1].1 > 0.0 && reserve[1].1 < 1.0);
+
+        net.gen.as_mut().unwrap()[1].control_mode = GenControlMode::ReactivePowerControl;
+        let reserve = solved.q_reserve(&net);
+        assert_eq!(reserve.len(), 1);
+    }
+
+    #[test]
+    fn test_voltage_control_status() {
+        let pf = PFNetwork::try_from(&generator_chain([100.0, 2.0])).unwrap();
+        let (v, iterations) = pf.run_pf(pf.create_v_init(), None, None);
+        let unlimited = SolvedNetwork::new(&pf, v, iterations);
+        assert!(unlimited.res_gen[1].q_mvar > 2.0);
+        let controlled = unlimited.voltage_control_status();
+        assert!(controlled.iter().all(|x| x.control == VoltageControl::Controlled));
+
+        let config = PowerFlowConfig {
+            enforce_gen_q_limits: true,
+            ..Default::default()
+        };
+        let solved = pf.solve_gen_q_limited(pf.create_v_init(), &config).unwrap();
+        let status = solved.voltage_control_status();
+        assert_eq!(status.len(), 2);
+        assert_eq!((status[0].element, status[0].bus), (ElementRef::Gen(0), 1));
+        assert_eq!(status[0].control, VoltageControl::Controlled);
+        assert_eq!((status[1].element, status[1].bus), (ElementRef::Gen(1), 2));
+        assert_eq!(status[1].control, VoltageControl::AtMaxQ(2.0));
+        assert_eq!(status[1].control.q_limit(), Some(2.0));
+
+        let gen = solved.res_gen.iter().find(|x| x.element == ElementRef::Gen(1)).unwrap();
+        assert!((gen.q_mvar - 2.0).abs() < 1e-9);
+        assert!(solved.bus_result(2).unwrap().vm_pu < 1.01);
+        assert!((solved.bus_result(1).unwrap().vm_pu - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_gen_q_limit_release() {
+        // both generators violate a limit at first; once the second one is held at its upper
+        // limit, the voltage at the first one sags and it has to supply instead of absorb
+        let mut net = generator_chain([100.0, 2.0]);
+        net.gen.as_mut().unwrap()[0].min_q_mvar = Some(-0.5);
+        let pf = PFNetwork::try_from(&net).unwrap();
+        let (v, iterations) = pf.run_pf(pf.create_v_init(), None, None);
+        let unlimited = SolvedNetwork::new(&pf, v, iterations);
+        assert!(unlimited.res_gen[0].q_mvar < -0.5 && unlimited.res_gen[1].q_mvar > 2.0);
+
+        let config = PowerFlowConfig {
+            enforce_gen_q_limits: true,
+            ..Default::default()
+        };
+        let solved = pf.solve_gen_q_limited(pf.create_v_init(), &config).unwrap();
+        let status = solved.voltage_control_status();
+        assert_eq!(status[0].control, VoltageControl::Controlled);
+        assert_eq!(status[1].control, VoltageControl::AtMaxQ(2.0));
+        assert!((solved.bus_result(1).unwrap().vm_pu - 1.0).abs() < 1e-9);
+        assert!(solved.res_gen[0].q_mvar > 0.0);
+
+        // generators without limits, as built by default, are never switched
+        for gen in net.gen.as_mut().unwrap() {
+            (gen.min_q_mvar, gen.max_q_mvar) = (None, None);
+        }
+        let pf = PFNetwork::try_from(&net).unwrap();
+        let solved = pf.solve_gen_q_limited(pf.create_v_init(), &config).unwrap();
+        let status = solved.voltage_control_status();
+        assert!(status.iter().all(|x| x.control == VoltageControl::Controlled));
+        assert!((solved.res_gen[1].q_mvar - unlimited.res_gen[1].q_mvar).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_distribute_slack() {
+        let (pf, _, _, _) = test_system();
+        let (v, iterations) = pf.run_pf(pf.create_v_init(), None, None);
+        let solved = SolvedNetwork::new(&pf, v, iterations);
+        let gens = &solved.res_gen;
+        let (slack, _) = solved.slack_power();
+
+        let factors: BTreeMap<_, _> = [(gens[0].element, 1.0), (gens[3].element, 3.0)].into();
+        let dispatch = solved.distribute_slack(&factors).unwrap();
+        assert_eq!(dispatch.len(), gens.len() + solved.res_sgen.len());
+        let total = |x: &[GenResult]| x.iter().map(|x| x.p_mw).sum::<f64>();
+        assert!((total(&dispatch) - (total(gens) + slack)).abs() < 1e-9);
+        assert!((dispatch[0].p_mw - gens[0].p_mw - 0.25 * slack).abs() < 1e-9);
+        assert!((dispatch[3].p_mw - gens[3].p_mw - 0.75 * slack).abs() < 1e-9);
+        assert_eq!(dispatch[1].p_mw, gens[1].p_mw);
+
+        assert!(solved.distribute_slack(&BTreeMap::new()).is_none());
+    }
+
+    #[test]
+    fn test_compare_results() {
+        let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let net = load_pandapower_json(format!("{}/cases/networks.json", dir)).unwrap();
+        let pf = PFNetwork::try_from(&net).unwrap();
+        let (v, iterations) = pf.run_pf(pf.create_v_init(), None, Some(1e-10));
+        let solved = SolvedNetwork::new(&pf, v, iterations);
+
+        // the voltages solved by pandapower
+        let mut reference = net.res_bus.clone().unwrap();
+        let comparison = solved.compare_results(&reference, 1e-6);
+        assert!(comparison.agrees(), "{:?}", comparison);
+        assert!(comparison.max_vm_diff_pu < 1e-6);
+        assert!(comparison.max_va_diff_degree < 1e-6_f64.to_degrees());
+
+        reference[3].vm_pu += 1e-3;
+        reference[4].va_degree *= 1.1;
+        reference.push(BusResult {
+            bus: 1000,
+            ..Default::default()
+        });
+        let comparison = solved.compare_results(&reference, 1e-6);
+        assert!(!comparison.agrees());
+        assert!((comparison.max_vm_diff_pu - 1e-3).abs() < 1e-9);
+        assert!((comparison.max_va_rel_diff - 0.1 / 1.1).abs() < 1e-9);
+        assert_eq!(comparison.exceeding, [reference[3].bus, reference[4].bus]);
+        assert_eq!(comparison.missing, [1000]);
+    }
+
+    #[test]
+    fn test_rotate_reference() {
+        let (pf, _, _, _) = test_system();
+        let (v, iterations) = pf.run_pf(pf.create_v_init(), None, None);
+        let mut solved = SolvedNetwork::new(&pf, v, iterations);
+        let before = solved.clone();
+        let bus = solved.res_bus[5].bus;
+        assert!(solved.rotate_reference(bus));
+        assert!(!solved.rotate_reference(-10));
+
+        assert!(solved.res_bus[5].va_degree.abs() < 1e-12);
+        assert!(solved.v[5].argument().abs() < 1e-12);
+        for (a, b) in solved.res_bus.iter().zip(before.res_bus.iter()) {
+            let shift = b.va_degree - a.va_degree;
+            assert!((shift - before.res_bus[5].va_degree).abs() < 1e-9);
+            assert!((a.vm_pu - b.vm_pu).abs() < 1e-12);
+            assert_eq!(a.p_mw, b.p_mw);
+        }
+        assert!((solved.v.map(|x| x.modulus()) - before.v.map(|x| x.modulus())).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_line_power_factor() {
+        let net = radial_network(2, &[(0, 1)]);
+        let pf = PFNetwork::try_from(&net).unwrap();
+        let (v, iterations) = pf.run_pf(pf.create_v_init(), None, None);
+        let solved = SolvedNetwork::new(&pf, v, iterations);
+
+        let line = &solved.res_line[0];
+        // the load end sees the power factor of the load, the line adds reactive demand
+        assert!((line.pf_to - 0.8).abs() < 1e-6);
+        assert!(line.pf_from > 0.0 && line.pf_from < 0.8);
+        assert!(power_factor(1.0, -1.0) < 0.0);
+        assert_eq!(power_factor(0.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn test_results_keyed_by_bus_id() {
+        let mut net: Network =
+            serde_json::from_str(crate::basic::system::test_ieee39::IEEE_39).unwrap();
+        // sparse, descending ids decouple the ids from the node indices
+        let id = |x: i64| 1000 - 10 * x;
+        net.bus.iter_mut().for_each(|x| x.index = id(x.index));
+        for x in net.line.iter_mut().flatten() {
+            (x.from_bus, x.to_bus) = (id(x.from_bus), id(x.to_bus));
+        }
+        for x in net.trafo.iter_mut().flatten() {
+            (x.hv_bus, x.lv_bus) = (id(x.hv_bus as i64) as i32, id(x.lv_bus as i64) as i32);
+        }
+        net.gen.iter_mut().flatten().for_each(|x| x.bus = id(x.bus));
+        net.load.iter_mut().flatten().for_each(|x| x.bus = id(x.bus));
+        net.ext_grid.iter_mut().flatten().for_each(|x| x.bus = id(x.bus));
+
+        let pf = PFNetwork::try_from(&net).unwrap();
+        let (v, iterations) = pf.run_pf(pf.create_v_init(), None, None);
+        let solved = SolvedNetwork::new(&pf, v, iterations);
+        for (res, bus) in solved.res_bus.iter().zip(net.bus.iter()) {
+            assert_eq!(res.bus, bus.index);
+        }
+        let ext = &net.ext_grid.as_ref().unwrap()[0];
+        let res = solved.bus_result(ext.bus).unwrap();
+        assert!((res.vm_pu - ext.vm_pu).abs() < 1e-9);
+        for gen in net.gen.iter().flatten() {
+            assert!((solved.bus_result(gen.bus).unwrap().vm_pu - gen.vm_pu).abs() < 1e-9);
+        }
+        assert!(solved.bus_result(1).is_none());
+
+        assert_eq!(ResultIndexBase::Zero.display(res.bus), res.bus);
+        assert_eq!(ResultIndexBase::One.display(res.bus), res.bus + 1);
+    }
+}
@@ -0,0 +1,186 @@
+use std::sync::Mutex;
+
+use nalgebra::DVector;
+use nalgebra_sparse::{CscMatrix, CsrMatrix};
+use num_complex::Complex64;
+
+use super::{solve_permuted, solve_permuted_with, PFNetwork, PowerFlowConfig, RunPF};
+use crate::basic::solver::{CachedRSparseSolver, SolverBackend};
+use crate::basic::PowerFlowError;
+
+/// Solves many injection scenarios on a network whose topology stays fixed.
+///
+/// The admittance matrix, the bus types and the bus permutation are assembled once on
+/// construction; each scenario only permutes its injections and runs the iterations. With the
+/// RSparse backend the symbolic analysis of the Jacobian, whose sparsity pattern is the same
+/// for every scenario, is also done once and shared. The external grid is an ideal slack, so
+/// `ext_grid_impedance` and `enforce_q_limits` of the configuration are not applied.
+#[derive(Debug)]
+pub struct MultiScenarioSolver {
+    /// The permutation into PV, PQ and slack order.
+    reorder: CsrMatrix<Complex64>,
+    /// The permuted nodal admittance matrix.
+    ybus: CscMatrix<Complex64>,
+    /// The permuted initial voltages.
+    v_init: DVector<Complex64>,
+    /// The number of PV buses.
+    npv: usize,
+    /// The number of PQ buses.
+    npq: usize,
+    /// The base power in MVA.
+    s_base: f64,
+    /// The power flow options.
+    config: PowerFlowConfig,
+    /// The linear solver keeping the symbolic analysis of the Jacobian.
+    solver: Mutex<CachedRSparseSolver>,
+}
+
+impl Clone for MultiScenarioSolver {
+    fn clone(&self) -> Self {
+        Self {
+            reorder: self.reorder.clone(),
+            ybus: self.ybus.clone(),
+            v_init: self.v_init.clone(),
+            config: self.config.clone(),
+            solver: Mutex::new(self.solver.lock().unwrap().clone()),
+            ..*self
+        }
+    }
+}
+
+impl MultiScenarioSolver {
+    /// Assembles the topology-dependent matrices of a network.
+    ///
+    /// # Arguments
+    ///
+    /// * `pf` - The network; its injections are not used.
+    /// * `config` - The power flow options used for every scenario.
+    pub fn new(pf: &PFNetwork, config: PowerFlowConfig) -> Self {
+        let (reorder, ybus, _, v_init, npv, npq) = pf.prepare_matrices(pf.create_v_init());
+        Self {
+            reorder,
+            ybus,
+            v_init,
+            npv,
+            npq,
+            s_base: pf.s_base,
+            config,
+            solver: Mutex::default(),
+        }
+    }
+
+    /// Returns the number of nodes a scenario must give injections for.
+    pub fn n_nodes(&self) -> usize {
+        self.v_init.len()
+    }
+
+    /// Solves one injection scenario.
+    ///
+    /// # Arguments
+    ///
+    /// * `s_bus` - The net power injected at each node in MW and MVar, in node order. The
+    ///   reactive injections of PV nodes and both injections of the slack are ignored.
+    ///
+    /// # Returns
+    ///
+    /// The converged voltages in node order and iterations, or the reason the power flow
+    /// failed; [`PowerFlowError::DimensionMismatch`] if there isn't one injection per node.
+    pub fn solve(
+        &self,
+        s_bus: &DVector<Complex64>,
+    ) -> Result<(DVector<Complex64>, usize), PowerFlowError> {
+        if s_bus.len() != self.n_nodes() {
+            return Err(PowerFlowError::DimensionMismatch {
+                expected: self.n_nodes(),
+                actual: s_bus.len(),
+            });
+        }
+        let sbus = &self.reorder * s_bus.unscale(self.s_base);
+        let (ybus, v_init, npv, npq) = (&self.ybus, &self.v_init, self.npv, self.npq);
+        let (s_base, config, history) = (self.s_base, &self.config, &mut Vec::new());
+        let back = self.reorder.transpose();
+        let rsparse = config.linear_solver.resolve(ybus.nrows()) == SolverBackend::Sparse;
+        let result = if rsparse && !cfg!(feature = "klu") {
+            let mut solver = self.solver.lock().unwrap().clone();
+            let result = solve_permuted_with(
+                ybus, &sbus, &[], v_init, npv, npq, s_base, config, history, &mut solver,
+            );
+            *self.solver.lock().unwrap() = solver;
+            result
+        } else {
+            solve_permuted(ybus, &sbus, &[], v_init, npv, npq, s_base, config, history)
+        };
+        match result {
+            Ok((v, iter)) => Ok((&back * &v, iter)),
+            Err(PowerFlowError::NotConverged { iterations, v }) => {
+                Err(PowerFlowError::NotConverged {
+                    iterations,
+                    v: &back * &v,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Solves every injection scenario, see [`MultiScenarioSolver::solve`].
+    pub fn solve_all(
+        &self,
+        scenarios: &[DVector<Complex64>],
+    ) -> Vec<Result<(DVector<Complex64>, usize), PowerFlowError>> {
+        scenarios.iter().map(|s_bus| self.solve(s_bus)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::system::test_ieee39;
+    use crate::io::pandapower::Network;
+
+    #[test]
+    fn test_multi_scenario_solver() {
+        let net: Network = serde_json::from_str(test_ieee39::IEEE_39).unwrap();
+        let pf = PFNetwork::try_from(&net).unwrap();
+        let solver = MultiScenarioSolver::new(&pf, PowerFlowConfig::default());
+        assert_eq!(solver.n_nodes(), pf.buses.len());
+
+        // Scales every load by a pseudo-random factor between 0.8 and 1.2.
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            0.8 + 0.4 * (seed >> 11) as f64 / (1u64 << 53) as f64
+        };
+        let networks: Vec<PFNetwork> = (0..100)
+            .map(|_| {
+                let mut pf = pf.clone();
+                pf.pq_loads.iter_mut().for_each(|x| x.s *= next());
+                pf
+            })
+            .collect();
+        let scenarios: Vec<_> = networks
+            .iter()
+            .map(|x| x.create_s_bus().scale(pf.s_base))
+            .collect();
+
+        let results = solver.solve_all(&scenarios);
+        assert_eq!(results.len(), 100);
+        assert!(results.iter().all(|x| x.is_ok()));
+        for i in [0, 17, 42, 73, 99] {
+            let (v, _) = results[i].as_ref().unwrap();
+            let (expected, _) = networks[i].run_pf(networks[i].create_v_init(), None, None);
+            assert!((v - expected).camax() < 1e-9);
+        }
+        assert!((&results[0].as_ref().unwrap().0 - &results[1].as_ref().unwrap().0).camax() > 1e-4);
+
+        let short = scenarios[0].rows(0, 10).into_owned();
+        assert!(matches!(
+            solver.solve(&short),
+            Err(PowerFlowError::DimensionMismatch {
+                expected: 39,
+                actual: 10
+            })
+        ));
+    }
+}
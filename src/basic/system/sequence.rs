@@ -0,0 +1,277 @@
+use std::f64::consts::PI;
+
+use nalgebra_sparse::{CooMatrix, CsrMatrix};
+use num_complex::Complex64;
+
+use super::NodeLookup;
+use crate::io::pandapower::{process_switch_state, Network, Transformer, SWITCH_BIG_ADMITTANCE};
+
+/// A sequence network of the symmetrical components besides the positive sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sequence {
+    Negative,
+    Zero,
+}
+
+/// Collects the per-unit admittance stamps of a sequence network.
+struct Stamps {
+    coo: CooMatrix<Complex64>,
+    s_base: f64,
+}
+
+impl Stamps {
+    /// Adds a per-unit series impedance between two nodes.
+    fn series(&mut self, f: usize, t: usize, z_pu: Complex64) {
+        let y = 1.0 / z_pu;
+        self.coo.push(f, f, y);
+        self.coo.push(t, t, y);
+        self.coo.push(f, t, -y);
+        self.coo.push(t, f, -y);
+    }
+
+    /// Adds a per-unit admittance from a node to ground.
+    fn shunt(&mut self, node: usize, y_pu: Complex64) {
+        self.coo.push(node, node, y_pu);
+    }
+}
+
+/// The winding connections of a transformer relevant for the zero sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Winding {
+    GroundedWye,
+    Wye,
+    Delta,
+    GroundedZigzag,
+    Zigzag,
+}
+
+/// Parses the vector group of a transformer into its HV and LV windings.
+///
+/// The HV winding is given in upper case, the LV winding in lower case, optionally followed by
+/// the phase shift clock number (`Dyn5`). A missing vector group defaults to `Dyn`, as in
+/// pandapower.
+fn windings(trafo: &Transformer) -> Option<(Winding, Winding)> {
+    let group = trafo.vector_group.as_deref().unwrap_or("Dyn");
+    let group = group.trim_end_matches(|c: char| c.is_ascii_digit());
+    let split = group.find(|c: char| c.is_ascii_lowercase())?;
+    let parse = |x: &str| match x.to_ascii_lowercase().as_str() {
+        "yn" => Some(Winding::GroundedWye),
+        "y" => Some(Winding::Wye),
+        "d" => Some(Winding::Delta),
+        "zn" => Some(Winding::GroundedZigzag),
+        "z" => Some(Winding::Zigzag),
+        _ => None,
+    };
+    Some((parse(&group[..split])?, parse(&group[split..])?))
+}
+
+/// Assembles the nodal admittance matrix of a sequence network for short-circuit studies.
+///
+/// Loads are neglected and transformer ratios are taken as nominal. Sources are represented by
+/// their internal impedances to ground: external grids by their short-circuit impedance (ideal
+/// external grids without `s_sc_max_mva` are left out) and, in the negative sequence,
+/// generators by their subtransient impedance. Generators have no zero-sequence path.
+///
+/// Missing zero-sequence line and transformer data fall back to the positive-sequence values.
+/// The zero-sequence paths of a transformer follow its vector group: a grounded wye winding
+/// couples to a grounded wye winding, and grounds its bus against a delta winding; a grounded
+/// zigzag winding grounds its bus; all other combinations are open.
+///
+/// Closed bus-bus switches join their buses in every sequence, modeled as in the power flow.
+///
+/// # Arguments
+///
+/// * `net` - The network data.
+/// * `sequence` - The sequence network to assemble.
+///
+/// # Returns
+///
+/// The admittance matrix in per unit on the system base, with the node order of [`NodeLookup`].
+pub fn sequence_y_bus(net: &Network, sequence: Sequence) -> CsrMatrix<Complex64> {
+    let lookup = NodeLookup::new(&net.bus);
+    let n = net.bus.len();
+    let s_base = net.sn_mva;
+    let mut stamps = Stamps {
+        coo: CooMatrix::new(n, n),
+        s_base,
+    };
+    let z_base = |node: usize| net.bus[node].vn_kv.powi(2) / s_base;
+    let zero = sequence == Sequence::Zero;
+
+    for line in net.line.iter().flatten().filter(|x| x.in_service) {
+        let (f, t) = (lookup[line.from_bus], lookup[line.to_bus]);
+        let (r, x, c) = if zero {
+            (
+                line.r0_ohm_per_km.unwrap_or(line.r_ohm_per_km),
+                line.x0_ohm_per_km.unwrap_or(line.x_ohm_per_km),
+                line.c0_nf_per_km.unwrap_or(line.c_nf_per_km),
+            )
+        } else {
+            (line.r_ohm_per_km, line.x_ohm_per_km, line.c_nf_per_km)
+        };
+        let parallel = line.parallel.max(1) as f64;
+        let z = Complex64::new(r, x) * line.length_km / parallel;
+        stamps.series(f, t, z / z_base(f));
+        let b = 2.0 * PI * net.f_hz * c * 1e-9 * line.length_km * parallel;
+        let g = line.g_us_per_km * 1e-6 * line.length_km * parallel;
+        let y_half = 0.5 * Complex64::new(g, b) * z_base(f);
+        if y_half != Complex64::new(0.0, 0.0) {
+            stamps.shunt(f, y_half);
+            stamps.shunt(t, y_half);
+        }
+    }
+
+    for trafo in net.trafo.iter().flatten().filter(|x| x.in_service) {
+        let (hv, lv) = (lookup[trafo.hv_bus as i64], lookup[trafo.lv_bus as i64]);
+        let (vk, vkr) = if zero {
+            (
+                trafo.vk0_percent.unwrap_or(trafo.vk_percent),
+                trafo.vkr0_percent.unwrap_or(trafo.vkr_percent),
+            )
+        } else {
+            (trafo.vk_percent, trafo.vkr_percent)
+        };
+        let scale = 0.01 * s_base / trafo.sn_mva / trafo.parallel.max(1) as f64;
+        let (z, r) = (vk * scale, vkr * scale);
+        let z = Complex64::new(r, (z * z - r * r).sqrt());
+        if !zero {
+            stamps.series(hv, lv, z);
+            continue;
+        }
+        use Winding::*;
+        match windings(trafo) {
+            Some((GroundedWye, GroundedWye)) => stamps.series(hv, lv, z),
+            Some((GroundedWye, Delta)) => stamps.shunt(hv, 1.0 / z),
+            Some((_, GroundedZigzag)) | Some((Delta, GroundedWye)) => stamps.shunt(lv, 1.0 / z),
+            Some((GroundedZigzag, _)) => stamps.shunt(hv, 1.0 / z),
+            _ => {}
+        }
+    }
+
+    let switches = net.switch.as_deref().unwrap_or_default();
+    for br in process_switch_state(&net.bus, &lookup, s_base, switches, SWITCH_BIG_ADMITTANCE) {
+        let (f, t) = (br.port.0[0] as usize, br.port.0[1] as usize);
+        stamps.series(f, t, s_base / (br.y.0 * br.v_base * br.v_base));
+    }
+
+    for ext in net.ext_grid.iter().flatten().filter(|x| x.in_service) {
+        let Some(s_sc) = ext.s_sc_max_mva.filter(|x| *x > 0.0 && x.is_finite()) else {
+            continue;
+        };
+        let rx = ext.rx_max.unwrap_or(0.0);
+        let x = s_base / s_sc / (1.0 + rx * rx).sqrt();
+        let z = if zero {
+            let x0 = ext.x0x_max.unwrap_or(1.0) * x;
+            Complex64::new(ext.r0x0_max.unwrap_or(rx) * x0, x0)
+        } else {
+            Complex64::new(rx * x, x)
+        };
+        stamps.shunt(lookup[ext.bus], 1.0 / z);
+    }
+
+    if !zero {
+        for gen in net.gen.iter().flatten().filter(|x| x.in_service) {
+            let (Some(xdss), Some(sn_mva)) = (gen.xdss_pu, gen.sn_mva) else {
+                continue;
+            };
+            let node = lookup[gen.bus];
+            let r = gen.rdss_ohm.unwrap_or(0.0) / z_base(node);
+            let z = Complex64::new(r, xdss * stamps.s_base / sn_mva);
+            stamps.shunt(node, 1.0 / z);
+        }
+    }
+
+    CsrMatrix::from(&stamps.coo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::pandapower::*;
+    use crate::io::test_networks::{self, ext_grid, trafo};
+
+    /// Builds a 110/20 kV transformer with the given vector group, fed by an external grid.
+    fn substation(vector_group: &str) -> Network {
+        Network {
+            f_hz: 50.0,
+            ext_grid: Some(vec![ExtGrid {
+                s_sc_max_mva: Some(1000.0),
+                rx_max: Some(0.1),
+                x0x_max: Some(2.0),
+                r0x0_max: Some(0.1),
+                ..ext_grid(0)
+            }]),
+            trafo: Some(vec![Transformer {
+                vk_percent: 12.0,
+                vkr_percent: 0.3,
+                vk0_percent: Some(10.0),
+                vkr0_percent: Some(0.3),
+                vector_group: Some(vector_group.to_string()),
+                ..trafo(0, 1)
+            }]),
+            ..test_networks::substation()
+        }
+    }
+
+    #[test]
+    fn test_zero_sequence_vector_group() {
+        let entry = |y: &CsrMatrix<Complex64>, i: usize, j: usize| {
+            y.get_entry(i, j)
+                .map_or(Complex64::new(0.0, 0.0), |x| x.into_value())
+        };
+        let z0 = Complex64::new(0.003, (0.1f64.powi(2) - 0.003f64.powi(2)).sqrt()) * 2.5;
+        let x0_ext = 2.0 * 0.1 / 1.01f64.sqrt();
+        let y0_ext = 1.0 / Complex64::new(0.1 * x0_ext, x0_ext);
+
+        // the delta winding blocks the zero sequence, the grounded wye closes it on the LV side
+        let y0 = sequence_y_bus(&substation("Dyn5"), Sequence::Zero);
+        assert_eq!(entry(&y0, 0, 1), Complex64::new(0.0, 0.0));
+        assert!((entry(&y0, 0, 0) - y0_ext).norm() < 1e-9);
+        assert!((entry(&y0, 1, 1) - 1.0 / z0).norm() < 1e-9);
+
+        // grounded wye on both sides passes the zero sequence through
+        let y0 = sequence_y_bus(&substation("YNyn0"), Sequence::Zero);
+        assert!((entry(&y0, 0, 1) + 1.0 / z0).norm() < 1e-9);
+        assert!((entry(&y0, 1, 1) - 1.0 / z0).norm() < 1e-9);
+
+        // without a grounded winding the LV bus is isolated in the zero sequence
+        let y0 = sequence_y_bus(&substation("Yy0"), Sequence::Zero);
+        assert_eq!(entry(&y0, 1, 1), Complex64::new(0.0, 0.0));
+
+        // the negative sequence is coupled regardless of the vector group
+        let y2 = sequence_y_bus(&substation("Dyn5"), Sequence::Negative);
+        assert!(entry(&y2, 0, 1).norm() > 0.0);
+    }
+
+    #[test]
+    fn test_sequence_switches() {
+        // a second LV bus behind a bus-bus switch
+        let mut net = substation("YNyn0");
+        net.bus.push(Bus {
+            index: 2,
+            in_service: true,
+            vn_kv: 20.0,
+            ..Default::default()
+        });
+        net.switch = Some(vec![Switch {
+            bus: 1,
+            element: 2,
+            et: Some("b".to_string()),
+            closed: false,
+            ..Default::default()
+        }]);
+        let y12 = |net: &Network, sequence| {
+            let y = sequence_y_bus(net, sequence);
+            y.get_entry(1, 2).unwrap().into_value()
+        };
+        for sequence in [Sequence::Negative, Sequence::Zero] {
+            assert_eq!(y12(&net, sequence), Complex64::new(0.0, 0.0));
+        }
+
+        net.switch.as_mut().unwrap()[0].closed = true;
+        for sequence in [Sequence::Negative, Sequence::Zero] {
+            let y = y12(&net, sequence);
+            assert!((y + SWITCH_BIG_ADMITTANCE).norm() < 1e-6, "{}", y);
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use nalgebra::{Complex, DVector};
+use nalgebra_sparse::{CooMatrix, CscMatrix, CsrMatrix};
+use num_complex::Complex64;
+use num_traits::Zero;
+
+#[cfg(feature = "klu")]
+use crate::basic::solver::KLUSolver;
+#[cfg(not(feature = "klu"))]
+use crate::basic::solver::RSparseSolver;
+use crate::basic::solver::Solve;
+
+use super::{sequence_y_bus, NodeLookup, PFNetwork, RunPF, Sequence};
+use crate::io::pandapower::Network;
+
+/// Solves `Y x = b` for a complex sparse matrix.
+///
+/// The system is solved through its real-valued equivalent `[G -B; B G] [xr; xi] = [br; bi]`,
+/// so that the real-valued solvers can be reused.
+///
+/// # Arguments
+///
+/// * `y` - The complex system matrix.
+/// * `b` - The complex right-hand side.
+/// * `solver` - The solver for the linear system.
+///
+/// # Returns
+///
+/// The solution vector, or an error if the solver failed.
+pub(crate) fn solve_complex<Solver: Solve>(
+    y: &CsrMatrix<Complex64>,
+    b: &DVector<Complex64>,
+    solver: &mut Solver,
+) -> Result<DVector<Complex64>, &'static str> {
+    let n = y.nrows();
+    let mut real = CooMatrix::new(2 * n, 2 * n);
+    for (i, j, v) in y.triplet_iter() {
+        real.push(i, j, v.re);
+        real.push(i, j + n, -v.im);
+        real.push(i + n, j, v.im);
+        real.push(i + n, j + n, v.re);
+    }
+    let real = CscMatrix::from(&real);
+    let (mut ap, mut ai, mut ax) = real.disassemble();
+    let mut x: Vec<f64> = b.iter().map(|x| x.re).chain(b.iter().map(|x| x.im)).collect();
+    solver.solve(&mut ap, &mut ai, &mut ax, &mut x, 2 * n)?;
+    Ok(DVector::from_fn(n, |i, _| Complex::new(x[i], x[i + n])))
+}
+
+/// Computes the driving-point impedance of a node from a nodal admittance matrix.
+///
+/// Only the island of the node is solved, so that isolated parts of the network, which make
+/// the matrix singular, don't matter.
+///
+/// # Returns
+///
+/// The impedance, or `None` if the island of the node has no path to ground.
+fn driving_point_impedance(y: &CsrMatrix<Complex64>, node: usize) -> Option<Complex64> {
+    let mut island = vec![None; y.nrows()];
+    let mut stack = vec![node];
+    let mut nodes = Vec::new();
+    while let Some(i) = stack.pop() {
+        if island[i].is_some() {
+            continue;
+        }
+        island[i] = Some(nodes.len());
+        nodes.push(i);
+        let row = y.row(i);
+        for (&j, v) in row.col_indices().iter().zip(row.values()) {
+            if !v.is_zero() && island[j].is_none() {
+                stack.push(j);
+            }
+        }
+    }
+
+    // the row sums are the admittances to ground, without any the matrix is singular
+    let mut coo = CooMatrix::new(nodes.len(), nodes.len());
+    let mut y_ground = Complex64::zero();
+    let mut y_max: f64 = 0.0;
+    for (i, j, v) in y.triplet_iter() {
+        if let (Some(i), Some(j)) = (island[i], island[j]) {
+            coo.push(i, j, *v);
+            y_ground += v;
+            y_max = y_max.max(v.norm());
+        }
+    }
+    if y_ground.norm() <= 1e-12 * y_max.max(1.0) {
+        return None;
+    }
+    let mut e = DVector::zeros(nodes.len());
+    e[0] = Complex::new(1.0, 0.0);
+    #[cfg(feature = "klu")]
+    let mut solver = KLUSolver::default();
+    #[cfg(not(feature = "klu"))]
+    let mut solver = RSparseSolver {};
+    let z = solve_complex(&CsrMatrix::from(&coo), &e, &mut solver).ok()?[0];
+    z.is_finite().then_some(z)
+}
+
+/// Computes the initial current of a single-line-to-ground fault at a bus.
+///
+/// The positive-, negative- and zero-sequence networks are connected in series at the
+/// faulted bus, so that `I = 3 V / (Z1 + Z2 + Z0)` with a prefault voltage of 1 pu. As usual in
+/// short-circuit studies, the positive-sequence impedance equals the negative-sequence one,
+/// see [`sequence_y_bus`] for the models.
+///
+/// # Arguments
+///
+/// * `net` - The network data.
+/// * `bus` - The identifier of the faulted bus.
+///
+/// # Returns
+///
+/// The magnitude of the fault current in kA, or `None` if the bus doesn't exist or has no path
+/// to ground in the negative or zero sequence.
+pub fn fault_slg(net: &Network, bus: i64) -> Option<f64> {
+    let node = NodeLookup::new(&net.bus).get(bus)?;
+    let z2 = driving_point_impedance(&sequence_y_bus(net, Sequence::Negative), node)?;
+    let z0 = driving_point_impedance(&sequence_y_bus(net, Sequence::Zero), node)?;
+    let i_base = net.sn_mva / (3f64.sqrt() * net.bus[node].vn_kv);
+    Some(3.0 / (2.0 * z2 + z0).norm() * i_base)
+}
+
+impl PFNetwork {
+    /// Builds the nodal admittance matrix used for short-circuit studies.
+    ///
+    /// Loads are neglected. The external grid is connected to ground through its source
+    /// impedance; an ideal external grid grounds its bus, which is then removed from the matrix.
+    ///
+    /// # Returns
+    ///
+    /// The admittance matrix and, for every node, its row in the matrix (`None` if grounded).
+    pub(crate) fn create_sc_y_bus(&self) -> (CsrMatrix<Complex64>, Vec<Option<usize>>) {
+        let ybus = self.create_y_bus();
+        let grounded = match self.ext.z_sc {
+            Some(_) => None,
+            None => Some(self.ext.bus as usize),
+        };
+        let mut rows = Vec::with_capacity(ybus.nrows());
+        let mut n = 0;
+        for node in 0..ybus.nrows() {
+            if Some(node) == grounded {
+                rows.push(None);
+            } else {
+                rows.push(Some(n));
+                n += 1;
+            }
+        }
+
+        let mut coo = CooMatrix::new(n, n);
+        for (i, j, v) in ybus.triplet_iter() {
+            if let (Some(i), Some(j)) = (rows[i], rows[j]) {
+                coo.push(i, j, *v);
+            }
+        }
+        if let (Some(z_sc), Some(row)) = (self.ext.z_sc, rows[self.ext.bus as usize]) {
+            coo.push(row, row, 1.0 / z_sc);
+        }
+        (CsrMatrix::from(&coo), rows)
+    }
+
+    /// Computes the Thevenin (driving-point) impedance seen from a bus.
+    ///
+    /// The impedance is the diagonal entry of the nodal impedance matrix of the short-circuit
+    /// model, in which loads are neglected and the external grid is represented by its source
+    /// impedance.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - The identifier of the bus.
+    ///
+    /// # Returns
+    ///
+    /// The Thevenin impedance in per unit on the system base, or `None` if the bus doesn't exist
+    /// or its island has no path to ground.
+    pub fn thevenin_impedance(&self, bus: i64) -> Option<Complex<f64>> {
+        let node = NodeLookup::new(&self.buses).get(bus)?;
+        let (ybus, rows) = self.create_sc_y_bus();
+        match rows[node] {
+            Some(row) => driving_point_impedance(&ybus, row),
+            None => Some(Complex::zero()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::pandapower::*;
+    use crate::io::test_networks::{ext_grid, substation, trafo};
+
+    /// Builds a 20 kV line fed by an external grid with the given short-circuit power.
+    fn feeder(s_sc_max_mva: Option<f64>) -> Network {
+        Network {
+            sn_mva: 100.0,
+            bus: (0..2)
+                .map(|index| Bus {
+                    index,
+                    in_service: true,
+                    vn_kv: 20.0,
+                    ..Default::default()
+                })
+                .collect(),
+            ext_grid: Some(vec![ExtGrid {
+                bus: 0,
+                in_service: true,
+                vm_pu: 1.0,
+                s_sc_max_mva,
+                rx_max: Some(0.1),
+                ..Default::default()
+            }]),
+            line: Some(vec![Line {
+                from_bus: 0,
+                to_bus: 1,
+                in_service: true,
+                length_km: 2.0,
+                parallel: 1,
+                r_ohm_per_km: 0.25,
+                x_ohm_per_km: 0.5,
+                ..Default::default()
+            }]),
+            load: Some(vec![Load {
+                bus: 1,
+                in_service: true,
+                p_mw: 1.0,
+                q_mvar: 0.5,
+                scaling: 1.0,
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_thevenin_impedance() {
+        let z_base = 20.0 * 20.0 / 100.0;
+        let z_line = Complex::new(0.5, 1.0) / z_base;
+
+        let pf = PFNetwork::try_from(feeder(Some(200.0))).unwrap();
+        let x_sc = 0.5 / (1.0f64 + 0.01).sqrt();
+        let z_sc = Complex::new(0.1 * x_sc, x_sc);
+        let z_th = pf.thevenin_impedance(1).unwrap();
+        assert!((z_th - (z_line + z_sc)).norm() < 1e-9, "{}", z_th);
+        assert!((pf.thevenin_impedance(0).unwrap() - z_sc).norm() < 1e-9);
+
+        // an ideal source only leaves the line impedance
+        let pf = PFNetwork::try_from(feeder(None)).unwrap();
+        assert!((pf.thevenin_impedance(1).unwrap() - z_line).norm() < 1e-9);
+        assert_eq!(pf.thevenin_impedance(0), Some(Complex::zero()));
+
+        // unknown buses and buses without a path to ground have no Thevenin impedance
+        assert_eq!(pf.thevenin_impedance(7), None);
+        let mut net = feeder(None);
+        net.bus.push(Bus {
+            index: 2,
+            in_service: true,
+            vn_kv: 20.0,
+            ..Default::default()
+        });
+        assert_eq!(PFNetwork::try_from(net).unwrap().thevenin_impedance(2), None);
+    }
+
+    #[test]
+    fn test_fault_slg() {
+        // a reactance-only grounded wye transformer, 10 % on 50 MVA
+        let mut net = Network {
+            ext_grid: Some(vec![ExtGrid {
+                s_sc_max_mva: Some(1000.0),
+                rx_max: Some(0.0),
+                x0x_max: Some(0.5),
+                ..ext_grid(0)
+            }]),
+            load: None,
+            trafo: Some(vec![Transformer {
+                sn_mva: 50.0,
+                vkr_percent: 0.0,
+                vk0_percent: Some(8.0),
+                vector_group: Some("YNyn0".to_string()),
+                ..trafo(0, 1)
+            }]),
+            ..substation()
+        };
+
+        // Z1 = Z2 = j0.1 and Z0 = j0.05 at the source: I = 3 / 0.25 = 12 pu
+        let i_base = |vn_kv: f64| 100.0 / (3f64.sqrt() * vn_kv);
+        let fault = |net: &Network, bus| fault_slg(net, bus).unwrap();
+        assert!((fault(&net, 0) - 12.0 * i_base(110.0)).abs() < 1e-9);
+        // behind the transformer Z1 = Z2 = j0.3 and Z0 = j0.21: I = 3 / 0.81 pu
+        let i_lv = 3.0 / 0.81 * i_base(20.0);
+        assert!((fault(&net, 1) - i_lv).abs() < 1e-9);
+        assert_eq!(fault_slg(&net, 7), None);
+
+        // a bus behind a closed bus-bus switch sees the fault level of its neighbour
+        net.bus.push(Bus {
+            index: 2,
+            in_service: true,
+            vn_kv: 20.0,
+            ..Default::default()
+        });
+        net.switch = Some(vec![Switch {
+            bus: 1,
+            element: 2,
+            et: Some("b".to_string()),
+            closed: true,
+            ..Default::default()
+        }]);
+        assert!((fault(&net, 2) - i_lv).abs() < 1e-6 * i_lv);
+        net.switch.as_mut().unwrap()[0].closed = false;
+        assert_eq!(fault_slg(&net, 2), None);
+
+        // a delta winding on the faulted side leaves no zero-sequence path
+        net.trafo.as_mut().unwrap()[0].vector_group = Some("YNd5".to_string());
+        assert_eq!(fault_slg(&net, 1), None);
+    }
+}
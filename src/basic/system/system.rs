@@ -1,8 +1,16 @@
 #[allow(unused_imports)]
 use std::{f64::consts::PI, str::FromStr};
+use std::collections::{HashMap, HashSet};
+use std::ops::Index;
 
 use super::{admittance, test_ieee39};
-use crate::basic::newtonpf::newton_pf;
+use crate::basic::newtonpf::{levenberg_marquardt_pf_with_loads, newton_pf_with_loads};
+use crate::basic::newtonpf::VoltageDependentLoad;
+use super::{add_custom_admittances, check_custom_nodes, CustomAdmittance, InitMode, IslandSlack};
+use super::{radial_jacobian_ordering, PowerFlowConfig, YBusCache};
+use super::{find_root, NodeMapping, SolvedNetwork, VoltageControl, VoltageControlStatus};
+use crate::basic::solver::{DenseSolver, PermutedSolver, Solve, SolverBackend};
+use crate::basic::PowerFlowError;
 #[allow(unused_imports)]
 use crate::basic::solver::RSparseSolver;
 use crate::io::pandapower::*;
@@ -10,21 +18,46 @@ use nalgebra::*;
 use nalgebra_sparse::*;
 use num_complex::Complex64;
 use num_traits::One;
+use serde::{Deserialize, Serialize};
 
+use crate::basic::solver::FaerSolver;
 #[cfg(feature = "klu")]
 use crate::basic::solver::KLUSolver;
 
 /// Represents the ground node in the network.
 pub const GND: i32 = -1;
 
+/// The deviation of a voltage magnitude in per unit below which it counts as at its setpoint.
+const VOLTAGE_TOLERANCE: f64 = 1e-6;
+
+/// Identifies the network element an equivalent model was derived from.
+///
+/// The wrapped value is the position of the element in its table of the imported network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ElementRef {
+    Line(usize),
+    Trafo(usize),
+    Switch(usize),
+    Load(usize),
+    Shunt(usize),
+    Gen(usize),
+    SGen(usize),
+}
+
 /// Represents a branch with admittance and port information.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct AdmittanceBranch {
     /// The admittance value of the branch.
     pub y: admittance::Admittance,
     /// The port information of the branch.
     pub port: admittance::Port2,
     pub v_base: f64,
+    /// The element this branch belongs to, if any.
+    pub element: Option<ElementRef>,
+    /// The phase shift in radians by which the voltage of the second port lags the first, as
+    /// of a phase-shifting transformer; it rotates the off-diagonal entries `-y e^(j shift)`
+    /// and `-y e^(-j shift)` of the branch, so the branch is not reciprocal unless it is zero.
+    pub shift: f64,
 }
 
 /// Represents a node with specified power and bus information in a power system.
@@ -32,8 +65,31 @@ pub struct AdmittanceBranch {
 pub struct PQNode {
     /// The complex power injected at the node.
     pub s: Complex<f64>,
-    /// The bus identifier of the node.
+    /// The node index of the bus.
     pub bus: i64,
+    /// The element this node belongs to, if any.
+    pub element: Option<ElementRef>,
+    /// The exponents of the voltage dependence of the active and reactive power, `None` for
+    /// constant power, see [`PQNode::power_at`].
+    pub exponents: Option<(f64, f64)>,
+}
+
+impl PQNode {
+    /// Returns the power of the node at a voltage magnitude.
+    ///
+    /// With exponents `(np, nq)` the power follows the exponential load model
+    /// `P = P0 * vm^np`, `Q = Q0 * vm^nq`, where `s` holds `P0 + jQ0` at rated voltage, so
+    /// exponents of 0, 1 and 2 give constant power, current and impedance.
+    ///
+    /// # Arguments
+    ///
+    /// * `vm` - The voltage magnitude in per unit.
+    pub fn power_at(&self, vm: f64) -> Complex64 {
+        match self.exponents {
+            Some((np, nq)) => Complex64::new(self.s.re * vm.powf(np), self.s.im * vm.powf(nq)),
+            None => self.s,
+        }
+    }
 }
 
 /// Represents a node with specified active power, voltage, and bus information in a power system.
@@ -43,8 +99,33 @@ pub struct PVNode {
     pub p: f64,
     /// The voltage magnitude at the node.
     pub v: f64,
-    /// The bus identifier of the node.
+    /// The node index of the bus.
     pub bus: i64,
+    /// The element this node belongs to, if any.
+    pub element: Option<ElementRef>,
+    /// The rated power in MVA, used to share the reactive power of a bus between its generators.
+    pub sn_mva: Option<f64>,
+    /// The lower reactive power limit in MVar, enforced if `enforce_gen_q_limits` is set.
+    pub q_min: Option<f64>,
+    /// The upper reactive power limit in MVar, enforced if `enforce_gen_q_limits` is set.
+    pub q_max: Option<f64>,
+    /// The slope of the voltage over the supplied reactive power in per unit per MVar if the
+    /// voltage follows the droop `v - droop * Q`, `None` for a fixed setpoint `v`.
+    pub droop: Option<f64>,
+}
+
+/// How the external grid constrains its bus in the power flow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtGridMode {
+    /// The bus voltage is held at the setpoint (slack bus).
+    #[default]
+    Voltage,
+    /// The grid injects the fixed Norton current `v / z_sc` through its source admittance, so
+    /// the bus voltage varies with the loading. Equivalent to a voltage source behind the
+    /// source impedance; without `z_sc` the power flow fails with
+    /// [`PowerFlowError::MissingSourceImpedance`].
+    Current,
 }
 
 /// Represents an external grid node with voltage, phase, and bus information.
@@ -54,8 +135,16 @@ pub struct ExtGridNode {
     pub v: f64,
     /// The phase angle at the external grid node.
     pub phase: f64,
-    /// The bus identifier of the external grid node.
+    /// The node index of the external grid bus.
     pub bus: i64,
+    /// The source impedance in per unit, or `None` for an ideal source.
+    pub z_sc: Option<Complex<f64>>,
+    /// The lower reactive power limit in MVar, if any.
+    pub q_min: Option<f64>,
+    /// The upper reactive power limit in MVar, if any.
+    pub q_max: Option<f64>,
+    /// How the external grid constrains its bus.
+    pub mode: ExtGridMode,
 }
 
 impl Default for ExtGridNode {
@@ -65,18 +154,97 @@ impl Default for ExtGridNode {
             v: 1.0,
             phase: Default::default(),
             bus: Default::default(),
+            z_sc: None,
+            q_min: None,
+            q_max: None,
+            mode: ExtGridMode::Voltage,
+        }
+    }
+}
+
+/// Maps the bus identifiers of the imported network to node indices of the power flow model.
+///
+/// Bus identifiers don't need to be contiguous or zero-based; nodes are numbered in the order
+/// the buses appear in the network.
+#[derive(Debug, Clone, Default)]
+pub struct NodeLookup(HashMap<i64, usize>);
+
+impl NodeLookup {
+    /// Creates the lookup for the given buses.
+    pub fn new(buses: &[Bus]) -> Self {
+        Self(buses.iter().enumerate().map(|(i, x)| (x.index, i)).collect())
+    }
+
+    /// Creates the lookup for the given buses, merging connected buses into one node.
+    ///
+    /// Each node is represented by its first bus in table order, and nodes are numbered in the
+    /// order of their representatives.
+    ///
+    /// # Arguments
+    ///
+    /// * `buses` - The buses of the network.
+    /// * `pairs` - The identifiers of the buses to merge; pairs with unknown buses are skipped.
+    ///
+    /// # Returns
+    ///
+    /// The lookup, which maps every bus to the node it was merged into, and the representative
+    /// bus of each node.
+    pub fn merged(buses: &[Bus], pairs: &[(i64, i64)]) -> (Self, Vec<Bus>) {
+        let mapping = NodeMapping::from_pairs(buses, pairs.iter().copied());
+        let mut representatives = Vec::with_capacity(mapping.len());
+        let mut lookup = HashMap::new();
+        for ((index, node), bus) in mapping.bus_nodes().zip(buses) {
+            if node == representatives.len() {
+                representatives.push(bus.clone());
+            }
+            lookup.insert(index, node);
         }
+        (Self(lookup), representatives)
+    }
+
+    /// Returns the node index of a bus, or `None` if the bus doesn't exist.
+    pub fn get(&self, bus: i64) -> Option<usize> {
+        self.0.get(&bus).copied()
+    }
+
+    /// Returns the number of nodes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if there are no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Index<i64> for NodeLookup {
+    type Output = usize;
+
+    /// Returns the node index of a bus.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bus doesn't exist.
+    fn index(&self, bus: i64) -> &usize {
+        self.0
+            .get(&bus)
+            .unwrap_or_else(|| panic!("bus {} doesn't exist", bus))
     }
 }
 
 /// Represents a power flow network with base voltage and power, bus, load, PV node, external grid node, and branch information.
-#[derive(Debug)]
+///
+/// The network consists of plain data only. It is usually converted from imported pandapower
+/// data, but can equally be built by hand, so the Ybus assembly and the Newton-Raphson solve
+/// don't depend on the importer.
+#[derive(Debug, Clone)]
 pub struct PFNetwork {
     /// The base voltage of the network.
     pub v_base: f64,
     /// The base power of the network.
     pub s_base: f64,
-    /// The list of buses in the network.
+    /// The list of buses in the network, where the position of a bus is its node index.
     pub buses: Vec<Bus>,
     /// The list of PQ nodes in the network.
     pub pq_loads: Vec<PQNode>,
@@ -86,6 +254,11 @@ pub struct PFNetwork {
     pub ext: ExtGridNode,
     /// The list of branches with admittance and port information in the network.
     pub y_br: Vec<AdmittanceBranch>,
+    /// The user-defined elements added to the Ybus alongside the branches.
+    pub custom: Vec<CustomAdmittance>,
+    /// The sparsity pattern of the Ybus, reused by repeated assemblies. Start with
+    /// `Default::default()` when building the network by hand.
+    pub ybus_pattern: YBusCache,
 }
 
 
@@ -103,6 +276,9 @@ pub struct PFNetwork {
 /// # Returns
 ///
 /// The nodal admittance matrix (Ybus) of the power flow network as a CSR (Compressed Sparse Row) matrix.
+///
+/// Superseded by [`YBusPattern`], kept as the reference for its results.
+#[cfg(test)]
 fn create_ybus(
     pf: &PFNetwork,
     incidence_matrix: &CooMatrix<Complex<f64>>,
@@ -136,6 +312,7 @@ fn create_ybus(
 /// # Returns
 ///
 /// The incidence matrix of the power flow network as a COO (Coordinate) matrix.
+#[cfg(test)]
 fn create_incidence_mat(nodes: usize, y_br: &Vec<AdmittanceBranch>) -> CooMatrix<Complex<f64>> {
     let mut incidence_matrix = CooMatrix::new(nodes, y_br.len());
     for (idx, i) in y_br.iter().enumerate() {
@@ -200,15 +377,116 @@ fn create_premute_mat(
     t
 }
 
+/// Iterates the power flow on matrices permuted into PV, PQ and slack order.
+///
+/// # Arguments
+///
+/// * `Ybus` - The permuted nodal admittance matrix.
+/// * `Sbus` - The permuted nodal power injections, in per unit.
+/// * `loads` - The voltage-dependent loads at their permuted positions, not part of `Sbus`.
+/// * `v_init` - The permuted initial voltages.
+/// * `npv` - The number of PV buses.
+/// * `npq` - The number of PQ buses.
+/// * `s_base` - The base power in MVA, used to convert the tolerance.
+/// * `config` - The power flow options.
+/// * `history` - Filled with the mismatch norm of every Newton-Raphson iterate, left empty by
+///   the regularized iteration.
+///
+/// # Returns
+///
+/// The permuted converged voltages and iterations, or the reason the power flow failed.
+#[allow(non_snake_case, clippy::too_many_arguments)]
+pub(crate) fn solve_permuted(
+    Ybus: &CscMatrix<Complex64>,
+    Sbus: &DVector<Complex64>,
+    loads: &[VoltageDependentLoad],
+    v_init: &DVector<Complex64>,
+    npv: usize,
+    npq: usize,
+    s_base: f64,
+    config: &PowerFlowConfig,
+    history: &mut Vec<f64>,
+) -> Result<(DVector<Complex64>, usize), PowerFlowError> {
+    let backend = config.linear_solver.resolve(Ybus.nrows());
+    if backend == SolverBackend::Faer && !cfg!(feature = "faer") {
+        return Err(PowerFlowError::SolverUnavailable);
+    }
+    let v = v_init;
+    // a radial network factorizes without fill-in in its leaf-first order
+    let order = match backend {
+        SolverBackend::Dense => None,
+        _ => radial_jacobian_ordering(Ybus, npv, npq),
+    };
+    macro_rules! iterate {
+        ($solver:expr) => {
+            match &order {
+                Some(order) => {
+                    let solver = &mut PermutedSolver { solver: $solver, order };
+                    solve_permuted_with(
+                        Ybus, Sbus, loads, v, npv, npq, s_base, config, history, solver,
+                    )
+                }
+                None => solve_permuted_with(
+                    Ybus, Sbus, loads, v, npv, npq, s_base, config, history, $solver,
+                ),
+            }
+        };
+    }
+    match backend {
+        SolverBackend::Dense => iterate!(&mut DenseSolver),
+        SolverBackend::Faer => iterate!(&mut FaerSolver),
+        #[cfg(feature = "klu")]
+        _ => iterate!(&mut KLUSolver::default()),
+        #[cfg(not(feature = "klu"))]
+        _ => iterate!(&mut RSparseSolver {}),
+    }
+}
+
+/// Iterates the power flow on permuted matrices with the given linear solver, ignoring
+/// `config.linear_solver`; see [`solve_permuted`] for the other arguments.
+///
+/// # Arguments
+///
+/// * `solver` - The solver of the linear systems.
+#[allow(non_snake_case, clippy::too_many_arguments)]
+pub(crate) fn solve_permuted_with<S: Solve>(
+    Ybus: &CscMatrix<Complex64>,
+    Sbus: &DVector<Complex64>,
+    loads: &[VoltageDependentLoad],
+    v_init: &DVector<Complex64>,
+    npv: usize,
+    npq: usize,
+    s_base: f64,
+    config: &PowerFlowConfig,
+    history: &mut Vec<f64>,
+    solver: &mut S,
+) -> Result<(DVector<Complex64>, usize), PowerFlowError> {
+    history.clear();
+    let tol = Some(config.tolerance_pu(s_base));
+    let max_it = Some(config.max_iterations);
+    let (v, s) = (v_init, Sbus);
+    if config.regularize {
+        levenberg_marquardt_pf_with_loads(Ybus, s, loads, v, npv, npq, tol, max_it, solver)
+    } else {
+        let stall = config.stall_patience;
+        newton_pf_with_loads(Ybus, s, loads, v, npv, npq, tol, max_it, stall, solver, history)
+    }
+}
+
 /// A trait for running power flow analysis.
 pub trait RunPF {
     /// Creates the nodal admittance matrix (Ybus) of the power flow network.
     fn create_y_bus(&self) -> CsrMatrix<Complex64>;
 
     /// Creates the nodal power injection vector (Sbus) of the power flow network.
+    ///
+    /// The injections of all loads and generators at a node add up.
     fn create_s_bus(&self) -> DVector<Complex64>;
 
     /// Creates the initial voltage vector (V_init) of the power flow network.
+    ///
+    /// Behind phase-shifting branches the angles start at the no-load shift, see
+    /// [`PFNetwork::phase_shift_angles`].
     fn create_v_init(&self) -> DVector<Complex64>;
 
     /// Runs the power flow analysis.
@@ -232,11 +510,8 @@ pub trait RunPF {
 
 impl RunPF for PFNetwork {
     fn create_y_bus(&self) -> CsrMatrix<Complex64> {
-        let nodes = self.buses.len();
-        let incidence_matrix = create_incidence_mat(nodes, &self.y_br);
-        let ybus = create_ybus(self, &incidence_matrix, self.y_br.as_slice());
-
-        ybus
+        let ybus = self.ybus_pattern.assemble(self.buses.len(), &self.y_br, self.s_base);
+        add_custom_admittances(ybus, &self.custom)
     }
 
     fn create_s_bus(&self) -> DVector<Complex64> {
@@ -261,6 +536,11 @@ impl RunPF for PFNetwork {
         for i in &self.pv_nodes {
             vbus[i.bus as usize] = Complex64::new(i.v, 0.0);
         }
+        if self.y_br.iter().any(|x| x.shift != 0.0) {
+            for (v, lag) in vbus.iter_mut().zip(self.phase_shift_angles()) {
+                *v *= Complex64::from_polar(1.0, self.ext.phase - lag);
+            }
+        }
         vbus[self.ext.bus as usize] = Complex64::from_polar(self.ext.v, self.ext.phase);
 
         vbus
@@ -272,21 +552,634 @@ impl RunPF for PFNetwork {
         max_it: Option<usize>,
         tol: Option<f64>,
     ) -> (DVector<Complex64>,usize) {
-        let (reorder, Ybus, Sbus, v_init, npv, npq) = self.prepare_matrices(v_init);
+        self.try_run_pf(v_init, max_it, tol).unwrap()
+    }
+}
 
-        #[cfg(feature = "klu")]
-        let mut solver = KLUSolver::default();
-        #[cfg(not(feature = "klu"))]
-        let mut solver = RSparseSolver {};
-        let v = newton_pf(&Ybus, &Sbus, &v_init, npv, npq, tol, max_it, &mut solver);
-        let (v, iter) = v.unwrap();
+impl PFNetwork {
+    /// Runs the power flow analysis, returning an error instead of panicking if it fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `v_init` - The initial voltage vector.
+    /// * `max_it` - The maximum number of iterations (optional).
+    /// * `tol` - The convergence tolerance (optional).
+    ///
+    /// # Returns
+    ///
+    /// The converged voltage vector and iterations, or the reason the power flow failed.
+    pub fn try_run_pf(
+        &self,
+        v_init: DVector<Complex64>,
+        max_it: Option<usize>,
+        tol: Option<f64>,
+    ) -> Result<(DVector<Complex64>, usize), PowerFlowError> {
+        let default = PowerFlowConfig::default();
+        let config = PowerFlowConfig {
+            max_iterations: max_it.unwrap_or(default.max_iterations),
+            tolerance: tol.unwrap_or(default.tolerance),
+            ..default
+        };
+        self.run_pf_with_config(v_init, &config)
+    }
+
+    /// Runs the power flow analysis with the given options.
+    ///
+    /// # Arguments
+    ///
+    /// * `v_init` - The initial voltage vector.
+    /// * `config` - The power flow options.
+    ///
+    /// # Returns
+    ///
+    /// The converged voltage vector and iterations, or the reason the power flow failed.
+    pub fn run_pf_with_config(
+        &self,
+        v_init: DVector<Complex64>,
+        config: &PowerFlowConfig,
+    ) -> Result<(DVector<Complex64>, usize), PowerFlowError> {
+        self.run_pf_traced(v_init, config).map(|(v, iter, _)| (v, iter))
+    }
+
+    /// Runs the power flow analysis with the given options, recording the mismatch history.
+    ///
+    /// # Arguments
+    ///
+    /// * `v_init` - The initial voltage vector.
+    /// * `config` - The power flow options.
+    ///
+    /// # Returns
+    ///
+    /// The converged voltage vector, iterations and the 2-norm of the mismatch in per unit of
+    /// every iterate of the last Newton-Raphson solve, or the reason the power flow failed.
+    /// The history is empty for the regularized iteration.
+    #[allow(non_snake_case)]
+    pub fn run_pf_traced(
+        &self,
+        v_init: DVector<Complex64>,
+        config: &PowerFlowConfig,
+    ) -> Result<(DVector<Complex64>, usize, Vec<f64>), PowerFlowError> {
+        check_custom_nodes(&self.custom, self.buses.len())?;
+        if config.init == InitMode::DcWarmStart {
+            let v_init = self.dc_warm_start(v_init, config);
+            let config = PowerFlowConfig {
+                init: InitMode::Flat,
+                ..config.clone()
+            };
+            return self.run_pf_traced(v_init, &config);
+        }
+
+        // droop nodes become PQ nodes, so they compose with the island slacks
+        if self.pv_nodes.iter().any(|x| x.droop.is_some()) {
+            return self.run_pf_droop(v_init, config);
+        }
+
+        if !config.island_slacks.is_empty() {
+            return self.run_pf_islands(v_init, config);
+        }
+
+        let current_mode = self.ext.mode == ExtGridMode::Current;
+        if current_mode && self.ext.z_sc.is_none() {
+            return Err(PowerFlowError::MissingSourceImpedance);
+        }
+        let behind_impedance = config.ext_grid_impedance || current_mode;
+        if let (true, Some(z_sc)) = (behind_impedance, self.ext.z_sc) {
+            let n = self.buses.len();
+            let pf = self.behind_source_impedance(z_sc);
+            let v_init = v_init.push(Complex64::from_polar(self.ext.v, self.ext.phase));
+            let config = PowerFlowConfig {
+                ext_grid_impedance: false,
+                ..config.clone()
+            };
+            return match pf.run_pf_traced(v_init, &config) {
+                Ok((v, iter, history)) => Ok((v.rows(0, n).into_owned(), iter, history)),
+                Err(PowerFlowError::NotConverged { iterations, v }) => {
+                    Err(PowerFlowError::NotConverged {
+                        iterations,
+                        v: v.rows(0, n).into_owned(),
+                    })
+                }
+                Err(e) => Err(e),
+            };
+        }
+
+        if config.enforce_q_limits && (self.ext.q_min.is_some() || self.ext.q_max.is_some()) {
+            return self.run_pf_ext_q_limited(v_init, config);
+        }
+
+        let (reorder, Ybus, mut Sbus, v_init, npv, npq) = self.prepare_matrices(v_init);
+        let loads = self.voltage_dependent_loads(&reorder, &mut Sbus);
+        let mut history = Vec::new();
+        let v = solve_permuted(
+            &Ybus,
+            &Sbus,
+            &loads,
+            &v_init,
+            npv,
+            npq,
+            self.s_base,
+            config,
+            &mut history,
+        );
+        let (v, iter) = v.map_err(|e| match e {
+            PowerFlowError::NotConverged { iterations, v } => PowerFlowError::NotConverged {
+                iterations,
+                v: reorder.transpose() * &v,
+            },
+            e => e,
+        })?;
         let x = reorder.transpose() * &v;
 
-        (x,iter)
+        Ok((x, iter, history))
+    }
+
+    /// Runs the power flow with a voltage reference in every island.
+    ///
+    /// The slack buses of [`PowerFlowConfig::island_slacks`] hold their configured voltages,
+    /// and the one in the island of the external grid takes its place. Fails with
+    /// [`PowerFlowError::DuplicateIslandSlack`] if two entries identify the same island, and
+    /// with [`PowerFlowError::UnsupportedWithIslandSlacks`] if the external grid is modeled
+    /// behind its source impedance or its reactive power limits are enforced.
+    fn run_pf_islands(
+        &self,
+        mut v_init: DVector<Complex64>,
+        config: &PowerFlowConfig,
+    ) -> Result<(DVector<Complex64>, usize, Vec<f64>), PowerFlowError> {
+        let current_mode = self.ext.mode == ExtGridMode::Current;
+        if current_mode || (config.ext_grid_impedance && self.ext.z_sc.is_some()) {
+            let option = "ext_grid_impedance";
+            return Err(PowerFlowError::UnsupportedWithIslandSlacks { option });
+        }
+        if config.enforce_q_limits && (self.ext.q_min.is_some() || self.ext.q_max.is_some()) {
+            let option = "enforce_q_limits";
+            return Err(PowerFlowError::UnsupportedWithIslandSlacks { option });
+        }
+        let mut pf = self.clone();
+        let slacks = self.slack_nodes(&config.island_slacks)?;
+        for (pos, (node, slack)) in slacks.iter().enumerate() {
+            let Some(slack) = slack else {
+                continue;
+            };
+            let phase = slack.va_degree.to_radians();
+            v_init[*node as usize] = Complex64::from_polar(slack.vm_pu, phase);
+            // the first slack replaces the external grid in its island
+            if pos == 0 {
+                (pf.ext.bus, pf.ext.v, pf.ext.phase) = (*node, slack.vm_pu, phase);
+            }
+        }
+        let slacks: Vec<i64> = slacks.into_iter().map(|(node, _)| node).collect();
+
+        let (reorder, ybus, mut sbus, v_init, npv, npq) =
+            pf.prepare_matrices_with_slacks(v_init, &slacks);
+        let loads = pf.voltage_dependent_loads(&reorder, &mut sbus);
+        let mut history = Vec::new();
+        let v = solve_permuted(
+            &ybus,
+            &sbus,
+            &loads,
+            &v_init,
+            npv,
+            npq,
+            self.s_base,
+            config,
+            &mut history,
+        );
+        let back = reorder.transpose();
+        match v {
+            Ok((v, iter)) => Ok((&back * &v, iter, history)),
+            Err(PowerFlowError::NotConverged { iterations, v }) => {
+                Err(PowerFlowError::NotConverged {
+                    iterations,
+                    v: &back * &v,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Resolves the slack nodes of the power flow with the voltage references of islands.
+    ///
+    /// # Arguments
+    ///
+    /// * `island_slacks` - The voltage references of islands, see
+    ///   [`PowerFlowConfig::island_slacks`].
+    ///
+    /// # Returns
+    ///
+    /// The slack nodes with their voltage references, the slack of the island of the external
+    /// grid first, which is the external grid itself without a reference; or
+    /// [`PowerFlowError::InvalidIslandSlack`] if a reference lies outside its island and
+    /// [`PowerFlowError::DuplicateIslandSlack`] if two references identify the same island.
+    pub(crate) fn slack_nodes(
+        &self,
+        island_slacks: &HashMap<i64, IslandSlack>,
+    ) -> Result<Vec<(i64, Option<IslandSlack>)>, PowerFlowError> {
+        let lookup = self.node_lookup();
+        let mut parent: Vec<usize> = (0..self.buses.len()).collect();
+        for br in &self.y_br {
+            let (f, t) = (br.port.0[0], br.port.0[1]);
+            if f != GND && t != GND {
+                let f = find_root(&mut parent, f as usize);
+                parent[f] = find_root(&mut parent, t as usize);
+            }
+        }
+
+        let mut slacks = vec![(self.ext.bus, None)];
+        let ext_island = find_root(&mut parent, self.ext.bus as usize);
+        let mut entries: Vec<_> = island_slacks.iter().collect();
+        entries.sort_unstable_by_key(|(member, _)| **member);
+        let mut islands = HashSet::new();
+        for (bus, slack) in entries {
+            let invalid = PowerFlowError::InvalidIslandSlack { bus: slack.bus };
+            let (member, node) = match (lookup.get(*bus), lookup.get(slack.bus)) {
+                (Some(member), Some(node)) => (member, node),
+                _ => return Err(invalid),
+            };
+            let island = find_root(&mut parent, node);
+            if find_root(&mut parent, member) != island {
+                return Err(invalid);
+            }
+            if !islands.insert(island) {
+                return Err(PowerFlowError::DuplicateIslandSlack { bus: *bus });
+            }
+            if island == ext_island {
+                slacks[0] = (node as i64, Some(*slack));
+            } else {
+                slacks.push((node as i64, Some(*slack)));
+            }
+        }
+        Ok(slacks)
+    }
+
+    /// Runs the power flow, keeping the reactive power of the external grid within its limits.
+    ///
+    /// If the external grid violates a limit, its voltage setpoint is adjusted by secant
+    /// iterations until its reactive power equals the limit, so that it stays the angle
+    /// reference while its voltage magnitude floats.
+    fn run_pf_ext_q_limited(
+        &self,
+        v_init: DVector<Complex64>,
+        config: &PowerFlowConfig,
+    ) -> Result<(DVector<Complex64>, usize, Vec<f64>), PowerFlowError> {
+        const MAX_SETPOINT_ITERATIONS: usize = 20;
+        let config = PowerFlowConfig {
+            enforce_q_limits: false,
+            ..config.clone()
+        };
+        let (mut v, mut iterations, mut history) = self.run_pf_traced(v_init, &config)?;
+        let q_a = self.ext_q(&v);
+        let target = q_a.clamp(
+            self.ext.q_min.unwrap_or(f64::NEG_INFINITY),
+            self.ext.q_max.unwrap_or(f64::INFINITY),
+        );
+        let tol = config.tolerance_pu(self.s_base) * self.s_base;
+        if (q_a - target).abs() < tol {
+            return Ok((v, iterations, history));
+        }
+
+        let mut pf = self.clone();
+        let node = self.ext.bus as usize;
+        let (mut vm_a, mut q_a) = (self.ext.v, q_a);
+        let mut vm_b = vm_a + if q_a > target { -0.01 } else { 0.01 };
+        for _ in 0..MAX_SETPOINT_ITERATIONS {
+            pf.ext.v = vm_b;
+            v[node] = Complex64::from_polar(vm_b, pf.ext.phase);
+            let (v_b, it, h) = pf.run_pf_traced(v, &config)?;
+            iterations += it;
+            (v, history) = (v_b, h);
+            let q_b = pf.ext_q(&v);
+            if (q_b - target).abs() < tol {
+                return Ok((v, iterations, history));
+            }
+            // without a change of the reactive power the secant is undefined, so step on
+            let vm_next = if (q_b - q_a).abs() > f64::EPSILON {
+                vm_b - (q_b - target) * (vm_b - vm_a) / (q_b - q_a)
+            } else {
+                vm_b + if q_b > target { -0.01 } else { 0.01 }
+            };
+            (vm_a, q_a, vm_b) = (vm_b, q_b, vm_next);
+        }
+        Err(PowerFlowError::NotConverged { iterations, v })
+    }
+
+    /// Runs the power flow with generators in voltage droop control.
+    ///
+    /// A droop node supplies the reactive power `Q = (v - vm) / droop` at which its bus voltage
+    /// `vm` equals `v - droop * Q`. As this is linear in `vm`, a droop node is solved as a PQ
+    /// node with a constant injection of `p + j v / droop` and a load of `j vm / droop` that
+    /// grows with the voltage magnitude, so that the droop enters the Newton-Raphson mismatch
+    /// and Jacobian like a voltage-dependent load. Droop nodes without an element or a positive
+    /// slope keep the fixed setpoint `v`.
+    fn run_pf_droop(
+        &self,
+        v_init: DVector<Complex64>,
+        config: &PowerFlowConfig,
+    ) -> Result<(DVector<Complex64>, usize, Vec<f64>), PowerFlowError> {
+        let mut pf = self.clone();
+        let (droop, fixed): (Vec<PVNode>, Vec<PVNode>) = pf
+            .pv_nodes
+            .iter()
+            .partition(|x| x.droop.is_some_and(|k| k > 0.0) && x.element.is_some());
+        pf.pv_nodes = fixed;
+        pf.pv_nodes.iter_mut().for_each(|x| x.droop = None);
+        for x in &droop {
+            let k = x.droop.unwrap();
+            let (bus, element) = (x.bus, x.element);
+            pf.pq_loads.push(PQNode {
+                s: -Complex64::new(x.p, x.v / k),
+                bus,
+                element,
+                exponents: None,
+            });
+            pf.pq_loads.push(PQNode {
+                s: Complex64::new(0.0, 1.0 / k),
+                bus,
+                element,
+                exponents: Some((0.0, 1.0)),
+            });
+        }
+        pf.run_pf_traced(v_init, config)
+    }
+
+    /// Returns a copy of the network with the voltage-dependent loads fixed at their power at
+    /// the given voltages, see [`PQNode::power_at`].
+    ///
+    /// # Arguments
+    ///
+    /// * `v` - The complex bus voltages in per unit.
+    pub fn loads_at(&self, v: &DVector<Complex64>) -> PFNetwork {
+        let mut pf = self.clone();
+        for x in pf.pq_loads.iter_mut().filter(|x| x.exponents.is_some()) {
+            x.s = x.power_at(v[x.bus as usize].norm());
+            x.exponents = None;
+        }
+        pf
+    }
+
+    /// Scales the power of all loads by a factor, e.g. to step toward the loadability limit.
+    ///
+    /// Only the nodes of load elements change; generators, static generators and shunts keep
+    /// their power, and the admittance matrix is not affected.
+    ///
+    /// # Arguments
+    ///
+    /// * `factor` - The factor applied to the active and reactive power of every load.
+    pub fn scale_loads(&mut self, factor: f64) {
+        let loads = self.pq_loads.iter_mut();
+        for x in loads.filter(|x| matches!(x.element, Some(ElementRef::Load(_)))) {
+            x.s *= factor;
+        }
+    }
+
+    /// Runs the power flow, switching voltage-controlled generators that violate their reactive
+    /// power limits to PQ and back.
+    ///
+    /// After each solve, every generator whose reactive power lies outside its limits is fixed
+    /// at the violated limit, and every fixed generator whose bus voltage has passed its
+    /// setpoint in the direction the limit was holding it back from is released to control
+    /// its voltage again. The power flow is solved again from the previous voltages until no
+    /// generator changes its state.
+    ///
+    /// # Arguments
+    ///
+    /// * `v_init` - The initial voltage vector.
+    /// * `config` - The power flow options.
+    ///
+    /// # Returns
+    ///
+    /// The results with the voltage control state of the generators, or the reason the power
+    /// flow failed.
+    pub fn solve_gen_q_limited(
+        &self,
+        v_init: DVector<Complex64>,
+        config: &PowerFlowConfig,
+    ) -> Result<SolvedNetwork, PowerFlowError> {
+        const MAX_SWITCH_ROUNDS: usize = 20;
+        let config = PowerFlowConfig {
+            enforce_gen_q_limits: false,
+            ..config.clone()
+        };
+        let tol = config.tolerance_pu(self.s_base) * self.s_base;
+        let mut switched: HashMap<ElementRef, VoltageControl> = HashMap::new();
+        let (mut v, mut iterations) = (v_init, 0);
+        for _ in 0..MAX_SWITCH_ROUNDS {
+            let pf = self.with_fixed_q(&switched);
+            // released generators start from their setpoints again
+            for x in &pf.pv_nodes {
+                let node = x.bus as usize;
+                v[node] = Complex64::from_polar(x.v, v[node].arg());
+            }
+            let (v_new, it, history) = pf.run_pf_traced(v, &config)?;
+            iterations += it;
+            v = v_new;
+            let solved = SolvedNetwork::new(&pf, v.clone(), iterations);
+            let q = |element| solved.res_gen.iter().find(|x| Some(x.element) == element);
+            let mut changed = false;
+            for x in &self.pv_nodes {
+                let Some(element) = x.element else {
+                    continue;
+                };
+                let vm = v[x.bus as usize].norm();
+                match switched.get(&element) {
+                    None => {
+                        let Some(q) = q(x.element).map(|x| x.q_mvar) else {
+                            continue;
+                        };
+                        let control = match (x.q_min, x.q_max) {
+                            (Some(q_min), _) if q < q_min - tol => VoltageControl::AtMinQ(q_min),
+                            (_, Some(q_max)) if q > q_max + tol => VoltageControl::AtMaxQ(q_max),
+                            _ => continue,
+                        };
+                        switched.insert(element, control);
+                        changed = true;
+                    }
+                    Some(&control) => {
+                        let q_limit = control.q_limit().unwrap_or_default();
+                        let setpoint = x.v - x.droop.unwrap_or(0.0) * q_limit;
+                        let released = match control {
+                            VoltageControl::AtMinQ(_) => vm < setpoint - VOLTAGE_TOLERANCE,
+                            VoltageControl::AtMaxQ(_) => vm > setpoint + VOLTAGE_TOLERANCE,
+                            VoltageControl::Controlled => false,
+                        };
+                        if released {
+                            switched.remove(&element);
+                            changed = true;
+                        }
+                    }
+                }
+            }
+            if !changed {
+                let mut solved = solved.with_mismatch_history(history);
+                solved.voltage_control = self
+                    .pv_nodes
+                    .iter()
+                    .filter_map(|x| {
+                        let element = x.element?;
+                        Some(VoltageControlStatus {
+                            element,
+                            bus: self.buses[x.bus as usize].index,
+                            control: switched
+                                .get(&element)
+                                .copied()
+                                .unwrap_or(VoltageControl::Controlled),
+                        })
+                    })
+                    .collect();
+                return Ok(solved);
+            }
+        }
+        Err(PowerFlowError::NotConverged { iterations, v })
+    }
+
+    /// Returns a copy of the network with the given generators fixed at a reactive power.
+    ///
+    /// # Arguments
+    ///
+    /// * `fixed` - The generators to fix, with the limit they are fixed at.
+    fn with_fixed_q(&self, fixed: &HashMap<ElementRef, VoltageControl>) -> PFNetwork {
+        let mut pf = self.clone();
+        let control = |x: &PVNode| x.element.and_then(|e| fixed.get(&e)).copied();
+        let (fixed, kept): (Vec<PVNode>, Vec<PVNode>) =
+            self.pv_nodes.iter().partition(|x| control(x).is_some());
+        pf.pv_nodes = kept;
+        for x in fixed {
+            let q = control(&x).and_then(|c| c.q_limit()).unwrap_or_default();
+            pf.pq_loads.push(PQNode {
+                s: -Complex64::new(x.p, q),
+                bus: x.bus,
+                element: x.element,
+                exponents: None,
+            });
+        }
+        pf
+    }
+
+    /// Computes the reactive power supplied by the external grid in MVar.
+    fn ext_q(&self, v: &DVector<Complex64>) -> f64 {
+        let node = self.ext.bus as usize;
+        let ybus = self.create_y_bus();
+        let row = ybus.row(node);
+        let i: Complex64 = row
+            .col_indices()
+            .iter()
+            .zip(row.values())
+            .map(|(k, y)| y * v[*k])
+            .sum();
+        let demand: f64 = self
+            .pq_loads
+            .iter()
+            .filter(|x| x.bus as usize == node)
+            .map(|x| x.s.im)
+            .sum();
+        (v[node] * i.conj()).im * self.s_base + demand
+    }
+
+    /// Builds a copy of the network in which the external grid is an ideal source behind its
+    /// source impedance.
+    ///
+    /// The source is an internal node appended after the buses, connected to the external grid
+    /// bus, which becomes a PQ bus.
+    ///
+    /// # Arguments
+    ///
+    /// * `z_sc` - The source impedance in per unit.
+    fn behind_source_impedance(&self, z_sc: Complex64) -> PFNetwork {
+        let bus = &self.buses[self.ext.bus as usize];
+        let v_base = bus.vn_kv;
+        let internal = Bus {
+            index: self.buses.iter().map(|b| b.index).max().unwrap_or(0) + 1,
+            name: Some("ext_grid internal".to_string()),
+            ..bus.clone()
+        };
+        let node = self.buses.len();
+        let mut y_br = self.y_br.clone();
+        y_br.push(AdmittanceBranch {
+            y: admittance::Admittance(self.s_base / (v_base * v_base) / z_sc),
+            port: admittance::Port2(vector![node as i32, self.ext.bus as i32]),
+            v_base,
+            element: None,
+            shift: 0.0,
+        });
+        let mut buses = self.buses.clone();
+        buses.push(internal);
+        PFNetwork {
+            v_base: self.v_base,
+            s_base: self.s_base,
+            buses,
+            pq_loads: self.pq_loads.clone(),
+            pv_nodes: self.pv_nodes.clone(),
+            ext: ExtGridNode {
+                bus: node as i64,
+                mode: ExtGridMode::Voltage,
+                ..self.ext
+            },
+            y_br,
+            custom: self.custom.clone(),
+            ybus_pattern: YBusCache::default(),
+        }
+    }
+
+    /// Classifies the nodes by bus type.
+    ///
+    /// A node with generators is a PV node unless it is the slack node; all other nodes are PQ
+    /// nodes. Each list is sorted by the order of appearance.
+    ///
+    /// # Returns
+    ///
+    /// The PV, PQ and slack node indices.
+    pub fn bus_types(&self) -> (Vec<i64>, Vec<i64>, Vec<i64>) {
+        self.bus_types_with_slacks(&[self.ext.bus])
+    }
+
+    /// Classifies the nodes by bus type with the given slack nodes, see
+    /// [`PFNetwork::bus_types`].
+    fn bus_types_with_slacks(&self, slacks: &[i64]) -> (Vec<i64>, Vec<i64>, Vec<i64>) {
+        let ext = slacks.to_vec();
+        let mut assigned = vec![false; self.buses.len()];
+        slacks.iter().for_each(|x| assigned[*x as usize] = true);
+        let mut pv = Vec::with_capacity(self.pv_nodes.len());
+        for x in &self.pv_nodes {
+            if !assigned[x.bus as usize] {
+                assigned[x.bus as usize] = true;
+                pv.push(x.bus);
+            }
+        }
+        let pq = (0..self.buses.len() as i64)
+            .filter(|x| !assigned[*x as usize])
+            .collect();
+        (pv, pq, ext)
+    }
+
+    /// Takes the voltage-dependent loads out of the permuted injections, so that the power flow
+    /// evaluates them at the voltages of every iterate.
+    ///
+    /// # Arguments
+    ///
+    /// * `reorder` - The permutation of [`PFNetwork::prepare_matrices`].
+    /// * `sbus` - The permuted injections in per unit, the loads are added back.
+    ///
+    /// # Returns
+    ///
+    /// The voltage-dependent loads at their permuted positions in per unit.
+    fn voltage_dependent_loads(
+        &self,
+        reorder: &CsrMatrix<Complex64>,
+        sbus: &mut DVector<Complex64>,
+    ) -> Vec<VoltageDependentLoad> {
+        let mut position = vec![0; self.buses.len()];
+        for (pos, row) in reorder.row_iter().enumerate() {
+            position[row.col_indices()[0]] = pos;
+        }
+        let loads = self.pq_loads.iter();
+        let loads = loads.filter_map(|x| Some((x, x.exponents?))).map(|(x, exponents)| {
+            let bus = position[x.bus as usize];
+            let s = x.s / self.s_base;
+            sbus[bus] += s;
+            VoltageDependentLoad { bus, s, exponents }
+        });
+        loads.collect()
     }
-}
 
-impl PFNetwork {
     /// Prepares matrices for power flow analysis.
     #[allow(non_snake_case)]
     pub fn prepare_matrices(
@@ -299,22 +1192,28 @@ impl PFNetwork {
         Matrix<Complex<f64>, Dyn, Const<1>, VecStorage<Complex<f64>, Dyn, Const<1>>>,
         usize,
         usize,
+    ) {
+        self.prepare_matrices_with_slacks(v_init, &[self.ext.bus])
+    }
+
+    /// Prepares matrices for power flow analysis with the given slack nodes, see
+    /// [`PFNetwork::prepare_matrices`].
+    #[allow(non_snake_case, clippy::type_complexity)]
+    fn prepare_matrices_with_slacks(
+        &self,
+        v_init: DVector<Complex64>,
+        slacks: &[i64],
+    ) -> (
+        CsrMatrix<Complex64>,
+        CscMatrix<Complex64>,
+        DVector<Complex64>,
+        DVector<Complex64>,
+        usize,
+        usize,
     ) {
         let Sbus = self.create_s_bus();
         let Ybus = self.create_y_bus();
-        let pv: Vec<_> = self.pv_nodes.iter().map(|x| x.bus).collect();
-        let ext: Vec<_> = vec![self.ext.bus];
-        let pq: Vec<_> = self
-            .buses
-            .iter()
-            .flat_map(|x| {
-                if pv.contains(&x.index) || ext.contains(&x.index) {
-                    None
-                } else {
-                    Some(x.index)
-                }
-            })
-            .collect();
+        let (pv, pq, ext) = self.bus_types_with_slacks(slacks);
 
         let reorder = create_premute_mat(&pv, &pq, &ext, self.buses.len());
         let from = CsrMatrix::from(&reorder);
@@ -324,7 +1223,7 @@ impl PFNetwork {
         )
         .unwrap();
         // Transform Ybus and Sbus according to the permutation
-        let Ybus: CscMatrix<_> = (&reorder * Ybus * &reorder.transpose()).transpose_as_csc();
+        let Ybus = CscMatrix::from(&(&reorder * Ybus * &reorder.transpose()));
 
         let Sbus = &reorder * Sbus;
         let v_init = &reorder * v_init;
@@ -336,6 +1235,8 @@ impl PFNetwork {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::basic::system::IslandSlack;
+    use crate::io::test_networks::{line, load, radial_network};
     #[test]
     fn test_pf() {
         let (pf, _pv, _, _) = test_system();
@@ -401,6 +1302,251 @@ mod tests {
         }
     }
     #[test]
+    fn test_scale_loads() {
+        let mut net = radial_network(3, &[(0, 1), (1, 2)]);
+        net.load.as_mut().unwrap().push(Load {
+            index: 1,
+            ..load(1, 2.0, 0.5)
+        });
+        net.sgen = Some(vec![SGen {
+            bus: 2,
+            p_mw: 1.0,
+            scaling: 1.0,
+            in_service: true,
+            ..Default::default()
+        }]);
+        net.shunt = Some(vec![Shunt {
+            bus: 1,
+            q_mvar: -0.75,
+            vn_kv: 20.0,
+            step: 1,
+            max_step: 1,
+            in_service: true,
+            ..Default::default()
+        }]);
+        let mut pf = PFNetwork::try_from(&net).unwrap();
+        let power = |pf: &PFNetwork, element| {
+            let node = pf.pq_loads.iter().find(|x| x.element == Some(element));
+            node.unwrap().s
+        };
+        let elements = [
+            ElementRef::Load(0),
+            ElementRef::Load(1),
+            ElementRef::SGen(0),
+            ElementRef::Shunt(0),
+        ];
+        let base = elements.map(|x| power(&pf, x));
+        let solve = |pf: &PFNetwork| {
+            let (v, _) = pf.run_pf_with_config(pf.create_v_init(), &Default::default()).unwrap();
+            v
+        };
+        let v_base = solve(&pf);
+
+        pf.scale_loads(1.5);
+        let scaled = elements.map(|x| power(&pf, x));
+        assert_eq!(scaled[0], base[0] * 1.5);
+        assert_eq!(scaled[1], base[1] * 1.5);
+        assert_eq!(scaled[2..], base[2..]);
+        let v_heavy = solve(&pf);
+        assert!(v_heavy[1].norm() < v_base[1].norm() - 1e-3);
+        assert!(v_heavy[2].norm() < v_base[2].norm() - 1e-3);
+
+        pf.scale_loads(1.0 / 1.5);
+        assert!((solve(&pf) - v_base).norm() < 1e-9);
+    }
+    #[test]
+    fn test_island_slacks() {
+        // the islands 0-1 and 2-3
+        let net = Network {
+            load: Some(vec![load(1, 4.0, 3.0), load(3, 4.0, 3.0)]),
+            ..radial_network(4, &[(0, 1), (2, 3)])
+        };
+        let pf = PFNetwork::try_from(&net).unwrap();
+        let slack = |bus, vm_pu, va_degree| IslandSlack {
+            bus,
+            vm_pu,
+            va_degree,
+        };
+        let mut config = PowerFlowConfig::default();
+        config.island_slacks.insert(1, slack(0, 1.02, 0.0));
+        config.island_slacks.insert(3, slack(2, 0.98, 10.0));
+        let (v, _) = pf.run_pf_with_config(pf.create_v_init(), &config).unwrap();
+        assert!((v[0] - Complex64::from_polar(1.02, 0.0)).norm() < 1e-12);
+        assert!((v[2] - Complex64::from_polar(0.98, 10f64.to_radians())).norm() < 1e-12);
+        assert!(v[1].norm() < 1.02 && v[3].norm() < 0.98);
+        // both islands carry the same load behind the same line
+        assert!((v[0] / v[1] - v[2] / v[3]).norm() < 0.01);
+
+        config.island_slacks.insert(3, slack(0, 0.98, 10.0));
+        match pf.run_pf_with_config(pf.create_v_init(), &config) {
+            Err(PowerFlowError::InvalidIslandSlack { bus }) => assert_eq!(bus, 0),
+            other => panic!("expected an invalid island slack, got {:?}", other.map(|x| x.1)),
+        }
+
+        // two entries for the same island are rejected
+        config.island_slacks.insert(3, slack(2, 0.98, 10.0));
+        config.island_slacks.insert(2, slack(3, 0.99, 0.0));
+        match pf.run_pf_with_config(pf.create_v_init(), &config) {
+            Err(PowerFlowError::DuplicateIslandSlack { bus }) => assert_eq!(bus, 3),
+            other => panic!("expected a duplicate island slack, got {:?}", other.map(|x| x.1)),
+        }
+        config.island_slacks.remove(&2);
+
+        // the external grid can't be limited or behind its impedance
+        let mut limited = pf.clone();
+        (limited.ext.q_max, limited.ext.z_sc) = (Some(0.1), Some(Complex::new(0.1, 1.0)));
+        let unsupported = |config: &PowerFlowConfig| {
+            match limited.run_pf_with_config(limited.create_v_init(), config) {
+                Err(PowerFlowError::UnsupportedWithIslandSlacks { option }) => option,
+                other => panic!("expected an unsupported option, got {:?}", other.map(|x| x.1)),
+            }
+        };
+        config.enforce_q_limits = true;
+        assert_eq!(unsupported(&config), "enforce_q_limits");
+        config.ext_grid_impedance = true;
+        assert_eq!(unsupported(&config), "ext_grid_impedance");
+    }
+    #[test]
+    fn test_dense_matches_sparse() {
+        let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let case9 = crate::io::matpower::load_matpower(format!("{}/cases/case9.m", dir));
+        let ieee118 = load_csv_zip(format!("{}/cases/IEEE118/data.zip", dir));
+        for (net, auto) in [(case9, SolverBackend::Dense), (ieee118, SolverBackend::Sparse)] {
+            let pf = PFNetwork::try_from(&net.unwrap()).unwrap();
+            let solve = |linear_solver| {
+                let config = PowerFlowConfig {
+                    linear_solver,
+                    ..Default::default()
+                };
+                pf.run_pf_with_config(pf.create_v_init(), &config).unwrap()
+            };
+            let (v_dense, it_dense) = solve(SolverBackend::Dense);
+            let (v_sparse, it_sparse) = solve(SolverBackend::Sparse);
+            assert_eq!(it_dense, it_sparse);
+            assert!((&v_dense - &v_sparse).norm() < 1e-9);
+            // small networks default to the dense solver, large ones to the sparse one
+            assert_eq!(SolverBackend::Auto.resolve(pf.buses.len()), auto);
+            let expected = match auto {
+                SolverBackend::Dense => (v_dense, it_dense),
+                _ => (v_sparse, it_sparse),
+            };
+            assert_eq!(solve(SolverBackend::Auto), expected);
+        }
+    }
+    #[cfg(feature = "faer")]
+    #[test]
+    fn test_faer_matches_sparse() {
+        let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let net = load_csv_zip(format!("{}/cases/IEEE118/data.zip", dir)).unwrap();
+        let pf = PFNetwork::try_from(&net).unwrap();
+        let solve = |linear_solver| {
+            let config = PowerFlowConfig {
+                linear_solver,
+                ..Default::default()
+            };
+            pf.run_pf_with_config(pf.create_v_init(), &config).unwrap()
+        };
+        let (v_faer, it_faer) = solve(SolverBackend::Faer);
+        let (v_sparse, it_sparse) = solve(SolverBackend::Sparse);
+        assert_eq!(it_faer, it_sparse);
+        assert!((v_faer - v_sparse).norm() < 1e-8);
+    }
+    #[cfg(not(feature = "faer"))]
+    #[test]
+    fn test_faer_unavailable() {
+        let (pf, _, _, _) = test_system();
+        let config = PowerFlowConfig {
+            linear_solver: SolverBackend::Faer,
+            ..Default::default()
+        };
+        let result = pf.run_pf_with_config(pf.create_v_init(), &config);
+        assert!(matches!(result, Err(PowerFlowError::SolverUnavailable)));
+    }
+    #[test]
+    fn test_numerical_breakdown() {
+        let (mut pf, _, _, _) = test_system();
+        // a series branch without impedance has an infinite admittance
+        let branch = pf.y_br.iter_mut().find(|x| x.port.0[1] != GND).unwrap();
+        branch.y = admittance::Admittance(Complex::new(1.0, 0.0) / Complex::new(0.0, 0.0));
+        match pf.run_pf_with_config(pf.create_v_init(), &PowerFlowConfig::default()) {
+            Err(PowerFlowError::NumericalBreakdown { iteration }) => assert_eq!(iteration, 0),
+            other => panic!("expected a numerical breakdown, got {:?}", other.map(|x| x.1)),
+        }
+    }
+    #[test]
+    fn test_hand_built_network() {
+        // a feeder 0-1-2 with a voltage-controlled generator at bus 1 and the load at bus 2
+        let net = Network {
+            gen: Some(vec![Gen {
+                bus: 1,
+                in_service: true,
+                p_mw: 1.0,
+                vm_pu: 1.01,
+                scaling: 1.0,
+                ..Default::default()
+            }]),
+            ..radial_network(3, &[(0, 1), (1, 2)])
+        };
+        let pf = PFNetwork::try_from(&net).unwrap();
+        let (v, iterations) = pf
+            .run_pf_with_config(pf.create_v_init(), &PowerFlowConfig::default())
+            .unwrap();
+        assert!(iterations < 10);
+        assert!((v[1].norm() - 1.01).abs() < 1e-9);
+        let mis = v.component_mul(&(pf.create_y_bus() * &v).conjugate()) - pf.create_s_bus();
+        // the PV bus balances its active power only, the PQ bus both
+        assert!(mis[1].re.abs() < 1e-6);
+        assert!(mis[2].norm() < 1e-6);
+    }
+    #[test]
+    fn test_regularized_solve() {
+        // a reactive line loaded close to its transfer limit of 8 pu, with a shunt capacitor
+        // compensating half of its susceptance, which makes the Jacobian singular at flat start;
+        // the line of 0.5 ohm at 20 kV is exactly 0.125 pu on the base of 100 MVA
+        let mut net = Network {
+            line: Some(vec![Line {
+                r_ohm_per_km: 0.0,
+                x_ohm_per_km: 0.25,
+                ..line(0, 1)
+            }]),
+            load: Some(vec![load(1, 720.0, 0.0)]),
+            ..radial_network(2, &[])
+        };
+        net.bus[1].b = Some(400.0);
+        let pf = PFNetwork::try_from(&net).unwrap();
+
+        // the dense solver reports the singular Jacobian, which RSparse can't factorize at all
+        let plain = PowerFlowConfig {
+            linear_solver: SolverBackend::Dense,
+            ..Default::default()
+        };
+        let plain = pf.run_pf_with_config(pf.create_v_init(), &plain);
+        assert!(matches!(plain, Err(PowerFlowError::NumericalBreakdown { .. })));
+
+        let config = PowerFlowConfig {
+            regularize: true,
+            ..Default::default()
+        };
+        let (v, _) = pf.run_pf_with_config(pf.create_v_init(), &config).unwrap();
+        let mis = v.component_mul(&(pf.create_y_bus() * &v).conjugate()) - pf.create_s_bus();
+        assert!(mis[1].norm() < 1e-6);
+    }
+    #[test]
+    fn test_stalled() {
+        let (mut pf, _, _, _) = test_system();
+        // far beyond the loadability limit there is no solution to converge to
+        pf.pq_loads.iter_mut().for_each(|x| x.s *= 10.0);
+        let config = PowerFlowConfig {
+            max_iterations: 100,
+            stall_patience: Some(5),
+            ..Default::default()
+        };
+        match pf.run_pf_with_config(pf.create_v_init(), &config) {
+            Err(PowerFlowError::Stalled { iteration }) => assert!(iteration < 30, "{}", iteration),
+            other => panic!("expected a stalled solve, got {:?}", other.map(|x| x.1)),
+        }
+    }
+    #[test]
     fn test_ybus() {
         let (pf, _pv, nodes, _) = test_system();
 
@@ -410,6 +1556,21 @@ mod tests {
         assert_eq!(nan, false, "invalid parameters {:?}", ybus.values());
     }
 
+    #[test]
+    fn test_ybus_matches_incidence_assembly() {
+        let net = crate::io::pandapower::load_csv_zip(format!(
+            "{}/cases/IEEE118/data.zip",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let pf = PFNetwork::try_from(net).unwrap();
+        let incidence_matrix = create_incidence_mat(pf.buses.len(), &pf.y_br);
+        let reference = create_ybus(&pf, &incidence_matrix, &pf.y_br);
+        let ybus = pf.create_y_bus();
+        let diff = DMatrix::from(&ybus) - DMatrix::from(&reference);
+        assert!(diff.iter().all(|x| x.norm() < 1e-9));
+    }
+
     #[test]
     fn test_node_reordering() {
         let (pf, pv, nodes, _) = test_system();
@@ -621,7 +1782,7 @@ mod tests {
 pub fn test_system() -> (PFNetwork, Vec<i64>, usize, Vec<Complex<f64>>) {
     let file_path = test_ieee39::IEEE_39;
     let net: Network = serde_json::from_str(file_path).unwrap();
-    let pf = PFNetwork::from(net);
+    let pf = PFNetwork::try_from(net).unwrap();
     let pv: Vec<_> = pf.pv_nodes.iter().map(|x| x.bus).collect();
     let nodes = pf.buses.len();
     let admits: Vec<_> = pf.y_br.iter().map(|x| x.y.0).collect();
@@ -0,0 +1,406 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use nalgebra_sparse::{io::save_to_matrix_market_file, pattern::SparsityPattern};
+use nalgebra_sparse::{CooMatrix, CscMatrix, CsrMatrix};
+use num_complex::Complex64;
+
+use super::dcpf::solve_real;
+use super::{AdmittanceBranch, PFNetwork, PowerFlowConfig, RunPF, GND};
+use crate::basic::system::admittance::Admittance;
+
+/// The fixed sparsity pattern of the nodal admittance matrix (Ybus).
+///
+/// The pattern is derived from the ports of the admittance branches only, so it can be reused
+/// for repeated assemblies as long as the topology doesn't change; then only the values are
+/// updated, without building an intermediate COO matrix.
+#[derive(Debug, Clone)]
+pub struct YBusPattern {
+    pattern: SparsityPattern,
+    /// The ports of the branches the pattern was built for.
+    ports: Vec<(i32, i32)>,
+    /// For every branch, the value positions of its `(f, f)`, `(t, t)`, `(f, t)` and `(t, f)`
+    /// entries (`None` for entries involving ground).
+    entries: Vec<[Option<usize>; 4]>,
+}
+
+impl YBusPattern {
+    /// Builds the pattern for the given branches.
+    ///
+    /// # Arguments
+    ///
+    /// * `nodes` - The number of nodes.
+    /// * `y_br` - The admittance branches.
+    pub fn new(nodes: usize, y_br: &[AdmittanceBranch]) -> Self {
+        let ports: Vec<_> = y_br.iter().map(|x| (x.port.0[0], x.port.0[1])).collect();
+        let mut rows: Vec<Vec<usize>> = vec![Vec::new(); nodes];
+        for &(f, t) in ports.iter() {
+            if f != GND {
+                rows[f as usize].push(f as usize);
+            }
+            if t != GND {
+                rows[t as usize].push(t as usize);
+            }
+            if f != GND && t != GND {
+                rows[f as usize].push(t as usize);
+                rows[t as usize].push(f as usize);
+            }
+        }
+        let mut offsets = Vec::with_capacity(nodes + 1);
+        let mut indices = Vec::new();
+        offsets.push(0);
+        for row in rows.iter_mut() {
+            row.sort_unstable();
+            row.dedup();
+            indices.extend_from_slice(row);
+            offsets.push(indices.len());
+        }
+
+        let position = |i: i32, j: i32| {
+            if i == GND || j == GND {
+                return None;
+            }
+            let (i, j) = (i as usize, j as usize);
+            let cols = &indices[offsets[i]..offsets[i + 1]];
+            cols.binary_search(&j).ok().map(|k| offsets[i] + k)
+        };
+        let entries = ports
+            .iter()
+            .map(|&(f, t)| {
+                [
+                    position(f, f),
+                    position(t, t),
+                    position(f, t),
+                    position(t, f),
+                ]
+            })
+            .collect();
+
+        let pattern =
+            SparsityPattern::try_from_offsets_and_indices(nodes, nodes, offsets, indices).unwrap();
+        Self {
+            pattern,
+            ports,
+            entries,
+        }
+    }
+
+    /// Returns `true` if the pattern was built for branches with the same ports.
+    pub fn matches(&self, y_br: &[AdmittanceBranch]) -> bool {
+        self.ports.len() == y_br.len()
+            && self
+                .ports
+                .iter()
+                .zip(y_br)
+                .all(|(&(f, t), x)| f == x.port.0[0] && t == x.port.0[1])
+    }
+
+    /// Assembles the Ybus in per unit.
+    ///
+    /// # Arguments
+    ///
+    /// * `y_br` - The admittance branches the pattern was built for.
+    /// * `s_base` - The base power of the network.
+    pub fn assemble(&self, y_br: &[AdmittanceBranch], s_base: f64) -> CsrMatrix<Complex64> {
+        let mut values = vec![Complex64::new(0.0, 0.0); self.pattern.nnz()];
+        self.accumulate(&mut values, y_br, s_base);
+        CsrMatrix::try_from_pattern_and_values(self.pattern.clone(), values).unwrap()
+    }
+
+    /// Overwrites the values of a Ybus assembled from this pattern.
+    ///
+    /// # Arguments
+    ///
+    /// * `ybus` - The Ybus to update, previously returned by [`YBusPattern::assemble`].
+    /// * `y_br` - The admittance branches the pattern was built for.
+    /// * `s_base` - The base power of the network.
+    pub fn update(&self, ybus: &mut CsrMatrix<Complex64>, y_br: &[AdmittanceBranch], s_base: f64) {
+        let values = ybus.values_mut();
+        values.fill(Complex64::new(0.0, 0.0));
+        self.accumulate(values, y_br, s_base);
+    }
+
+    fn accumulate(&self, values: &mut [Complex64], y_br: &[AdmittanceBranch], s_base: f64) {
+        for (br, entries) in y_br.iter().zip(self.entries.iter()) {
+            let y = br.y.0 * (br.v_base * br.v_base) / s_base;
+            let rotation = Complex64::from_polar(1.0, br.shift);
+            let stamps = [y, y, -y * rotation, -y * rotation.conj()];
+            for (entry, stamp) in entries.iter().zip(stamps) {
+                if let Some(pos) = entry {
+                    values[*pos] += stamp;
+                }
+            }
+        }
+    }
+}
+
+/// A [`YBusPattern`] built on first use and kept for later assemblies of the same network.
+///
+/// The pattern is rebuilt if the number of nodes or the ports of the branches have changed
+/// since, so the branches can still be edited freely.
+#[derive(Debug, Default)]
+pub struct YBusCache(Mutex<Option<YBusPattern>>);
+
+impl Clone for YBusCache {
+    fn clone(&self) -> Self {
+        Self(Mutex::new(self.0.lock().unwrap().clone()))
+    }
+}
+
+impl YBusCache {
+    /// Assembles the Ybus in per unit from the cached pattern.
+    ///
+    /// # Arguments
+    ///
+    /// * `nodes` - The number of nodes.
+    /// * `y_br` - The admittance branches.
+    /// * `s_base` - The base power of the network.
+    pub fn assemble(
+        &self,
+        nodes: usize,
+        y_br: &[AdmittanceBranch],
+        s_base: f64,
+    ) -> CsrMatrix<Complex64> {
+        let mut cached = self.0.lock().unwrap();
+        let pattern = match cached.take() {
+            Some(x) if x.pattern.major_dim() == nodes && x.matches(y_br) => x,
+            _ => YBusPattern::new(nodes, y_br),
+        };
+        cached.insert(pattern).assemble(y_br, s_base)
+    }
+}
+
+impl PFNetwork {
+    /// Writes the nodal admittance matrix (Ybus) in the MatrixMarket format.
+    ///
+    /// The matrix is written as a complex coordinate matrix in per unit, with one-based row and
+    /// column indices in node order, so it can be read by external numerical tools.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file to write.
+    pub fn write_ybus_mm<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        save_to_matrix_market_file(&self.create_y_bus(), path)
+    }
+
+    /// Assembles the nodal admittance matrix at a harmonic of the fundamental frequency.
+    ///
+    /// The reactances of the series branches and the susceptances of the branches to ground
+    /// are scaled by the harmonic order, while resistances and conductances are kept. The
+    /// branches of the transformer taps are scaled like shunts, which is exact at the nominal
+    /// ratio only; user-defined admittances are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `h` - The harmonic order, 1 for the fundamental.
+    ///
+    /// # Returns
+    ///
+    /// The admittance matrix in per unit, in node order.
+    pub fn harmonic_y_bus(&self, h: f64) -> CsrMatrix<Complex64> {
+        let y_br: Vec<AdmittanceBranch> = self
+            .y_br
+            .iter()
+            .map(|br| {
+                let y = if br.port.0.iter().any(|x| *x == GND) {
+                    Complex64::new(br.y.0.re, h * br.y.0.im)
+                } else {
+                    let z = 1.0 / br.y.0;
+                    1.0 / Complex64::new(z.re, h * z.im)
+                };
+                AdmittanceBranch {
+                    y: Admittance(y),
+                    ..br.clone()
+                }
+            })
+            .collect();
+        YBusPattern::new(self.buses.len(), &y_br).assemble(&y_br, self.s_base)
+    }
+
+    /// Computes the driving-point impedance of a bus at a harmonic.
+    ///
+    /// The external grid is an ideal voltage source, which shorts the harmonic currents, so
+    /// the impedance is the one seen between the bus and the slack through the network.
+    ///
+    /// # Arguments
+    ///
+    /// * `bus` - The identifier of the bus.
+    /// * `h` - The harmonic order, see [`PFNetwork::harmonic_y_bus`].
+    /// * `config` - The power flow options, which select the linear solver.
+    ///
+    /// # Returns
+    ///
+    /// The impedance in per unit, zero at the slack, or `None` if the bus doesn't exist or has
+    /// no path to the slack.
+    pub fn harmonic_impedance(
+        &self,
+        bus: i64,
+        h: f64,
+        config: &PowerFlowConfig,
+    ) -> Option<Complex64> {
+        let node = self.node_lookup().get(bus)?;
+        let slack = self.ext.bus as usize;
+        if node == slack {
+            return Some(Complex64::new(0.0, 0.0));
+        }
+        // the complex system without the slack as the real system [G -B; B G]
+        let n = self.buses.len() - 1;
+        let reduced = |i: usize| if i < slack { i } else { i - 1 };
+        let mut coo = CooMatrix::new(2 * n, 2 * n);
+        for (i, j, y) in self.harmonic_y_bus(h).triplet_iter() {
+            if i == slack || j == slack {
+                continue;
+            }
+            let (i, j) = (reduced(i), reduced(j));
+            coo.push(i, j, y.re);
+            coo.push(i, n + j, -y.im);
+            coo.push(n + i, j, y.im);
+            coo.push(n + i, n + j, y.re);
+        }
+        let mut b = vec![0.0; 2 * n];
+        b[reduced(node)] = 1.0;
+        solve_real(config.linear_solver, &CscMatrix::from(&coo), &mut b).ok()?;
+        let z = Complex64::new(b[reduced(node)], b[n + reduced(node)]);
+        (z.is_finite() && z.norm() < 1e12).then_some(z)
+    }
+
+    /// Extracts the nodal susceptance matrix (Bbus), the imaginary part of the Ybus.
+    ///
+    /// The matrix keeps the sparsity pattern of the Ybus, so entries whose admittance is
+    /// purely real are stored as explicit zeros. For the DC power flow and PTDFs note that
+    /// the susceptances of inductive branches are negative.
+    ///
+    /// # Returns
+    ///
+    /// The susceptance matrix in per unit, in node order.
+    pub fn b_bus(&self) -> CsrMatrix<f64> {
+        let ybus = self.create_y_bus();
+        let values = ybus.values().iter().map(|x| x.im).collect();
+        CsrMatrix::try_from_pattern_and_values(ybus.pattern().clone(), values).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::system::test_system;
+    use nalgebra_sparse::io::load_coo_from_matrix_market_file;
+
+    #[test]
+    fn test_update_values() {
+        let (mut pf, _, _, _) = test_system();
+        let pattern = YBusPattern::new(pf.buses.len(), &pf.y_br);
+        let mut ybus = pattern.assemble(&pf.y_br, pf.s_base);
+        assert!(pattern.matches(&pf.y_br));
+
+        pf.y_br.iter_mut().for_each(|x| x.y.0 *= 2.0);
+        pattern.update(&mut ybus, &pf.y_br, pf.s_base);
+        let expected = pf.create_y_bus();
+        assert_eq!(ybus.pattern(), expected.pattern());
+        let diff = ybus
+            .values()
+            .iter()
+            .zip(expected.values())
+            .map(|(a, b)| (a - b).norm())
+            .fold(0.0, f64::max);
+        assert!(diff < 1e-9);
+
+        pf.y_br.pop();
+        assert!(!pattern.matches(&pf.y_br));
+    }
+
+    #[test]
+    fn test_cached_pattern() {
+        let (mut pf, _, _, _) = test_system();
+        let fresh = |pf: &PFNetwork| {
+            YBusPattern::new(pf.buses.len(), &pf.y_br).assemble(&pf.y_br, pf.s_base)
+        };
+        assert!(pf.ybus_pattern.0.lock().unwrap().is_none());
+        assert_eq!(pf.create_y_bus(), fresh(&pf));
+        assert!(pf.ybus_pattern.0.lock().unwrap().is_some());
+
+        // new values reuse the pattern, new ports rebuild it
+        pf.y_br.iter_mut().for_each(|x| x.y.0 *= 2.0);
+        assert_eq!(pf.create_y_bus(), fresh(&pf));
+        pf.y_br.pop();
+        assert_eq!(pf.create_y_bus(), fresh(&pf));
+        let cached = pf.ybus_pattern.0.lock().unwrap();
+        assert!(cached.as_ref().unwrap().matches(&pf.y_br));
+    }
+
+    #[test]
+    fn test_b_bus() {
+        let (pf, _, _, _) = test_system();
+        let ybus = pf.create_y_bus();
+        let bbus = pf.b_bus();
+        assert_eq!(bbus.pattern(), ybus.pattern());
+        for (b, y) in bbus.triplet_iter().zip(ybus.triplet_iter()) {
+            assert_eq!((b.0, b.1, *b.2), (y.0, y.1, y.2.im));
+        }
+    }
+
+    #[test]
+    fn test_harmonic_y_bus() {
+        let (pf, _, _, _) = test_system();
+        let fundamental = pf.create_y_bus();
+        let fifth = pf.harmonic_y_bus(5.0);
+        assert_eq!(fifth.pattern(), fundamental.pattern());
+        let mut checked = 0;
+        for ((i, j, y1), (_, _, y5)) in fundamental.triplet_iter().zip(fifth.triplet_iter()) {
+            let (a, b) = (i as i32, j as i32);
+            let series = pf.y_br.iter().filter(|br| {
+                let (f, t) = (br.port.0[0], br.port.0[1]);
+                (f, t) == (a, b) || (t, f) == (a, b)
+            });
+            if i == j || series.count() != 1 {
+                continue;
+            }
+            // the branch impedance between two nodes joined by a single branch
+            let (z1, z5) = (-1.0 / y1, -1.0 / y5);
+            assert!((z5.re - z1.re).abs() < 1e-9 * z1.norm(), "{} {}", z1, z5);
+            assert!((z5.im - 5.0 * z1.im).abs() < 1e-9 * z1.norm(), "{} {}", z1, z5);
+            checked += 1;
+        }
+        assert!(checked > 30);
+
+        // the driving-point impedance is the diagonal of the inverse with the slack grounded
+        let config = PowerFlowConfig::default();
+        let slack = pf.ext.bus as usize;
+        let keep: Vec<usize> = (0..pf.buses.len()).filter(|x| *x != slack).collect();
+        let dense = nalgebra::DMatrix::from_fn(keep.len(), keep.len(), |i, j| {
+            fifth
+                .get_entry(keep[i], keep[j])
+                .map_or(Complex64::new(0.0, 0.0), |x| x.into_value())
+        });
+        let z = dense.try_inverse().unwrap();
+        for (i, node) in keep.iter().enumerate().step_by(7) {
+            let bus = pf.buses[*node].index;
+            let z_kk = pf.harmonic_impedance(bus, 5.0, &config).unwrap();
+            assert!((z_kk - z[(i, i)]).norm() < 1e-9 * z_kk.norm(), "{} {}", z_kk, z[(i, i)]);
+        }
+        let slack_bus = pf.buses[slack].index;
+        assert_eq!(pf.harmonic_impedance(slack_bus, 5.0, &config), Some(Complex64::new(0.0, 0.0)));
+        assert_eq!(pf.harmonic_impedance(-1, 5.0, &config), None);
+    }
+
+    #[test]
+    fn test_write_ybus_mm() {
+        let (pf, _, _, _) = test_system();
+        let path = std::env::temp_dir().join(format!("rustpower_ybus_{}.mtx", std::process::id()));
+        pf.write_ybus_mm(&path).unwrap();
+        let header = std::fs::read_to_string(&path).unwrap();
+        assert!(header.starts_with("%%matrixmarket matrix coordinate complex general"));
+        let coo = load_coo_from_matrix_market_file::<Complex64, _>(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let expected = pf.create_y_bus();
+        let read = CsrMatrix::from(&coo);
+        assert_eq!((read.nrows(), read.ncols()), (pf.buses.len(), pf.buses.len()));
+        assert_eq!(read.nnz(), expected.nnz());
+        for (row, col) in [(0, 0), (0, 1), (1, 0), (30, 30)] {
+            let a = read.get_entry(row, col).unwrap().into_value();
+            let b = expected.get_entry(row, col).unwrap().into_value();
+            assert!((a - b).norm() < 1e-9 * b.norm(), "{} {}", a, b);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use num_complex::Complex64;
+
+use super::{terminal_powers, BranchResult, ElementRef, PFNetwork, SolvedNetwork, GND};
+
+/// Represents the aggregated power balance of a zone.
+///
+/// The balance closes per zone: generation minus load minus losses equals the export.
+#[derive(Debug, Clone, Default)]
+pub struct ZoneSummary {
+    /// The zone label of the buses, `None` for buses without a zone.
+    pub zone: Option<i64>,
+    /// The active power demand of loads and shunt elements in MW.
+    pub p_load_mw: f64,
+    /// The reactive power demand of loads and shunt elements in MVar.
+    pub q_load_mvar: f64,
+    /// The active power supplied by generators, static generators and the external grid in MW.
+    pub p_gen_mw: f64,
+    /// The reactive power supplied by generators, static generators and the external grid in MVar.
+    pub q_gen_mvar: f64,
+    /// The active power losses of the branches within the zone in MW.
+    pub p_loss_mw: f64,
+    /// The reactive power consumed by the branches within the zone in MVar.
+    pub q_loss_mvar: f64,
+    /// The active power flowing out of the zone over the tie branches in MW.
+    pub p_export_mw: f64,
+    /// The reactive power flowing out of the zone over the tie branches in MVar.
+    pub q_export_mvar: f64,
+}
+
+impl SolvedNetwork {
+    /// Aggregates load, generation, losses and interchange per zone.
+    ///
+    /// A branch element connecting buses of different zones is a tie; its terminal flows
+    /// (including its shunt admittances) count as export of the zone at each end. All other
+    /// branches contribute to the losses of their zone.
+    ///
+    /// # Arguments
+    ///
+    /// * `pf` - The power flow network the results were solved for.
+    ///
+    /// # Returns
+    ///
+    /// The summaries, ordered by zone.
+    pub fn zone_summary(&self, pf: &PFNetwork) -> Vec<ZoneSummary> {
+        let zone_of = |node: i32| pf.buses[node as usize].zone;
+        let mut zones: BTreeMap<Option<i64>, ZoneSummary> = pf
+            .buses
+            .iter()
+            .map(|x| {
+                let summary = ZoneSummary {
+                    zone: x.zone,
+                    ..Default::default()
+                };
+                (x.zone, summary)
+            })
+            .collect();
+
+        let mut demand = vec![Complex64::new(0.0, 0.0); pf.buses.len()];
+        for x in &pf.pq_loads {
+            if !matches!(x.element, Some(ElementRef::SGen(_) | ElementRef::Gen(_))) {
+                demand[x.bus as usize] += x.power_at(self.res_bus[x.bus as usize].vm_pu);
+            }
+        }
+        for (node, res) in self.res_bus.iter().enumerate() {
+            let summary = zones.get_mut(&pf.buses[node].zone).unwrap();
+            let injection = Complex64::new(-res.p_mw, -res.q_mvar);
+            let generation = injection + demand[node];
+            summary.p_load_mw += demand[node].re;
+            summary.q_load_mvar += demand[node].im;
+            summary.p_gen_mw += generation.re;
+            summary.q_gen_mvar += generation.im;
+        }
+
+        let ports = |x: [i32; 2]| x.into_iter().filter(|node| *node != GND);
+        let mut element_zones: BTreeMap<ElementRef, HashSet<Option<i64>>> = BTreeMap::new();
+        for br in pf.y_br.iter() {
+            if let Some(element) = br.element {
+                let zones = element_zones.entry(element).or_default();
+                zones.extend(ports([br.port.0[0], br.port.0[1]]).map(zone_of));
+            }
+        }
+        for br in pf.y_br.iter() {
+            let is_tie = match br.element {
+                Some(element) => element_zones[&element].len() > 1,
+                None => {
+                    let zones: HashSet<_> =
+                        ports([br.port.0[0], br.port.0[1]]).map(zone_of).collect();
+                    zones.len() > 1
+                }
+            };
+            for (node, s) in terminal_powers(pf, br, &self.v) {
+                if node == GND {
+                    continue;
+                }
+                let summary = zones.get_mut(&zone_of(node)).unwrap();
+                if is_tie {
+                    summary.p_export_mw += s.re;
+                    summary.q_export_mvar += s.im;
+                } else {
+                    summary.p_loss_mw += s.re;
+                    summary.q_loss_mvar += s.im;
+                }
+            }
+        }
+        zones.into_values().collect()
+    }
+
+    /// Returns the lines and transformers whose terminal buses lie in different zones.
+    ///
+    /// # Arguments
+    ///
+    /// * `pf` - The power flow network the results were solved for.
+    pub fn tie_lines(&self, pf: &PFNetwork) -> Vec<&BranchResult> {
+        let zones: HashMap<i64, Option<i64>> = pf.buses.iter().map(|x| (x.index, x.zone)).collect();
+        self.res_line
+            .iter()
+            .chain(self.res_trafo.iter())
+            .filter(|x| zones[&x.from_bus] != zones[&x.to_bus])
+            .collect()
+    }
+
+    /// Computes the net active power interchange of every zone over the tie lines.
+    ///
+    /// The interchange of a zone is the sum of the tie-line flows leaving it, measured at the
+    /// terminals inside the zone, so that it is positive for an exporting zone.
+    ///
+    /// # Arguments
+    ///
+    /// * `pf` - The power flow network the results were solved for.
+    ///
+    /// # Returns
+    ///
+    /// The net interchange in MW by zone; zones without tie lines are omitted.
+    pub fn area_interchange(&self, pf: &PFNetwork) -> BTreeMap<Option<i64>, f64> {
+        let zones: HashMap<i64, Option<i64>> = pf.buses.iter().map(|x| (x.index, x.zone)).collect();
+        let mut interchange = BTreeMap::new();
+        for x in self.tie_lines(pf) {
+            *interchange.entry(zones[&x.from_bus]).or_insert(0.0) += x.p_from_mw;
+            *interchange.entry(zones[&x.to_bus]).or_insert(0.0) += x.p_to_mw;
+        }
+        interchange
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::system::RunPF;
+    use crate::io::pandapower::*;
+    use crate::io::test_networks::*;
+
+    /// Builds a four-bus feeder whose second line ties zone 1 (buses 0 and 1) to zone 2.
+    fn two_zone_feeder() -> Network {
+        let mut bus = buses(4, 20.0);
+        bus.iter_mut().for_each(|x| x.zone = Some(1 + x.index / 2));
+        let charged = |(from_bus, to_bus)| Line {
+            c_nf_per_km: 10.0,
+            ..line(from_bus, to_bus)
+        };
+        Network {
+            bus,
+            ext_grid: Some(vec![ext_grid(0)]),
+            line: Some([(0, 1), (1, 2), (2, 3)].map(charged).into()),
+            load: Some(vec![load(1, 2.0, 0.5), load(3, 2.0, 0.5)]),
+            ..Default::default()
+        }
+    }
+
+    fn solve(net: &Network) -> (PFNetwork, SolvedNetwork) {
+        let pf = PFNetwork::try_from(net).unwrap();
+        let (v, iterations) = pf.run_pf(pf.create_v_init(), Some(10), Some(1e-8));
+        let solved = SolvedNetwork::new(&pf, v, iterations);
+        (pf, solved)
+    }
+
+    #[test]
+    fn test_zone_interchange() {
+        let (pf, solved) = solve(&two_zone_feeder());
+        let summary = solved.zone_summary(&pf);
+        assert_eq!(summary.len(), 2);
+        let tie = solved
+            .res_line
+            .iter()
+            .find(|x| x.element == ElementRef::Line(1))
+            .unwrap();
+        let (exporter, importer) = (&summary[0], &summary[1]);
+        assert!((exporter.p_export_mw - tie.p_from_mw).abs() < 1e-9);
+        assert!((importer.p_export_mw - tie.p_to_mw).abs() < 1e-9);
+        for x in &summary {
+            let imbalance = x.p_gen_mw - x.p_load_mw - x.p_loss_mw;
+            assert!((imbalance - x.p_export_mw).abs() < 1e-6, "{:?}", x);
+        }
+        assert!(importer.p_gen_mw.abs() < 1e-6);
+        assert!((importer.p_load_mw - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_area_interchange() {
+        let mut net = two_zone_feeder();
+        // a lossless tie line transfers the same power out of one area into the other
+        let tie = &mut net.line.as_mut().unwrap()[1];
+        tie.r_ohm_per_km = 0.0;
+        tie.c_nf_per_km = 0.0;
+        let (pf, solved) = solve(&net);
+
+        let ties = solved.tie_lines(&pf);
+        assert_eq!(ties.len(), 1);
+        assert_eq!(ties[0].element, ElementRef::Line(1));
+
+        let interchange = solved.area_interchange(&pf);
+        let (a, b) = (interchange[&Some(1)], interchange[&Some(2)]);
+        assert!(a > 2.0);
+        assert!((a + b).abs() < 1e-9, "{} {}", a, b);
+        let summary = solved.zone_summary(&pf);
+        assert!((summary[0].p_export_mw - a).abs() < 1e-9);
+    }
+}
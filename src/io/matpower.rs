@@ -0,0 +1,397 @@
+//! Converts MATPOWER case files into the pandapower network structure.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fs;
+use std::io::{Error, ErrorKind};
+
+use super::pandapower::{Bus, ExtGrid, Gen, Line, Load, Network, SGen, Transformer};
+
+/// The columns of the MATPOWER bus table.
+const BUS_I: usize = 0;
+const BUS_TYPE: usize = 1;
+const PD: usize = 2;
+const QD: usize = 3;
+const GS: usize = 4;
+const BS: usize = 5;
+const VM: usize = 7;
+const VA: usize = 8;
+const BASE_KV: usize = 9;
+const ZONE: usize = 10;
+const VMAX: usize = 11;
+const VMIN: usize = 12;
+
+/// The columns of the MATPOWER generator table.
+const GEN_BUS: usize = 0;
+const PG: usize = 1;
+const QG: usize = 2;
+const QMAX: usize = 3;
+const QMIN: usize = 4;
+const VG: usize = 5;
+const MBASE: usize = 6;
+const GEN_STATUS: usize = 7;
+const PMAX: usize = 8;
+const PMIN: usize = 9;
+
+/// The columns of the MATPOWER branch table.
+const F_BUS: usize = 0;
+const T_BUS: usize = 1;
+const BR_R: usize = 2;
+const BR_X: usize = 3;
+const BR_B: usize = 4;
+const RATE_A: usize = 5;
+const TAP: usize = 8;
+const SHIFT: usize = 9;
+const BR_STATUS: usize = 10;
+
+/// The MATPOWER bus types.
+const REF: f64 = 3.0;
+const PQ: f64 = 1.0;
+const NONE: f64 = 4.0;
+
+/// Returns an error for malformed case data.
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Returns the text assigned to a field of the case struct, e.g. `mpc.bus = [...];`.
+fn field<'a>(content: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!("mpc.{}", name);
+    let mut rest = content;
+    while let Some(start) = rest.find(&pattern) {
+        let after = rest[start + pattern.len()..].trim_start();
+        if let Some(value) = after.strip_prefix('=') {
+            let value = value.trim_start();
+            let end = if value.starts_with('[') {
+                value.find(']').map(|x| x + 1)
+            } else {
+                value.find(';')
+            };
+            return Some(&value[..end.unwrap_or(value.len())]);
+        }
+        rest = after;
+    }
+    None
+}
+
+/// Parses a number of the case file.
+fn number(text: &str) -> Result<f64, Error> {
+    match text {
+        "Inf" | "inf" => Ok(f64::INFINITY),
+        "-Inf" | "-inf" => Ok(f64::NEG_INFINITY),
+        _ => text
+            .parse()
+            .map_err(|_| invalid(format!("{} is not a number", text))),
+    }
+}
+
+/// Parses a matrix of the case file, whose rows are separated by semicolons or line breaks.
+///
+/// # Arguments
+///
+/// * `content` - The case file without comments.
+/// * `name` - The name of the field holding the matrix.
+/// * `columns` - The minimal number of columns of each row.
+///
+/// # Returns
+///
+/// The rows, or an error if the field is missing or malformed.
+fn matrix(content: &str, name: &str, columns: usize) -> Result<Vec<Vec<f64>>, Error> {
+    let value = field(content, name).ok_or_else(|| invalid(format!("mpc.{} is missing", name)))?;
+    let body = value
+        .strip_prefix('[')
+        .and_then(|x| x.strip_suffix(']'))
+        .ok_or_else(|| invalid(format!("mpc.{} is not a matrix", name)))?;
+    let mut rows = Vec::new();
+    for row in body.split([';', '\n']) {
+        let row = row
+            .split(|x: char| x.is_whitespace() || x == ',')
+            .filter(|x| !x.is_empty())
+            .map(number)
+            .collect::<Result<Vec<_>, _>>()?;
+        if row.is_empty() {
+            continue;
+        }
+        if row.len() < columns {
+            return Err(invalid(format!(
+                "a row of mpc.{} has {} columns instead of {}",
+                name,
+                row.len(),
+                columns
+            )));
+        }
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Returns the pandapower index of a MATPOWER bus number, which counts from one.
+fn bus_index(number: f64) -> i64 {
+    number as i64 - 1
+}
+
+/// Parses the content of a MATPOWER case file.
+///
+/// Buses keep their number shifted to count from zero, as in pandapower's conversion. The
+/// generators at the reference bus become the external grid, the ones at PQ buses static
+/// generators and the others voltage-controlled generators. Branches without a tap ratio or
+/// phase shift between buses of the same voltage become lines of 1 km, the others transformers
+/// rated at the base power with the tap on the from side; the charging of transformer branches
+/// is not modeled.
+///
+/// # Arguments
+///
+/// * `content` - The content of the case file.
+///
+/// # Returns
+///
+/// The network, or an error if the bus, generator or branch table is missing or malformed.
+pub fn parse_matpower(content: &str) -> Result<Network, Error> {
+    let content = content
+        .lines()
+        .map(|x| x.split('%').next().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let base_mva = match field(&content, "baseMVA") {
+        Some(value) => number(value.trim())?,
+        None => 100.0,
+    };
+    let bus_rows = matrix(&content, "bus", VMIN + 1)?;
+    let gen_rows = matrix(&content, "gen", PMIN + 1)?;
+    let branch_rows = matrix(&content, "branch", BR_STATUS + 1)?;
+
+    let bus_types: HashMap<i64, f64> = bus_rows
+        .iter()
+        .map(|x| (bus_index(x[BUS_I]), x[BUS_TYPE]))
+        .collect();
+    let base_kv: HashMap<i64, f64> = bus_rows
+        .iter()
+        .map(|x| (bus_index(x[BUS_I]), x[BASE_KV]))
+        .collect();
+    let bus_kv = |bus: i64| {
+        base_kv
+            .get(&bus)
+            .copied()
+            .ok_or_else(|| invalid(format!("bus {} is missing", bus + 1)))
+    };
+
+    let mut bus = Vec::new();
+    let mut load = Vec::new();
+    for row in &bus_rows {
+        let index = bus_index(row[BUS_I]);
+        bus.push(Bus {
+            index,
+            in_service: row[BUS_TYPE] != NONE,
+            max_vm_pu: Some(row[VMAX]),
+            min_vm_pu: Some(row[VMIN]),
+            name: Some((index + 1).to_string()),
+            type_: Some("b".to_string()),
+            vn_kv: row[BASE_KV],
+            zone: Some(row[ZONE] as i64),
+            g: (row[GS] != 0.0).then_some(row[GS]),
+            b: (row[BS] != 0.0).then_some(row[BS]),
+        });
+        if row[PD] != 0.0 || row[QD] != 0.0 {
+            load.push(Load {
+                index: load.len() as i64,
+                bus: index,
+                in_service: true,
+                p_mw: row[PD],
+                q_mvar: row[QD],
+                scaling: 1.0,
+                ..Default::default()
+            });
+        }
+    }
+
+    let mut gen = Vec::new();
+    let mut sgen = Vec::new();
+    let mut ext_grid: Vec<ExtGrid> = Vec::new();
+    for row in &gen_rows {
+        let index = bus_index(row[GEN_BUS]);
+        let bus_type = *bus_types
+            .get(&index)
+            .ok_or_else(|| invalid(format!("bus {} is missing", index + 1)))?;
+        let in_service = row[GEN_STATUS] > 0.0;
+        if bus_type == REF && in_service && !ext_grid.iter().any(|x| x.bus == index) {
+            let va_degree = bus_rows.iter().find(|x| bus_index(x[BUS_I]) == index);
+            ext_grid.push(ExtGrid {
+                bus: index,
+                in_service,
+                vm_pu: row[VG],
+                va_degree: va_degree.map_or(0.0, |x| x[VA]),
+                max_p_mw: Some(row[PMAX]),
+                min_p_mw: Some(row[PMIN]),
+                max_q_mvar: Some(row[QMAX]),
+                min_q_mvar: Some(row[QMIN]),
+                slack_weight: 1.0,
+                ..Default::default()
+            });
+        } else if bus_type == PQ {
+            sgen.push(SGen {
+                index: sgen.len() as i64,
+                bus: index,
+                p_mw: row[PG],
+                q_mvar: row[QG],
+                sn_mva: Some(row[MBASE]),
+                scaling: 1.0,
+                in_service,
+                ..Default::default()
+            });
+        } else {
+            gen.push(Gen {
+                index: gen.len() as i64,
+                bus: index,
+                in_service,
+                p_mw: row[PG],
+                scaling: 1.0,
+                sn_mva: Some(row[MBASE]),
+                vm_pu: row[VG],
+                max_p_mw: row[PMAX],
+                min_p_mw: row[PMIN],
+                max_q_mvar: Some(row[QMAX]),
+                min_q_mvar: Some(row[QMIN]),
+                ..Default::default()
+            });
+        }
+    }
+    // a reference bus without a generator in service holds its voltage from the bus table
+    for row in bus_rows.iter().filter(|x| x[BUS_TYPE] == REF) {
+        let index = bus_index(row[BUS_I]);
+        if !ext_grid.iter().any(|x| x.bus == index) {
+            ext_grid.push(ExtGrid {
+                bus: index,
+                in_service: true,
+                vm_pu: row[VM],
+                va_degree: row[VA],
+                slack_weight: 1.0,
+                ..Default::default()
+            });
+        }
+    }
+    for (index, x) in ext_grid.iter_mut().enumerate() {
+        x.index = index as i64;
+    }
+
+    let f_hz = 50.0;
+    let mut line = Vec::new();
+    let mut trafo = Vec::new();
+    for row in &branch_rows {
+        let (from_bus, to_bus) = (bus_index(row[F_BUS]), bus_index(row[T_BUS]));
+        let (from_kv, to_kv) = (bus_kv(from_bus)?, bus_kv(to_bus)?);
+        let in_service = row[BR_STATUS] > 0.0;
+        if row[TAP] == 0.0 && row[SHIFT] == 0.0 && from_kv == to_kv {
+            let z_base = from_kv * from_kv / base_mva;
+            let max_i_ka = if row[RATE_A] > 0.0 {
+                row[RATE_A] / (3f64.sqrt() * from_kv)
+            } else {
+                f64::INFINITY
+            };
+            line.push(Line {
+                index: line.len() as i64,
+                c_nf_per_km: row[BR_B] / z_base / (2.0 * PI * f_hz) * 1e9,
+                df: 1.0,
+                from_bus,
+                to_bus,
+                in_service,
+                length_km: 1.0,
+                max_i_ka,
+                parallel: 1,
+                r_ohm_per_km: row[BR_R] * z_base,
+                x_ohm_per_km: row[BR_X] * z_base,
+                type_: Some("ol".to_string()),
+                ..Default::default()
+            });
+        } else {
+            let from_hv = from_kv >= to_kv;
+            let (hv_bus, lv_bus) = if from_hv { (from_bus, to_bus) } else { (to_bus, from_bus) };
+            let tap = if row[TAP] == 0.0 { 1.0 } else { row[TAP] };
+            trafo.push(Transformer {
+                index: trafo.len() as i64,
+                df: 1.0,
+                hv_bus: hv_bus as i32,
+                lv_bus: lv_bus as i32,
+                in_service,
+                parallel: 1,
+                sn_mva: base_mva,
+                vn_hv_kv: from_kv.max(to_kv),
+                vn_lv_kv: from_kv.min(to_kv),
+                vk_percent: row[BR_R].hypot(row[BR_X]) * 100.0,
+                vkr_percent: row[BR_R] * 100.0,
+                tap_side: Some(if from_hv { "hv" } else { "lv" }.to_string()),
+                tap_ratio_magnitude: Some(tap),
+                tap_ratio_degree: Some(row[SHIFT]),
+                ..Default::default()
+            });
+        }
+    }
+
+    Ok(Network {
+        gen: Some(gen),
+        bus,
+        load: Some(load),
+        line: Some(line),
+        trafo: Some(trafo),
+        shunt: None,
+        ext_grid: Some(ext_grid),
+        sgen: Some(sgen),
+        switch: None,
+        characteristic: None,
+        measurement: None,
+        res_bus: None,
+        line_geodata: None,
+        f_hz,
+        sn_mva: base_mva,
+        ignored: Default::default(),
+    })
+}
+
+/// Loads a MATPOWER case file.
+///
+/// # Arguments
+///
+/// * `file_path` - The path of the `.m` file.
+///
+/// # Returns
+///
+/// The network, or an error if the file can't be read or parsed, see [`parse_matpower`].
+pub fn load_matpower(file_path: String) -> Result<Network, Error> {
+    parse_matpower(&fs::read_to_string(file_path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::prelude::*;
+
+    #[test]
+    fn test_case9() {
+        let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let net = load_matpower(format!("{}/cases/case9.m", dir)).unwrap();
+        assert_eq!(net.bus.len(), 9);
+        assert_eq!(net.line.as_ref().unwrap().len(), 9);
+        assert_eq!(net.ext_grid.as_ref().unwrap()[0].bus, 0);
+        assert_eq!(net.gen.as_ref().unwrap().len(), 2);
+
+        // the MATPOWER power flow solution of case9
+        let solved = PowerGrid::new(net).solve().unwrap();
+        let vm = solved.res_bus.iter().map(|x| x.vm_pu).collect::<Vec<_>>();
+        let expected = [1.04, 1.025, 1.025, 1.0258, 1.0127, 1.0324, 1.0159, 1.0258, 0.9956];
+        for (vm, expected) in vm.iter().zip(expected) {
+            assert!((vm - expected).abs() < 1e-4, "{} != {}", vm, expected);
+        }
+        let slack = solved.res_slack;
+        assert!((slack.re - 71.64).abs() < 1e-2, "{}", slack.re);
+        assert!((slack.im - 27.05).abs() < 1e-2, "{}", slack.im);
+    }
+
+    #[test]
+    fn test_malformed_case() {
+        let error = parse_matpower("mpc.baseMVA = 100;").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let case = "mpc.bus = [1 3 0 0 0 0 1 1 0 345 1 1.1 high];";
+        assert_eq!(parse_matpower(case).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
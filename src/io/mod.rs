@@ -1 +1,5 @@
+pub mod matpower;
 pub mod pandapower;
+#[cfg(test)]
+pub(crate) mod test_networks;
+pub mod validate;
@@ -2,11 +2,14 @@ use csv::ReaderBuilder;
 use nalgebra::{vector, Complex};
 use serde::Deserializer;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 use std::f64::consts::PI;
 use std::{fs, fs::File};
 use std::{io::Read, option::Option};
 
+use super::matpower::load_matpower;
 use crate::basic::system::*;
+use crate::basic::PowerFlowError;
 use crate::prelude::admittance::*;
 
 use serde_json;
@@ -14,6 +17,9 @@ use serde_json::{Map, Value};
 
 /// This module is used to parse pandapower network parameters
 
+/// The default per-unit admittance used to model ideal (zero-impedance) closed switches.
+pub(crate) const SWITCH_BIG_ADMITTANCE: f64 = 1e6;
+
 /// Deserializes a number from JSON format.
 fn from_number<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
 where
@@ -56,11 +62,19 @@ pub struct Bus {
     pub vn_kv: f64,
     #[serde(deserialize_with = "from_number")]
     pub zone: Option<i64>,
+    /// The nodal shunt conductance, as MW consumed at 1 pu voltage.
+    #[serde(default)]
+    pub g: Option<f64>,
+    /// The nodal shunt susceptance, as MVar injected at 1 pu voltage.
+    #[serde(default)]
+    pub b: Option<f64>,
 }
 
 /// Represents a generator in the network.
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Gen {
+    #[serde(default)]
+    pub index: i64,
     pub bus: i64,
     pub controllable: Option<bool>,
     pub in_service: bool,
@@ -74,14 +88,91 @@ pub struct Gen {
     pub slack: bool,
     pub max_p_mw: f64,
     pub min_p_mw: f64,
-    pub max_q_mvar: f64,
-    pub min_q_mvar: f64,
+    /// The upper reactive power limit in MVar, unlimited if not given.
+    pub max_q_mvar: Option<f64>,
+    /// The lower reactive power limit in MVar, unlimited if not given.
+    pub min_q_mvar: Option<f64>,
     pub slack_weight: f64,
+    /// The unit of `p_mw`, MW if not given.
+    #[serde(default)]
+    pub unit: Option<PowerUnit>,
+    /// The subtransient reactance in per unit on the generator rating.
+    #[serde(default)]
+    pub xdss_pu: Option<f64>,
+    /// The subtransient resistance in ohm.
+    #[serde(default)]
+    pub rdss_ohm: Option<f64>,
+    /// Whether the generator regulates its bus voltage or runs at a fixed reactive power.
+    #[serde(default)]
+    pub control_mode: GenControlMode,
+    /// The reactive power setpoint in MVar, used with [`GenControlMode::ReactivePowerControl`].
+    #[serde(default)]
+    pub q_mvar: f64,
+    /// The slope of the voltage over the supplied reactive power in per unit per MVar, used with
+    /// [`GenControlMode::VoltageDroop`].
+    #[serde(default)]
+    pub droop_pu_per_mvar: f64,
+    /// The step-up transformer connecting the generator terminal to `bus`, expanded by
+    /// [`Network::expand_unit_transformers`].
+    #[serde(default)]
+    pub unit_transformer: Option<UnitTransformer>,
+}
+
+/// The step-up transformer of a generator, see [`Gen::unit_transformer`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct UnitTransformer {
+    /// The rated power in MVA.
+    pub sn_mva: f64,
+    /// The rated voltage of the generator side in kV, which becomes the nominal voltage of the
+    /// terminal bus.
+    pub vn_lv_kv: f64,
+    /// The short-circuit voltage in percent.
+    pub vk_percent: f64,
+    /// The real part of the short-circuit voltage in percent.
+    pub vkr_percent: f64,
+}
+
+/// The control mode of a generator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GenControlMode {
+    /// Regulates the bus voltage to `vm_pu`, making the bus a PV bus.
+    #[default]
+    VoltageControl,
+    /// Injects fixed active and reactive power, leaving the bus a PQ bus.
+    ReactivePowerControl,
+    /// Regulates the bus voltage to `vm_pu - droop_pu_per_mvar * q_mvar` with the supplied
+    /// reactive power, so that generators with droop share the reactive power.
+    VoltageDroop,
+}
+
+/// The unit of the power values of a load or generator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerUnit {
+    /// MW and MVar.
+    #[default]
+    #[serde(rename = "MW")]
+    Mw,
+    /// Per unit on the base power of the network.
+    #[serde(rename = "pu")]
+    PerUnit,
+}
+
+impl PowerUnit {
+    /// Returns the factor converting values in this unit to MW.
+    pub fn to_mw(self, s_base: f64) -> f64 {
+        match self {
+            PowerUnit::Mw => 1.0,
+            PowerUnit::PerUnit => s_base,
+        }
+    }
 }
 
 /// Represents a load in the network.
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Load {
+    #[serde(default)]
+    pub index: i64,
     pub bus: i64,
     pub const_i_percent: f64,
     pub const_z_percent: f64,
@@ -94,17 +185,65 @@ pub struct Load {
     pub sn_mva: Option<f64>,
     #[serde(rename = "type")]
     pub type_: Option<String>, // Added underscore to avoid conflict with Rust keyword
+    /// The unit of `p_mw` and `q_mvar`, MW and MVar if not given.
+    #[serde(default)]
+    pub unit: Option<PowerUnit>,
+    /// The power factor; if given, the reactive power is derived from it instead of `q_mvar`.
+    /// It must lie in `(0, 1]`, which [`Network::validate`] checks.
+    #[serde(default)]
+    pub cos_phi: Option<f64>,
+    /// The reactive behavior at `cos_phi`, underexcited if not given.
+    #[serde(default)]
+    pub mode: Option<PowerFactorMode>,
+    /// The exponent of the voltage dependence of the active power, `P = p_mw * vm^exp_p`.
+    #[serde(default)]
+    pub exp_p: Option<f64>,
+    /// The exponent of the voltage dependence of the reactive power, `Q = q_mvar * vm^exp_q`.
+    #[serde(default)]
+    pub exp_q: Option<f64>,
+}
+
+/// The reactive behavior of a load specified by its power factor, as in pandapower's
+/// `pq_from_cosphi`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerFactorMode {
+    /// Inductive, consuming reactive power (lagging).
+    #[default]
+    #[serde(alias = "ind")]
+    Underexcited,
+    /// Capacitive, supplying reactive power (leading).
+    #[serde(alias = "cap")]
+    Overexcited,
+}
+
+impl Load {
+    /// Returns the reactive power demand, derived from the power factor if one is given.
+    pub fn q(&self) -> f64 {
+        let Some(cos_phi) = self.cos_phi else {
+            return self.q_mvar;
+        };
+        let q = self.p_mw.abs() * cos_phi.acos().tan();
+        match self.mode.unwrap_or_default() {
+            PowerFactorMode::Underexcited => q,
+            PowerFactorMode::Overexcited => -q,
+        }
+    }
 }
 
 /// Represents a line in the network.
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Line {
+    #[serde(default)]
+    pub index: i64,
     pub c_nf_per_km: f64,
     pub df: f64,
     pub from_bus: i64,
     pub to_bus: i64,
     pub g_us_per_km: f64,
     pub in_service: bool,
+    /// The length, NaN if not given, see [`Network::infer_line_lengths`].
+    #[serde(default = "missing_length")]
     pub length_km: f64,
     pub max_i_ka: f64,
     pub max_loading_percent: Option<f64>,
@@ -115,11 +254,31 @@ pub struct Line {
     pub x_ohm_per_km: f64,
     pub name: Option<String>,
     pub std_type: Option<String>,
+    /// The zero-sequence resistance per km.
+    #[serde(default)]
+    pub r0_ohm_per_km: Option<f64>,
+    /// The zero-sequence reactance per km.
+    #[serde(default)]
+    pub x0_ohm_per_km: Option<f64>,
+    /// The zero-sequence capacitance per km.
+    #[serde(default)]
+    pub c0_nf_per_km: Option<f64>,
+    /// The thermal limit as apparent power in MVA, for lines rated in power rather than
+    /// current.
+    #[serde(default)]
+    pub max_s_mva: Option<f64>,
+}
+
+/// The length of a line without a given length.
+fn missing_length() -> f64 {
+    f64::NAN
 }
 
 /// Represents a transformer in the network.
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Transformer {
+    #[serde(default)]
+    pub index: i64,
     pub df: f64,
     pub hv_bus: i32,
     pub i0_percent: f64,
@@ -144,11 +303,170 @@ pub struct Transformer {
     pub tap_min: Option<f64>,
     pub tap_step_degree: Option<f64>,
     pub tap_step_percent: Option<f64>,
+    pub tap_dependent_impedance: Option<bool>,
+    #[serde(default)]
+    pub tap_changer_type: Option<String>,
+    #[serde(default, deserialize_with = "from_number")]
+    pub vk_percent_characteristic: Option<i64>,
+    #[serde(default, deserialize_with = "from_number")]
+    pub vkr_percent_characteristic: Option<i64>,
+    /// The vector group, e.g. `Dyn` or `YNyn`, which determines the zero-sequence paths.
+    #[serde(default)]
+    pub vector_group: Option<String>,
+    /// The zero-sequence short-circuit voltage in percent.
+    #[serde(default)]
+    pub vk0_percent: Option<f64>,
+    /// The real part of the zero-sequence short-circuit voltage in percent.
+    #[serde(default)]
+    pub vkr0_percent: Option<f64>,
+    /// The controller adjusting the tap position, if any.
+    #[serde(default)]
+    pub control: Option<ControlMode>,
+    /// The magnitude of a directly specified off-nominal ratio, which overrides the ratio
+    /// derived from the tap position.
+    #[serde(default)]
+    pub tap_ratio_magnitude: Option<f64>,
+    /// The angle in degrees of a directly specified off-nominal ratio, ignored without
+    /// `tap_ratio_magnitude`.
+    #[serde(default)]
+    pub tap_ratio_degree: Option<f64>,
+}
+
+/// A controller adjusting an element between power flow solves.
+///
+/// Each variant applies to one element type and is ignored on the others.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ControlMode {
+    /// Discrete tap control, stepping the tap until the voltage of a bus lies within a band.
+    /// Applies to transformers.
+    ///
+    /// The controlled bus is taken to be on the LV side of the transformer.
+    TapControl {
+        /// The pandapower id of the controlled bus.
+        bus: i64,
+        /// The lower bound of the voltage band in per unit.
+        vm_lower_pu: f64,
+        /// The upper bound of the voltage band in per unit.
+        vm_upper_pu: f64,
+    },
+    /// Switched shunt control, selecting the step that brings the voltage of a bus closest to
+    /// a target. Applies to shunts.
+    SwitchedShunt {
+        /// The pandapower id of the controlled bus.
+        bus: i64,
+        /// The voltage target in per unit.
+        vm_pu: f64,
+    },
+}
+
+/// The kind of tap changer of a transformer, as in pandapower's `tap_changer_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TapChangerType {
+    /// Changes the voltage magnitude, rotated by `tap_step_degree` if given.
+    Ratio,
+    /// Symmetrical phase shifter, changes the angle by `2 atan(du / 2)` at constant magnitude.
+    Symmetrical,
+    /// Ideal phase shifter, changes the angle by `tap_step_degree` per step at constant magnitude.
+    Ideal,
+}
+
+impl Gen {
+    /// Returns whether the generator regulates its bus voltage, with a fixed setpoint or with
+    /// droop.
+    pub fn is_voltage_controlled(&self) -> bool {
+        matches!(
+            self.control_mode,
+            GenControlMode::VoltageControl | GenControlMode::VoltageDroop
+        )
+    }
+}
+
+impl Line {
+    /// Computes the total series impedance in ohm.
+    ///
+    /// The impedance per km is multiplied by the length and divided by the number of parallel
+    /// systems.
+    pub fn series_impedance(&self) -> Complex<f64> {
+        Complex::new(self.r_ohm_per_km, self.x_ohm_per_km) * self.length_km / self.parallel as f64
+    }
+
+    /// Returns whether the line has neither resistance nor reactance, so that it joins its
+    /// buses like an ideal switch.
+    pub fn is_zero_impedance(&self) -> bool {
+        self.series_impedance() == Complex::new(0.0, 0.0)
+    }
+}
+
+impl Transformer {
+    /// Returns the rated power available for loading in MVA.
+    ///
+    /// The rated power of a unit is derated by `df` and multiplied by the number of parallel
+    /// units. A `df` outside of `(0, 1]` is invalid in pandapower and reported by
+    /// [`Network::validate`].
+    pub fn rated_mva(&self) -> f64 {
+        self.sn_mva * self.df * self.parallel.max(1) as f64
+    }
+
+    /// Returns the tap step direction that raises the LV voltage.
+    ///
+    /// A tap on the HV side raises the LV voltage when its ratio decreases, a tap on the LV side
+    /// when its ratio increases. Without a tap step the direction is zero.
+    pub fn raising_tap_step(&self) -> f64 {
+        let step = self.tap_step_percent.unwrap_or(0.0);
+        if step == 0.0 || step.is_nan() {
+            return 0.0;
+        }
+        let sign = step.signum();
+        match self.tap_side.as_deref() {
+            Some("lv") => sign,
+            _ => -sign,
+        }
+    }
+
+    /// Returns the tap changer type, or `None` if it isn't supported.
+    ///
+    /// Without `tap_changer_type`, the legacy `tap_phase_shifter` flag selects between an ideal
+    /// phase shifter and a ratio tap changer.
+    pub fn changer_type(&self) -> Option<TapChangerType> {
+        match self.tap_changer_type.as_deref() {
+            Some("Ratio") => Some(TapChangerType::Ratio),
+            Some("Symmetrical") => Some(TapChangerType::Symmetrical),
+            Some("Ideal") => Some(TapChangerType::Ideal),
+            Some(_) => None,
+            None if self.tap_phase_shifter => Some(TapChangerType::Ideal),
+            None => Some(TapChangerType::Ratio),
+        }
+    }
+
+    /// Computes the complex off-nominal ratio of the tap changer at the current tap position.
+    ///
+    /// The ratio multiplies the rated voltage of the tap side. Unsupported tap changer types are
+    /// treated as ratio tap changers. A directly specified ratio takes precedence, so that
+    /// stepping the tap position has no effect on it.
+    pub fn tap_ratio(&self) -> Complex<f64> {
+        if let Some(magnitude) = self.tap_ratio_magnitude {
+            let degree = self.tap_ratio_degree.unwrap_or(0.0);
+            return Complex::from_polar(magnitude, degree.to_radians());
+        }
+        let steps = self.tap_pos.unwrap_or(0.0) - self.tap_neutral.unwrap_or(0.0);
+        let du = 0.01 * self.tap_step_percent.unwrap_or(0.0) * steps;
+        let step_degree = self.tap_step_degree.unwrap_or(0.0);
+        match self.changer_type().unwrap_or(TapChangerType::Ratio) {
+            TapChangerType::Ratio => {
+                Complex::new(1.0, 0.0) + Complex::from_polar(du, step_degree.to_radians())
+            }
+            TapChangerType::Symmetrical => Complex::from_polar(1.0, 2.0 * (0.5 * du).atan()),
+            TapChangerType::Ideal => Complex::from_polar(1.0, (steps * step_degree).to_radians()),
+        }
+    }
 }
 
 /// Represents an external grid in the network.
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct ExtGrid {
+    #[serde(default)]
+    pub index: i64,
     pub bus: i64,
     pub in_service: bool,
     pub va_degree: f64,
@@ -159,11 +477,24 @@ pub struct ExtGrid {
     pub min_q_mvar: Option<f64>,
     pub slack_weight: f64,
     pub name: Option<String>,
+    pub s_sc_max_mva: Option<f64>,
+    pub rx_max: Option<f64>,
+    /// The ratio of the zero- to the positive-sequence source reactance.
+    #[serde(default)]
+    pub x0x_max: Option<f64>,
+    /// The R/X ratio of the zero-sequence source impedance.
+    #[serde(default)]
+    pub r0x0_max: Option<f64>,
+    /// How the grid constrains its bus, holding the voltage if not given.
+    #[serde(default)]
+    pub mode: ExtGridMode,
 }
 
 /// Represents the data from the sgen.
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct SGen {
+    #[serde(default)]
+    pub index: i64,
     pub name: Option<String>,
     pub bus: i64,
     pub p_mw: f64,
@@ -178,8 +509,10 @@ pub struct SGen {
 }
 
 /// Represents a shunt in the network.
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Shunt {
+    #[serde(default)]
+    pub index: i64,
     pub bus: i64,
     pub q_mvar: f64,
     pub p_mw: f64,
@@ -188,12 +521,32 @@ pub struct Shunt {
     pub max_step: i32,
     pub in_service: bool,
     pub name: Option<String>,
+    /// The controller switching the steps, if any.
+    #[serde(default)]
+    pub control: Option<ControlMode>,
+}
+
+impl Shunt {
+    /// Returns the step direction that raises the bus voltage.
+    ///
+    /// Capacitor steps (negative `q_mvar`) raise the voltage when switched in, reactor steps
+    /// when switched out.
+    pub fn raising_step(&self) -> i32 {
+        if self.q_mvar < 0.0 {
+            1
+        } else {
+            -1
+        }
+    }
 }
 /// Represents a switch in the network.
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Switch {
+    #[serde(default)]
+    pub index: i64,
     pub bus: i64,
     pub element: i64,
+    pub et: Option<String>,
     #[serde(rename = "type")]
     pub type_: Option<String>,
     pub closed: bool,
@@ -201,8 +554,152 @@ pub struct Switch {
     pub z_ohm: f64,
 }
 
+/// Represents a measurement in the network, e.g. for state estimation.
+#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+pub struct Measurement {
+    #[serde(default)]
+    pub index: i64,
+    pub name: Option<String>,
+    /// The measured quantity: `"v"`, `"va"`, `"p"`, `"q"` or `"i"`.
+    pub measurement_type: String,
+    /// The type of the measured element: `"bus"`, `"line"` or `"trafo"`.
+    pub element_type: String,
+    /// The id of the measured element.
+    pub element: i64,
+    /// The measured value in per unit (voltages), MW/MVar (powers) or kA (currents).
+    pub value: f64,
+    /// The standard deviation of the measurement, in the unit of the value.
+    pub std_dev: f64,
+    /// The side of a branch measurement, given as side name or bus id.
+    #[serde(default, deserialize_with = "from_str")]
+    pub side: Option<String>,
+}
+
+/// Represents a piecewise-linear characteristic curve, e.g. `vk_percent` over tap position.
+#[derive(Default, Debug, Serialize, Clone)]
+pub struct Characteristic {
+    pub index: i64,
+    pub x_vals: Vec<f64>,
+    pub y_vals: Vec<f64>,
+}
+
+impl<'de> Deserialize<'de> for Characteristic {
+    /// Deserializes either a plain `{index, x_vals, y_vals}` record or a pandapower
+    /// `characteristic` table row, where the curve is stored as a serialized object.
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Curve {
+            x_vals: Vec<f64>,
+            y_vals: Vec<f64>,
+        }
+        let val: Value = Deserialize::deserialize(deserializer)?;
+        let index = val.get("index").and_then(|v| v.as_i64()).unwrap_or(0);
+        let curve = match val.get("object").and_then(|v| v.get("_object")) {
+            Some(Value::String(s)) => serde_json::from_str::<Curve>(s),
+            Some(v) => serde_json::from_value::<Curve>(v.clone()),
+            None => serde_json::from_value::<Curve>(val),
+        }
+        .map_err(serde::de::Error::custom)?;
+        Ok(Characteristic {
+            index,
+            x_vals: curve.x_vals,
+            y_vals: curve.y_vals,
+        })
+    }
+}
+
+impl Characteristic {
+    /// Evaluates the curve at `x` by linear interpolation, holding the end values outside the range.
+    pub fn interpolate(&self, x: f64) -> f64 {
+        let n = self.x_vals.len().min(self.y_vals.len());
+        if n == 0 {
+            return f64::NAN;
+        }
+        if x <= self.x_vals[0] {
+            return self.y_vals[0];
+        }
+        for i in 1..n {
+            if x <= self.x_vals[i] {
+                let (x0, x1) = (self.x_vals[i - 1], self.x_vals[i]);
+                let (y0, y1) = (self.y_vals[i - 1], self.y_vals[i]);
+                return y0 + (y1 - y0) * (x - x0) / (x1 - x0);
+            }
+        }
+        self.y_vals[n - 1]
+    }
+}
+
+/// The geometry of a line as a polyline, from the pandapower `line_geodata` table.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct LineGeodata {
+    /// The line identifier.
+    #[serde(default)]
+    pub index: i64,
+    /// The points of the polyline as `[x, y]` pairs.
+    pub coords: Vec<[f64; 2]>,
+}
+
+/// The coordinate system of the line geodata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeoCoordinates {
+    /// Longitude and latitude in degrees (WGS84), measured along great circles.
+    Geographic,
+    /// Planar coordinates in km.
+    Kilometers,
+}
+
+impl GeoCoordinates {
+    /// Computes the length of a polyline in km.
+    pub fn length_km(&self, coords: &[[f64; 2]]) -> f64 {
+        const EARTH_RADIUS_KM: f64 = 6371.0088;
+        coords
+            .windows(2)
+            .map(|x| {
+                let ([x0, y0], [x1, y1]) = (x[0], x[1]);
+                match self {
+                    GeoCoordinates::Geographic => {
+                        let (lat0, lat1) = (y0.to_radians(), y1.to_radians());
+                        let dlat = lat1 - lat0;
+                        let dlon = (x1 - x0).to_radians();
+                        let h = (0.5 * dlat).sin().powi(2)
+                            + lat0.cos() * lat1.cos() * (0.5 * dlon).sin().powi(2);
+                        2.0 * EARTH_RADIUS_KM * h.sqrt().asin()
+                    }
+                    GeoCoordinates::Kilometers => (x1 - x0).hypot(y1 - y0),
+                }
+            })
+            .sum()
+    }
+}
+
+/// The installed and dispatched generation of a network against its load, see
+/// [`Network::capacity_summary`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CapacitySummary {
+    /// The total maximum active power `max_p_mw` of the generators in MW.
+    pub installed_mw: f64,
+    /// The total active power setpoint of the generators in MW.
+    pub dispatched_mw: f64,
+    /// The total active power of the static generators in MW.
+    pub sgen_mw: f64,
+    /// The total active power demand of the loads in MW.
+    pub load_mw: f64,
+}
+
+impl CapacitySummary {
+    /// Returns the reserve margin, the installed capacity and static generation in excess of
+    /// the load relative to the load, or `None` without load.
+    pub fn reserve_margin(&self) -> Option<f64> {
+        let capacity = self.installed_mw + self.sgen_mw;
+        (self.load_mw > 0.0).then(|| (capacity - self.load_mw) / self.load_mw)
+    }
+}
+
 /// Represents a network.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Network {
     pub gen: Option<Vec<Gen>>,
     pub bus: Vec<Bus>,
@@ -213,8 +710,167 @@ pub struct Network {
     pub ext_grid: Option<Vec<ExtGrid>>,
     pub sgen: Option<Vec<SGen>>,
     pub switch: Option<Vec<Switch>>,
+    pub characteristic: Option<Vec<Characteristic>>,
+    pub measurement: Option<Vec<Measurement>>,
+    /// The bus results stored with the network, e.g. by pandapower, as a reference for
+    /// [`SolvedNetwork::compare_results`].
+    #[serde(default)]
+    pub res_bus: Option<Vec<BusResult>>,
+    /// The geometry of the lines, see [`Network::infer_line_lengths`].
+    #[serde(default)]
+    pub line_geodata: Option<Vec<LineGeodata>>,
+    /// The system frequency, which scales the line charging susceptance.
+    #[serde(default = "default_f_hz")]
     pub f_hz: f64,
     pub sn_mva: f64,
+    /// The element tables skipped on import, with their number of rows.
+    #[serde(skip)]
+    pub ignored: BTreeMap<String, usize>,
+}
+
+/// The pandapower default system frequency in Hz.
+fn default_f_hz() -> f64 {
+    50.0
+}
+
+impl Network {
+    /// Returns whether the network has a slack, see [`Network::slack_ext_grid`] and
+    /// [`Network::slack_gen`].
+    pub fn has_slack(&self) -> bool {
+        self.slack_ext_grid().is_some() || self.slack_gen().is_some()
+    }
+
+    /// Returns the external grid that is the slack of the power flow: the first one that is in
+    /// service at a bus in service.
+    pub fn slack_ext_grid(&self) -> Option<&ExtGrid> {
+        let mut ext_grids = self.ext_grid.iter().flatten();
+        ext_grids.find(|x| x.in_service && self.bus_in_service(x.bus))
+    }
+
+    /// Returns the generator that is the slack of the power flow if there is no external grid:
+    /// the first one marked as `slack` that is in service at a bus in service.
+    pub fn slack_gen(&self) -> Option<&Gen> {
+        let mut gens = self.gen.iter().flatten();
+        gens.find(|x| x.in_service && x.slack && self.bus_in_service(x.bus))
+    }
+
+    /// Returns whether a bus exists and is in service.
+    fn bus_in_service(&self, bus: i64) -> bool {
+        self.bus.iter().any(|x| x.index == bus && x.in_service)
+    }
+
+    /// Sums the installed and dispatched generation and the load of the in-service elements.
+    ///
+    /// The external grid has no capacity and isn't included.
+    pub fn capacity_summary(&self) -> CapacitySummary {
+        let to_mw = |unit: Option<PowerUnit>| unit.unwrap_or_default().to_mw(self.sn_mva);
+        let gens = self.gen.iter().flatten().filter(|x| x.in_service);
+        let sgens = self.sgen.iter().flatten().filter(|x| x.in_service);
+        let loads = self.load.iter().flatten().filter(|x| x.in_service);
+        CapacitySummary {
+            installed_mw: gens.clone().map(|x| x.max_p_mw).sum(),
+            dispatched_mw: gens.map(|x| x.p_mw * to_mw(x.unit)).sum(),
+            sgen_mw: sgens.map(|x| x.p_mw).sum(),
+            load_mw: loads.map(|x| x.p_mw * to_mw(x.unit)).sum(),
+        }
+    }
+
+    /// Expands the unit transformers of the generators into explicit elements.
+    ///
+    /// Each generator with a [`UnitTransformer`] is moved to a new terminal bus, which is
+    /// connected to its former bus by a new transformer. The new buses and transformers get
+    /// identifiers above the existing ones and are in service with their generator.
+    pub fn expand_unit_transformers(&mut self) {
+        let mut next_bus = self.bus.iter().map(|x| x.index + 1).max().unwrap_or(0);
+        let trafos = self.trafo.iter().flatten();
+        let mut next_trafo = trafos.map(|x| x.index + 1).max().unwrap_or(0);
+        for gen in self.gen.iter_mut().flatten() {
+            let Some(unit) = gen.unit_transformer.take() else {
+                continue;
+            };
+            let Some(hv) = self.bus.iter().find(|x| x.index == gen.bus) else {
+                continue;
+            };
+            let terminal = Bus {
+                index: next_bus,
+                in_service: true,
+                name: gen.name.clone(),
+                vn_kv: unit.vn_lv_kv,
+                ..hv.clone()
+            };
+            let trafo = Transformer {
+                index: next_trafo,
+                hv_bus: gen.bus as i32,
+                lv_bus: next_bus as i32,
+                in_service: gen.in_service,
+                df: 1.0,
+                parallel: 1,
+                sn_mva: unit.sn_mva,
+                vn_hv_kv: hv.vn_kv,
+                vn_lv_kv: unit.vn_lv_kv,
+                vk_percent: unit.vk_percent,
+                vkr_percent: unit.vkr_percent,
+                ..Default::default()
+            };
+            self.bus.push(terminal);
+            self.trafo.get_or_insert_with(Vec::new).push(trafo);
+            gen.bus = next_bus;
+            next_bus += 1;
+            next_trafo += 1;
+        }
+    }
+
+    /// Reports the elements that were skipped on import because their type is not supported.
+    ///
+    /// # Returns
+    ///
+    /// The number of skipped elements by table name, e.g. `"trafo3w"` or `"impedance"`. Tables
+    /// without rows are not listed.
+    pub fn import_report(&self) -> &BTreeMap<String, usize> {
+        &self.ignored
+    }
+
+    /// Derives the missing line lengths from the geometry in `line_geodata`.
+    ///
+    /// Only lines whose length is missing (NaN) or not positive and which have a polyline of at
+    /// least two points are changed; given lengths are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `coordinates` - The coordinate system of the geodata.
+    ///
+    /// # Returns
+    ///
+    /// The number of lines whose length was derived.
+    pub fn infer_line_lengths(&mut self, coordinates: GeoCoordinates) -> usize {
+        let geodata = self.line_geodata.as_deref().unwrap_or_default();
+        let mut count = 0;
+        for line in self.line.iter_mut().flatten() {
+            if line.length_km > 0.0 {
+                continue;
+            }
+            let coords = geodata.iter().find(|x| x.index == line.index);
+            if let Some(x) = coords.filter(|x| x.coords.len() > 1) {
+                line.length_km = coordinates.length_km(&x.coords);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Lists the measurements of an element.
+    ///
+    /// # Arguments
+    ///
+    /// * `element_type` - The type of the element, e.g. `"bus"` or `"line"`.
+    /// * `element` - The id of the element.
+    pub fn measurements_of(&self, element_type: &str, element: i64) -> Vec<&Measurement> {
+        self.measurement
+            .iter()
+            .flatten()
+            .filter(|x| x.element_type == element_type && x.element == element)
+            .collect()
+    }
 }
 
 /// Trait for saving a network to CSV files.
@@ -240,23 +896,46 @@ impl Default for Network {
             ext_grid: None,
             sgen: None,
             switch: None,
-            f_hz: 60.0,
+            characteristic: None,
+            measurement: None,
+            res_bus: None,
+            line_geodata: None,
+            f_hz: default_f_hz(),
             sn_mva: 100.0,
+            ignored: BTreeMap::new(),
         }
     }
 }
 
 /// Loads a pandapower CSV file into a vector of the specified type.
-fn load_pandapower_csv<T: for<'de> Deserialize<'de>>(name: String) -> Vec<T> {
-    let file = read_csv(&name).unwrap();
-    let mut rdr = ReaderBuilder::new().from_reader(file.as_bytes());
+fn load_pandapower_csv<T: for<'de> Deserialize<'de>>(
+    name: String,
+) -> Result<Vec<T>, std::io::Error> {
+    let file = read_csv(&name)?;
+    parse_csv_records(&file)
+}
+
+/// Deserializes the records of a pandapower CSV table.
+///
+/// Tables exported without an `index` column are indexed by row position.
+fn parse_csv_records<T: for<'de> Deserialize<'de>>(
+    content: &str,
+) -> Result<Vec<T>, std::io::Error> {
+    let mut rdr = ReaderBuilder::new().from_reader(content.as_bytes());
     let mut records: Vec<T> = Vec::new();
-    let headers = rdr.headers().unwrap().to_owned();
-    for (_idx, i) in rdr.records().enumerate() {
-        let record = i.unwrap();
-        records.push(record.deserialize(Some(&headers)).unwrap());
+    let mut headers = rdr.headers()?.to_owned();
+    let positional = !headers.iter().any(|x| x == "index");
+    if positional {
+        headers.push_field("index");
+    }
+    for (idx, i) in rdr.records().enumerate() {
+        let mut record = i?;
+        if positional {
+            record.push_field(&idx.to_string());
+        }
+        records.push(record.deserialize(Some(&headers))?);
     }
-    records
+    Ok(records)
 }
 
 /// Reads a CSV file and replaces "True"/"False" with "true"/"false".
@@ -269,7 +948,11 @@ fn read_csv(name: &str) -> Result<String, std::io::Error> {
 }
 
 /// Loads a CSV folder into a Network structure.
-pub fn load_csv_folder(folder: String) -> Network {
+///
+/// # Returns
+///
+/// The network, or an error if a table can't be read or parsed.
+pub fn load_csv_folder(folder: String) -> Result<Network, std::io::Error> {
     let bus = folder.to_owned() + "/bus.csv";
     let gen = folder.to_owned() + "/gen.csv";
     let line = folder.to_owned() + "/line.csv";
@@ -279,179 +962,577 @@ pub fn load_csv_folder(folder: String) -> Network {
     let load = folder.to_owned() + "/load.csv";
     let sgen = folder.to_owned() + "/sgen.csv";
     let mut net = Network::default();
-    net.bus = load_pandapower_csv(bus);
-    net.gen = Some(load_pandapower_csv(gen));
-    net.line = Some(load_pandapower_csv(line));
-    net.shunt = Some(load_pandapower_csv(shunt));
-    net.trafo = Some(load_pandapower_csv(trafo));
-    net.ext_grid = Some(load_pandapower_csv(extgrid));
-    net.load = Some(load_pandapower_csv(load));
-    net.sgen = Some(load_pandapower_csv(sgen));
-    net
+    net.bus = load_pandapower_csv(bus)?;
+    net.gen = Some(load_pandapower_csv(gen)?);
+    net.line = Some(load_pandapower_csv(line)?);
+    net.shunt = Some(load_pandapower_csv(shunt)?);
+    net.trafo = Some(load_pandapower_csv(trafo)?);
+    net.ext_grid = Some(load_pandapower_csv(extgrid)?);
+    net.load = Some(load_pandapower_csv(load)?);
+    net.sgen = Some(load_pandapower_csv(sgen)?);
+    Ok(net)
 }
 
 /// Converts a line to its equivalent admittance branches.
-fn line_to_admit(wbase: f64, bus: &[Bus], line: &Line) -> Vec<AdmittanceBranch> {
+fn line_to_admit(
+    wbase: f64,
+    bus: &[Bus],
+    lookup: &NodeLookup,
+    line: &Line,
+) -> Vec<AdmittanceBranch> {
     let mut out = Vec::new();
     let (mut shunt_f, mut shunt_t) = (AdmittanceBranch::default(), AdmittanceBranch::default());
     let b = wbase * 1e-9 * line.c_nf_per_km * line.length_km * (line.parallel as f64);
     let g = line.g_us_per_km * line.length_km * 1e-6 * (line.parallel as f64);
-    let v_base = bus[line.from_bus as usize].vn_kv;
+    let (from, to) = (lookup[line.from_bus], lookup[line.to_bus]);
+    let v_base = bus[from].vn_kv;
     let a = Admittance(0.5 * Complex { re: g, im: b });
     if line.g_us_per_km != 0.0 || line.c_nf_per_km != 0.0 {
         shunt_f.y = a.clone();
         shunt_f.v_base = v_base;
         shunt_t.y = a;
         shunt_t.v_base = v_base;
-        shunt_f.port = Port2(vector![line.from_bus as i32, GND]);
-        shunt_t.port = Port2(vector![line.to_bus as i32, GND]);
+        shunt_f.port = Port2(vector![from as i32, GND]);
+        shunt_t.port = Port2(vector![to as i32, GND]);
         out.push(shunt_f);
         out.push(shunt_t);
     }
 
-    let rl = line.r_ohm_per_km * line.length_km * (line.parallel as f64);
-    let xl = line.x_ohm_per_km * line.length_km * (line.parallel as f64);
+    // parallel systems divide the series impedance and multiply the shunt admittance
     let l = AdmittanceBranch {
-        y: Admittance(1.0 / Complex { re: rl, im: xl }),
-        port: Port2(vector![line.from_bus as i32, line.to_bus as i32]),
+        y: Admittance(1.0 / line.series_impedance()),
+        port: Port2(vector![from as i32, to as i32]),
         v_base,
+        element: None,
+        shift: 0.0,
     };
     out.push(l);
     out
 }
 
+/// Converts a line that an open switch disconnects at one end to the admittance it adds at the
+/// other end, the charging of the open end seen through the series impedance.
+///
+/// # Arguments
+///
+/// * `closed` - The identifier of the bus the line stays connected to.
+fn open_line_to_admit(
+    wbase: f64,
+    bus: &[Bus],
+    lookup: &NodeLookup,
+    line: &Line,
+    closed: i64,
+) -> Vec<AdmittanceBranch> {
+    let b = wbase * 1e-9 * line.c_nf_per_km * line.length_km * (line.parallel as f64);
+    let g = line.g_us_per_km * line.length_km * 1e-6 * (line.parallel as f64);
+    if g == 0.0 && b == 0.0 {
+        return Vec::new();
+    }
+    let y_half = 0.5 * Complex { re: g, im: b };
+    let y_series = 1.0 / line.series_impedance();
+    let node = lookup[closed];
+    vec![AdmittanceBranch {
+        y: Admittance(y_half + y_series * y_half / (y_series + y_half)),
+        port: Port2(vector![node as i32, GND]),
+        v_base: bus[lookup[line.from_bus]].vn_kv,
+        element: None,
+        shift: 0.0,
+    }]
+}
+
 /// Converts a load to its equivalent PQ nodes.
-fn load_to_pqnode(item: &Load) -> [PQNode; 1] {
-    let s = Complex::new(item.p_mw, item.q_mvar);
+///
+/// A load with `exp_p` or `exp_q` follows the exponential load model, with a missing exponent
+/// taken as 0 (constant power).
+fn load_to_pqnode(item: &Load, s_base: f64) -> [PQNode; 1] {
+    let scale = item.scaling * item.unit.unwrap_or_default().to_mw(s_base);
+    let s = Complex::new(item.p_mw, item.q()) * scale;
     let bus = item.bus;
-    [PQNode { s, bus }]
+    let exponents = (item.exp_p.is_some() || item.exp_q.is_some())
+        .then(|| (item.exp_p.unwrap_or(0.0), item.exp_q.unwrap_or(0.0)));
+    [PQNode {
+        s,
+        bus,
+        element: None,
+        exponents,
+    }]
+}
+
+/// Converts a generator in reactive power control to its equivalent PQ nodes.
+fn gen_to_pqnode(item: &Gen, s_base: f64) -> [PQNode; 1] {
+    let scale = item.scaling * item.unit.unwrap_or_default().to_mw(s_base);
+    let s = -Complex::new(item.p_mw, item.q_mvar) * scale;
+    let bus = item.bus;
+    [PQNode {
+        s,
+        bus,
+        element: None,
+        exponents: None,
+    }]
 }
 
 /// Converts a generator to its equivalent PV nodes.
-fn gen_to_pvnode(item: &Gen) -> [PVNode; 1] {
-    let p = item.p_mw;
+fn gen_to_pvnode(item: &Gen, s_base: f64) -> [PVNode; 1] {
+    let p = item.p_mw * item.scaling * item.unit.unwrap_or_default().to_mw(s_base);
     let v = item.vm_pu;
     let bus = item.bus;
-    [PVNode { p, v, bus }]
+    let finite = |x: Option<f64>| x.filter(|x| x.is_finite());
+    [PVNode {
+        p,
+        v,
+        bus,
+        element: None,
+        sn_mva: item.sn_mva,
+        q_min: finite(item.min_q_mvar),
+        q_max: finite(item.max_q_mvar),
+        droop: (item.control_mode == GenControlMode::VoltageDroop)
+            .then_some(item.droop_pu_per_mvar),
+    }]
 }
 
 /// Converts an external grid to its equivalent external grid node.
-fn extgrid_to_extnode(item: &ExtGrid) -> [ExtGridNode; 1] {
+///
+/// The source impedance is derived from the short-circuit power (without voltage factor) and
+/// the R/X ratio, if given.
+fn extgrid_to_extnode(item: &ExtGrid, s_base: f64) -> [ExtGridNode; 1] {
     let bus = item.bus;
     let v = item.vm_pu;
     let phase = item.va_degree.to_radians();
+    let z_sc = item
+        .s_sc_max_mva
+        .filter(|s_sc| *s_sc > 0.0 && s_sc.is_finite())
+        .map(|s_sc| {
+            let z = s_base / s_sc;
+            let x = z / (1.0 + item.rx_max.unwrap_or(0.0).powi(2)).sqrt();
+            Complex::new(item.rx_max.unwrap_or(0.0) * x, x)
+        });
+
+    let finite = |x: Option<f64>| x.filter(|x| x.is_finite());
+
+    [ExtGridNode {
+        v,
+        phase,
+        bus,
+        z_sc,
+        q_min: finite(item.min_q_mvar),
+        q_max: finite(item.max_q_mvar),
+        mode: item.mode,
+    }]
+}
 
-    [ExtGridNode { v, phase, bus }]
+/// Converts the slack generator to an ideal external grid node, for networks without an
+/// external grid, see [`Network::slack_gen`].
+///
+/// The generator is kept as PV node at the slack bus as well.
+fn slack_gen_to_extnode(net: &Network) -> Option<ExtGridNode> {
+    let gen = net.slack_gen()?;
+    Some(ExtGridNode {
+        v: gen.vm_pu,
+        bus: gen.bus,
+        ..Default::default()
+    })
 }
 
 /// Converts a shunt to its equivalent PQ nodes.
-fn shunt_to_pqnode(item: &Shunt) -> [PQNode; 1] {
-    let s = Complex::new(item.p_mw, item.q_mvar);
+///
+/// pandapower gives `p_mw` and `q_mvar` per step at 1 pu, so they are scaled by the
+/// switched-in steps; a shunt with no step switched in draws nothing.
+fn shunt_to_pqnode(item: &Shunt, _s_base: f64) -> [PQNode; 1] {
+    let s = Complex::new(item.p_mw, item.q_mvar) * item.step as f64;
     let bus = item.bus;
-    [PQNode { s, bus }]
+    [PQNode {
+        s,
+        bus,
+        element: None,
+        exponents: None,
+    }]
 }
 
 /// Converts a shunt to its equivalent PQ nodes.
-fn sgen_to_pqnode(item: &SGen) -> [PQNode; 1] {
-    let s = Complex::new(-item.p_mw, -item.q_mvar);
+fn sgen_to_pqnode(item: &SGen, _s_base: f64) -> [PQNode; 1] {
+    let s = -Complex::new(item.p_mw, item.q_mvar) * item.scaling;
     let bus = item.bus;
-    [PQNode { s, bus }]
+    [PQNode {
+        s,
+        bus,
+        element: None,
+        exponents: None,
+    }]
+}
+
+/// Looks up the tap-dependent value of a transformer parameter.
+///
+/// Returns `default` unless `tap_dependent_impedance` is set and the referenced characteristic exists.
+fn tap_dependent(
+    item: &Transformer,
+    characteristic: Option<i64>,
+    characteristics: &[Characteristic],
+    default: f64,
+) -> f64 {
+    if !item.tap_dependent_impedance.unwrap_or(false) {
+        return default;
+    }
+    let tap_pos = item.tap_pos.unwrap_or(item.tap_neutral.unwrap_or(0.0));
+    characteristic
+        .and_then(|idx| characteristics.iter().find(|c| c.index == idx))
+        .map_or(default, |c| c.interpolate(tap_pos))
 }
 
 /// Converts a transformer to its equivalent admittance branches.
-fn trafo_to_admit(item: &Transformer) -> Vec<AdmittanceBranch> {
-    let v_base = item.vn_lv_kv;
-    let vkr = item.vkr_percent * 0.01;
-    let vk = item.vk_percent * 0.01;
-
-    let tap_m = 1.0
-        + (item.tap_pos.unwrap_or(0.0) - item.tap_neutral.unwrap_or(0.0))
-            * 0.01
-            * item.tap_step_percent.unwrap_or(0.0);
-    let zbase = v_base * v_base / item.sn_mva;
+///
+/// The off-nominal ratio accounts for the tap position as well as for rated winding voltages
+/// that differ from the nominal voltages of the connected buses on either side. Unless
+/// `magnetizing` is unset, the magnetizing branch sits between the halves of the short circuit
+/// impedance as in the T-model of pandapower, converted to the equivalent pi-model.
+fn trafo_to_admit(
+    bus: &[Bus],
+    lookup: &NodeLookup,
+    characteristics: &[Characteristic],
+    item: &Transformer,
+    magnetizing: bool,
+) -> Vec<AdmittanceBranch> {
+    let (hv_bus, lv_bus) = (lookup[item.hv_bus as i64], lookup[item.lv_bus as i64]);
+    let v_base = bus[lv_bus].vn_kv;
+    let vkr = tap_dependent(
+        item,
+        item.vkr_percent_characteristic,
+        characteristics,
+        item.vkr_percent,
+    ) * 0.01;
+    let vk = tap_dependent(
+        item,
+        item.vk_percent_characteristic,
+        characteristics,
+        item.vk_percent,
+    ) * 0.01;
+
+    // the pi-model carries the magnitude of the ratio, which is referred to the HV side, so a
+    // tap on the LV side divides it; its angle adds to the phase shift of the vector group
+    let ratio = item.tap_ratio();
+    let tap = ratio.norm();
+    let (tap_hv, tap_lv, tap_shift) = match item.tap_side.as_deref() {
+        Some("lv") => (1.0, tap, -ratio.arg()),
+        _ => (tap, 1.0, ratio.arg()),
+    };
+    let shift = item.shift_degree.to_radians() + tap_shift;
+    let ratio_hv = tap_hv * item.vn_hv_kv / bus[hv_bus].vn_kv;
+    let ratio_lv = tap_lv * item.vn_lv_kv / bus[lv_bus].vn_kv;
+    let (hv_bus, lv_bus) = (hv_bus as i32, lv_bus as i32);
+    let tap_m = ratio_hv / ratio_lv;
+    let zbase = item.vn_lv_kv * item.vn_lv_kv / item.sn_mva;
     let z = zbase * vk;
     let parallel = item.parallel;
 
     let re = zbase * vkr;
     let im = (z.powi(2) - re.powi(2)).sqrt();
-    let port = Port2(vector![item.hv_bus, item.lv_bus]);
-    let y = 1.0 / (Complex { re, im } * parallel as f64);
+    let z_sc = Complex { re, im } / parallel as f64;
+
+    // the magnetizing admittance at the LV rated voltage, consuming the iron losses and the
+    // no-load current; the T-model of pandapower splits the short circuit impedance evenly
+    // around it, which is turned into the equivalent pi-model
+    let vn_lv2 = item.vn_lv_kv * item.vn_lv_kv;
+    let g = 0.001 * item.pfe_kw / vn_lv2;
+    let y0 = 0.01 * item.i0_percent * item.sn_mva / vn_lv2;
+    let b = (y0 * y0 - g * g).max(0.0).sqrt() * item.i0_percent.signum();
+    let y_m = Complex { re: g, im: -b } * parallel as f64;
+    let (y, c) = if magnetizing && y_m.norm() > 0.0 && y_m.is_finite() {
+        let (z_a, z_c) = (0.5 * z_sc, 1.0 / y_m);
+        let z_sum = z_a * z_a + 2.0 * z_a * z_c;
+        (z_c / z_sum, 2.0 * z_a / z_sum)
+    } else {
+        (1.0 / z_sc, Complex::new(0.0, 0.0))
+    };
+    let port = Port2(vector![hv_bus, lv_bus]);
     let sc = AdmittanceBranch {
         y: Admittance(y / tap_m),
         port,
         v_base,
+        element: None,
+        shift,
     };
     let mut v = Vec::new();
     v.push(sc);
     v.push(AdmittanceBranch {
         y: Admittance((1.0 - tap_m) * y / tap_m.powi(2)),
-        port: Port2(vector![item.hv_bus, GND]),
+        port: Port2(vector![hv_bus, GND]),
         v_base,
+        element: None,
+        shift: 0.0,
     });
     v.push(AdmittanceBranch {
         y: Admittance((1.0 - 1.0 / tap_m) * y),
-        port: Port2(vector![item.lv_bus, GND]),
+        port: Port2(vector![lv_bus, GND]),
         v_base,
+        element: None,
+        shift: 0.0,
     });
-    let re = zbase * (0.001 * item.pfe_kw) / item.sn_mva;
-    let im = zbase / (0.01 * item.i0_percent);
-    let c = parallel as f64 / Complex { re, im };
-
-    if c.is_nan() {
+    if c.norm() == 0.0 {
         return v;
     }
-    let port = Port2(vector![item.hv_bus, GND]);
+    let port = Port2(vector![hv_bus, GND]);
     let y = Admittance(0.5 * c / tap_m.powi(2));
-    let shunt = AdmittanceBranch { y, port, v_base };
+    let shunt = AdmittanceBranch {
+        y,
+        port,
+        v_base,
+        element: None,
+        shift: 0.0,
+    };
     v.push(shunt);
-    let port = Port2(vector![item.lv_bus, GND]);
+    let port = Port2(vector![lv_bus, GND]);
     let y = Admittance(0.5 * c);
-    let shunt = AdmittanceBranch { y, port, v_base };
+    let shunt = AdmittanceBranch {
+        y,
+        port,
+        v_base,
+        element: None,
+        shift: 0.0,
+    };
     v.push(shunt);
     v
 }
 
+/// Converts the nodal shunts of the buses to admittance branches to ground.
+fn bus_shunts_to_admit(bus: &[Bus], lookup: &NodeLookup) -> Vec<AdmittanceBranch> {
+    bus.iter()
+        .filter_map(|x| {
+            let s = Complex::new(x.g.unwrap_or(0.0), x.b.unwrap_or(0.0));
+            if s == Complex::new(0.0, 0.0) {
+                return None;
+            }
+            Some(AdmittanceBranch {
+                y: Admittance(s / (x.vn_kv * x.vn_kv)),
+                port: Port2(vector![lookup[x.index] as i32, GND]),
+                v_base: x.vn_kv,
+                element: None,
+                shift: 0.0,
+            })
+        })
+        .collect()
+}
+
+/// Converts the closed bus-bus switches to admittance branches.
+///
+/// Switches with a positive `z_ohm` are modeled by their impedance, ideal ones by the large
+/// per-unit admittance `big_admittance`. Redundant switches between the same pair of buses are
+/// modeled only once, using the one with the lowest impedance, so that they don't add up to a
+/// multiple of the admittance.
+///
+/// For bus-bus switches both `bus` and `element` are bus identifiers, which are resolved to
+/// node indices through the lookup. Ideal switches are merged in a union-find forest, so chained
+/// switches that close a loop add no redundant branch.
+pub(crate) fn process_switch_state(
+    bus: &[Bus],
+    lookup: &NodeLookup,
+    s_base: f64,
+    switches: &[Switch],
+    big_admittance: f64,
+) -> Vec<AdmittanceBranch> {
+    let mut pairs: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+    for (idx, sw) in switches.iter().enumerate() {
+        if !sw.closed || sw.et.as_deref() != Some("b") {
+            continue;
+        }
+        let (f, t) = (lookup[sw.bus], lookup[sw.element]);
+        if f == t {
+            continue;
+        }
+        let key = (f.min(t), f.max(t));
+        pairs
+            .entry(key)
+            .and_modify(|cur| {
+                if sw.z_ohm < switches[*cur].z_ohm {
+                    *cur = idx;
+                }
+            })
+            .or_insert(idx);
+    }
+
+    // an ideal switch between buses already merged by other ideal switches would only close a
+    // loop of large admittances; the pairs are visited in node order, so the same switch is
+    // dropped whatever the order of the switch table
+    let mut parent: Vec<usize> = (0..bus.len()).collect();
+    pairs
+        .into_iter()
+        .filter(|((f, t), idx)| {
+            if switches[*idx].z_ohm > 0.0 {
+                return true;
+            }
+            let (a, b) = (find_root(&mut parent, *f), find_root(&mut parent, *t));
+            parent[a.max(b)] = a.min(b);
+            a != b
+        })
+        .map(|((f, t), idx)| {
+            let v_base = bus[f].vn_kv;
+            let z_ohm = switches[idx].z_ohm;
+            let y = if z_ohm > 0.0 {
+                1.0 / z_ohm
+            } else {
+                big_admittance * s_base / (v_base * v_base)
+            };
+            AdmittanceBranch {
+                y: Admittance(Complex::new(y, 0.0)),
+                port: Port2(vector![f as i32, t as i32]),
+                v_base,
+                element: Some(ElementRef::Switch(idx)),
+                shift: 0.0,
+            }
+        })
+        .collect()
+}
+
+/// Collects the pairs of buses joined without impedance, by closed ideal bus-bus switches and
+/// by in-service lines without impedance.
+pub(crate) fn zero_impedance_pairs(net: &Network) -> Vec<(i64, i64)> {
+    let switches = net
+        .switch
+        .iter()
+        .flatten()
+        .filter(|x| x.closed && x.et.as_deref() == Some("b") && x.z_ohm <= 0.0)
+        .map(|x| (x.bus, x.element));
+    let lines = net
+        .line
+        .iter()
+        .flatten()
+        .filter(|x| x.in_service() && x.is_zero_impedance())
+        .map(|x| (x.from_bus, x.to_bus));
+    switches.chain(lines).collect()
+}
+
+/// Marks the admittance branches as derived from the given element.
+fn tag_branches(mut branches: Vec<AdmittanceBranch>, element: ElementRef) -> Vec<AdmittanceBranch> {
+    branches.iter_mut().for_each(|b| b.element = Some(element));
+    branches
+}
+
+/// Elements that can be taken out of service, which are then left out of the power flow.
+trait InService {
+    fn in_service(&self) -> bool;
+}
+
+macro_rules! impl_in_service {
+    ($($t:ty),*) => {
+        $(impl InService for $t {
+            fn in_service(&self) -> bool {
+                self.in_service
+            }
+        })*
+    };
+}
+
+impl_in_service!(Line, Transformer, Load, Gen, SGen, Shunt);
+
 /// Collects PQ nodes from the given items using the provided converter function.
+///
+/// The converter receives the base power of the network to convert per-unit values.
+/// The bus identifiers of the items are replaced by their node indices, and the nodes are tagged
+/// with the position of their item. Out-of-service items are skipped.
 #[inline(always)]
-fn collect_pq_nodes<T>(items: Option<Vec<T>>, converter: fn(&T) -> [PQNode; 1]) -> Vec<PQNode> {
+fn collect_pq_nodes<T: InService>(
+    items: &Option<Vec<T>>,
+    lookup: &NodeLookup,
+    s_base: f64,
+    converter: fn(&T, f64) -> [PQNode; 1],
+    element: fn(usize) -> ElementRef,
+) -> Vec<PQNode> {
     items
-        .unwrap_or_else(Vec::new)
+        .as_deref()
+        .unwrap_or_default()
         .iter()
-        .flat_map(converter)
+        .enumerate()
+        .filter(|(_, item)| item.in_service())
+        .flat_map(|(idx, item)| converter(item, s_base).map(|x| (idx, x)))
+        .map(|(idx, mut x)| {
+            x.bus = lookup[x.bus] as i64;
+            x.element = Some(element(idx));
+            x
+        })
         .collect()
 }
 
 /// Reads a CSV file from the given map and deserializes it into a vector of the specified type.
+///
+/// # Returns
+///
+/// The records, `None` if the file is missing or empty, or an error if it can't be parsed.
 fn csv_from_map<T: for<'de> Deserialize<'de>>(
     map: &std::collections::HashMap<String, String>,
     key: &str,
-) -> Option<Vec<T>> {
-    if !map.contains_key(key) {
-        return None;
-    }
-    let s = map
-        .get(key)
-        .unwrap()
-        .replace("True", "true")
-        .replace("False", "false");
-    let mut rdr = ReaderBuilder::new().from_reader(s.as_bytes());
-    let mut records: Vec<T> = Vec::new();
-    let headers = rdr.headers().unwrap().to_owned();
-    for (_idx, i) in rdr.records().enumerate() {
-        let record = i.unwrap();
-        records.push(record.deserialize(Some(&headers)).unwrap());
-    }
-    if records.is_empty() {
-        return None;
-    }
-    Some(records)
+) -> Result<Option<Vec<T>>, std::io::Error> {
+    let Some(content) = map.get(key) else {
+        return Ok(None);
+    };
+    let s = content.replace("True", "true").replace("False", "false");
+    let records: Vec<T> = parse_csv_records(&s)?;
+    Ok((!records.is_empty()).then_some(records))
+}
+
+/// The element tables read by the importers.
+const SUPPORTED_TABLES: [&str; 11] = [
+    "bus",
+    "gen",
+    "line",
+    "shunt",
+    "trafo",
+    "ext_grid",
+    "load",
+    "sgen",
+    "switch",
+    "characteristic",
+    "measurement",
+];
+
+/// Tables without physical meaning for the power flow, which are not reported when skipped.
+const AUXILIARY_TABLES: [&str; 6] = [
+    "bus_geodata",
+    "line_geodata",
+    "poly_cost",
+    "pwl_cost",
+    "controller",
+    "group",
+];
+
+/// Checks whether a table holds elements the importers skip.
+fn is_ignored_table(name: &str) -> bool {
+    !SUPPORTED_TABLES.contains(&name)
+        && !AUXILIARY_TABLES.contains(&name)
+        && !name.starts_with("res_")
+        && !name.starts_with('_')
+}
+
+/// Counts the rows of the skipped CSV tables of a map of file names to contents.
+fn ignored_csv_tables(map: &std::collections::HashMap<String, String>) -> BTreeMap<String, usize> {
+    map.iter()
+        .filter_map(|(file, content)| {
+            let name = file.rsplit('/').next()?.strip_suffix(".csv")?;
+            let rows = ReaderBuilder::new()
+                .from_reader(content.as_bytes())
+                .records()
+                .count();
+            (is_ignored_table(name) && rows > 0).then(|| (name.to_string(), rows))
+        })
+        .collect()
+}
+
+/// Counts the rows of the skipped data frames of a pandapower JSON object.
+fn ignored_json_tables(object: &Map<String, Value>) -> BTreeMap<String, usize> {
+    object
+        .iter()
+        .filter(|(name, _)| is_ignored_table(name))
+        .filter_map(|(name, value)| {
+            let frame = value.as_object()?.get("_object")?.as_str()?;
+            let frame: Value = serde_json::from_str(frame).ok()?;
+            let rows = frame.get("data")?.as_array()?.len();
+            (rows > 0).then(|| (name.clone(), rows))
+        })
+        .collect()
 }
 
 /// Macro to read network data from a CSV file.
 macro_rules! read_csv_network {
     ($net:ident, $map:ident, { $($field:ident: $file:expr),* $(,)? }) => {
         $(
-            $net.$field = csv_from_map(&$map, $file);
+            $net.$field = csv_from_map(&$map, $file)?;
         )*
     };
 }
@@ -459,7 +1540,7 @@ macro_rules! read_csv_network {
 macro_rules! read_json_network {
     ($net:ident, $map:ident, { $($field:ident: $file:expr),* $(,)? }) => {
         $(
-            $net.$field = load_pandapower_element_json(&$map, $file);
+            $net.$field = load_pandapower_element_json(&$map, $file)?;
         )*
     };
 }
@@ -470,17 +1551,19 @@ pub fn load_csv_zip(name: String) -> Result<Network, std::io::Error> {
     let mut zip = zip::ZipArchive::new(f)?;
     let mut map = std::collections::HashMap::new();
     for i in 0..zip.len() {
-        let mut file = zip.by_index(i).unwrap();
+        let mut file = zip.by_index(i)?;
 
         if file.is_file() {
             let mut s = String::with_capacity(file.size() as usize);
-            file.read_to_string(&mut s).unwrap();
+            file.read_to_string(&mut s)?;
             map.insert(file.name().to_owned(), s);
         }
     }
 
     let mut net = Network::default();
-    net.bus = csv_from_map(&map, "bus.csv").unwrap();
+    net.bus = csv_from_map(&map, "bus.csv")?.ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, "the archive has no bus.csv")
+    })?;
     read_csv_network!(net, map, {
         gen: "gen.csv",
         line: "line.csv",
@@ -489,20 +1572,55 @@ pub fn load_csv_zip(name: String) -> Result<Network, std::io::Error> {
         ext_grid: "ext_grid.csv",
         load: "load.csv",
         sgen:"sgen.csv",
-        switch:"switch.csv"
+        switch:"switch.csv",
+        measurement:"measurement.csv",
+        res_bus: "res_bus.csv"
     });
+    net.ignored = ignored_csv_tables(&map);
     Ok(net)
 }
 
 fn load_json_from_str(file_content: &str) -> Result<Map<String, Value>, std::io::Error> {
     let parsed: Value = serde_json::from_str(&file_content)?;
-    let obj: Map<String, Value> = parsed.as_object().unwrap().clone();
-    Ok(obj)
+    match parsed {
+        Value::Object(obj) => Ok(obj),
+        _ => Err(invalid_json("the document is not a JSON object")),
+    }
+}
+
+/// Creates the error of a JSON document that doesn't have the pandapower layout.
+fn invalid_json(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// The magic bytes at the start of a gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Decompresses a gzip stream.
+#[cfg(feature = "flate2")]
+fn gunzip(bytes: &[u8]) -> Result<String, std::io::Error> {
+    let mut content = String::new();
+    flate2::read::GzDecoder::new(bytes).read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// Fails on a gzip stream, as decompression requires the `flate2` feature.
+#[cfg(not(feature = "flate2"))]
+fn gunzip(_bytes: &[u8]) -> Result<String, std::io::Error> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "reading gzip-compressed files requires the `flate2` feature",
+    ))
 }
 
 fn load_json(file_path: String) -> Result<Map<String, Value>, std::io::Error> {
-    let file_content =
-        fs::read_to_string(file_path).expect(format!("Error reading file network file").as_str());
+    let bytes = fs::read(&file_path)?;
+    let file_content = if bytes.starts_with(&GZIP_MAGIC) || file_path.ends_with(".gz") {
+        gunzip(&bytes)?
+    } else {
+        String::from_utf8(bytes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
+    };
     let obj = load_json_from_str(&file_content);
     obj
 }
@@ -510,60 +1628,93 @@ fn load_json(file_path: String) -> Result<Map<String, Value>, std::io::Error> {
 fn load_pandapower_element_json<T: serde::de::DeserializeOwned>(
     object: &Map<String, Value>,
     key: &str,
-) -> Option<Vec<T>> {
+) -> Result<Option<Vec<T>>, std::io::Error> {
     let element = object
         .get(key)
         .and_then(|v| v.as_object())
         .and_then(|v| v.get("_object"))
         .and_then(|v| v.as_str());
-    if element.is_none() {
-        return None;
-    }
+    let Some(element) = element else {
+        return Ok(None);
+    };
     let mut elements = Vec::new();
-    let element = element.unwrap();
-    let map = load_json_from_str(element).unwrap();
+    let map = load_json_from_str(element)?;
 
+    // exports drop the columns and data of some tables without rows
     let headers = map
         .get("columns")
         .and_then(|v| v.as_array())
-        .unwrap()
-        .to_owned();
-
-    let rows = map.get("data").and_then(|v| v.as_array()).unwrap();
-
-    for (index, row) in rows.iter().enumerate() {
-        let obj: Map<String, Value> = Map::new();
-        let mut obj: Map<String, Value> =
-            headers
-                .iter()
-                .zip(row.as_array().unwrap().iter())
-                .fold(obj, |mut acc, (k, v)| {
-                    let key = k.as_str().unwrap();
-                    let value = v.to_owned();
-                    acc.insert(key.to_string(), value);
-                    acc
-                });
+        .cloned()
+        .unwrap_or_default();
+
+    let rows = match map.get("data").and_then(|v| v.as_array()) {
+        Some(rows) => rows,
+        None => return Ok(Some(elements)),
+    };
+    let index = map.get("index").and_then(|v| v.as_array());
+
+    for (row_idx, row) in rows.iter().enumerate() {
+        let row = row
+            .as_array()
+            .ok_or_else(|| invalid_json("a row of a table is not an array"))?;
+        let mut obj: Map<String, Value> = Map::new();
+        for (k, v) in headers.iter().zip(row) {
+            let key = k
+                .as_str()
+                .ok_or_else(|| invalid_json("a column name of a table is not a string"))?;
+            obj.insert(key.to_string(), v.to_owned());
+        }
 
+        let index = index
+            .and_then(|x| x.get(row_idx))
+            .and_then(|x| x.as_i64())
+            .unwrap_or(row_idx as i64);
         obj.insert(
             "index".to_string(),
-            Value::Number(serde_json::Number::from(index as i64)),
+            Value::Number(serde_json::Number::from(index)),
         );
 
         println!("key: {} Obj: {:?}", key, obj);
 
-        let elem: T = serde_json::from_value(obj.clone().into()).unwrap();
+        let elem: T = serde_json::from_value(obj.into())?;
         elements.push(elem);
     }
 
-    return Some(elements);
+    Ok(Some(elements))
 }
 
-pub fn load_pandapower_json(file_path: String) -> Network {
-    let map: Map<String, Value> = load_json(file_path).unwrap();
-    let object: &Map<String, Value> = map.get("_object").and_then(|v| v.as_object()).unwrap();
+/// Loads a network from a pandapower JSON file, plain or gzip-compressed.
+///
+/// # Returns
+///
+/// The network, or an error if the file can't be read or parsed.
+pub fn load_pandapower_json(file_path: String) -> Result<Network, std::io::Error> {
+    let map: Map<String, Value> = load_json(file_path)?;
+    load_pandapower_json_obj(&map)
+}
+
+/// Reads a network from a parsed pandapower JSON document.
+///
+/// Tables missing from the document are read as empty, so exports that omit the tables
+/// without rows (no `shunt`, no `trafo3w`, ...) load as well.
+///
+/// # Arguments
+///
+/// * `map` - The top-level object of the document, which holds the tables under `_object`.
+///
+/// # Returns
+///
+/// The network, or an error if a table can't be parsed; a document without `_object` gives
+/// an empty network.
+pub fn load_pandapower_json_obj(map: &Map<String, Value>) -> Result<Network, std::io::Error> {
+    let empty = Map::new();
+    let object: &Map<String, Value> = map
+        .get("_object")
+        .and_then(|v| v.as_object())
+        .unwrap_or(&empty);
 
     let mut net = Network::default();
-    net.bus = load_pandapower_element_json(object, "bus").unwrap();
+    net.bus = load_pandapower_element_json(object, "bus")?.unwrap_or_default();
     read_json_network!(net, object, {
         gen: "gen",
         line: "line",
@@ -572,55 +1723,231 @@ pub fn load_pandapower_json(file_path: String) -> Network {
         ext_grid: "ext_grid",
         load: "load",
         sgen:"sgen",
-        switch:"switch"
+        switch:"switch",
+        characteristic:"characteristic",
+        measurement:"measurement",
+        res_bus: "res_bus",
+        line_geodata: "line_geodata"
     });
+    if let Some(f_hz) = object.get("f_hz").and_then(Value::as_f64) {
+        net.f_hz = f_hz;
+    }
+    net.ignored = ignored_json_tables(object);
+
+    Ok(net)
+}
+
+/// The magic bytes at the start of a ZIP archive.
+const ZIP_MAGIC: [u8; 4] = [0x50, 0x4b, 0x03, 0x04];
 
-    return net;
+/// Loads a network, detecting the format from the path and the content.
+///
+/// Folders are read as pandapower CSV folders, files with the `.m` extension as MATPOWER case
+/// files, see [`load_matpower`], ZIP archives as zipped CSV files, and plain or
+/// gzip-compressed JSON files as pandapower JSON.
+///
+/// # Arguments
+///
+/// * `file_path` - The path of the file or folder.
+///
+/// # Returns
+///
+/// The network, or an error if the file can't be read or its format isn't supported.
+pub fn load_network(file_path: String) -> Result<Network, std::io::Error> {
+    let path = std::path::Path::new(&file_path);
+    if path.is_dir() {
+        return load_csv_folder(file_path);
+    }
+    if path.extension().is_some_and(|x| x == "m") {
+        return load_matpower(file_path);
+    }
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(&ZIP_MAGIC) {
+        return load_csv_zip(file_path);
+    }
+    let is_json = bytes.iter().find(|x| !x.is_ascii_whitespace()) == Some(&b'{');
+    if !is_json && !bytes.starts_with(&GZIP_MAGIC) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("the format of {} is not recognized", file_path),
+        ));
+    }
+    load_pandapower_json(file_path)
 }
 
-impl From<Network> for PFNetwork {
-    fn from(value: Network) -> Self {
-        let v_base = value.bus[value.ext_grid.as_ref().unwrap()[0].bus as usize].vn_kv;
+impl TryFrom<&Network> for PFNetwork {
+    type Error = PowerFlowError;
+
+    fn try_from(value: &Network) -> Result<Self, Self::Error> {
+        Self::from_network(value, &PowerFlowConfig::default())
+    }
+}
+
+impl PFNetwork {
+    /// Assembles the power flow model of a network.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The network data.
+    /// * `config` - The power flow options; `include_trafo_magnetizing`,
+    ///   `switch_big_admittance` and `merge_zero_impedance` affect the assembly.
+    ///
+    /// Unit transformers of generators are expanded on a copy of the network, see
+    /// [`Network::expand_unit_transformers`].
+    ///
+    /// # Returns
+    ///
+    /// The power flow model, or [`PowerFlowError::NoSlackBus`] if the network has neither an
+    /// in-service external grid nor a slack generator, or
+    /// [`PowerFlowError::MissingLineLength`] if a line in service has no length.
+    pub fn from_network(
+        value: &Network,
+        config: &PowerFlowConfig,
+    ) -> Result<Self, PowerFlowError> {
+        if value.gen.iter().flatten().any(|x| x.unit_transformer.is_some()) {
+            let mut expanded = value.clone();
+            expanded.expand_unit_transformers();
+            return Self::from_network(&expanded, config);
+        }
+        let lines = value.line.as_deref().unwrap_or_default();
+        if let Some(line) = lines.iter().find(|x| x.in_service() && x.length_km.is_nan()) {
+            return Err(PowerFlowError::MissingLineLength { line: line.index });
+        }
+        let merge = config.merge_zero_impedance;
+        let (lookup, nodes) = if merge {
+            NodeLookup::merged(&value.bus, &zero_impedance_pairs(value))
+        } else {
+            (NodeLookup::new(&value.bus), value.bus.clone())
+        };
+        let bus = &nodes;
         let s_base = value.sn_mva;
+        let ext = match value.slack_ext_grid() {
+            Some(ext) => extgrid_to_extnode(ext, s_base)[0],
+            None => slack_gen_to_extnode(value).ok_or(PowerFlowError::NoSlackBus)?,
+        };
+        let ext = ExtGridNode {
+            bus: lookup[ext.bus] as i64,
+            ..ext
+        };
+        let v_base = bus[ext.bus as usize].vn_kv;
         let wbase = value.f_hz * 2.0 * PI;
-        let binding = value.line.unwrap_or(Vec::new());
-        let bus = &value.bus;
-        let a = binding
+        let switches = value.switch.as_deref().unwrap_or_default();
+        // the line ends disconnected by an open line switch, as (line, bus)
+        let open_ends: HashSet<(i64, i64)> = switches
             .iter()
-            .flat_map(|x| line_to_admit(wbase, bus, x).into_iter());
+            .filter(|x| !x.closed && x.et.as_deref() == Some("l"))
+            .map(|x| (x.element, x.bus))
+            .collect();
+        let a = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.in_service() && !(merge && x.is_zero_impedance()))
+            .flat_map(|(idx, x)| {
+                let open = |end: i64| open_ends.contains(&(x.index, end));
+                let mut branches = match (open(x.from_bus), open(x.to_bus)) {
+                    (false, false) => line_to_admit(wbase, bus, &lookup, x),
+                    (true, true) => Vec::new(),
+                    (true, false) => open_line_to_admit(wbase, bus, &lookup, x, x.to_bus),
+                    (false, true) => open_line_to_admit(wbase, bus, &lookup, x, x.from_bus),
+                };
+                if x.is_zero_impedance() {
+                    // modeled like an ideal switch instead of an infinite admittance
+                    for br in branches.iter_mut().filter(|br| br.port.0.iter().all(|x| *x != GND)) {
+                        let y = config.switch_big_admittance * s_base / br.v_base.powi(2);
+                        br.y = Admittance(Complex::new(y, 0.0));
+                    }
+                }
+                tag_branches(branches, ElementRef::Line(idx))
+            });
 
-        let binding = value.trafo.unwrap_or(Vec::new());
-        let b = binding.iter().flat_map(|x| trafo_to_admit(x).into_iter());
-        let y_br = a.chain(b).collect();
+        let characteristics = value.characteristic.as_deref().unwrap_or_default();
+        let magnetizing = config.include_trafo_magnetizing;
+        let b = value
+            .trafo
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.in_service())
+            .flat_map(|(idx, x)| {
+                tag_branches(
+                    trafo_to_admit(bus, &lookup, characteristics, x, magnetizing),
+                    ElementRef::Trafo(idx),
+                )
+            });
+        let c = process_switch_state(bus, &lookup, s_base, switches, config.switch_big_admittance);
+        let shunts = bus_shunts_to_admit(&value.bus, &lookup);
+        let y_br = a.chain(b).chain(c).chain(shunts).collect();
 
-        let ext = extgrid_to_extnode(&value.ext_grid.unwrap_or(Vec::new())[0])[0];
-        let pq_loads = collect_pq_nodes(value.load, load_to_pqnode)
-            .into_iter()
-            .chain(collect_pq_nodes(value.shunt, shunt_to_pqnode))
-            .chain(collect_pq_nodes(value.sgen, sgen_to_pqnode))
-            .collect();
+        let gens = value.gen.as_deref().unwrap_or_default();
+        let pq_loads = collect_pq_nodes(
+            &value.load,
+            &lookup,
+            s_base,
+            load_to_pqnode,
+            ElementRef::Load,
+        )
+        .into_iter()
+        .chain(collect_pq_nodes(
+            &value.shunt,
+            &lookup,
+            s_base,
+            shunt_to_pqnode,
+            ElementRef::Shunt,
+        ))
+        .chain(collect_pq_nodes(
+            &value.sgen,
+            &lookup,
+            s_base,
+            sgen_to_pqnode,
+            ElementRef::SGen,
+        ))
+        .chain(
+            collect_pq_nodes(&value.gen, &lookup, s_base, gen_to_pqnode, ElementRef::Gen)
+                .into_iter()
+                .filter(|x| match x.element {
+                    Some(ElementRef::Gen(idx)) => !gens[idx].is_voltage_controlled(),
+                    _ => false,
+                }),
+        )
+        .collect();
 
-        let pv_nodes = value
-            .gen
-            .unwrap_or(Vec::new())
+        let pv_nodes = gens
             .iter()
-            .map(|x| gen_to_pvnode(x).into_iter())
-            .flatten()
+            .enumerate()
+            .filter(|(_, x)| x.in_service() && x.is_voltage_controlled())
+            .flat_map(|(idx, x)| gen_to_pvnode(x, s_base).map(|x| (idx, x)))
+            .map(|(idx, x)| PVNode {
+                bus: lookup[x.bus] as i64,
+                element: Some(ElementRef::Gen(idx)),
+                ..x
+            })
             .collect();
-        Self {
+        Ok(Self {
             v_base,
             s_base,
             pq_loads,
             pv_nodes,
             ext,
             y_br,
-            buses: value.bus,
-        }
+            custom: Vec::new(),
+            buses: nodes,
+            ybus_pattern: Default::default(),
+        })
+    }
+}
+
+impl TryFrom<Network> for PFNetwork {
+    type Error = PowerFlowError;
+
+    fn try_from(value: Network) -> Result<Self, Self::Error> {
+        Self::try_from(&value)
     }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::io::test_networks::{buses, ext_grid, line, load, substation, trafo};
     use std::env;
 
     #[test]
@@ -628,10 +1955,318 @@ mod tests {
         let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
         let folder = format!("{}/cases", dir);
         let filepath: String = folder.to_owned() + "/networks.json";
-        let net = load_pandapower_json(filepath);
+        let net = load_pandapower_json(filepath).unwrap();
+        assert_eq!(net.f_hz, 50.0);
+        let res_bus = net.res_bus.as_ref().unwrap();
+        assert_eq!(res_bus.len(), net.bus.len());
+        assert!(net.bus.iter().all(|b| res_bus.iter().any(|x| x.bus == b.index)));
         println!("{:?}", net);
     }
 
+    #[test]
+    fn test_infer_line_lengths() {
+        let frame = |columns: &[&str], index: &[i64], data: Value| {
+            let table = serde_json::json!({"columns": columns, "index": index, "data": data});
+            serde_json::json!({"_class": "DataFrame", "_object": table.to_string()})
+        };
+        let object = serde_json::json!({
+            "line": frame(
+                &[
+                    "from_bus", "to_bus", "r_ohm_per_km", "x_ohm_per_km", "c_nf_per_km",
+                    "g_us_per_km", "max_i_ka", "df", "parallel", "in_service",
+                ],
+                &[4],
+                serde_json::json!([[0, 1, 0.1, 0.3, 10.0, 0.0, 0.4, 1.0, 1, true]]),
+            ),
+            "line_geodata": frame(
+                &["coords"],
+                &[4],
+                serde_json::json!([[[[10.0, 0.0], [10.1, 0.0], [10.1, 0.1]]]]),
+            ),
+        });
+        let document = serde_json::json!({"_object": object});
+        let mut net = load_pandapower_json_obj(document.as_object().unwrap()).unwrap();
+        assert!(net.line.as_ref().unwrap()[0].length_km.is_nan());
+        assert_eq!(net.line_geodata.as_ref().unwrap()[0].index, 4);
+        let config = PowerFlowConfig::default();
+        let missing = PFNetwork::from_network(&net, &config);
+        assert!(matches!(missing, Err(PowerFlowError::MissingLineLength { line: 4 })));
+
+        // two legs of 0.1 degree at the equator, about 11.12 km each
+        assert_eq!(net.infer_line_lengths(GeoCoordinates::Geographic), 1);
+        let line = &net.line.as_ref().unwrap()[0];
+        assert!((line.length_km - 22.239).abs() < 0.01, "{}", line.length_km);
+        let z = line.series_impedance();
+        assert!((z - Complex::new(0.1, 0.3) * line.length_km).norm() < 1e-12);
+        // given lengths are kept
+        assert_eq!(net.infer_line_lengths(GeoCoordinates::Kilometers), 0);
+
+        let square = [[0.0, 0.0], [3.0, 0.0], [3.0, 4.0]];
+        assert_eq!(GeoCoordinates::Kilometers.length_km(&square), 7.0);
+    }
+
+    #[test]
+    fn test_load_network() {
+        let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+        for file in ["networks.json", "IEEE118/data.zip", "case9.m"] {
+            let net = load_network(format!("{}/cases/{}", dir, file)).unwrap();
+            assert!(!net.bus.is_empty());
+            let solved = PowerGrid::new(net).solve();
+            assert!(solved.is_ok(), "{}: {:?}", file, solved.err());
+        }
+
+        let error = load_network(format!("{}/Cargo.toml", dir)).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(load_network(format!("{}/cases/missing.json", dir)).is_err());
+        // a folder without the CSV tables and a malformed table are errors, not panics
+        assert!(load_network(format!("{}/cases", dir)).is_err());
+        let table = serde_json::json!({"columns": ["vn_kv"], "index": [0], "data": [["high"]]});
+        let document = serde_json::json!({"_object": {"bus": {"_object": table.to_string()}}});
+        let path = std::env::temp_dir().join(format!("rustpower_net_{}.json", std::process::id()));
+        fs::write(&path, document.to_string()).unwrap();
+        let error = load_network(path.to_string_lossy().into_owned()).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_capacity_summary() {
+        let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let mut net = load_csv_zip(format!("{}/cases/IEEE118/data.zip", dir)).unwrap();
+        net.gen.as_mut().unwrap()[0].in_service = false;
+        let summary = net.capacity_summary();
+
+        let (mut installed, mut dispatched, mut load) = (0.0, 0.0, 0.0);
+        for gen in net.gen.iter().flatten().skip(1) {
+            installed += gen.max_p_mw;
+            dispatched += gen.p_mw;
+        }
+        for x in net.load.iter().flatten() {
+            load += x.p_mw;
+        }
+        let sgen: f64 = net.sgen.iter().flatten().map(|x| x.p_mw).sum();
+        assert!((summary.installed_mw - installed).abs() < 1e-9);
+        assert!((summary.dispatched_mw - dispatched).abs() < 1e-9);
+        assert!((summary.sgen_mw - sgen).abs() < 1e-9);
+        assert!((summary.load_mw - load).abs() < 1e-9);
+        let margin = (installed + sgen - load) / load;
+        assert!((summary.reserve_margin().unwrap() - margin).abs() < 1e-12);
+        assert!(summary.installed_mw > summary.dispatched_mw && summary.load_mw > 0.0);
+        assert_eq!(Network::default().capacity_summary().reserve_margin(), None);
+    }
+
+    #[test]
+    fn test_line_charging_frequency() {
+        let mut net = Network {
+            bus: buses(2, 110.0),
+            ext_grid: Some(vec![ext_grid(0)]),
+            line: Some(vec![Line {
+                length_km: 10.0,
+                r_ohm_per_km: 0.1,
+                x_ohm_per_km: 0.4,
+                c_nf_per_km: 10.0,
+                ..line(0, 1)
+            }]),
+            ..Default::default()
+        };
+        let charging = |net: &Network| {
+            let pf = PFNetwork::try_from(net).unwrap();
+            pf.y_br.iter().find(|x| x.port.0[1] == GND).unwrap().y.0.im
+        };
+
+        assert_eq!(net.f_hz, 50.0);
+        let b_50 = charging(&net);
+        assert!((b_50 - 0.5 * 2.0 * PI * 50.0 * 10.0 * 10.0 * 1e-9).abs() < 1e-12);
+        net.f_hz = 60.0;
+        assert!((charging(&net) / b_50 - 1.2).abs() < 1e-12);
+
+        let json = serde_json::to_value(&net).unwrap();
+        let mut json = json.as_object().unwrap().clone();
+        json.remove("f_hz");
+        let parsed: Network = serde_json::from_value(json.into()).unwrap();
+        assert_eq!(parsed.f_hz, 50.0);
+    }
+
+    #[test]
+    fn test_out_of_service_elements() {
+        let base = substation();
+        let mut net = substation();
+        net.trafo.as_mut().unwrap().push(trafo(0, 1));
+        net.load.as_mut().unwrap().push(load(1, 5.0, 1.0));
+        net.gen = Some(vec![Gen {
+            bus: 1,
+            p_mw: 10.0,
+            vm_pu: 1.02,
+            scaling: 1.0,
+            ..Default::default()
+        }]);
+        net.sgen = Some(vec![SGen {
+            bus: 1,
+            p_mw: 3.0,
+            scaling: 1.0,
+            ..Default::default()
+        }]);
+        net.shunt = Some(vec![Shunt {
+            bus: 1,
+            q_mvar: 2.0,
+            vn_kv: 20.0,
+            step: 1,
+            max_step: 1,
+            ..Default::default()
+        }]);
+        net.trafo.as_mut().unwrap()[1].in_service = false;
+        net.load.as_mut().unwrap()[1].in_service = false;
+
+        // elements out of service are left out as if they didn't exist
+        let expected = PFNetwork::try_from(&base).unwrap();
+        let pf = PFNetwork::try_from(&net).unwrap();
+        assert_eq!(pf.create_y_bus(), expected.create_y_bus());
+        assert_eq!(pf.create_s_bus(), expected.create_s_bus());
+        assert!(pf.pv_nodes.is_empty());
+        assert_eq!(pf.y_br.len(), expected.y_br.len());
+    }
+
+    #[test]
+    fn test_shunt_steps() {
+        // pandapower gives the shunt power per step at 1 pu and reports
+        // res_shunt = step * (p_mw + j q_mvar) * vm^2
+        let network = |step| {
+            let mut net = substation();
+            net.load = None;
+            net.shunt = Some(vec![Shunt {
+                bus: 0,
+                p_mw: 0.1,
+                q_mvar: -2.0,
+                vn_kv: 110.0,
+                step,
+                max_step: 4,
+                in_service: true,
+                ..Default::default()
+            }]);
+            net
+        };
+        // the slack bus holds 1 pu and the shunt is the only consumer, so the external grid
+        // supplies exactly the shunt result
+        let solved = PowerGrid::new(network(3)).solve().unwrap();
+        assert!((solved.res_slack - Complex::new(0.3, -6.0)).norm() < 1e-6);
+
+        let solved = PowerGrid::new(network(0)).solve().unwrap();
+        assert!(solved.res_slack.norm() < 1e-6);
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn test_load_gzip_json() {
+        let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+        let folder = format!("{}/cases", dir);
+        let plain = load_pandapower_json(folder.to_owned() + "/networks.json").unwrap();
+        let gzipped = load_pandapower_json(folder.to_owned() + "/networks.json.gz").unwrap();
+        assert_eq!(format!("{:?}", plain), format!("{:?}", gzipped));
+    }
+
+    #[test]
+    fn test_load_measurements() {
+        let table = serde_json::json!({
+            "columns": ["name", "measurement_type", "element_type", "element", "value", "std_dev", "side"],
+            "index": [4, 9],
+            "data": [
+                [null, "v", "bus", 1, 1.02, 0.004, null],
+                ["flow", "p", "line", 3, 12.5, 0.1, "from"]
+            ]
+        });
+        let object = serde_json::json!({"measurement": {"_object": table.to_string()}});
+        let net = Network {
+            measurement: load_pandapower_element_json(object.as_object().unwrap(), "measurement")
+                .unwrap(),
+            ..Default::default()
+        };
+
+        let v = net.measurements_of("bus", 1);
+        assert_eq!(v.len(), 1);
+        assert_eq!((v[0].index, v[0].measurement_type.as_str()), (4, "v"));
+        assert_eq!(v[0].value, 1.02);
+        let p = net.measurements_of("line", 3);
+        assert_eq!(p.len(), 1);
+        assert_eq!((p[0].index, p[0].side.as_deref()), (9, Some("from")));
+        assert!(net.measurements_of("line", 1).is_empty());
+    }
+
+    #[test]
+    fn test_import_report() {
+        let frame = |rows: usize| {
+            let table = serde_json::json!({
+                "columns": ["from_bus", "to_bus"],
+                "index": (0..rows).collect::<Vec<_>>(),
+                "data": vec![[0, 1]; rows],
+            });
+            serde_json::json!({"_class": "DataFrame", "_object": table.to_string()})
+        };
+        let object = serde_json::json!({
+            "impedance": frame(2),
+            "trafo3w": frame(0),
+            "line": frame(3),
+            "res_bus": frame(2),
+            "bus_geodata": frame(2),
+            "f_hz": 50.0,
+        });
+        let ignored = ignored_json_tables(object.as_object().unwrap());
+        assert_eq!(ignored.len(), 1);
+        assert_eq!(ignored["impedance"], 2);
+
+        let map = [
+            ("bus.csv", "index,vn_kv\n0,110\n"),
+            ("net/ward.csv", "bus,ps_mw\n0,1.0\n1,2.0\n"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        let ignored = ignored_csv_tables(&map.into_iter().collect());
+        assert_eq!(ignored.into_iter().collect::<Vec<_>>(), [("ward".to_string(), 2)]);
+    }
+
+    #[test]
+    fn test_load_minimal_json() {
+        let frame = |columns: &[&str], data: Value| {
+            let table = serde_json::json!({"columns": columns, "data": data});
+            serde_json::json!({"_class": "DataFrame", "_object": table.to_string()})
+        };
+        let document = serde_json::json!({
+            "_class": "pandapowerNet",
+            "_object": {
+                "bus": frame(
+                    &["name", "vn_kv", "type", "zone", "in_service"],
+                    serde_json::json!(vec![serde_json::json!([null, 20.0, "b", null, true]); 2]),
+                ),
+                "line": frame(
+                    &[
+                        "from_bus", "to_bus", "length_km", "r_ohm_per_km", "x_ohm_per_km",
+                        "c_nf_per_km", "g_us_per_km", "max_i_ka", "df", "parallel", "in_service",
+                    ],
+                    serde_json::json!([[0, 1, 2.0, 0.1, 0.3, 10.0, 0.0, 0.4, 1.0, 1, true]]),
+                ),
+                "load": frame(
+                    &[
+                        "bus", "p_mw", "q_mvar", "const_z_percent", "const_i_percent", "scaling",
+                        "in_service",
+                    ],
+                    serde_json::json!([[1, 4.0, 1.0, 0.0, 0.0, 1.0, true]]),
+                ),
+                "ext_grid": frame(
+                    &["bus", "vm_pu", "va_degree", "slack_weight", "in_service"],
+                    serde_json::json!([[0, 1.02, 0.0, 1.0, true]]),
+                ),
+                // an empty table whose columns and data were dropped by the export
+                "shunt": {"_class": "DataFrame", "_object": "{}"},
+            },
+        });
+        let net = load_pandapower_json_obj(document.as_object().unwrap()).unwrap();
+        assert_eq!(net.bus.len(), 2);
+        assert!(net.trafo.is_none() && net.gen.is_none());
+        assert_eq!(net.shunt.as_ref().map(Vec::len), Some(0));
+
+        let solved = PowerGrid::new(net).solve().unwrap();
+        let vm = solved.res_bus[1].vm_pu;
+        assert!(vm < 1.02 && vm > 0.95, "{}", vm);
+    }
+
     #[test]
     fn test_load_csv() -> () {
         let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
@@ -649,7 +2284,7 @@ mod tests {
     fn load_csv_all() -> () {
         let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
         let folder = format!("{}/cases/IEEE118", dir);
-        let mut net = load_csv_folder(folder);
+        let mut net = load_csv_folder(folder).unwrap();
         net.f_hz = 60.0;
         net.sn_mva = 100.0;
     }
@@ -660,4 +2295,649 @@ mod tests {
         let name = folder.to_owned() + "/data.zip";
         load_csv_zip(name).unwrap();
     }
+
+    /// Builds the [`substation`] with its tap at a position and characteristics of vk and vkr
+    /// over the tap position.
+    fn trafo_network(tap_pos: f64, tap_dependent_impedance: bool) -> Network {
+        let mut net = Network {
+            characteristic: Some(vec![
+                Characteristic {
+                    index: 0,
+                    x_vals: vec![-9.0, 0.0, 9.0],
+                    y_vals: vec![12.0, 10.0, 8.0],
+                },
+                Characteristic {
+                    index: 1,
+                    x_vals: vec![-9.0, 0.0, 9.0],
+                    y_vals: vec![0.6, 0.5, 0.4],
+                },
+            ]),
+            ..substation()
+        };
+        let trafo = &mut net.trafo.as_mut().unwrap()[0];
+        trafo.tap_pos = Some(tap_pos);
+        trafo.tap_dependent_impedance = Some(tap_dependent_impedance);
+        trafo.vk_percent_characteristic = Some(0);
+        trafo.vkr_percent_characteristic = Some(1);
+        net
+    }
+
+    #[test]
+    fn test_phase_shifting_transformer() {
+        let mut net = trafo_network(0.0, false);
+        net.trafo.as_mut().unwrap()[0].shift_degree = 30.0;
+        let neutral = PFNetwork::try_from(trafo_network(0.0, false)).unwrap();
+        let shifted = PFNetwork::try_from(&net).unwrap();
+        let (y0, y) = (neutral.create_y_bus(), shifted.create_y_bus());
+        let entry = |y: &nalgebra_sparse::CsrMatrix<Complex<f64>>, i, j| {
+            y.get_entry(i, j).unwrap().into_value()
+        };
+        let rotation = Complex::from_polar(1.0, 30f64.to_radians());
+        // the diagonal blocks keep the magnitude model, the off-diagonal ones rotate
+        assert!((entry(&y, 0, 0) - entry(&y0, 0, 0)).norm() < 1e-12);
+        assert!((entry(&y, 1, 1) - entry(&y0, 1, 1)).norm() < 1e-12);
+        assert!((entry(&y, 0, 1) - entry(&y0, 0, 1) * rotation).norm() < 1e-12);
+        assert!((entry(&y, 1, 0) - entry(&y0, 1, 0) * rotation.conj()).norm() < 1e-12);
+
+        // the LV voltage lags by the shift, without changing the magnitudes or the losses
+        let solve = |pf: &PFNetwork| pf.run_pf(pf.create_v_init(), Some(10), Some(1e-8)).0;
+        let (v0, v) = (solve(&neutral), solve(&shifted));
+        assert!((v[1].norm() - v0[1].norm()).abs() < 1e-9);
+        let lag = (v0[1] / v[1]).arg().to_degrees();
+        assert!((lag - 30.0).abs() < 1e-6, "{}", lag);
+        let power = ext_grid_power(trafo_network(0.0, false)) - ext_grid_power(net);
+        assert!(power.norm() < 1e-6);
+    }
+
+    #[test]
+    fn test_parallel_transformers() {
+        let mut parallel = trafo_network(0.0, false);
+        parallel.trafo.as_mut().unwrap()[0].parallel = 2;
+        let mut separate = trafo_network(0.0, false);
+        let second = trafo_network(0.0, false).trafo.unwrap().pop().unwrap();
+        separate.trafo.as_mut().unwrap().push(second);
+
+        let solve = |net: &Network| {
+            let pf = PFNetwork::try_from(net).unwrap();
+            let (v, iterations) = pf.run_pf(pf.create_v_init(), Some(10), Some(1e-8));
+            SolvedNetwork::new(&pf, v, iterations)
+        };
+        let (parallel, separate) = (solve(&parallel), solve(&separate));
+        assert_eq!(separate.res_trafo.len(), 2);
+        let combined = &parallel.res_trafo[0];
+        for x in &separate.res_trafo {
+            assert!((2.0 * x.p_from_mw - combined.p_from_mw).abs() < 1e-6);
+            assert!((2.0 * x.q_from_mvar - combined.q_from_mvar).abs() < 1e-6);
+        }
+        assert!((parallel.res_bus[1].vm_pu - separate.res_bus[1].vm_pu).abs() < 1e-9);
+
+        // a single unit carries the load with a larger voltage drop
+        let single = solve(&trafo_network(0.0, false));
+        assert!(single.res_bus[1].vm_pu < parallel.res_bus[1].vm_pu);
+    }
+
+    #[test]
+    fn test_line_length() {
+        let line = Line {
+            from_bus: 0,
+            to_bus: 1,
+            in_service: true,
+            parallel: 1,
+            length_km: 12.5,
+            r_ohm_per_km: 0.2,
+            x_ohm_per_km: 0.4,
+            ..Default::default()
+        };
+        let bus = (0..2)
+            .map(|index| Bus {
+                index,
+                in_service: true,
+                vn_kv: 20.0,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let branches = line_to_admit(0.0, &bus, &NodeLookup::new(&bus), &line);
+        assert_eq!(branches.len(), 1);
+        let y = branches[0].y.0;
+        assert!((y - 1.0 / Complex::<f64>::new(2.5, 5.0)).norm() < 1e-12);
+        assert!((y - 1.0 / Complex::<f64>::new(0.2, 0.4)).norm() > 1.0);
+    }
+
+    #[test]
+    fn test_unit_transformer() {
+        let unit = UnitTransformer {
+            sn_mva: 60.0,
+            vn_lv_kv: 10.5,
+            vk_percent: 12.0,
+            vkr_percent: 0.4,
+        };
+        let network = |expanded: bool| {
+            let mut net = trafo_network(0.0, false);
+            net.gen = Some(vec![Gen {
+                index: 3,
+                bus: 1,
+                in_service: true,
+                p_mw: 30.0,
+                vm_pu: 1.02,
+                scaling: 1.0,
+                unit_transformer: Some(unit),
+                ..Default::default()
+            }]);
+            if expanded {
+                net.bus.push(Bus {
+                    index: 2,
+                    in_service: true,
+                    vn_kv: 10.5,
+                    ..Default::default()
+                });
+                net.trafo.as_mut().unwrap().push(Transformer {
+                    index: 1,
+                    hv_bus: 1,
+                    lv_bus: 2,
+                    in_service: true,
+                    df: 1.0,
+                    parallel: 1,
+                    sn_mva: 60.0,
+                    vn_hv_kv: 20.0,
+                    vn_lv_kv: 10.5,
+                    vk_percent: 12.0,
+                    vkr_percent: 0.4,
+                    ..Default::default()
+                });
+                let gen = &mut net.gen.as_mut().unwrap()[0];
+                gen.bus = 2;
+                gen.unit_transformer = None;
+            }
+            net
+        };
+
+        let mut implicit = network(false);
+        implicit.expand_unit_transformers();
+        assert_eq!(implicit.bus.len(), 3);
+        assert_eq!(implicit.bus[2].vn_kv, 10.5);
+        assert_eq!(implicit.gen.as_ref().unwrap()[0].bus, 2);
+        assert!(implicit.gen.as_ref().unwrap()[0].unit_transformer.is_none());
+
+        let implicit = PowerGrid::new(network(false)).solve().unwrap();
+        let explicit = PowerGrid::new(network(true)).solve().unwrap();
+        assert_eq!(implicit.res_bus.len(), 3);
+        assert_eq!(implicit.res_trafo.len(), 2);
+        for (a, b) in implicit.res_bus.iter().zip(&explicit.res_bus) {
+            assert_eq!(a.bus, b.bus);
+            assert!((a.vm_pu - b.vm_pu).abs() < 1e-9);
+            assert!((a.p_mw - b.p_mw).abs() < 1e-6 && (a.q_mvar - b.q_mvar).abs() < 1e-6);
+        }
+        assert!((implicit.res_bus[2].vm_pu - 1.02).abs() < 1e-6);
+        assert!((implicit.res_gen[0].q_mvar - explicit.res_gen[0].q_mvar).abs() < 1e-6);
+
+        // the assembly expands them as well, on a copy of the network
+        let net = network(false);
+        let expected = PFNetwork::try_from(&network(true)).unwrap();
+        assert_eq!(PFNetwork::try_from(&net).unwrap().create_y_bus(), expected.create_y_bus());
+        assert!(net.gen.as_ref().unwrap()[0].unit_transformer.is_some());
+    }
+
+    #[test]
+    fn test_tap_side() {
+        let solve = |tap_side: &str, p_mw: f64| {
+            let mut net = trafo_network(4.0, false);
+            net.trafo.as_mut().unwrap()[0].tap_side = Some(tap_side.to_string());
+            let load = &mut net.load.as_mut().unwrap()[0];
+            load.p_mw = p_mw;
+            load.q_mvar = 0.4 * p_mw;
+            let pf = PFNetwork::try_from(&net).unwrap();
+            let (v, _) = pf.run_pf(pf.create_v_init(), Some(10), Some(1e-8));
+            v[1].norm()
+        };
+        // without load, a tap 5 % above neutral lowers the LV voltage on the HV side and
+        // raises it on the LV side
+        assert!((solve("hv", 0.0) - 1.0 / 1.05).abs() < 1e-9);
+        assert!((solve("lv", 0.0) - 1.05).abs() < 1e-9);
+
+        let (hv, lv) = (solve("hv", 20.0), solve("lv", 20.0));
+        assert!(hv < 1.0 / 1.05 && lv < 1.05);
+        assert!(lv > 1.0 && hv < 1.0);
+    }
+
+    /// Solves the network and returns the complex power drawn from the external grid in MVA.
+    fn ext_grid_power(net: Network) -> Complex<f64> {
+        let pf = PFNetwork::try_from(net).unwrap();
+        let (v, _) = pf.run_pf(pf.create_v_init(), Some(10), Some(1e-8));
+        let i = pf.create_y_bus() * &v;
+        v[0] * i[0].conj() * pf.s_base
+    }
+
+    #[test]
+    fn test_characteristic_interpolation() {
+        let c = Characteristic {
+            index: 0,
+            x_vals: vec![-9.0, 0.0, 9.0],
+            y_vals: vec![12.0, 10.0, 8.0],
+        };
+        assert_eq!(c.interpolate(0.0), 10.0);
+        assert_eq!(c.interpolate(4.5), 9.0);
+        assert_eq!(c.interpolate(-20.0), 12.0);
+        assert_eq!(c.interpolate(20.0), 8.0);
+
+        let row = serde_json::json!({
+            "object": {"_module": "pandapower.control.util.characteristic",
+                       "_class": "Characteristic",
+                       "_object": "{\"x_vals\": [0.0, 1.0], \"y_vals\": [5.0, 6.0]}"},
+            "index": 3
+        });
+        let c: Characteristic = serde_json::from_value(row).unwrap();
+        assert_eq!(c.index, 3);
+        assert_eq!(c.interpolate(0.5), 5.5);
+    }
+
+    #[test]
+    fn test_per_unit_load() {
+        let mw = ext_grid_power(trafo_network(0.0, false));
+
+        let mut net = trafo_network(0.0, false);
+        let load = &mut net.load.as_mut().unwrap()[0];
+        load.p_mw = 0.2;
+        load.q_mvar = 0.08;
+        load.unit = Some(PowerUnit::PerUnit);
+        let pu = ext_grid_power(net);
+        assert!((mw - pu).norm() < 1e-9, "{} {}", mw, pu);
+
+        let unit: PowerUnit = serde_json::from_str("\"pu\"").unwrap();
+        assert_eq!(unit, PowerUnit::PerUnit);
+        assert_eq!(unit.to_mw(100.0), 100.0);
+    }
+
+    #[test]
+    fn test_colocated_injections() {
+        let mut net = trafo_network(0.0, false);
+        let load = |p_mw, q_mvar| Load {
+            bus: 1,
+            in_service: true,
+            p_mw,
+            q_mvar,
+            scaling: 1.0,
+            ..Default::default()
+        };
+        net.load = Some(vec![load(4.0, 1.0), load(6.0, 2.5), load(2.0, -0.5)]);
+        net.sgen = Some(vec![SGen {
+            bus: 1,
+            p_mw: 3.0,
+            q_mvar: 0.5,
+            scaling: 1.0,
+            in_service: true,
+            ..Default::default()
+        }]);
+        let pf = PFNetwork::try_from(&net).unwrap();
+        assert_eq!(pf.pq_loads.iter().filter(|x| x.bus == 1).count(), 4);
+
+        let s_bus = pf.create_s_bus();
+        let expected = Complex::new(3.0 - 12.0, 0.5 - 3.0) / 100.0;
+        assert!((s_bus[1] - expected).norm() < 1e-12, "{}", s_bus[1]);
+
+        let (v, iterations) = pf.run_pf(pf.create_v_init(), None, None);
+        let solved = SolvedNetwork::new(&pf, v, iterations);
+        let bus = solved.bus_result(1).unwrap();
+        assert!((bus.p_mw - 9.0).abs() < 1e-4 && (bus.q_mvar - 2.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_exponential_load() {
+        let solve = |net: &Network| {
+            let pf = PFNetwork::try_from(net).unwrap();
+            pf.run_pf(pf.create_v_init(), None, None)
+        };
+        let with_exponents = |exp: f64| {
+            let mut net = trafo_network(0.0, false);
+            let load = &mut net.load.as_mut().unwrap()[0];
+            (load.exp_p, load.exp_q) = (Some(exp), Some(exp));
+            solve(&net)
+        };
+
+        // constant impedance: the same demand at 1 pu as a bus shunt, solved in as many
+        // Newton-Raphson iterations since the load is part of the Jacobian
+        let mut net = trafo_network(0.0, false);
+        net.load = None;
+        (net.bus[1].g, net.bus[1].b) = (Some(20.0), Some(-8.0));
+        let (v_z, iterations_z) = solve(&net);
+        let (v, iterations) = with_exponents(2.0);
+        assert!((&v - &v_z).camax() < 1e-6, "{} {}", v[1], v_z[1]);
+        assert_eq!(iterations, iterations_z);
+
+        // constant power
+        let (v_pq, _) = solve(&trafo_network(0.0, false));
+        let (v, _) = with_exponents(0.0);
+        assert!((&v - &v_pq).camax() < 1e-9);
+        assert!((v_pq[1].norm() - v_z[1].norm()).abs() > 1e-4);
+    }
+
+    #[test]
+    fn test_load_power_factor() {
+        let mut net = trafo_network(0.0, false);
+        let load = &mut net.load.as_mut().unwrap()[0];
+        load.p_mw = 18.0;
+        load.q_mvar = 0.0;
+        load.cos_phi = Some(0.9);
+        let q_mvar = 18.0 * (1.0f64 - 0.81).sqrt() / 0.9;
+        let s_bus = PFNetwork::try_from(&net).unwrap().create_s_bus();
+        assert!((s_bus[1] + Complex::new(18.0, q_mvar) / 100.0).norm() < 1e-12);
+
+        let mode: PowerFactorMode = serde_json::from_str("\"overexcited\"").unwrap();
+        let load = &mut net.load.as_mut().unwrap()[0];
+        load.mode = Some(mode);
+        assert!((load.q() + q_mvar).abs() < 1e-12);
+        let mode: PowerFactorMode = serde_json::from_str("\"ind\"").unwrap();
+        assert_eq!(mode, PowerFactorMode::Underexcited);
+    }
+
+    #[test]
+    fn test_bus_shunt() {
+        let lv_voltage = |b: Option<f64>| {
+            let mut net = trafo_network(0.0, false);
+            net.bus[1].b = b;
+            let pf = PFNetwork::try_from(net).unwrap();
+            let (v, _) = pf.run_pf(pf.create_v_init(), Some(10), Some(1e-8));
+            v[1].norm()
+        };
+        let plain = lv_voltage(None);
+        assert_eq!(plain, lv_voltage(Some(0.0)));
+        // a capacitive shunt supplies part of the reactive load and raises the voltage
+        assert!(lv_voltage(Some(5.0)) > plain + 1e-3);
+    }
+
+    #[test]
+    fn test_tap_changer_type() {
+        let mut trafo = trafo_network(4.0, false).trafo.unwrap().remove(0);
+        let ratio = trafo.tap_ratio();
+        assert!((ratio - Complex::new(1.05, 0.0)).norm() < 1e-12);
+
+        trafo.tap_changer_type = Some("Symmetrical".to_string());
+        let symmetrical = trafo.tap_ratio();
+        assert!((symmetrical.norm() - 1.0).abs() < 1e-12);
+        assert!((symmetrical.arg() - 2.0 * 0.025f64.atan()).abs() < 1e-12);
+        assert!((symmetrical - ratio).norm() > 1e-3);
+
+        trafo.tap_changer_type = Some("Tabular".to_string());
+        assert_eq!(trafo.changer_type(), None);
+    }
+
+    #[test]
+    fn test_direct_tap_ratio() {
+        let ybus = |net: &Network| {
+            let pf = PFNetwork::try_from(net).unwrap();
+            YBusPattern::new(pf.buses.len(), &pf.y_br).assemble(&pf.y_br, pf.s_base)
+        };
+        let stepped = trafo_network(4.0, false);
+        let expected = stepped.trafo.as_ref().unwrap()[0].tap_ratio();
+
+        let mut direct = trafo_network(0.0, false);
+        let trafo = &mut direct.trafo.as_mut().unwrap()[0];
+        trafo.tap_ratio_magnitude = Some(expected.norm());
+        trafo.tap_ratio_degree = Some(expected.arg().to_degrees());
+        assert!((trafo.tap_ratio() - expected).norm() < 1e-12);
+        // the direct ratio overrides the tap position
+        trafo.tap_pos = Some(-9.0);
+        assert!((trafo.tap_ratio() - expected).norm() < 1e-12);
+
+        let (a, b) = (ybus(&stepped), ybus(&direct));
+        assert_eq!(a.col_indices(), b.col_indices());
+        for (x, y) in a.values().iter().zip(b.values()) {
+            assert!((x - y).norm() < 1e-12);
+        }
+
+        // the angle of a direct ratio shifts the phase like the vector group does
+        let mut angled = trafo_network(0.0, false);
+        let trafo = &mut angled.trafo.as_mut().unwrap()[0];
+        trafo.tap_ratio_magnitude = Some(1.02);
+        trafo.tap_ratio_degree = Some(5.0);
+        let mut shifted = trafo_network(0.0, false);
+        let trafo = &mut shifted.trafo.as_mut().unwrap()[0];
+        trafo.tap_ratio_magnitude = Some(1.02);
+        trafo.shift_degree = 5.0;
+        let (a, b) = (ybus(&angled), ybus(&shifted));
+        for (x, y) in a.values().iter().zip(b.values()) {
+            assert!((x - y).norm() < 1e-12);
+        }
+        let mut magnitude = trafo_network(0.0, false);
+        magnitude.trafo.as_mut().unwrap()[0].tap_ratio_magnitude = Some(1.02);
+        let y01 = |net: &Network| ybus(net).get_entry(0, 1).unwrap().into_value();
+        let rotation = y01(&angled) / y01(&magnitude);
+        assert!((rotation - Complex::from_polar(1.0, 5f64.to_radians())).norm() < 1e-12);
+
+        let solve = |net: &Network| {
+            let pf = PFNetwork::try_from(net).unwrap();
+            pf.run_pf(pf.create_v_init(), Some(10), Some(1e-8)).0
+        };
+        let (v0, v) = (solve(&magnitude), solve(&angled));
+        assert!(((v0[1] / v[1]).arg().to_degrees() - 5.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_tap_dependent_impedance() {
+        // at the neutral tap the characteristic reproduces the nameplate impedance
+        let neutral = ext_grid_power(trafo_network(0.0, false));
+        let neutral_char = ext_grid_power(trafo_network(0.0, true));
+        assert!((neutral - neutral_char).norm() < 1e-6);
+
+        // at the extreme tap the characteristic lowers vk, reducing the reactive losses
+        let extreme = ext_grid_power(trafo_network(9.0, false));
+        let extreme_char = ext_grid_power(trafo_network(9.0, true));
+        assert!((extreme.re - extreme_char.re).abs() < 0.1);
+        assert!(extreme_char.im < extreme.im - 0.05);
+        // losses scale roughly with vk: 8 % vs 10 % of the series reactance
+        let q_loss = extreme.im - 8.0;
+        let q_loss_char = extreme_char.im - 8.0;
+        assert!((q_loss_char / q_loss - 0.8).abs() < 0.05);
+    }
+
+    /// Builds two 20 kV buses coupled by the given switches, with a load behind the switches.
+    fn switch_network(switches: Vec<Switch>) -> Network {
+        Network {
+            sn_mva: 100.0,
+            bus: (0..2)
+                .map(|index| Bus {
+                    index,
+                    in_service: true,
+                    vn_kv: 20.0,
+                    ..Default::default()
+                })
+                .collect(),
+            ext_grid: Some(vec![ExtGrid {
+                bus: 0,
+                in_service: true,
+                vm_pu: 1.0,
+                ..Default::default()
+            }]),
+            load: Some(vec![Load {
+                bus: 1,
+                in_service: true,
+                p_mw: 1.0,
+                q_mvar: 0.5,
+                scaling: 1.0,
+                ..Default::default()
+            }]),
+            switch: Some(switches),
+            ..Default::default()
+        }
+    }
+
+    fn bus_switch(bus: i64, element: i64, closed: bool) -> Switch {
+        Switch {
+            bus,
+            element,
+            et: Some("b".to_string()),
+            closed,
+            ..Default::default()
+        }
+    }
+
+    /// Assembles a network with the switches modeled as branches instead of merged.
+    fn with_switch_branches(net: &Network) -> PFNetwork {
+        let config = PowerFlowConfig {
+            merge_zero_impedance: false,
+            ..Default::default()
+        };
+        PFNetwork::from_network(net, &config).unwrap()
+    }
+
+    #[test]
+    fn test_duplicate_switches() {
+        let single = with_switch_branches(&switch_network(vec![bus_switch(0, 1, true)]));
+        let duplicated = with_switch_branches(&switch_network(vec![
+            bus_switch(0, 1, true),
+            bus_switch(1, 0, true),
+            bus_switch(0, 1, false),
+        ]));
+        let y_single = single.create_y_bus();
+        let y_duplicated = duplicated.create_y_bus();
+        let y01 = y_duplicated.get_entry(0, 1).unwrap().into_value();
+        assert!((y01 + SWITCH_BIG_ADMITTANCE).norm() < 1e-6, "{}", y01);
+        assert_eq!(y_single, y_duplicated);
+
+        // an impedance switch in parallel to an ideal one is superseded by the ideal one
+        let mut sw = bus_switch(1, 0, true);
+        sw.z_ohm = 0.1;
+        let mixed = with_switch_branches(&switch_network(vec![sw, bus_switch(0, 1, true)]));
+        assert_eq!(mixed.create_y_bus(), y_single);
+
+        let (v, _) = duplicated.run_pf(duplicated.create_v_init(), Some(10), Some(1e-8));
+        assert!((v[0] - v[1]).norm() < 1e-6);
+    }
+
+    #[test]
+    fn test_switch_big_admittance() {
+        let net = switch_network(vec![bus_switch(0, 1, true)]);
+        let config = PowerFlowConfig {
+            switch_big_admittance: 1e4,
+            merge_zero_impedance: false,
+            ..Default::default()
+        };
+        let pf = PFNetwork::from_network(&net, &config).unwrap();
+        let switch = pf.y_br.iter().find(|x| x.element == Some(ElementRef::Switch(0)));
+        let y = switch.unwrap().y.0;
+        assert!((y - Complex::new(1e4 * 100.0 / (20.0 * 20.0), 0.0)).norm() < 1e-9, "{}", y);
+
+        let y01 = pf.create_y_bus().get_entry(0, 1).unwrap().into_value();
+        assert!((y01 + 1e4).norm() < 1e-6, "{}", y01);
+        assert_eq!(
+            PFNetwork::try_from(&net).unwrap().create_y_bus(),
+            PFNetwork::from_network(&net, &PowerFlowConfig::default()).unwrap().create_y_bus()
+        );
+    }
+
+    #[test]
+    fn test_merge_zero_impedance() {
+        let mut net = switch_network(vec![bus_switch(0, 1, true)]);
+        net.bus.extend((2..4).map(|index| Bus {
+            index,
+            in_service: true,
+            vn_kv: 20.0,
+            ..Default::default()
+        }));
+        net.bus[3].b = Some(0.5);
+        let line = |from_bus, to_bus, x_ohm_per_km| Line {
+            from_bus,
+            to_bus,
+            in_service: true,
+            length_km: 1.0,
+            parallel: 1,
+            r_ohm_per_km: 0.0,
+            x_ohm_per_km,
+            ..Default::default()
+        };
+        net.line = Some(vec![line(1, 2, 0.4), line(2, 3, 0.0)]);
+        net.load.as_mut().unwrap()[0].bus = 3;
+
+        let solve = |merge_zero_impedance| {
+            let config = PowerFlowConfig {
+                merge_zero_impedance,
+                ..Default::default()
+            };
+            let pf = PFNetwork::from_network(&net, &config).unwrap();
+            let (v, _) = pf.run_pf(pf.create_v_init(), Some(10), Some(1e-8));
+            (pf.buses.len(), v[pf.node_lookup()[2]])
+        };
+        let (n_kept, v_kept) = solve(false);
+        let (n_merged, v_merged) = solve(true);
+        assert_eq!(n_kept, net.bus.len());
+        assert_eq!(n_merged, 2);
+        assert!((v_kept - v_merged).norm() < 1e-6, "{} {}", v_kept, v_merged);
+        assert!(v_merged.norm() > 1.0 - 1e-3);
+
+        let (lookup, nodes) = NodeLookup::merged(&net.bus, &zero_impedance_pairs(&net));
+        assert_eq!(nodes.iter().map(|x| x.index).collect::<Vec<_>>(), [0, 2]);
+        assert_eq!([0, 1, 2, 3].map(|bus| lookup[bus]), [0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn test_chained_switches_order() {
+        let chain = [(0, 1), (1, 2), (2, 0)];
+        let network = |order: [usize; 3]| {
+            let switches = order.map(|i| bus_switch(chain[i].0, chain[i].1, true));
+            let mut net = switch_network(switches.into());
+            net.bus.push(Bus {
+                index: 2,
+                in_service: true,
+                vn_kv: 20.0,
+                ..Default::default()
+            });
+            net
+        };
+
+        let orders = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+        let base = network(orders[0]);
+        let mapping = NodeMapping::new(&base);
+        let ybus = with_switch_branches(&base).create_y_bus();
+        assert_eq!(mapping.sets(), vec![vec![0, 1, 2]]);
+        for order in orders {
+            let net = network(order);
+            assert_eq!(NodeMapping::new(&net), mapping);
+            let pf = with_switch_branches(&net);
+            let is_switch = |x: &&AdmittanceBranch| matches!(x.element, Some(ElementRef::Switch(_)));
+            assert_eq!(pf.y_br.iter().filter(is_switch).count(), 2);
+            assert_eq!(pf.create_y_bus(), ybus);
+        }
+    }
+
+    #[test]
+    fn test_switch_non_contiguous_buses() {
+        let mut net = switch_network(vec![bus_switch(20, 35, true)]);
+        net.bus = [35, 10, 20]
+            .into_iter()
+            .map(|index| Bus {
+                index,
+                in_service: true,
+                vn_kv: 20.0,
+                ..Default::default()
+            })
+            .collect();
+        net.ext_grid.as_mut().unwrap()[0].bus = 10;
+        net.load.as_mut().unwrap()[0].bus = 35;
+        net.line = Some(vec![Line {
+            from_bus: 10,
+            to_bus: 20,
+            in_service: true,
+            length_km: 1.0,
+            parallel: 1,
+            r_ohm_per_km: 0.1,
+            x_ohm_per_km: 0.3,
+            ..Default::default()
+        }]);
+
+        let pf = with_switch_branches(&net);
+        let lookup = NodeLookup::new(&pf.buses);
+        let (n10, n20, n35) = (lookup[10], lookup[20], lookup[35]);
+        assert_eq!((n10, n20, n35), (1, 2, 0));
+        assert_eq!(pf.ext.bus, n10 as i64);
+        assert_eq!(pf.pq_loads[0].bus, n35 as i64);
+
+        let ybus = pf.create_y_bus();
+        let y = ybus.get_entry(n20, n35).unwrap().into_value();
+        assert!((y + SWITCH_BIG_ADMITTANCE).norm() < 1e-6, "{}", y);
+        assert!(ybus.get_entry(n10, n35).unwrap().into_value().norm() < 1e-12);
+
+        let (v, _) = pf.run_pf(pf.create_v_init(), Some(10), Some(1e-8));
+        assert!((v[n20] - v[n35]).norm() < 1e-6);
+        assert!((v[n10] - v[n20]).norm() > 1e-4);
+    }
 }
+
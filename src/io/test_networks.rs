@@ -0,0 +1,106 @@
+//! Small networks shared by the unit tests.
+
+use super::pandapower::*;
+
+/// Builds in-service buses with the identifiers `0..n` at one rated voltage.
+pub(crate) fn buses(n: i64, vn_kv: f64) -> Vec<Bus> {
+    (0..n)
+        .map(|index| Bus {
+            index,
+            in_service: true,
+            vn_kv,
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Builds an external grid holding its bus at 1 pu.
+pub(crate) fn ext_grid(bus: i64) -> ExtGrid {
+    ExtGrid {
+        bus,
+        in_service: true,
+        vm_pu: 1.0,
+        ..Default::default()
+    }
+}
+
+/// Builds a 2 km line of 0.25 + j0.5 ohm/km.
+pub(crate) fn line(from_bus: i64, to_bus: i64) -> Line {
+    Line {
+        from_bus,
+        to_bus,
+        in_service: true,
+        length_km: 2.0,
+        parallel: 1,
+        r_ohm_per_km: 0.25,
+        x_ohm_per_km: 0.5,
+        ..Default::default()
+    }
+}
+
+/// Builds an in-service load at its rated power.
+pub(crate) fn load(bus: i64, p_mw: f64, q_mvar: f64) -> Load {
+    Load {
+        bus,
+        in_service: true,
+        p_mw,
+        q_mvar,
+        scaling: 1.0,
+        ..Default::default()
+    }
+}
+
+/// Builds a 20 kV network fed by an external grid at bus 0, with a line of [`line`] per
+/// branch and a load of 4 MW and 3 MVar at the last bus.
+///
+/// # Arguments
+///
+/// * `n_bus` - The number of buses.
+/// * `branches` - The buses at the ends of each line.
+pub(crate) fn radial_network(n_bus: i64, branches: &[(i64, i64)]) -> Network {
+    Network {
+        bus: buses(n_bus, 20.0),
+        ext_grid: Some(vec![ext_grid(0)]),
+        line: Some(branches.iter().map(|&(f, t)| line(f, t)).collect()),
+        load: Some(vec![load(n_bus - 1, 4.0, 3.0)]),
+        ..Default::default()
+    }
+}
+
+/// Builds a 40 MVA 110/20 kV transformer with a vk of 10 % and a vkr of 0.5 %, and a tap
+/// changer on the HV side at its neutral position, with steps of 1.25 % up to +-9.
+pub(crate) fn trafo(hv_bus: i32, lv_bus: i32) -> Transformer {
+    Transformer {
+        hv_bus,
+        lv_bus,
+        in_service: true,
+        parallel: 1,
+        df: 1.0,
+        sn_mva: 40.0,
+        vn_hv_kv: 110.0,
+        vn_lv_kv: 20.0,
+        vk_percent: 10.0,
+        vkr_percent: 0.5,
+        tap_side: Some("hv".to_string()),
+        tap_neutral: Some(0.0),
+        tap_min: Some(-9.0),
+        tap_max: Some(9.0),
+        tap_pos: Some(0.0),
+        tap_step_percent: Some(1.25),
+        ..Default::default()
+    }
+}
+
+/// Builds a substation feeding a load of 20 MW and 8 MVar at the 20 kV bus 1 through a
+/// [`trafo`] from an external grid at the 110 kV bus 0.
+pub(crate) fn substation() -> Network {
+    let mut bus = buses(2, 110.0);
+    bus[1].vn_kv = 20.0;
+    Network {
+        bus,
+        ext_grid: Some(vec![ext_grid(0)]),
+        load: Some(vec![load(1, 20.0, 8.0)]),
+        trafo: Some(vec![trafo(0, 1)]),
+        ..Default::default()
+    }
+}
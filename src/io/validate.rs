@@ -0,0 +1,470 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use super::pandapower::{Gen, GenControlMode, Network};
+use crate::basic::system::NodeMapping;
+
+/// A modeling problem found in the network data.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationIssue {
+    /// The bus is not connected to any in-service line or transformer, or closed bus-bus switch.
+    FloatingBus { bus: i64 },
+    /// The tap position of the transformer is outside of `tap_min..=tap_max`.
+    TapOutOfRange { trafo: i64, tap_pos: f64 },
+    /// The tap changer type of the transformer isn't supported.
+    UnsupportedTapChanger { trafo: i64 },
+    /// The length of the line isn't positive, so its impedance vanishes.
+    InvalidLineLength { line: i64, length_km: f64 },
+    /// Both the resistance and the reactance of the line are zero, so its admittance is
+    /// infinite. A negative reactance, as of series-compensated lines, is valid.
+    ZeroLineImpedance { line: i64 },
+    /// The voltage-controlled generators share a node, directly or through closed bus-bus
+    /// switches, but have different voltage setpoints.
+    ConflictingVoltageSetpoints { gens: Vec<i64> },
+    /// The power factor of the load lies outside `(0, 1]`, so its reactive power is undefined.
+    InvalidPowerFactor { load: i64, cos_phi: f64 },
+    /// The derating factor of the transformer lies outside `(0, 1]`, so its loading is
+    /// meaningless.
+    InvalidDeratingFactor { trafo: i64, df: f64 },
+    /// The bus-bus switch refers to a bus that doesn't exist.
+    UnknownSwitchBus { switch: i64, bus: i64 },
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationIssue::FloatingBus { bus } => {
+                write!(f, "bus {} has no connected branches", bus)
+            }
+            ValidationIssue::TapOutOfRange { trafo, tap_pos } => {
+                write!(
+                    f,
+                    "tap position {} of trafo {} is out of range",
+                    tap_pos, trafo
+                )
+            }
+            ValidationIssue::UnsupportedTapChanger { trafo } => {
+                write!(f, "tap changer type of trafo {} is not supported", trafo)
+            }
+            ValidationIssue::InvalidLineLength { line, length_km } => {
+                write!(f, "line {} has a non-positive length of {} km", line, length_km)
+            }
+            ValidationIssue::ZeroLineImpedance { line } => {
+                write!(f, "line {} has neither resistance nor reactance", line)
+            }
+            ValidationIssue::ConflictingVoltageSetpoints { gens } => {
+                write!(
+                    f,
+                    "generators {:?} control the same node with different voltage setpoints",
+                    gens
+                )
+            }
+            ValidationIssue::InvalidPowerFactor { load, cos_phi } => {
+                write!(f, "load {} has an invalid power factor of {}", load, cos_phi)
+            }
+            ValidationIssue::InvalidDeratingFactor { trafo, df } => {
+                write!(f, "trafo {} has an invalid derating factor of {}", trafo, df)
+            }
+            ValidationIssue::UnknownSwitchBus { switch, bus } => {
+                write!(f, "switch {} refers to the unknown bus {}", switch, bus)
+            }
+        }
+    }
+}
+
+impl Network {
+    /// Checks the network data for common modeling errors.
+    ///
+    /// The checks are cheap and don't require assembling the network, so they can be run before
+    /// every solve.
+    ///
+    /// # Returns
+    ///
+    /// The issues found, empty if the network looks valid.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        if self.bus.len() > 1 {
+            let degree = self.branch_count();
+            issues.extend(
+                self.bus
+                    .iter()
+                    .filter(|b| !degree.contains_key(&b.index))
+                    .map(|b| ValidationIssue::FloatingBus { bus: b.index }),
+            );
+        }
+        for trafo in self.trafo.iter().flatten() {
+            let tap_pos = trafo.tap_pos.unwrap_or(0.0);
+            let below = trafo.tap_min.is_some_and(|min| tap_pos < min);
+            let above = trafo.tap_max.is_some_and(|max| tap_pos > max);
+            if trafo.tap_pos.is_some() && (below || above) {
+                issues.push(ValidationIssue::TapOutOfRange {
+                    trafo: trafo.index,
+                    tap_pos,
+                });
+            }
+            if trafo.changer_type().is_none() {
+                issues.push(ValidationIssue::UnsupportedTapChanger { trafo: trafo.index });
+            }
+            if !(trafo.df > 0.0 && trafo.df <= 1.0) {
+                issues.push(ValidationIssue::InvalidDeratingFactor {
+                    trafo: trafo.index,
+                    df: trafo.df,
+                });
+            }
+        }
+        issues.extend(
+            self.line
+                .iter()
+                .flatten()
+                .filter(|x| x.length_km <= 0.0 || x.length_km.is_nan())
+                .map(|x| ValidationIssue::InvalidLineLength {
+                    line: x.index,
+                    length_km: x.length_km,
+                }),
+        );
+        issues.extend(
+            self.line
+                .iter()
+                .flatten()
+                .filter(|x| x.length_km > 0.0 && x.r_ohm_per_km == 0.0 && x.x_ohm_per_km == 0.0)
+                .map(|x| ValidationIssue::ZeroLineImpedance { line: x.index }),
+        );
+        let known: HashSet<i64> = self.bus.iter().map(|x| x.index).collect();
+        for sw in self.switch.iter().flatten() {
+            let buses = if sw.et.as_deref() == Some("b") {
+                vec![sw.bus, sw.element]
+            } else {
+                vec![sw.bus]
+            };
+            issues.extend(buses.into_iter().filter(|x| !known.contains(x)).map(|bus| {
+                ValidationIssue::UnknownSwitchBus {
+                    switch: sw.index,
+                    bus,
+                }
+            }));
+        }
+        issues.extend(self.voltage_setpoint_conflicts());
+        for load in self.load.iter().flatten() {
+            match load.cos_phi {
+                Some(cos_phi) if !(cos_phi > 0.0 && cos_phi <= 1.0) => {
+                    issues.push(ValidationIssue::InvalidPowerFactor {
+                        load: load.index,
+                        cos_phi,
+                    });
+                }
+                _ => {}
+            }
+        }
+        issues
+    }
+
+    /// Finds the nodes whose voltage-controlled generators disagree on the setpoint.
+    ///
+    /// Generators in voltage droop control are left out, as their voltage deviates from the
+    /// setpoint by design.
+    fn voltage_setpoint_conflicts(&self) -> Vec<ValidationIssue> {
+        let mapping = NodeMapping::new(self);
+        let mut nodes: BTreeMap<usize, Vec<&Gen>> = BTreeMap::new();
+        let gens = self.gen.iter().flatten();
+        let fixed = |x: &&Gen| x.control_mode == GenControlMode::VoltageControl;
+        for gen in gens.filter(|x| x.in_service).filter(fixed) {
+            if let Some(node) = mapping.node(gen.bus) {
+                nodes.entry(node).or_default().push(gen);
+            }
+        }
+        nodes
+            .into_values()
+            .filter(|gens| gens.iter().any(|x| (x.vm_pu - gens[0].vm_pu).abs() > 1e-9))
+            .map(|gens| ValidationIssue::ConflictingVoltageSetpoints {
+                gens: gens.iter().map(|x| x.index).collect(),
+            })
+            .collect()
+    }
+
+    /// Counts the branches connected to each bus, in the same way as they are assembled.
+    fn branch_count(&self) -> HashMap<i64, usize> {
+        let lines = self
+            .line
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .filter(|x| x.in_service)
+            .map(|x| (x.from_bus, x.to_bus));
+        let trafos = self
+            .trafo
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .filter(|x| x.in_service)
+            .map(|x| (x.hv_bus as i64, x.lv_bus as i64));
+        let switches = self
+            .switch
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .filter(|x| x.closed && x.et.as_deref() == Some("b"))
+            .map(|x| (x.bus, x.element));
+
+        let mut degree = HashMap::new();
+        for (f, t) in lines.chain(trafos).chain(switches) {
+            if f == t {
+                continue;
+            }
+            *degree.entry(f).or_insert(0) += 1;
+            *degree.entry(t).or_insert(0) += 1;
+        }
+        degree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic::system::PowerGrid;
+    use crate::io::pandapower::*;
+    use crate::io::test_networks::*;
+
+    #[test]
+    fn test_floating_bus() {
+        let mut net = Network {
+            bus: (0..3)
+                .map(|index| Bus {
+                    index,
+                    in_service: true,
+                    vn_kv: 20.0,
+                    ..Default::default()
+                })
+                .collect(),
+            line: Some(vec![Line {
+                from_bus: 0,
+                to_bus: 1,
+                in_service: true,
+                length_km: 1.0,
+                parallel: 1,
+                r_ohm_per_km: 0.1,
+                x_ohm_per_km: 0.1,
+                ..Default::default()
+            }]),
+            load: Some(vec![Load {
+                bus: 2,
+                in_service: true,
+                p_mw: 1.0,
+                scaling: 1.0,
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        assert_eq!(
+            net.validate(),
+            vec![ValidationIssue::FloatingBus { bus: 2 }]
+        );
+
+        net.switch = Some(vec![Switch {
+            bus: 1,
+            element: 2,
+            et: Some("b".to_string()),
+            closed: true,
+            ..Default::default()
+        }]);
+        assert!(net.validate().is_empty());
+
+        // an out-of-service line isn't assembled, so it doesn't connect its buses
+        net.line.as_mut().unwrap()[0].in_service = false;
+        assert_eq!(
+            net.validate(),
+            vec![ValidationIssue::FloatingBus { bus: 0 }]
+        );
+    }
+
+    #[test]
+    fn test_tap_out_of_range() {
+        let mut net = Network {
+            bus: (0..2)
+                .map(|index| Bus {
+                    index,
+                    in_service: true,
+                    vn_kv: 20.0,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let trafo = |index, tap_pos| Transformer {
+            index,
+            hv_bus: 0,
+            lv_bus: 1,
+            in_service: true,
+            df: 1.0,
+            tap_pos: Some(tap_pos),
+            tap_min: Some(-9.0),
+            tap_max: Some(9.0),
+            ..Default::default()
+        };
+        net.trafo = Some(vec![trafo(4, 9.0), trafo(5, 12.0)]);
+        assert_eq!(
+            net.validate(),
+            vec![ValidationIssue::TapOutOfRange {
+                trafo: 5,
+                tap_pos: 12.0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_line_length() {
+        let with_length = |index, length_km| Line {
+            index,
+            length_km,
+            ..line(0, 1)
+        };
+        let net = Network {
+            bus: buses(2, 20.0),
+            line: Some(vec![with_length(0, 2.0), with_length(1, 0.0), with_length(2, -1.0)]),
+            ..Default::default()
+        };
+        assert_eq!(
+            net.validate(),
+            vec![
+                ValidationIssue::InvalidLineLength {
+                    line: 1,
+                    length_km: 0.0
+                },
+                ValidationIssue::InvalidLineLength {
+                    line: 2,
+                    length_km: -1.0
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_series_compensated_line() {
+        // bus 2 is fed directly by line 0 and through bus 1 by lines 1 and 2
+        let network = |x_ohm_per_km: f64| {
+            let with_x = |index, from_bus, to_bus, x_ohm_per_km| Line {
+                index,
+                length_km: 10.0,
+                r_ohm_per_km: 0.01,
+                x_ohm_per_km,
+                ..line(from_bus, to_bus)
+            };
+            Network {
+                bus: buses(3, 110.0),
+                ext_grid: Some(vec![ext_grid(0)]),
+                line: Some(vec![
+                    with_x(0, 0, 2, 0.4),
+                    with_x(1, 0, 1, 0.4),
+                    with_x(2, 1, 2, x_ohm_per_km),
+                ]),
+                load: Some(vec![load(2, 60.0, 10.0)]),
+                ..Default::default()
+            }
+        };
+
+        let compensated = network(-0.2);
+        assert!(compensated.validate().is_empty());
+        let mut shorted = network(0.0);
+        shorted.line.as_mut().unwrap()[2].r_ohm_per_km = 0.0;
+        assert_eq!(
+            shorted.validate(),
+            vec![ValidationIssue::ZeroLineImpedance { line: 2 }]
+        );
+
+        // the negative reactance of line 2 shortens the path through bus 1, which then carries
+        // about two thirds of the load instead of half of it
+        let transfer = |net| {
+            let solved = PowerGrid::new(net).solve().unwrap();
+            solved.res_line[1].p_from_mw
+        };
+        let plain = transfer(network(0.0));
+        let series = transfer(compensated);
+        assert!((plain - 30.0).abs() < 1.0, "{}", plain);
+        assert!((series - 40.0).abs() < 1.0, "{}", series);
+    }
+
+    #[test]
+    fn test_voltage_setpoint_conflict() {
+        let gen = |index, bus, vm_pu| Gen {
+            index,
+            bus,
+            in_service: true,
+            vm_pu,
+            ..Default::default()
+        };
+        let mut net = Network {
+            bus: buses(3, 20.0),
+            line: Some(vec![line(0, 1), line(1, 2)]),
+            gen: Some(vec![gen(4, 0, 1.02), gen(5, 1, 1.0), gen(6, 2, 1.0)]),
+            switch: Some(vec![Switch {
+                bus: 0,
+                element: 1,
+                et: Some("b".to_string()),
+                closed: false,
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        assert!(net.validate().is_empty());
+
+        net.switch.as_mut().unwrap()[0].closed = true;
+        assert_eq!(
+            net.validate(),
+            vec![ValidationIssue::ConflictingVoltageSetpoints { gens: vec![4, 5] }]
+        );
+        // generators in droop control don't hold their setpoint
+        net.gen.as_mut().unwrap()[0].control_mode = GenControlMode::VoltageDroop;
+        assert!(net.validate().is_empty());
+        // agreeing setpoints on merged buses are fine
+        net.gen.as_mut().unwrap()[0].control_mode = GenControlMode::VoltageControl;
+        net.gen.as_mut().unwrap()[0].vm_pu = 1.0;
+        assert!(net.validate().is_empty());
+
+        // a switch to an unknown bus is reported instead of merged
+        net.switch.as_mut().unwrap()[0].element = 7;
+        assert_eq!(
+            net.validate(),
+            vec![ValidationIssue::UnknownSwitchBus { switch: 0, bus: 7 }]
+        );
+    }
+
+    #[test]
+    fn test_invalid_power_factor() {
+        let mut net = substation();
+        let with_cos_phi = |index, cos_phi| Load {
+            index,
+            cos_phi: Some(cos_phi),
+            ..load(1, 2.0, 0.0)
+        };
+        net.load = Some(vec![
+            with_cos_phi(0, 0.9),
+            with_cos_phi(1, 1.0),
+            with_cos_phi(2, 1.2),
+            with_cos_phi(3, 0.0),
+        ]);
+        assert_eq!(
+            net.validate(),
+            vec![
+                ValidationIssue::InvalidPowerFactor {
+                    load: 2,
+                    cos_phi: 1.2
+                },
+                ValidationIssue::InvalidPowerFactor {
+                    load: 3,
+                    cos_phi: 0.0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_derating_factor() {
+        let mut net = substation();
+        let trafos = net.trafo.as_mut().unwrap();
+        trafos.push(Transformer { index: 1, df: 0.8, ..trafo(0, 1) });
+        trafos.push(Transformer { index: 2, df: 0.0, ..trafo(0, 1) });
+        assert_eq!(
+            net.validate(),
+            vec![ValidationIssue::InvalidDeratingFactor { trafo: 2, df: 0.0 }]
+        );
+    }
+}
@@ -2,7 +2,9 @@ mod basic;
 pub mod io;
 pub mod prelude {
     use crate::basic;
+    pub use crate::io::matpower;
     pub use crate::io::pandapower;
+    pub use crate::io::validate::ValidationIssue;
     pub use basic::system::*;
     pub use basic::*;
 }